@@ -0,0 +1,388 @@
+use chrono::Utc;
+use log::{info, warn};
+use nix::sys::signal::{Signal, kill};
+use nix::unistd::Pid;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::{AlertConfig, AlertRule};
+use crate::procinfo::{ProcState, run_state};
+use crate::spawn::unblock_signals;
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What a rule is checked against, for one process at one sample.
+pub struct Sample<'a> {
+    pub pid: u32,
+    pub process_name: &'a str,
+    pub cpu_percent: f32,
+    pub rss_kb: u64,
+    pub fd_count: usize,
+}
+
+/// Runs the actions of `[alert]` rules when a process starts matching them.
+pub struct Alerts {
+    rules: Vec<AlertRule>,
+    agent: ureq::Agent,
+    /// Whether any rule needs the time processes spend in D state.
+    track_d_state: bool,
+}
+
+/// Whether every condition of `rule` holds, with `d_state` the time the
+/// process has been in D state.
+fn matches(rule: &AlertRule, sample: &Sample, d_state: Option<Duration>) -> bool {
+    let conditions = [
+        rule.cpu_percent.map(|t| sample.cpu_percent as f64 > t),
+        rule.rss_mb.map(|t| sample.rss_kb > t * 1024),
+        rule.d_state_sec
+            .map(|t| d_state.is_some_and(|d| d > Duration::from_secs(t))),
+        rule.fd_count.map(|t| sample.fd_count > t),
+    ];
+    // Rules without conditions are rejected by `Alerts::new`.
+    conditions.iter().flatten().all(|&held| held)
+}
+
+/// Parses `TERM`, `SIGTERM` or `15` into a signal.
+fn parse_signal(name: &str) -> Option<Signal> {
+    if let Ok(n) = name.parse::<i32>() {
+        return Signal::try_from(n).ok();
+    }
+    let upper = name.to_ascii_uppercase();
+    let full = if upper.starts_with("SIG") {
+        upper
+    } else {
+        format!("SIG{}", upper)
+    };
+    full.parse().ok()
+}
+
+/// The file freezing the cgroup of a process, given its
+/// `/proc/<pid>/cgroup`, and what to write to it: `cgroup.freeze` on
+/// cgroup v2, else the v1 freezer.
+fn freezer_file(cgroup: &str) -> Option<(String, &'static str)> {
+    for line in cgroup.lines() {
+        let mut parts = line.splitn(3, ':');
+        let (Some(_), Some(controllers), Some(path)) = (parts.next(), parts.next(), parts.next())
+        else {
+            continue;
+        };
+        if controllers.split(',').any(|c| c == "freezer") {
+            return Some((
+                format!("/sys/fs/cgroup/freezer{}/freezer.state", path),
+                "FROZEN",
+            ));
+        }
+        if controllers.is_empty() && path != "/" {
+            return Some((format!("/sys/fs/cgroup{}/cgroup.freeze", path), "1"));
+        }
+    }
+    None
+}
+
+/// Whether writing `file`, as given by `freezer_file`, would also freeze
+/// the process in `cgroup`, as it is in that cgroup or one below it.
+fn freezes(file: &str, cgroup: &str) -> bool {
+    let Some((own, _)) = freezer_file(cgroup) else {
+        return false;
+    };
+    match (Path::new(&own).parent(), Path::new(file).parent()) {
+        (Some(own), Some(frozen)) => own.starts_with(frozen),
+        _ => false,
+    }
+}
+
+fn read_cgroup(pid: u32) -> Option<String> {
+    fs::read_to_string(format!("/proc/{}/cgroup", pid)).ok()
+}
+
+fn rule_name(rule: &AlertRule, index: usize) -> String {
+    rule.name
+        .clone()
+        .unwrap_or_else(|| format!("rule{}", index + 1))
+}
+
+impl Alerts {
+    /// Rules with enforcement actions keep only their other actions unless
+    /// `allow_actions` is set.
+    pub fn new(config: &AlertConfig, allow_actions: bool) -> Option<Alerts> {
+        let rules: Vec<AlertRule> = config
+            .rule
+            .iter()
+            .flatten()
+            .enumerate()
+            .filter(|(i, r)| {
+                let any = r.cpu_percent.is_some()
+                    || r.rss_mb.is_some()
+                    || r.d_state_sec.is_some()
+                    || r.fd_count.is_some();
+                if !any {
+                    warn!("alert {} has no condition, skipping", rule_name(r, *i));
+                }
+                any
+            })
+            .map(|(i, r)| {
+                let mut r = r.clone();
+                let enforces = r.signal.is_some() || r.freeze.unwrap_or(false);
+                if enforces && !allow_actions {
+                    warn!(
+                        "alert {} signals or freezes processes, which needs --allow-actions",
+                        rule_name(&r, i)
+                    );
+                    r.signal = None;
+                    r.freeze = None;
+                }
+                if let Some(name) = &r.signal
+                    && parse_signal(name).is_none()
+                {
+                    warn!("alert {} has unknown signal {}", rule_name(&r, i), name);
+                    r.signal = None;
+                }
+                r
+            })
+            .collect();
+        if rules.is_empty() {
+            return None;
+        }
+        Some(Alerts {
+            track_d_state: rules.iter().any(|r| r.d_state_sec.is_some()),
+            rules,
+            agent: ureq::AgentBuilder::new().timeout(WEBHOOK_TIMEOUT).build(),
+        })
+    }
+
+    /// Checks the rules for `sample` and runs the actions of those that
+    /// started matching since the previous sample. Returns the names of the
+    /// ones that asked for a marker in the log entry.
+    pub fn check(&self, sample: &Sample, state: &mut ProcState, now: Instant) -> Vec<String> {
+        if self.track_d_state {
+            if run_state(sample.pid) == Some('D') {
+                state.d_state_since.get_or_insert(now);
+            } else {
+                state.d_state_since = None;
+            }
+        }
+        let d_state = state.d_state_since.map(|since| now - since);
+        let mut markers = Vec::new();
+        for (i, rule) in self.rules.iter().enumerate() {
+            if !matches(rule, sample, d_state) {
+                state.alerting.remove(&i);
+                continue;
+            }
+            if !state.alerting.insert(i) {
+                continue;
+            }
+            let name = rule_name(rule, i);
+            info!(
+                "alert {} fired for process {} ({})",
+                name, sample.pid, sample.process_name
+            );
+            self.fire(rule, &name, sample);
+            if rule.marker.unwrap_or(false) {
+                markers.push(name);
+            }
+        }
+        markers
+    }
+
+    fn fire(&self, rule: &AlertRule, name: &str, sample: &Sample) {
+        if rule.freeze.unwrap_or(false) {
+            freeze(name, sample.pid);
+        }
+        if let Some(signal) = rule.signal.as_deref().and_then(parse_signal) {
+            send_signal(name, sample.pid, signal);
+        }
+        if let Some(command) = &rule.command {
+            let mut cmd = Command::new("sh");
+            cmd.arg("-c")
+                .arg(command)
+                .env("FUZMON_ALERT", name)
+                .env("FUZMON_PID", sample.pid.to_string())
+                .env("FUZMON_PROCESS_NAME", sample.process_name)
+                .env("FUZMON_CPU_PERCENT", format!("{:.1}", sample.cpu_percent))
+                .env("FUZMON_RSS_KB", sample.rss_kb.to_string())
+                .env("FUZMON_FD_COUNT", sample.fd_count.to_string());
+            unblock_signals(&mut cmd);
+            match cmd.spawn() {
+                // Waited for elsewhere so a slow command does not hold up
+                // monitoring.
+                Ok(mut child) => {
+                    thread::spawn(move || child.wait());
+                }
+                Err(e) => warn!("failed to run alert command {}: {}", command, e),
+            }
+        }
+        if let Some(url) = &rule.webhook {
+            let body = serde_json::json!({
+                "alert": name,
+                "timestamp": Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+                "pid": sample.pid,
+                "process_name": sample.process_name,
+                "cpu_time_percent": sample.cpu_percent,
+                "rss_kb": sample.rss_kb,
+                "fd_count": sample.fd_count,
+            });
+            let agent = self.agent.clone();
+            let url = url.clone();
+            thread::spawn(move || {
+                let sent = agent
+                    .post(&url)
+                    .set("Content-Type", "application/json")
+                    .send_string(&body.to_string());
+                if let Err(e) = sent {
+                    warn!("failed to post alert to {}: {}", url, e);
+                }
+            });
+        }
+    }
+}
+
+/// fuzmon itself and init are never signaled or frozen.
+fn protected(pid: u32) -> bool {
+    pid == std::process::id() || pid == 1
+}
+
+fn send_signal(name: &str, pid: u32, signal: Signal) {
+    if protected(pid) {
+        warn!("alert {} not sending {} to pid {}", name, signal, pid);
+        return;
+    }
+    match kill(Pid::from_raw(pid as i32), signal) {
+        Ok(()) => info!("alert {} sent {} to pid {}", name, signal, pid),
+        Err(e) => warn!(
+            "alert {} failed to send {} to pid {}: {}",
+            name, signal, pid, e
+        ),
+    }
+}
+
+fn freeze(name: &str, pid: u32) {
+    let Some(cgroup) = read_cgroup(pid) else {
+        return;
+    };
+    let Some((file, value)) = freezer_file(&cgroup) else {
+        warn!("alert {} found no freezer for pid {}", name, pid);
+        return;
+    };
+    let own = read_cgroup(std::process::id());
+    if protected(pid) || own.is_some_and(|own| freezes(&file, &own)) {
+        warn!(
+            "alert {} not freezing the cgroup of pid {}, fuzmon is in it",
+            name, pid
+        );
+        return;
+    }
+    match fs::write(&file, value) {
+        Ok(()) => info!("alert {} froze {} for pid {}", name, file, pid),
+        Err(e) => warn!("alert {} failed to write {}: {}", name, file, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(cpu_percent: f32, rss_kb: u64) -> Sample<'static> {
+        Sample {
+            pid: std::process::id(),
+            process_name: "server",
+            cpu_percent,
+            rss_kb,
+            fd_count: 10,
+        }
+    }
+
+    fn rule(toml_text: &str) -> AlertRule {
+        toml::from_str(toml_text).unwrap()
+    }
+
+    #[test]
+    fn all_conditions_must_hold() {
+        let r = rule("cpu_percent = 90.0\nrss_mb = 1");
+        assert!(matches(&r, &sample(95.0, 2048), None));
+        assert!(!matches(&r, &sample(95.0, 512), None));
+        assert!(!matches(&r, &sample(50.0, 2048), None));
+        let stuck = rule("d_state_sec = 30");
+        assert!(matches(
+            &stuck,
+            &sample(0.0, 0),
+            Some(Duration::from_secs(31))
+        ));
+        assert!(!matches(
+            &stuck,
+            &sample(0.0, 0),
+            Some(Duration::from_secs(5))
+        ));
+        assert!(!matches(&stuck, &sample(0.0, 0), None));
+        assert!(matches(&rule("fd_count = 5"), &sample(0.0, 0), None));
+    }
+
+    #[test]
+    fn signals_parsed_by_name_or_number() {
+        assert_eq!(parse_signal("TERM"), Some(Signal::SIGTERM));
+        assert_eq!(parse_signal("sigkill"), Some(Signal::SIGKILL));
+        assert_eq!(parse_signal("9"), Some(Signal::SIGKILL));
+        assert_eq!(parse_signal("BOGUS"), None);
+    }
+
+    #[test]
+    fn freezer_found_for_cgroup_versions() {
+        assert_eq!(
+            freezer_file("0::/system.slice/batch.service\n"),
+            Some((
+                "/sys/fs/cgroup/system.slice/batch.service/cgroup.freeze".into(),
+                "1"
+            ))
+        );
+        assert_eq!(
+            freezer_file("12:cpu,cpuacct:/a\n7:freezer:/jobs/42\n"),
+            Some((
+                "/sys/fs/cgroup/freezer/jobs/42/freezer.state".into(),
+                "FROZEN"
+            ))
+        );
+        assert_eq!(freezer_file("0::/\n"), None);
+    }
+
+    #[test]
+    fn cgroups_holding_fuzmon_not_frozen() {
+        let (file, _) = freezer_file("0::/system.slice\n").unwrap();
+        assert!(freezes(&file, "0::/system.slice\n"));
+        assert!(freezes(&file, "0::/system.slice/fuzmon.service\n"));
+        assert!(!freezes(&file, "0::/system.slice-other/fuzmon.service\n"));
+        assert!(!freezes(&file, "0::/user.slice\n"));
+        assert!(!freezes(&file, "0::/\n"));
+        let (file, _) = freezer_file("7:freezer:/jobs\n").unwrap();
+        assert!(freezes(&file, "7:freezer:/jobs/42\n"));
+        assert!(!freezes(&file, "7:freezer:/other\n"));
+    }
+
+    #[test]
+    fn enforcement_needs_allow_actions() {
+        let config: AlertConfig =
+            toml::from_str("[[rule]]\nrss_mb = 1\nsignal = \"KILL\"\nfreeze = true\n").unwrap();
+        let denied = Alerts::new(&config, false).unwrap();
+        assert!(denied.rules[0].signal.is_none() && denied.rules[0].freeze.is_none());
+        let allowed = Alerts::new(&config, true).unwrap();
+        assert_eq!(allowed.rules[0].signal.as_deref(), Some("KILL"));
+    }
+
+    #[test]
+    fn rules_fire_once_until_cleared() {
+        let config: AlertConfig = toml::from_str(
+            "[[rule]]\nname = \"hot\"\ncpu_percent = 90.0\nmarker = true\n\
+             [[rule]]\nmarker = true\n",
+        )
+        .unwrap();
+        let alerts = Alerts::new(&config, false).unwrap();
+        assert_eq!(alerts.rules.len(), 1);
+        let mut state = ProcState::default();
+        let now = Instant::now();
+        assert_eq!(alerts.check(&sample(95.0, 0), &mut state, now), ["hot"]);
+        assert!(alerts.check(&sample(99.0, 0), &mut state, now).is_empty());
+        assert!(alerts.check(&sample(10.0, 0), &mut state, now).is_empty());
+        assert_eq!(alerts.check(&sample(95.0, 0), &mut state, now), ["hot"]);
+    }
+}
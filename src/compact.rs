@@ -0,0 +1,358 @@
+use serde::de::{Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
+use serde_json::{Map, Value, json};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Field names of the `msgpackc` format, written as their index. Existing
+/// logs depend on the positions, so names are only ever appended.
+const KEYS: &[&str] = &[
+    "version",
+    "timestamp",
+    "pid",
+    "process_name",
+    "cpu_time_percent",
+    "memory",
+    "cmdline",
+    "env",
+    "hostname",
+    "boot_id",
+    "fd_events",
+    "threads",
+    "delta",
+    "rss_kb",
+    "vsz_kb",
+    "swap_kb",
+    "tid",
+    "stacktrace",
+    "python_stacktrace",
+    "python_thread",
+    "php_stacktrace",
+    "merged_stacktrace",
+    "truncated",
+    "registers",
+    "stack_id",
+    "addr",
+    "func",
+    "file",
+    "line",
+    "name",
+    "owns_gil",
+    "active",
+    "fd",
+    "event",
+    "path",
+    "rip",
+    "rsp",
+    "rbp",
+    "rax",
+    "rdi",
+    "rsi",
+    "rdx",
+    "rcx",
+    "r8",
+    "r9",
+    "ppid",
+    "capture_error",
+    "start_time",
+    "stopped",
+    "alerts",
+    "self_overhead",
+];
+
+/// Fields whose strings are written once per pid, together with their
+/// number, and then referred to by that number.
+const INTERNED: &[&str] = &["process_name", "func", "file", "path"];
+
+/// Set on the first record of a pid after its strings were forgotten, so a
+/// reader starting at an index mark knows where numbering restarts.
+const RESET_KEY: u64 = 255;
+
+/// A MessagePack value whose maps may have integer keys, which
+/// `serde_json::Value` cannot hold.
+#[derive(Debug, PartialEq)]
+pub enum Packed {
+    Nil,
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Str(String),
+    Array(Vec<Packed>),
+    Map(Vec<(Packed, Packed)>),
+}
+
+impl Serialize for Packed {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Packed::Nil => s.serialize_unit(),
+            Packed::Bool(b) => s.serialize_bool(*b),
+            Packed::Int(i) => s.serialize_i64(*i),
+            Packed::UInt(u) => s.serialize_u64(*u),
+            Packed::Float(f) => s.serialize_f64(*f),
+            Packed::Str(v) => s.serialize_str(v),
+            Packed::Array(items) => {
+                let mut seq = s.serialize_seq(Some(items.len()))?;
+                for item in items {
+                    seq.serialize_element(item)?;
+                }
+                seq.end()
+            }
+            Packed::Map(entries) => {
+                let mut map = s.serialize_map(Some(entries.len()))?;
+                for (k, v) in entries {
+                    map.serialize_entry(k, v)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+struct PackedVisitor;
+
+impl<'de> Visitor<'de> for PackedVisitor {
+    type Value = Packed;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a MessagePack value")
+    }
+
+    fn visit_unit<E>(self) -> Result<Packed, E> {
+        Ok(Packed::Nil)
+    }
+
+    fn visit_none<E>(self) -> Result<Packed, E> {
+        Ok(Packed::Nil)
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<Packed, E> {
+        Ok(Packed::Bool(v))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Packed, E> {
+        Ok(Packed::Int(v))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Packed, E> {
+        Ok(Packed::UInt(v))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<Packed, E> {
+        Ok(Packed::Float(v))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Packed, E> {
+        Ok(Packed::Str(v.to_string()))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Packed, A::Error> {
+        let mut items = Vec::new();
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(Packed::Array(items))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Packed, A::Error> {
+        let mut entries = Vec::new();
+        while let Some(entry) = map.next_entry()? {
+            entries.push(entry);
+        }
+        Ok(Packed::Map(entries))
+    }
+}
+
+impl<'de> Deserialize<'de> for Packed {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Packed, D::Error> {
+        d.deserialize_any(PackedVisitor)
+    }
+}
+
+/// Strings already written for one pid, numbered in order of appearance.
+#[derive(Default)]
+pub struct Interner {
+    ids: HashMap<String, u64>,
+}
+
+impl Interner {
+    pub fn clear(&mut self) {
+        self.ids.clear();
+    }
+
+    fn pack(&mut self, value: &Value, key: Option<&str>) -> Packed {
+        match value {
+            Value::Null => Packed::Nil,
+            Value::Bool(b) => Packed::Bool(*b),
+            Value::Number(n) => match (n.as_u64(), n.as_i64()) {
+                (Some(u), _) => Packed::UInt(u),
+                (None, Some(i)) => Packed::Int(i),
+                _ => Packed::Float(n.as_f64().unwrap_or_default()),
+            },
+            Value::String(s) if key.is_some_and(|k| INTERNED.contains(&k)) => {
+                if let Some(id) = self.ids.get(s) {
+                    return Packed::UInt(*id);
+                }
+                let id = self.ids.len() as u64;
+                self.ids.insert(s.clone(), id);
+                // Numbered explicitly, so a lost record does not shift the
+                // strings after it.
+                Packed::Array(vec![Packed::UInt(id), Packed::Str(s.clone())])
+            }
+            Value::String(s) => Packed::Str(s.clone()),
+            Value::Array(items) => Packed::Array(items.iter().map(|v| self.pack(v, key)).collect()),
+            Value::Object(obj) => Packed::Map(
+                obj.iter()
+                    .map(|(k, v)| {
+                        let key = match KEYS.iter().position(|name| name == k) {
+                            Some(i) => Packed::UInt(i as u64),
+                            None => Packed::Str(k.clone()),
+                        };
+                        (key, self.pack(v, Some(k)))
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Converts a log entry into its compact form, replacing strings seen
+    /// before by their number.
+    pub fn encode(&mut self, entry: &Value) -> Packed {
+        let reset = self.ids.is_empty();
+        let mut packed = self.pack(entry, None);
+        if reset && let Packed::Map(entries) = &mut packed {
+            entries.push((Packed::UInt(RESET_KEY), Packed::Bool(true)));
+        }
+        packed
+    }
+}
+
+/// Strings read so far for each pid, by number, the reverse of `Interner`.
+#[derive(Default)]
+pub struct Strings {
+    by_pid: HashMap<u64, HashMap<u64, String>>,
+}
+
+fn unpack(packed: Packed, key: Option<&str>, strings: &mut HashMap<u64, String>) -> Value {
+    let interned = key.is_some_and(|k| INTERNED.contains(&k));
+    match packed {
+        Packed::Nil => Value::Null,
+        Packed::Bool(b) => json!(b),
+        Packed::UInt(id) if interned => {
+            // Only when the record introducing it was lost.
+            let s = strings.get(&id).map_or("?", String::as_str);
+            json!(s)
+        }
+        Packed::Int(i) => json!(i),
+        Packed::UInt(u) => json!(u),
+        Packed::Float(f) => json!(f),
+        Packed::Str(s) => {
+            // Logs written before strings carried their number.
+            if interned {
+                strings.insert(strings.len() as u64, s.clone());
+            }
+            Value::String(s)
+        }
+        Packed::Array(items) => {
+            if interned && let [Packed::UInt(id), Packed::Str(s)] = &items[..] {
+                strings.insert(*id, s.clone());
+                return Value::String(s.clone());
+            }
+            Value::Array(items.into_iter().map(|v| unpack(v, key, strings)).collect())
+        }
+        Packed::Map(entries) => {
+            let mut obj = Map::new();
+            for (k, v) in entries {
+                let name = match k {
+                    Packed::UInt(i) => KEYS
+                        .get(i as usize)
+                        .map_or_else(|| i.to_string(), |n| n.to_string()),
+                    Packed::Str(s) => s,
+                    _ => continue,
+                };
+                let v = unpack(v, Some(&name), strings);
+                obj.insert(name, v);
+            }
+            Value::Object(obj)
+        }
+    }
+}
+
+impl Strings {
+    /// Forgets every string after corrupt records were skipped, as these
+    /// may have renumbered them, so names not written again read as `?`
+    /// rather than as another string.
+    pub fn resync(&mut self) {
+        self.by_pid.clear();
+    }
+
+    /// Expands a compact record back into the entry it was written from.
+    pub fn decode(&mut self, packed: Packed) -> Value {
+        let pid_key = KEYS.iter().position(|k| *k == "pid").unwrap_or_default() as u64;
+        let mut pid = 0;
+        let mut reset = false;
+        let mut packed = packed;
+        if let Packed::Map(entries) = &mut packed {
+            for (k, v) in entries.iter() {
+                match (k, v) {
+                    (Packed::UInt(k), Packed::UInt(p)) if *k == pid_key => pid = *p,
+                    (Packed::UInt(RESET_KEY), _) => reset = true,
+                    _ => {}
+                }
+            }
+            entries.retain(|(k, _)| *k != Packed::UInt(RESET_KEY));
+        }
+        let strings = self.by_pid.entry(pid).or_default();
+        if reset {
+            strings.clear();
+        }
+        unpack(packed, None, strings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(pid: u32, func: &str) -> Value {
+        json!({
+            "pid": pid,
+            "process_name": "worker",
+            "memory": {"rss_kb": 1, "vsz_kb": 2, "swap_kb": 0},
+            "threads": [{"tid": pid, "stacktrace": [{"func": func}, {"func": "main"}]}],
+            "custom": "kept",
+        })
+    }
+
+    #[test]
+    fn round_trips_with_interned_strings() {
+        let entries = [entry(1, "a"), entry(2, "b"), entry(1, "b"), entry(1, "a")];
+        let mut interners: HashMap<u32, Interner> = HashMap::new();
+        let mut bytes = Vec::new();
+        for e in &entries {
+            let pid = e["pid"].as_u64().unwrap() as u32;
+            let packed = interners.entry(pid).or_default().encode(e);
+            let record = rmp_serde::to_vec(&packed).unwrap();
+            bytes.push(record);
+        }
+        let named = rmp_serde::encode::to_vec_named(&entries[3]).unwrap();
+        assert!(bytes[3].len() * 2 < named.len(), "{:?}", bytes[3]);
+
+        let mut strings = Strings::default();
+        for (e, b) in entries.iter().zip(&bytes) {
+            let packed: Packed = rmp_serde::from_slice(b).unwrap();
+            assert_eq!(&strings.decode(packed), e);
+        }
+    }
+
+    #[test]
+    fn reset_restarts_numbering() {
+        let mut interner = Interner::default();
+        let first = interner.encode(&entry(1, "a"));
+        interner.clear();
+        let second = interner.encode(&entry(1, "b"));
+        let mut strings = Strings::default();
+        strings.decode(first);
+        assert_eq!(strings.decode(second), entry(1, "b"));
+    }
+}
@@ -1,13 +1,27 @@
 use clap::{Parser, Subcommand};
 use log::warn;
+use regex::{Regex, RegexBuilder};
 use serde::Deserialize;
 use std::fs;
+use std::io::Write;
+
+use crate::log::{LogEntry, TimeRange, is_stdout, output_root};
+use crate::procinfo::hostname;
+use crate::query::Condition;
+use fuzmon::utils::Timezone;
 
 #[derive(Parser)]
 #[command(name = "fuzmon")]
 pub struct Cli {
     #[command(subcommand)]
     pub command: Option<Commands>,
+    /// Only print errors, without the summary at the end of a run
+    #[arg(short, long, global = true, conflicts_with = "log_level")]
+    pub quiet: bool,
+    /// Level of the diagnostics on stderr: off, error, warn, info, debug or
+    /// trace. Overrides RUST_LOG
+    #[arg(long, global = true, value_name = "LEVEL")]
+    pub log_level: Option<log::LevelFilter>,
 }
 
 #[derive(Subcommand)]
@@ -18,24 +32,326 @@ pub enum Commands {
     Dump(DumpArgs),
     /// Generate HTML report
     Report(ReportArgs),
+    /// Delete old log directories
+    Prune(PruneArgs),
+    /// Convert logs to another format
+    Convert(ConvertArgs),
+    /// Merge logs from several output directories
+    Merge(MergeArgs),
+    /// Train a zstd dictionary from existing logs
+    Dict(DictArgs),
+    /// Show processes live in the terminal without writing logs
+    Top(TopArgs),
+    /// Print the log entries matching filters as JSON lines
+    Query(QueryArgs),
+    /// Print entries as they are written to the logs
+    Tail(TailArgs),
+    /// Search recorded stacks for a function or file
+    Grep(GrepArgs),
+    /// Print the stats of the report as a table
+    Stats(StatsArgs),
+    /// Manage config files
+    Config(ConfigArgs),
+    /// Write a systemd unit file running fuzmon with a config
+    InstallService(InstallServiceArgs),
+}
+
+/// Parses a duration such as `90`, `45s`, `30m`, `2h` or `1h30m` into
+/// seconds. Plain numbers are seconds.
+fn parse_duration(s: &str) -> Result<u64, String> {
+    if let Ok(secs) = s.parse() {
+        return Ok(secs);
+    }
+    let mut total = 0u64;
+    let mut digits = String::new();
+    for c in s.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86400,
+            _ => return Err(format!("unknown unit {:?} in {:?}", c, s)),
+        };
+        let n: u64 = digits
+            .parse()
+            .map_err(|_| format!("missing number before {:?} in {:?}", c, s))?;
+        total += n * unit;
+        digits.clear();
+    }
+    if !digits.is_empty() {
+        return Err(format!("missing unit after {} in {:?}", digits, s));
+    }
+    Ok(total)
+}
+
+/// Log formats fuzmon writes and reads.
+pub const LOG_FORMATS: [&str; 3] = ["jsonl", "msgpacks", "msgpackc"];
+
+/// Parses a log format of `output.format`: one of [`LOG_FORMATS`] or the
+/// older `json` and `msgpack` names, with an optional `.zst` suffix.
+pub fn parse_log_format(s: &str) -> Result<String, String> {
+    let (name, suffix) = match s.strip_suffix(".zst") {
+        Some(name) => (name, ".zst"),
+        None => (s, ""),
+    };
+    let name = match name {
+        "json" => "jsonl",
+        "msgpack" => "msgpacks",
+        other => other,
+    };
+    if !LOG_FORMATS.contains(&name) {
+        return Err(format!(
+            "unknown log format {:?}, expected one of {}",
+            s,
+            LOG_FORMATS.join(", ")
+        ));
+    }
+    Ok(format!("{}{}", name, suffix))
+}
+
+/// Parses a format `convert` writes: a log format without the `.zst`
+/// suffix, which `--compress` stands for, or `parquet`.
+fn parse_convert_format(s: &str) -> Result<String, String> {
+    if s == "parquet" || LOG_FORMATS.contains(&s) {
+        return Ok(s.to_string());
+    }
+    Err(format!(
+        "unknown format {:?}, expected one of {}, parquet",
+        s,
+        LOG_FORMATS.join(", ")
+    ))
+}
+
+/// Parses an RFC 3339 time such as `2025-06-14T10:00:00Z` into unix seconds.
+fn parse_time(s: &str) -> Result<i64, String> {
+    chrono::DateTime::parse_from_rfc3339(s)
+        .map(|t| t.timestamp())
+        .map_err(|e| e.to_string())
+}
+
+/// Which entries of the logs a subcommand reads.
+#[derive(clap::Args, Default, Clone)]
+pub struct EntryFilter {
+    /// Only entries of these pids (repeatable or comma separated)
+    #[arg(long, value_delimiter = ',')]
+    pub pid: Vec<u32>,
+    /// Only entries whose process name matches this regex
+    #[arg(long)]
+    pub name: Option<Regex>,
+    /// Only entries at or after this time (RFC 3339)
+    #[arg(long, visible_alias = "since", value_parser = parse_time)]
+    pub from: Option<i64>,
+    /// Only entries at or before this time (RFC 3339)
+    #[arg(long, visible_alias = "until", value_parser = parse_time)]
+    pub to: Option<i64>,
+}
+
+impl EntryFilter {
+    pub fn time_range(&self) -> TimeRange {
+        TimeRange {
+            from: self.from,
+            to: self.to,
+        }
+    }
+
+    pub fn matches_pid(&self, pid: u32) -> bool {
+        self.pid.is_empty() || self.pid.contains(&pid)
+    }
+
+    pub fn matches_name(&self, name: &str) -> bool {
+        self.name.as_ref().is_none_or(|re| re.is_match(name))
+    }
+
+    /// Whether `e` is of one of the pids and names. The time range is left
+    /// to the readers, which skip the parts of logs out of it.
+    pub fn matches(&self, e: &LogEntry) -> bool {
+        self.matches_pid(e.pid) && self.matches_name(&e.process_name)
+    }
 }
 
 #[derive(Parser, Clone)]
 pub struct DumpArgs {
+    /// Path to log file or directory, searched recursively
+    pub path: String,
+    /// Only descend this many directories below the path; 0 only reads the
+    /// logs directly in it
+    #[arg(long, value_name = "N")]
+    pub max_depth: Option<usize>,
+    /// Only read the date directories with these names, e.g. 20250615
+    /// (repeatable or comma separated)
+    #[arg(long, value_delimiter = ',', value_name = "YYYYMMDD")]
+    pub date: Vec<String>,
+    #[command(flatten)]
+    pub filter: EntryFilter,
+    /// Print entries as JSON lines, without the file names
+    #[arg(long)]
+    pub json: bool,
+    /// Print entries as indented JSON, without the file names
+    #[arg(long, conflicts_with = "json")]
+    pub pretty: bool,
+}
+
+#[derive(Parser, Clone)]
+pub struct QueryArgs {
     /// Path to log file or directory
     pub path: String,
+    #[command(flatten)]
+    pub filter: EntryFilter,
+    /// Only entries where a field compares to a value, e.g. 'rss_kb>1000000'
+    /// (repeatable, all must hold)
+    #[arg(long = "where")]
+    pub conditions: Vec<Condition>,
 }
 
 #[derive(Parser, Clone)]
+pub struct StatsArgs {
+    /// Path to log file or directory
+    pub path: String,
+    #[command(flatten)]
+    pub filter: EntryFilter,
+    /// Print the summary of `report` as JSON instead
+    #[arg(long)]
+    pub json: bool,
+    /// Path to configuration file
+    #[arg(short = 'c', long)]
+    pub config: Option<String>,
+}
+
+#[derive(Parser, Clone)]
+pub struct GrepArgs {
+    /// Path to log file or directory
+    pub path: String,
+    /// Regex matched against the function and file of every frame
+    pub pattern: Regex,
+    #[command(flatten)]
+    pub filter: EntryFilter,
+}
+
+#[derive(Parser, Clone)]
+pub struct TailArgs {
+    /// Path to log file or directory
+    pub path: String,
+    #[command(flatten)]
+    pub filter: EntryFilter,
+}
+
+#[derive(Parser, Default, Clone)]
 pub struct ReportArgs {
     /// Path to log file or directory
     pub path: String,
+    #[command(flatten)]
+    pub filter: EntryFilter,
+    /// Number of processes with the highest CPU usage to report
+    #[arg(long)]
+    pub top_cpu: Option<usize>,
+    /// Number of processes with the highest RSS to report
+    #[arg(long)]
+    pub top_rss: Option<usize>,
+    /// Color theme of the pages and graphs
+    #[arg(long, value_parser = ["light", "dark"])]
+    pub theme: Option<String>,
+    /// Time zone of the times shown: local, utc or a name like Asia/Tokyo
+    #[arg(long)]
+    pub timezone: Option<String>,
     /// Path to configuration file
     #[arg(short = 'c', long)]
     pub config: Option<String>,
     /// Output directory for HTML report
     #[arg(short = 'o', long)]
     pub output: Option<String>,
+    /// Also write a speedscope profile of every reported process
+    #[arg(long)]
+    pub speedscope: bool,
+    /// Also write trace.json covering all reported processes of a directory
+    #[arg(long)]
+    pub combined_trace: bool,
+    /// Render daily trends per process name across the date directories
+    #[arg(long, conflicts_with = "baseline")]
+    pub trend: bool,
+    /// Also print the index and process pages to PDF with a headless browser
+    #[arg(long)]
+    pub pdf: bool,
+    /// Regenerate the report whenever the logs change
+    #[arg(long, conflicts_with_all = ["baseline", "trend"])]
+    pub watch: bool,
+    /// Compare with the logs of an earlier run instead, per process name
+    #[arg(long)]
+    pub baseline: Option<String>,
+    /// List processes aggregated by process name or normalized command line
+    #[arg(long, value_parser = ["name", "cmdline"])]
+    pub group_by: Option<String>,
+    /// Also write summary.md next to summary.json
+    #[arg(long)]
+    pub summary_md: bool,
+}
+
+#[derive(Parser, Clone)]
+pub struct ConvertArgs {
+    /// Log file or directory to read
+    pub input: String,
+    /// Output file, or directory when the input is a directory
+    pub output: String,
+    /// Output format, or parquet for a table of the sampled metrics
+    #[arg(long, default_value = "jsonl", value_parser = parse_convert_format)]
+    pub format: String,
+    /// Compress the output with zstd
+    #[arg(long)]
+    pub compress: bool,
+}
+
+#[derive(Parser, Clone)]
+pub struct MergeArgs {
+    /// Output directories to merge
+    #[arg(required = true)]
+    pub dirs: Vec<String>,
+    /// Directory for the merged logs
+    #[arg(short = 'o', long)]
+    pub output: String,
+    /// Output format
+    #[arg(long, default_value = "jsonl", value_parser = LOG_FORMATS)]
+    pub format: String,
+    /// Compress the output with zstd
+    #[arg(long)]
+    pub compress: bool,
+}
+
+#[derive(Parser, Clone)]
+pub struct DictArgs {
+    /// Log files or directories to take samples from
+    #[arg(required = true)]
+    pub inputs: Vec<String>,
+    /// Path of the dictionary to write
+    #[arg(short = 'o', long)]
+    pub output: String,
+    /// Format of the logs the dictionary will be used for
+    #[arg(long, default_value = "jsonl", value_parser = LOG_FORMATS)]
+    pub format: String,
+    /// Maximum dictionary size in bytes
+    #[arg(long, default_value_t = 112640)]
+    pub max_size: usize,
+}
+
+#[derive(Parser, Clone)]
+pub struct PruneArgs {
+    /// Path to configuration file
+    #[arg(short = 'c', long)]
+    pub config: Option<String>,
+    /// Log directory to prune, defaults to the configured output path
+    #[arg(short = 'o', long)]
+    pub output: Option<String>,
+    /// Remove date directories older than this many days
+    #[arg(long)]
+    pub keep_days: Option<u64>,
+    /// Remove the oldest date directories until the total is below this size
+    #[arg(long)]
+    pub max_total_gb: Option<f64>,
+    /// Only print what would be removed
+    #[arg(long)]
+    pub dry_run: bool,
 }
 
 #[derive(Parser, Default, Clone)]
@@ -49,17 +365,111 @@ pub struct RunArgs {
     /// User name filter
     #[arg(long)]
     pub target_user: Option<String>,
+    /// Monitor the processes whose name or command line matches this regex
+    #[arg(long)]
+    pub name: Option<Regex>,
+    /// Also monitor fuzmon itself and the processes it started
+    #[arg(long)]
+    pub include_self: bool,
     /// Output directory for logs
     #[arg(short = 'o', long)]
     pub output: Option<String>,
+    /// Seconds between samples, overriding monitor.interval_sec
+    #[arg(long)]
+    pub interval: Option<u64>,
+    /// Log format, overriding output.format
+    #[arg(long, value_parser = parse_log_format)]
+    pub format: Option<String>,
+    /// Compress logs with zstd, or not with --compress=false
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    pub compress: Option<bool>,
+    /// Minimum CPU percent of recorded processes
+    #[arg(long)]
+    pub record_threshold: Option<f64>,
+    /// Minimum CPU percent of processes whose stacks are captured
+    #[arg(long)]
+    pub stacktrace_threshold: Option<f64>,
+    /// Stop after this long, such as 90s, 30m or 2h
+    #[arg(long, value_parser = parse_duration)]
+    pub duration: Option<u64>,
     /// Verbose output
     #[arg(short, long)]
     pub verbose: bool,
+    /// Print which processes the filters select, and why others are
+    /// skipped, then exit
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Let alert rules signal processes and freeze cgroups
+    #[arg(long)]
+    pub allow_actions: bool,
+    /// Run the command as this user, with their uid, gid and home, while
+    /// fuzmon keeps running as root to trace it
+    #[arg(long, requires = "command")]
+    pub run_as: Option<String>,
+    /// Run the command on a pseudo-terminal of its own, for interactive
+    /// tools
+    #[arg(long, requires = "command")]
+    pub pty: bool,
+    /// Fork to the background, logging to --log-file
+    #[arg(long)]
+    pub daemon: bool,
+    /// File the pid of the daemon is written to
+    #[arg(long, requires = "daemon")]
+    pub pidfile: Option<String>,
+    /// File the daemon logs to, next to the output directory by default
+    #[arg(long, requires = "daemon")]
+    pub log_file: Option<String>,
     /// Command to run and monitor
     #[arg(trailing_var_arg = true)]
     pub command: Vec<String>,
 }
 
+#[derive(Parser, Clone)]
+pub struct ConfigArgs {
+    #[command(subcommand)]
+    pub command: ConfigCommands,
+}
+
+#[derive(Subcommand, Clone)]
+pub enum ConfigCommands {
+    /// Write a commented config with every setting at its default
+    Init(ConfigInitArgs),
+}
+
+#[derive(Parser, Clone)]
+pub struct ConfigInitArgs {
+    /// File to write, never overwritten
+    #[arg(default_value = "fuzmon.toml")]
+    pub path: String,
+}
+
+#[derive(Parser, Clone)]
+pub struct InstallServiceArgs {
+    /// Config file the service runs with
+    #[arg(short = 'c', long)]
+    pub config: Option<String>,
+    /// Unit file to write
+    #[arg(
+        short = 'o',
+        long,
+        default_value = "/etc/systemd/system/fuzmon.service"
+    )]
+    pub output: String,
+}
+
+#[derive(Parser, Default, Clone)]
+pub struct TopArgs {
+    /// PID to show
+    #[arg(short, long)]
+    pub pid: Option<i32>,
+    /// Path to configuration file
+    #[arg(short = 'c', long)]
+    pub config: Option<String>,
+    /// User name filter
+    #[arg(long)]
+    pub target_user: Option<String>,
+}
+
 #[derive(Default, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct FilterConfig {
@@ -67,6 +477,20 @@ pub struct FilterConfig {
     pub target_user: Option<String>,
     #[serde(default)]
     pub ignore_process_name: Option<Vec<String>>,
+    /// Regexes matched against the full command line: processes matching
+    /// any `ignore_cmdline` are skipped, and when `include_cmdline` is set
+    /// only processes matching one of it are monitored.
+    #[serde(default)]
+    pub ignore_cmdline: Option<Vec<String>>,
+    #[serde(default)]
+    pub include_cmdline: Option<Vec<String>>,
+    /// Regex the name or command line of monitored processes must match.
+    #[serde(default)]
+    pub target_process_name: Option<String>,
+    /// Also monitor fuzmon and the processes it started, instead of only
+    /// recording its own CPU and memory.
+    #[serde(default)]
+    pub include_self: Option<bool>,
 }
 
 #[derive(Default, Deserialize)]
@@ -78,6 +502,57 @@ pub struct OutputConfig {
     pub path: Option<String>,
     #[serde(default)]
     pub compress: Option<bool>,
+    #[serde(default)]
+    pub layout: Option<String>,
+    #[serde(default)]
+    pub rollover: Option<String>,
+    #[serde(default)]
+    pub delta: Option<bool>,
+    #[serde(default)]
+    pub delta_memory_tolerance_kb: Option<u64>,
+    #[serde(default)]
+    pub zstd_level: Option<i32>,
+    #[serde(default)]
+    pub zstd_dictionary: Option<String>,
+    #[serde(default)]
+    pub timezone: Option<String>,
+    #[serde(default)]
+    pub flush_interval_sec: Option<u64>,
+    #[serde(default)]
+    pub fsync: Option<bool>,
+    #[serde(default)]
+    pub otlp: Option<String>,
+    #[serde(default)]
+    pub prometheus_listen: Option<String>,
+    #[serde(default)]
+    pub statsd: Option<String>,
+    #[serde(default)]
+    pub statsd_template: Option<String>,
+    #[serde(default)]
+    pub remote_url: Option<String>,
+    #[serde(default)]
+    pub remote_spool: Option<String>,
+    #[serde(default)]
+    pub syslog: Option<String>,
+    #[serde(default)]
+    pub s3_bucket: Option<String>,
+    #[serde(default)]
+    pub s3_region: Option<String>,
+    #[serde(default)]
+    pub s3_endpoint: Option<String>,
+    #[serde(default)]
+    pub s3_prefix: Option<String>,
+    /// Size the date directories under the output path may take up.
+    #[serde(default)]
+    pub max_total_size_gb: Option<f64>,
+    /// What happens beyond `max_total_size_gb`: `prune` removes the oldest
+    /// date directories, `stop` stops monitoring.
+    #[serde(default)]
+    pub max_total_size_action: Option<String>,
+    /// Patterns of environment variable names whose values are masked in
+    /// the logs and reports, matched case insensitively.
+    #[serde(default)]
+    pub redact_env: Option<Vec<String>>,
 }
 
 #[derive(Default, Deserialize)]
@@ -86,9 +561,55 @@ pub struct MonitorConfig {
     #[serde(default)]
     pub interval_sec: Option<u64>,
     #[serde(default)]
+    pub stacktrace_interval_sec: Option<u64>,
+    #[serde(default)]
     pub record_cpu_time_percent_threshold: Option<f64>,
     #[serde(default)]
     pub stacktrace_cpu_time_percent_threshold: Option<f64>,
+    /// Seconds after which monitoring stops as on SIGINT.
+    #[serde(default)]
+    pub max_duration_sec: Option<u64>,
+    /// Also capture stacks of processes with at least this much RSS.
+    #[serde(default)]
+    pub stacktrace_rss_mb_threshold: Option<u64>,
+    /// Also capture stacks of processes whose RSS grew by this much since
+    /// their last captured stack, or since they were first seen.
+    #[serde(default)]
+    pub stacktrace_rss_growth_mb: Option<u64>,
+    /// Consecutive samples CPU must stay above the stack trace threshold
+    /// before stacks are captured.
+    #[serde(default)]
+    pub stacktrace_trigger_samples: Option<u32>,
+    /// Samples stacks are still captured for once CPU drops below it.
+    #[serde(default)]
+    pub stacktrace_hold_samples: Option<u32>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StacktraceConfig {
+    #[serde(default)]
+    pub debuginfod: Option<bool>,
+    #[serde(default)]
+    pub max_pause_ms: Option<u64>,
+    #[serde(default)]
+    pub max_frames: Option<usize>,
+    #[serde(default)]
+    pub max_thread_frames: Option<usize>,
+    #[serde(default)]
+    pub shorten_cpp_names: Option<bool>,
+    #[serde(default)]
+    pub capture_registers: Option<bool>,
+    #[serde(default)]
+    pub thread_cpu: Option<bool>,
+    #[serde(default)]
+    pub top_threads_per_process: Option<usize>,
+    #[serde(default)]
+    pub backend: Option<String>,
+    #[serde(default)]
+    pub capture_workers: Option<usize>,
+    #[serde(default)]
+    pub max_captures_per_sec: Option<f64>,
 }
 
 #[derive(Default, Deserialize, Clone)]
@@ -98,6 +619,81 @@ pub struct ReportConfig {
     pub top_cpu: Option<usize>,
     #[serde(default)]
     pub top_rss: Option<usize>,
+    /// RSS growth in KB per minute above which steadily growing processes
+    /// are listed as possible leaks.
+    #[serde(default)]
+    pub leak_slope_kb_per_min: Option<f64>,
+    /// CPU usage a process must stay at or above for `spike_min_sec` to
+    /// have the period highlighted as a spike.
+    #[serde(default)]
+    pub spike_cpu_percent: Option<f64>,
+    #[serde(default)]
+    pub spike_min_sec: Option<u64>,
+    /// Color theme of the pages and graphs, `light` or `dark`.
+    #[serde(default)]
+    pub theme: Option<String>,
+    /// Size of the graphs in pixels at 96 DPI.
+    #[serde(default)]
+    pub graph_width: Option<u32>,
+    #[serde(default)]
+    pub graph_height: Option<u32>,
+    /// Resolution of the graphs, scaling up their size, fonts and lines.
+    #[serde(default)]
+    pub graph_dpi: Option<u32>,
+    /// Number of labels on the time and value axes.
+    #[serde(default)]
+    pub graph_x_labels: Option<usize>,
+    #[serde(default)]
+    pub graph_y_labels: Option<usize>,
+    /// Time zone of the graph axes and times on the pages, `local`, `utc`
+    /// or a zoneinfo name. Follows `output.timezone` when unset.
+    #[serde(default)]
+    pub timezone: Option<String>,
+    /// Chromium compatible browser that prints the pages with `--pdf`.
+    #[serde(default)]
+    pub pdf_browser: Option<String>,
+}
+
+/// A condition on a process and what to do when it starts to hold. Every
+/// condition set must hold: `cpu_percent` and `rss_mb` are exceeded,
+/// `d_state_sec` spent in D state, and `fd_count` open files.
+#[derive(Default, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct AlertRule {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub cpu_percent: Option<f64>,
+    #[serde(default)]
+    pub rss_mb: Option<u64>,
+    #[serde(default)]
+    pub d_state_sec: Option<u64>,
+    #[serde(default)]
+    pub fd_count: Option<usize>,
+    /// Shell command run with `FUZMON_ALERT`, `FUZMON_PID` and the values
+    /// checked in its environment.
+    #[serde(default)]
+    pub command: Option<String>,
+    /// URL receiving a JSON POST about the process.
+    #[serde(default)]
+    pub webhook: Option<String>,
+    /// Whether to list the rule in `alerts` of the log entry.
+    #[serde(default)]
+    pub marker: Option<bool>,
+    /// Signal sent to the process, such as `TERM` or `KILL`. Enforcement
+    /// actions only run with `--allow-actions`.
+    #[serde(default)]
+    pub signal: Option<String>,
+    /// Freeze the cgroup of the process.
+    #[serde(default)]
+    pub freeze: Option<bool>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlertConfig {
+    #[serde(default)]
+    pub rule: Option<Vec<AlertRule>>,
 }
 
 #[derive(Default, Deserialize)]
@@ -111,6 +707,10 @@ pub struct Config {
     pub monitor: MonitorConfig,
     #[serde(default)]
     pub report: ReportConfig,
+    #[serde(default)]
+    pub stacktrace: StacktraceConfig,
+    #[serde(default)]
+    pub alert: AlertConfig,
 }
 
 pub fn load_config(path: &str) -> Config {
@@ -124,49 +724,292 @@ pub fn load_config(path: &str) -> Config {
     })
 }
 
-pub fn uid_from_name(name: &str) -> Option<u32> {
+/// Config written by `fuzmon config init`, every setting commented out
+/// with its default.
+const DEFAULT_CONFIG: &str = include_str!("default_config.toml");
+
+pub fn config(args: &ConfigArgs) {
+    match &args.command {
+        ConfigCommands::Init(args) => init_config(&args.path),
+    }
+}
+
+fn init_config(path: &str) {
+    let written = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .and_then(|mut f| f.write_all(DEFAULT_CONFIG.as_bytes()));
+    match written {
+        Ok(()) => println!("Wrote {}", path),
+        Err(e) => eprintln!("failed to write {}: {}", path, e),
+    }
+}
+
+/// An account from /etc/passwd.
+pub struct User {
+    pub name: String,
+    pub uid: u32,
+    pub gid: u32,
+    pub home: String,
+}
+
+pub fn user_from_name(name: &str) -> Option<User> {
     let passwd = fs::read_to_string("/etc/passwd").ok()?;
-    for line in passwd.lines() {
-        let mut parts = line.split(':');
-        if let (Some(user), Some(_), Some(uid_str)) = (parts.next(), parts.next(), parts.next()) {
-            if user == name {
-                if let Ok(uid) = uid_str.parse::<u32>() {
-                    return Some(uid);
-                }
-            }
+    passwd.lines().find_map(|line| {
+        let fields: Vec<&str> = line.split(':').collect();
+        if fields.first() != Some(&name) {
+            return None;
         }
-    }
-    None
+        let uid = fields.get(2)?.parse().ok()?;
+        Some(User {
+            name: name.to_string(),
+            uid,
+            gid: fields.get(3).and_then(|g| g.parse().ok()).unwrap_or(uid),
+            home: fields.get(5).unwrap_or(&"/").to_string(),
+        })
+    })
+}
+
+pub fn uid_from_name(name: &str) -> Option<u32> {
+    user_from_name(name).map(|u| u.uid)
+}
+
+/// Replaces `{hostname}` in `output.path`. `{date}` is left for the writer
+/// since it changes while running.
+pub fn expand_output_path(path: &str) -> String {
+    let host = hostname().unwrap_or_else(|| "unknown".into());
+    path.replace("{hostname}", &host)
+}
+
+/// Applies `output.timezone` to the whole process by setting `TZ`, falling
+/// back to UTC when it is unknown, and returns how dates are taken in it.
+/// Must be called before any other thread is started.
+pub fn apply_output_timezone(output: &OutputConfig) -> Timezone {
+    let name = output.timezone.as_deref().unwrap_or("utc");
+    Timezone::apply(name).unwrap_or_else(|| {
+        warn!("unknown timezone {}, using utc", name);
+        Timezone::Utc
+    })
+}
+
+fn default_redact_env() -> Vec<String> {
+    ["SECRET", "TOKEN", "PASSWORD", "KEY"]
+        .map(String::from)
+        .to_vec()
+}
+
+/// Compiles `output.redact_env`, or the default patterns when it is unset.
+pub fn redact_env_patterns(output: &OutputConfig) -> Vec<Regex> {
+    output
+        .redact_env
+        .clone()
+        .unwrap_or_else(default_redact_env)
+        .iter()
+        .filter_map(|p| {
+            RegexBuilder::new(p)
+                .case_insensitive(true)
+                .build()
+                .map_err(|e| warn!("invalid redact_env pattern {}: {}", p, e))
+                .ok()
+        })
+        .collect()
 }
 
+/// Where logs go without `-o` or `output.path`.
+const DEFAULT_OUTPUT_PATH: &str = "/tmp/fuzmon";
+
+/// Samples CPU must stay above the stack trace threshold for stacks, and
+/// samples they stay due for after it drops, unless configured.
+pub const DEFAULT_STACKTRACE_TRIGGER_SAMPLES: u32 = 1;
+pub const DEFAULT_STACKTRACE_HOLD_SAMPLES: u32 = 0;
+
+/// Native frames unwound per thread, and threads of one process unwound at
+/// once, unless configured.
+pub const DEFAULT_MAX_FRAMES: usize = 32;
+pub const DEFAULT_CAPTURE_WORKERS: usize = 4;
+
 pub fn merge_config(mut cfg: Config, args: &RunArgs) -> Config {
     if let Some(ref u) = args.target_user {
         cfg.filter.target_user = Some(u.clone());
     }
+    if let Some(ref re) = args.name {
+        cfg.filter.target_process_name = Some(re.as_str().to_string());
+    }
+    if args.include_self {
+        cfg.filter.include_self = Some(true);
+    }
     if let Some(ref p) = args.output {
         cfg.output.path = Some(p.clone());
     }
+    if args.interval.is_some() {
+        cfg.monitor.interval_sec = args.interval;
+    }
+    if let Some(ref f) = args.format {
+        cfg.output.format = Some(f.clone());
+    }
+    if args.compress.is_some() {
+        cfg.output.compress = args.compress;
+    }
+    if args.record_threshold.is_some() {
+        cfg.monitor.record_cpu_time_percent_threshold = args.record_threshold;
+    }
+    if args.stacktrace_threshold.is_some() {
+        cfg.monitor.stacktrace_cpu_time_percent_threshold = args.stacktrace_threshold;
+    }
+    if args.duration.is_some() {
+        cfg.monitor.max_duration_sec = args.duration;
+    }
     if cfg.output.path.is_none() {
-        cfg.output.path = Some("/tmp/fuzmon".into());
+        cfg.output.path = Some(DEFAULT_OUTPUT_PATH.into());
     }
+    cfg.output.path = cfg.output.path.as_deref().map(expand_output_path);
     if cfg.output.compress.is_none() {
         cfg.output.compress = Some(true);
     }
+    if cfg.output.flush_interval_sec.is_none() {
+        cfg.output.flush_interval_sec = Some(0);
+    }
+    if cfg.output.fsync.is_none() {
+        cfg.output.fsync = Some(false);
+    }
+    if cfg.output.timezone.is_none() {
+        cfg.output.timezone = Some("utc".into());
+    }
+    if cfg.output.layout.is_none() {
+        cfg.output.layout = Some("per_pid".into());
+    }
+    if cfg.output.rollover.is_none() {
+        cfg.output.rollover = Some("day".into());
+    }
+    if cfg.output.delta.is_none() {
+        cfg.output.delta = Some(false);
+    }
+    if cfg.output.delta_memory_tolerance_kb.is_none() {
+        cfg.output.delta_memory_tolerance_kb = Some(0);
+    }
+    if cfg.output.zstd_level.is_none() {
+        cfg.output.zstd_level = Some(3);
+    }
+    if cfg.output.remote_spool.is_none() {
+        // Kept next to the logs rather than inside so reports skip it, or
+        // where they go by default when they are streamed to stdout.
+        let path = cfg.output.path.as_deref().filter(|p| !is_stdout(p));
+        let root = output_root(path.unwrap_or(DEFAULT_OUTPUT_PATH)).trim_end_matches('/');
+        cfg.output.remote_spool = Some(format!("{}.spool", root));
+    }
+    cfg.output.remote_spool = cfg
+        .output
+        .remote_spool
+        .map(|p| match std::path::absolute(&p) {
+            Ok(abs) => abs.to_string_lossy().into_owned(),
+            Err(_) => p,
+        });
+    if cfg.output.s3_region.is_none() {
+        cfg.output.s3_region = Some("us-east-1".into());
+    }
+    if cfg.output.max_total_size_action.is_none() {
+        cfg.output.max_total_size_action = Some("prune".into());
+    }
+    if cfg.output.redact_env.is_none() {
+        cfg.output.redact_env = Some(default_redact_env());
+    }
     if cfg.monitor.record_cpu_time_percent_threshold.is_none() {
         cfg.monitor.record_cpu_time_percent_threshold = Some(0.0);
     }
     if cfg.monitor.stacktrace_cpu_time_percent_threshold.is_none() {
         cfg.monitor.stacktrace_cpu_time_percent_threshold = Some(1.0);
     }
+    if cfg.filter.include_self.is_none() {
+        cfg.filter.include_self = Some(false);
+    }
+    if cfg.monitor.stacktrace_trigger_samples.is_none() {
+        cfg.monitor.stacktrace_trigger_samples = Some(DEFAULT_STACKTRACE_TRIGGER_SAMPLES);
+    }
+    if cfg.monitor.stacktrace_hold_samples.is_none() {
+        cfg.monitor.stacktrace_hold_samples = Some(DEFAULT_STACKTRACE_HOLD_SAMPLES);
+    }
+    if cfg.stacktrace.debuginfod.is_none() {
+        cfg.stacktrace.debuginfod = Some(false);
+    }
+    if cfg.stacktrace.max_pause_ms.is_none() {
+        cfg.stacktrace.max_pause_ms = Some(50);
+    }
+    if cfg.stacktrace.max_frames.is_none() {
+        cfg.stacktrace.max_frames = Some(DEFAULT_MAX_FRAMES);
+    }
+    if cfg.stacktrace.shorten_cpp_names.is_none() {
+        cfg.stacktrace.shorten_cpp_names = Some(false);
+    }
+    if cfg.stacktrace.capture_registers.is_none() {
+        cfg.stacktrace.capture_registers = Some(false);
+    }
+    if cfg.stacktrace.thread_cpu.is_none() {
+        cfg.stacktrace.thread_cpu = Some(false);
+    }
+    if cfg.stacktrace.backend.is_none() {
+        cfg.stacktrace.backend = Some("frame_pointer".into());
+    }
+    if cfg.stacktrace.capture_workers.is_none() {
+        cfg.stacktrace.capture_workers = Some(DEFAULT_CAPTURE_WORKERS);
+    }
     cfg
 }
 
-pub fn finalize_report_config(mut cfg: ReportConfig) -> ReportConfig {
+/// Defaults of `[report]`, filled in by `finalize_report_config`.
+pub const DEFAULT_REPORT_TOP: usize = 10;
+pub const DEFAULT_LEAK_SLOPE_KB_PER_MIN: f64 = 1024.0;
+pub const DEFAULT_SPIKE_CPU_PERCENT: f64 = 90.0;
+pub const DEFAULT_SPIKE_MIN_SEC: u64 = 30;
+
+pub fn finalize_report_config(mut cfg: ReportConfig, args: &ReportArgs) -> ReportConfig {
+    if args.top_cpu.is_some() {
+        cfg.top_cpu = args.top_cpu;
+    }
+    if args.top_rss.is_some() {
+        cfg.top_rss = args.top_rss;
+    }
+    if args.theme.is_some() {
+        cfg.theme = args.theme.clone();
+    }
+    if args.timezone.is_some() {
+        cfg.timezone = args.timezone.clone();
+    }
     if cfg.top_cpu.is_none() {
-        cfg.top_cpu = Some(10);
+        cfg.top_cpu = Some(DEFAULT_REPORT_TOP);
     }
     if cfg.top_rss.is_none() {
-        cfg.top_rss = Some(10);
+        cfg.top_rss = Some(DEFAULT_REPORT_TOP);
+    }
+    if cfg.leak_slope_kb_per_min.is_none() {
+        cfg.leak_slope_kb_per_min = Some(DEFAULT_LEAK_SLOPE_KB_PER_MIN);
+    }
+    if cfg.spike_cpu_percent.is_none() {
+        cfg.spike_cpu_percent = Some(DEFAULT_SPIKE_CPU_PERCENT);
+    }
+    if cfg.spike_min_sec.is_none() {
+        cfg.spike_min_sec = Some(DEFAULT_SPIKE_MIN_SEC);
+    }
+    if cfg.theme.is_none() {
+        cfg.theme = Some("light".into());
+    }
+    if cfg.pdf_browser.is_none() {
+        cfg.pdf_browser = Some("chromium".into());
+    }
+    if cfg.graph_width.is_none() {
+        cfg.graph_width = Some(600);
+    }
+    if cfg.graph_height.is_none() {
+        cfg.graph_height = Some(300);
+    }
+    if cfg.graph_dpi.is_none() {
+        cfg.graph_dpi = Some(96);
+    }
+    if cfg.graph_x_labels.is_none() {
+        cfg.graph_x_labels = Some(5);
+    }
+    if cfg.graph_y_labels.is_none() {
+        cfg.graph_y_labels = Some(5);
     }
     cfg
 }
@@ -179,6 +1022,7 @@ pub fn parse_cli() -> Cli {
 mod tests {
     use super::*;
     use std::fs;
+    use std::path::Path;
     use tempfile::NamedTempFile;
 
     #[test]
@@ -205,14 +1049,105 @@ mod tests {
         let cfg = load_config(tmp.path().to_str().unwrap());
         let args = RunArgs {
             target_user: Some("foo".into()),
+            name: Some(Regex::new("^nginx").unwrap()),
             output: Some("/tmp/b".into()),
             ..Default::default()
         };
         let merged = merge_config(cfg, &args);
         assert_eq!(merged.filter.target_user.as_deref(), Some("foo"));
+        assert_eq!(merged.filter.target_process_name.as_deref(), Some("^nginx"));
         assert_eq!(merged.output.path.as_deref(), Some("/tmp/b"));
     }
 
+    #[test]
+    fn monitor_flags_override_config() {
+        let cfg: Config = toml::from_str(
+            "[output]\nformat = \"msgpacks\"\ncompress = true\n\
+             [monitor]\ninterval_sec = 60\nrecord_cpu_time_percent_threshold = 5.0\n",
+        )
+        .unwrap();
+        let args = RunArgs {
+            interval: Some(1),
+            format: Some("jsonl".into()),
+            compress: Some(false),
+            stacktrace_threshold: Some(20.0),
+            ..Default::default()
+        };
+        let merged = merge_config(cfg, &args);
+        assert_eq!(merged.monitor.interval_sec, Some(1));
+        assert_eq!(merged.output.format.as_deref(), Some("jsonl"));
+        assert_eq!(merged.output.compress, Some(false));
+        assert_eq!(merged.monitor.record_cpu_time_percent_threshold, Some(5.0));
+        assert_eq!(
+            merged.monitor.stacktrace_cpu_time_percent_threshold,
+            Some(20.0)
+        );
+        let parsed = RunArgs::try_parse_from(["run", "--compress", "--record-threshold", "2"]);
+        let parsed = parsed.unwrap();
+        assert_eq!(parsed.compress, Some(true));
+        assert_eq!(parsed.record_threshold, Some(2.0));
+    }
+
+    #[test]
+    fn durations_parsed_into_seconds() {
+        assert_eq!(parse_duration("90"), Ok(90));
+        assert_eq!(parse_duration("45s"), Ok(45));
+        assert_eq!(parse_duration("30m"), Ok(1800));
+        assert_eq!(parse_duration("1h30m"), Ok(5400));
+        assert_eq!(parse_duration("2d"), Ok(172800));
+        assert!(parse_duration("2x").is_err());
+        assert!(parse_duration("h").is_err());
+        assert!(parse_duration("1h30").is_err());
+    }
+
+    #[test]
+    fn entry_filters_shared_by_subcommands() {
+        let args = Cli::try_parse_from([
+            "fuzmon",
+            "grep",
+            "logs",
+            "main",
+            "--pid",
+            "1,2",
+            "--name",
+            "^php",
+            "--since",
+            "2025-06-14T10:00:00Z",
+        ])
+        .unwrap();
+        let Some(Commands::Grep(grep)) = args.command else {
+            panic!("not grep");
+        };
+        assert_eq!(grep.filter.pid, vec![1, 2]);
+        assert_eq!(grep.filter.time_range().from, Some(1749895200));
+        assert!(grep.filter.matches_name("php-fpm"));
+        assert!(!grep.filter.matches_name("nginx"));
+        for sub in ["dump", "query", "tail", "report", "stats"] {
+            let parsed = Cli::try_parse_from([
+                "fuzmon",
+                sub,
+                "logs",
+                "--name",
+                "x",
+                "--until",
+                "2025-06-14T10:00:00Z",
+            ]);
+            assert!(parsed.is_ok(), "{}", sub);
+        }
+    }
+
+    #[test]
+    fn log_formats_parsed() {
+        assert_eq!(parse_log_format("msgpackc"), Ok("msgpackc".into()));
+        assert_eq!(parse_log_format("json.zst"), Ok("jsonl.zst".into()));
+        assert_eq!(parse_log_format("msgpack"), Ok("msgpacks".into()));
+        assert!(parse_log_format("parquet").is_err());
+        assert!(parse_log_format("csv.zst").is_err());
+        assert_eq!(parse_convert_format("parquet"), Ok("parquet".into()));
+        assert!(parse_convert_format("jsonl.zst").is_err());
+        assert!(RunArgs::try_parse_from(["fuzmon", "--format", "xml"]).is_err());
+    }
+
     #[test]
     fn default_output_path() {
         let cfg = Config::default();
@@ -220,18 +1155,138 @@ mod tests {
         let merged = merge_config(cfg, &args);
         assert_eq!(merged.output.path.as_deref(), Some("/tmp/fuzmon"));
         assert_eq!(merged.output.compress, Some(true));
+        assert_eq!(merged.output.layout.as_deref(), Some("per_pid"));
+        assert_eq!(merged.output.rollover.as_deref(), Some("day"));
+        assert_eq!(merged.output.timezone.as_deref(), Some("utc"));
+        assert_eq!(merged.output.flush_interval_sec, Some(0));
+        assert_eq!(merged.output.fsync, Some(false));
+        assert_eq!(merged.output.s3_bucket, None);
+        assert_eq!(merged.output.s3_region.as_deref(), Some("us-east-1"));
+        assert_eq!(merged.output.delta, Some(false));
+        assert_eq!(merged.output.delta_memory_tolerance_kb, Some(0));
+        assert_eq!(merged.output.zstd_level, Some(3));
+        assert_eq!(merged.output.zstd_dictionary, None);
+        assert_eq!(merged.output.max_total_size_gb, None);
+        assert_eq!(
+            merged.output.max_total_size_action.as_deref(),
+            Some("prune")
+        );
+        assert_eq!(
+            merged.output.redact_env,
+            Some(vec![
+                "SECRET".to_string(),
+                "TOKEN".to_string(),
+                "PASSWORD".to_string(),
+                "KEY".to_string()
+            ])
+        );
         assert_eq!(merged.monitor.record_cpu_time_percent_threshold, Some(0.0));
         assert_eq!(
             merged.monitor.stacktrace_cpu_time_percent_threshold,
             Some(1.0)
         );
+        assert_eq!(merged.filter.include_self, Some(false));
+        assert_eq!(merged.monitor.stacktrace_trigger_samples, Some(1));
+        assert_eq!(merged.monitor.stacktrace_hold_samples, Some(0));
+        assert_eq!(merged.stacktrace.debuginfod, Some(false));
+        assert_eq!(merged.stacktrace.max_pause_ms, Some(50));
+        assert_eq!(merged.stacktrace.max_frames, Some(32));
+        assert_eq!(merged.stacktrace.max_thread_frames, None);
+        assert_eq!(merged.stacktrace.shorten_cpp_names, Some(false));
+        assert_eq!(merged.stacktrace.capture_registers, Some(false));
+        assert_eq!(merged.stacktrace.thread_cpu, Some(false));
+        assert_eq!(merged.stacktrace.top_threads_per_process, None);
+        assert_eq!(merged.stacktrace.backend.as_deref(), Some("frame_pointer"));
+        assert_eq!(merged.stacktrace.capture_workers, Some(4));
+        assert_eq!(merged.monitor.stacktrace_interval_sec, None);
+        assert_eq!(merged.stacktrace.max_captures_per_sec, None);
+        assert_eq!(
+            merged.output.remote_spool.as_deref(),
+            Some("/tmp/fuzmon.spool")
+        );
+    }
+
+    #[test]
+    fn remote_spool_is_absolute_without_log_files() {
+        let args = RunArgs {
+            output: Some("-".into()),
+            ..Default::default()
+        };
+        let merged = merge_config(Config::default(), &args);
+        assert_eq!(
+            merged.output.remote_spool.as_deref(),
+            Some("/tmp/fuzmon.spool")
+        );
+        let args = RunArgs {
+            output: Some("logs".into()),
+            ..Default::default()
+        };
+        let merged = merge_config(Config::default(), &args);
+        let spool = merged.output.remote_spool.unwrap();
+        assert!(Path::new(&spool).is_absolute(), "{}", spool);
+        assert!(spool.ends_with("/logs.spool"), "{}", spool);
+    }
+
+    #[test]
+    fn output_path_template() {
+        let args = RunArgs {
+            output: Some("/var/log/fuzmon/{hostname}/{date}".into()),
+            ..Default::default()
+        };
+        let merged = merge_config(Config::default(), &args);
+        let host = hostname().unwrap_or_else(|| "unknown".into());
+        assert_eq!(
+            merged.output.path,
+            Some(format!("/var/log/fuzmon/{}/{{date}}", host))
+        );
+        assert_eq!(
+            merged.output.remote_spool,
+            Some(format!("/var/log/fuzmon/{}.spool", host))
+        );
+        assert_eq!(output_root("logs/{date}"), "logs/");
+        assert_eq!(output_root("logs/day-{date}/x"), "logs/");
+        assert_eq!(output_root("{date}"), ".");
+        assert_eq!(output_root("/tmp/fuzmon"), "/tmp/fuzmon");
     }
 
     #[test]
     fn report_config_defaults() {
-        let cfg = finalize_report_config(ReportConfig::default());
-        assert_eq!(cfg.top_cpu, Some(10));
-        assert_eq!(cfg.top_rss, Some(10));
+        let cfg = finalize_report_config(ReportConfig::default(), &ReportArgs::default());
+        assert_eq!(cfg.top_cpu, Some(DEFAULT_REPORT_TOP));
+        assert_eq!(cfg.top_rss, Some(DEFAULT_REPORT_TOP));
+        assert_eq!(
+            cfg.leak_slope_kb_per_min,
+            Some(DEFAULT_LEAK_SLOPE_KB_PER_MIN)
+        );
+        assert_eq!(cfg.spike_cpu_percent, Some(90.0));
+        assert_eq!(cfg.spike_min_sec, Some(30));
+        assert_eq!(cfg.theme.as_deref(), Some("light"));
+        assert_eq!(cfg.graph_width, Some(600));
+        assert_eq!(cfg.graph_height, Some(300));
+        assert_eq!(cfg.graph_dpi, Some(96));
+        assert_eq!(cfg.graph_x_labels, Some(5));
+        assert_eq!(cfg.graph_y_labels, Some(5));
+        assert_eq!(cfg.timezone, None);
+        assert_eq!(cfg.pdf_browser.as_deref(), Some("chromium"));
+    }
+
+    #[test]
+    fn report_cli_overrides_config() {
+        let cfg = ReportConfig {
+            top_cpu: Some(3),
+            top_rss: Some(4),
+            timezone: Some("utc".into()),
+            ..Default::default()
+        };
+        let args = ReportArgs {
+            top_cpu: Some(20),
+            timezone: Some("Asia/Tokyo".into()),
+            ..Default::default()
+        };
+        let cfg = finalize_report_config(cfg, &args);
+        assert_eq!(cfg.top_cpu, Some(20));
+        assert_eq!(cfg.top_rss, Some(4));
+        assert_eq!(cfg.timezone.as_deref(), Some("Asia/Tokyo"));
     }
 
     #[test]
@@ -269,4 +1324,97 @@ mod tests {
         assert!(msg.contains("foo"));
         assert!(msg.contains("unknown field"));
     }
+
+    #[test]
+    fn default_config_lists_the_defaults() {
+        // Uncommenting every setting must give a valid config with the
+        // values used when they are unset.
+        let text: String = DEFAULT_CONFIG
+            .lines()
+            .map(|l| match l.strip_prefix("# ") {
+                Some(kv) if kv.contains(" = ") => kv,
+                _ => l,
+            })
+            .map(|l| format!("{}\n", l))
+            .collect();
+        let cfg: Config = toml::from_str(&text).unwrap();
+        let defaults = merge_config(Config::default(), &RunArgs::default());
+        assert_eq!(cfg.output.format.as_deref(), Some("jsonl.zst"));
+        assert_eq!(cfg.output.path, defaults.output.path);
+        assert_eq!(cfg.output.compress, defaults.output.compress);
+        assert_eq!(cfg.output.layout, defaults.output.layout);
+        assert_eq!(cfg.output.rollover, defaults.output.rollover);
+        assert_eq!(cfg.output.timezone, defaults.output.timezone);
+        assert_eq!(cfg.output.delta, defaults.output.delta);
+        assert_eq!(
+            cfg.output.delta_memory_tolerance_kb,
+            defaults.output.delta_memory_tolerance_kb
+        );
+        assert_eq!(cfg.output.zstd_level, defaults.output.zstd_level);
+        assert_eq!(
+            cfg.output.flush_interval_sec,
+            defaults.output.flush_interval_sec
+        );
+        assert_eq!(cfg.output.fsync, defaults.output.fsync);
+        assert_eq!(cfg.output.redact_env, defaults.output.redact_env);
+        assert_eq!(
+            cfg.output.max_total_size_action,
+            defaults.output.max_total_size_action
+        );
+        assert_eq!(cfg.output.remote_spool, defaults.output.remote_spool);
+        assert_eq!(cfg.output.s3_region, defaults.output.s3_region);
+        assert_eq!(
+            cfg.monitor.record_cpu_time_percent_threshold,
+            defaults.monitor.record_cpu_time_percent_threshold
+        );
+        assert_eq!(
+            cfg.monitor.stacktrace_cpu_time_percent_threshold,
+            defaults.monitor.stacktrace_cpu_time_percent_threshold
+        );
+        assert_eq!(cfg.filter.include_self, defaults.filter.include_self);
+        assert_eq!(
+            cfg.monitor.stacktrace_trigger_samples,
+            defaults.monitor.stacktrace_trigger_samples
+        );
+        assert_eq!(
+            cfg.monitor.stacktrace_hold_samples,
+            defaults.monitor.stacktrace_hold_samples
+        );
+        assert_eq!(cfg.stacktrace.backend, defaults.stacktrace.backend);
+        assert_eq!(cfg.stacktrace.debuginfod, defaults.stacktrace.debuginfod);
+        assert_eq!(
+            cfg.stacktrace.max_pause_ms,
+            defaults.stacktrace.max_pause_ms
+        );
+        assert_eq!(cfg.stacktrace.max_frames, defaults.stacktrace.max_frames);
+        assert_eq!(
+            cfg.stacktrace.shorten_cpp_names,
+            defaults.stacktrace.shorten_cpp_names
+        );
+        assert_eq!(
+            cfg.stacktrace.capture_registers,
+            defaults.stacktrace.capture_registers
+        );
+        assert_eq!(cfg.stacktrace.thread_cpu, defaults.stacktrace.thread_cpu);
+        assert_eq!(
+            cfg.stacktrace.capture_workers,
+            defaults.stacktrace.capture_workers
+        );
+        let report = finalize_report_config(ReportConfig::default(), &ReportArgs::default());
+        assert_eq!(cfg.report.top_cpu, report.top_cpu);
+        assert_eq!(cfg.report.top_rss, report.top_rss);
+        assert_eq!(
+            cfg.report.leak_slope_kb_per_min,
+            report.leak_slope_kb_per_min
+        );
+        assert_eq!(cfg.report.spike_cpu_percent, report.spike_cpu_percent);
+        assert_eq!(cfg.report.spike_min_sec, report.spike_min_sec);
+        assert_eq!(cfg.report.theme, report.theme);
+        assert_eq!(cfg.report.graph_width, report.graph_width);
+        assert_eq!(cfg.report.graph_height, report.graph_height);
+        assert_eq!(cfg.report.graph_dpi, report.graph_dpi);
+        assert_eq!(cfg.report.graph_x_labels, report.graph_x_labels);
+        assert_eq!(cfg.report.graph_y_labels, report.graph_y_labels);
+        assert_eq!(cfg.report.pdf_browser, report.pdf_browser);
+    }
 }
@@ -0,0 +1,117 @@
+use log::warn;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::compact::Interner;
+use crate::config::ConvertArgs;
+use crate::log::{LogEntry, LogFormat, append_log, collect_files, dedup_stacks, read_log_entries};
+use crate::parquet::write_parquet;
+
+/// What `convert` writes: logs, or a Parquet table of their metrics.
+enum Target {
+    Log(LogFormat),
+    Parquet { compress: bool },
+}
+
+impl Target {
+    fn extension(&self) -> &'static str {
+        match self {
+            Target::Log(format) => format.extension(),
+            Target::Parquet { .. } => "parquet",
+        }
+    }
+
+    fn write(&self, output: &Path, entries: Vec<LogEntry>) -> bool {
+        match self {
+            Target::Log(format) => write_entries(output, entries, format),
+            Target::Parquet { compress } => match write_parquet(output, &entries, *compress) {
+                Ok(()) => true,
+                Err(e) => {
+                    warn!("failed to write {}: {}", output.display(), e);
+                    false
+                }
+            },
+        }
+    }
+}
+
+/// Replaces `.jsonl`, `.msgpacks` and an optional `.zst` suffix of `name`
+/// with the extension of the target format.
+fn converted_name(name: &str, ext: &str) -> String {
+    let stem = name.strip_suffix(".zst").unwrap_or(name);
+    let stem = stem
+        .strip_suffix(".jsonl")
+        .or_else(|| stem.strip_suffix(".msgpacks"))
+        .or_else(|| stem.strip_suffix(".msgpackc"))
+        .unwrap_or(stem);
+    format!("{}.{}", stem, ext)
+}
+
+fn convert_file(input: &Path, output: &Path, target: &Target) {
+    let entries = match read_log_entries(input) {
+        Ok(e) => e,
+        Err(e) => {
+            warn!("failed to read {}: {}", input.display(), e);
+            return;
+        }
+    };
+    if target.write(output, entries) {
+        println!("{} -> {}", input.display(), output.display());
+    }
+}
+
+/// Writes `entries` to a fresh log file at `output`, storing repeated stacks
+/// as references like `fuzmon run` does.
+pub fn write_entries(output: &Path, entries: Vec<LogEntry>, format: &LogFormat) -> bool {
+    // Entries are appended one by one, so start from an empty file.
+    if output.exists()
+        && let Err(e) = fs::remove_file(output)
+    {
+        warn!("failed to replace {}: {}", output.display(), e);
+        return false;
+    }
+    let out = output.to_string_lossy();
+    let mut written = HashSet::new();
+    let mut interners: HashMap<u32, Interner> = HashMap::new();
+    for mut entry in entries {
+        dedup_stacks(&mut entry, &mut written);
+        let interner = interners.entry(entry.pid).or_default();
+        append_log(&out, &entry, format, interner);
+    }
+    true
+}
+
+pub fn convert(args: &ConvertArgs) {
+    let target = if args.format == "parquet" {
+        Target::Parquet {
+            compress: args.compress,
+        }
+    } else {
+        Target::Log(LogFormat {
+            use_msgpack: args.format != "jsonl",
+            compact: args.format == "msgpackc",
+            compress: args.compress,
+            ..Default::default()
+        })
+    };
+    let ext = target.extension();
+    let input = Path::new(&args.input);
+    if !input.is_dir() {
+        convert_file(input, Path::new(&args.output), &target);
+        return;
+    }
+    let mut files = Vec::new();
+    collect_files(input, &mut files);
+    files.sort();
+    for f in files {
+        let Ok(rel) = f.strip_prefix(input) else {
+            continue;
+        };
+        let name = rel.file_name().unwrap_or_default().to_string_lossy();
+        let out: PathBuf = Path::new(&args.output)
+            .join(rel)
+            .with_file_name(converted_name(&name, ext));
+        convert_file(&f, &out, &target);
+    }
+}
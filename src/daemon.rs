@@ -0,0 +1,98 @@
+use log::warn;
+use nix::errno::Errno;
+use nix::fcntl::{Flock, FlockArg, OFlag};
+use nix::unistd::{ForkResult, dup2, fork, getpid, pipe2, setsid};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+
+/// The forked daemon. Dropping it removes the pid file.
+pub struct Daemon {
+    /// The pid file, locked for as long as the daemon runs.
+    pidfile: Option<(PathBuf, Flock<File>)>,
+    ready: Option<File>,
+}
+
+impl Daemon {
+    /// Lets the parent exit successfully, once signal handlers are set up
+    /// so the pid in the pid file can be sent SIGTERM right away.
+    pub fn started(&mut self) {
+        if let Some(mut ready) = self.ready.take()
+            && let Err(e) = ready.write_all(&[1])
+        {
+            warn!("failed to notify the parent process: {}", e);
+        }
+    }
+}
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        // Removed while still locked, so no other daemon writes it first.
+        if let Some((path, _)) = &self.pidfile {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Opens and locks the pid file at `path`, failing when another fuzmon
+/// holds it. The lock is shared with the forked daemon.
+fn lock_pidfile(path: &Path) -> io::Result<Flock<File>> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+    match Flock::lock(file, FlockArg::LockExclusiveNonblock) {
+        Ok(lock) => Ok(lock),
+        Err((_, Errno::EWOULDBLOCK)) => {
+            let pid = fs::read_to_string(path).unwrap_or_default();
+            Err(io::Error::other(format!(
+                "fuzmon already running as pid {}",
+                pid.trim()
+            )))
+        }
+        Err((_, e)) => Err(e.into()),
+    }
+}
+
+/// Forks to the background, detaching from the terminal with stdout and
+/// stderr appended to `log`. The parent exits once `Daemon::started` is
+/// called, or fails when the daemon exits before.
+pub fn daemonize(log: &Path, pidfile: Option<&Path>) -> io::Result<Daemon> {
+    let lock = pidfile.map(lock_pidfile).transpose()?;
+    let log_file = OpenOptions::new().create(true).append(true).open(log)?;
+    // Kept from the command, which would keep the parent waiting.
+    let (ready_read, ready_write) = pipe2(OFlag::O_CLOEXEC)?;
+    // SAFETY: no other threads are running yet, so the child may continue
+    // as normal.
+    match unsafe { fork() }? {
+        ForkResult::Parent { child } => {
+            drop(ready_write);
+            let mut ready = [0u8];
+            let started = File::from(ready_read).read(&mut ready).unwrap_or(0) == 1;
+            if started {
+                println!("fuzmon running in the background as pid {}", child);
+                std::process::exit(0);
+            }
+            eprintln!("fuzmon failed to start, see {}", log.display());
+            std::process::exit(1);
+        }
+        ForkResult::Child => drop(ready_read),
+    }
+    setsid()?;
+    let null = File::open("/dev/null")?;
+    dup2(null.as_raw_fd(), 0)?;
+    dup2(log_file.as_raw_fd(), 1)?;
+    dup2(log_file.as_raw_fd(), 2)?;
+    if let Some(lock) = &lock {
+        let mut file: &File = lock;
+        file.set_len(0)?;
+        file.write_all(format!("{}\n", getpid()).as_bytes())?;
+    }
+    Ok(Daemon {
+        pidfile: pidfile.map(Path::to_path_buf).zip(lock),
+        ready: Some(File::from(ready_write)),
+    })
+}
@@ -0,0 +1,139 @@
+use log::{info, warn};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub fn build_id_hex(build_id: &[u8]) -> String {
+    build_id.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn server_urls() -> Vec<String> {
+    env::var("DEBUGINFOD_URLS")
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(|s| s.trim_end_matches('/').to_string())
+        .collect()
+}
+
+fn cache_dir() -> PathBuf {
+    if let Ok(dir) = env::var("DEBUGINFOD_CACHE_PATH") {
+        return PathBuf::from(dir);
+    }
+    let base = env::var("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|_| env::var("HOME").map(|h| Path::new(&h).join(".cache")))
+        .unwrap_or_else(|_| env::temp_dir());
+    base.join("fuzmon").join("debuginfod")
+}
+
+fn download(url: &str, dest: &Path) -> io::Result<()> {
+    let agent = ureq::AgentBuilder::new().timeout(FETCH_TIMEOUT).build();
+    let resp = agent.get(url).call().map_err(io::Error::other)?;
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Write to a temporary name first so an interrupted download never
+    // looks like a valid cache entry.
+    let tmp = dest.with_extension("part");
+    let mut file = fs::File::create(&tmp)?;
+    io::copy(&mut resp.into_reader(), &mut file)?;
+    fs::rename(&tmp, dest)
+}
+
+/// Returns the cached debug info for `build_id`, downloading it from the
+/// first of `urls` that has it.
+pub fn fetch_debuginfo(build_id: &[u8], urls: &[String], cache: &Path) -> Option<PathBuf> {
+    let id = build_id_hex(build_id);
+    let dest = cache.join(&id).join("debuginfo");
+    if dest.is_file() {
+        return Some(dest);
+    }
+    for base in urls {
+        let url = format!("{}/buildid/{}/debuginfo", base, id);
+        match download(&url, &dest) {
+            Ok(()) => {
+                info!("fetched debug info {} from {}", id, base);
+                return Some(dest);
+            }
+            Err(e) => warn!("debuginfod {} failed: {}", url, e),
+        }
+    }
+    None
+}
+
+/// Build-ids being fetched, mapped to true, or that no server had, mapped
+/// to false so they are not asked for again.
+static FETCHES: LazyLock<Mutex<HashMap<String, bool>>> = LazyLock::new(Default::default);
+
+pub enum Lookup {
+    Found(PathBuf),
+    /// Being downloaded in the background; ask again once `is_fetching`
+    /// is false.
+    Fetching,
+    Missing,
+}
+
+/// Looks up debug info by build-id using the servers in `DEBUGINFOD_URLS`.
+/// Downloads take a thread of their own, so captures are not held up by
+/// slow servers.
+pub fn find_debuginfo(build_id: &[u8]) -> Lookup {
+    let urls = server_urls();
+    if urls.is_empty() {
+        return Lookup::Missing;
+    }
+    let cache = cache_dir();
+    let id = build_id_hex(build_id);
+    let dest = cache.join(&id).join("debuginfo");
+    if dest.is_file() {
+        return Lookup::Found(dest);
+    }
+    let mut fetches = FETCHES.lock().unwrap();
+    match fetches.get(&id) {
+        Some(true) => return Lookup::Fetching,
+        Some(false) => return Lookup::Missing,
+        None => {}
+    }
+    fetches.insert(id.clone(), true);
+    let build_id = build_id.to_vec();
+    thread::spawn(move || {
+        let found = fetch_debuginfo(&build_id, &urls, &cache).is_some();
+        let mut fetches = FETCHES.lock().unwrap();
+        if found {
+            fetches.remove(&id);
+        } else {
+            fetches.insert(id, false);
+        }
+    });
+    Lookup::Fetching
+}
+
+/// Whether the debug info of the hex build-id `id` is still downloading.
+pub fn is_fetching(id: &str) -> bool {
+    FETCHES.lock().unwrap().get(id) == Some(&true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn cached_debuginfo_is_reused() {
+        let dir = tempdir().unwrap();
+        let id = [0xde, 0xad, 0x01];
+        assert!(fetch_debuginfo(&id, &[], dir.path()).is_none());
+
+        let cached = dir.path().join("dead01").join("debuginfo");
+        fs::create_dir_all(cached.parent().unwrap()).unwrap();
+        fs::write(&cached, b"elf").unwrap();
+        let urls = vec!["http://127.0.0.1:1".to_string()];
+        assert_eq!(fetch_debuginfo(&id, &urls, dir.path()), Some(cached));
+    }
+}
@@ -0,0 +1,71 @@
+use log::warn;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::compact::Interner;
+use crate::config::DictArgs;
+use crate::log::{
+    LogEntry, LogFormat, collect_files, dedup_stacks, encode_record, read_log_entries,
+};
+
+/// Serializes `entries` the way `fuzmon run` writes them, one sample per
+/// entry, so the dictionary learns what the compressor will actually see.
+fn samples(entries: Vec<LogEntry>, format: &LogFormat) -> Vec<Vec<u8>> {
+    let mut written = HashSet::new();
+    let mut interners: HashMap<u32, Interner> = HashMap::new();
+    entries
+        .into_iter()
+        .filter_map(|mut entry| {
+            dedup_stacks(&mut entry, &mut written);
+            let interner = interners.entry(entry.pid).or_default();
+            encode_record(&entry, format, interner)
+        })
+        .collect()
+}
+
+pub fn train(args: &DictArgs) {
+    let format = LogFormat {
+        use_msgpack: args.format != "jsonl",
+        compact: args.format == "msgpackc",
+        ..Default::default()
+    };
+    let mut files: Vec<PathBuf> = Vec::new();
+    for input in &args.inputs {
+        let path = Path::new(input);
+        if path.is_dir() {
+            collect_files(path, &mut files);
+        } else {
+            files.push(path.to_path_buf());
+        }
+    }
+    files.sort();
+    let mut all = Vec::new();
+    for f in files {
+        match read_log_entries(&f) {
+            Ok(entries) => all.extend(samples(entries, &format)),
+            Err(e) => warn!("failed to read {}: {}", f.display(), e),
+        }
+    }
+    let dict = match zstd::dict::from_samples(&all, args.max_size) {
+        Ok(d) => d,
+        Err(e) => {
+            eprintln!(
+                "failed to train dictionary from {} entries: {}",
+                all.len(),
+                e
+            );
+            return;
+        }
+    };
+    if let Err(e) = fs::write(&args.output, &dict) {
+        eprintln!("failed to write {}: {}", args.output, e);
+        return;
+    }
+    println!(
+        "{} ({} bytes from {} entries)",
+        args.output,
+        dict.len(),
+        all.len()
+    );
+}
@@ -1,32 +1,69 @@
-use std::fs;
+use std::io::{self, Write};
 use std::path::Path;
 
-use crate::log::read_log_entries;
+use crate::config::DumpArgs;
+use crate::log::{LogEntry, TimeRange, log_files, read_log_checked};
 
-pub fn dump(path: &str) {
-    let p = Path::new(path);
-    if p.is_dir() {
-        if let Ok(entries) = fs::read_dir(p) {
-            for entry in entries.flatten() {
-                let file_path = entry.path();
-                if file_path.is_file() {
-                    dump_file(&file_path);
-                }
-            }
+pub fn dump(args: &DumpArgs) {
+    let range = args.filter.time_range();
+    let mut out = io::stdout().lock();
+    let root = Path::new(&args.path);
+    for f in log_files(root) {
+        if !(root.is_file() || selected(root, &f, args)) {
+            continue;
+        }
+        if !dump_file(&f, &range, args, &mut out) {
+            return;
         }
+    }
+}
+
+/// Whether `file`, found under `root`, is within `--max-depth` directories
+/// of it and under one of the `--date` directories.
+fn selected(root: &Path, file: &Path, args: &DumpArgs) -> bool {
+    let dirs: Vec<&str> = file
+        .strip_prefix(root)
+        .ok()
+        .and_then(Path::parent)
+        .map(|p| p.iter().filter_map(|c| c.to_str()).collect())
+        .unwrap_or_default();
+    args.max_depth.is_none_or(|depth| dirs.len() <= depth)
+        && (args.date.is_empty() || dirs.iter().any(|d| args.date.iter().any(|date| date == d)))
+}
+
+/// `e` as `--json` or `--pretty` ask, or in the Rust debug format.
+fn format_entry(e: &LogEntry, args: &DumpArgs) -> serde_json::Result<String> {
+    if args.pretty {
+        serde_json::to_string_pretty(e)
+    } else if args.json {
+        serde_json::to_string(e)
     } else {
-        dump_file(p);
+        Ok(format!("{:?}", e))
     }
 }
 
-fn dump_file(path: &Path) {
-    println!("{}", path.display());
-    match read_log_entries(path) {
-        Ok(entries) => {
-            for e in entries {
-                println!("{:?}", e);
+/// Prints the entries of `path`; false once the reader, such as `head`,
+/// went away.
+fn dump_file(path: &Path, range: &TimeRange, args: &DumpArgs, out: &mut impl Write) -> bool {
+    // JSON output stays parseable as a whole without the file names.
+    if !(args.json || args.pretty) && writeln!(out, "{}", path.display()).is_err() {
+        return false;
+    }
+    match read_log_checked(path, range) {
+        Ok((entries, corrupt)) => {
+            for e in entries.iter().filter(|e| args.filter.matches(e)) {
+                let Ok(text) = format_entry(e, args) else {
+                    continue;
+                };
+                if writeln!(out, "{}", text).is_err() {
+                    return false;
+                }
+            }
+            if corrupt > 0 {
+                eprintln!("{}: skipped {} corrupt records", path.display(), corrupt);
             }
         }
         Err(e) => eprintln!("failed to read {}: {}", path.display(), e),
     }
+    true
 }
@@ -0,0 +1,99 @@
+use regex::Regex;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::config::GrepArgs;
+use crate::log::{Frame, LogEntry, frame_name, log_files, thread_stack, visit_log_range};
+
+fn frame_matches(f: &Frame, re: &Regex) -> bool {
+    f.func.as_deref().is_some_and(|s| re.is_match(s))
+        || f.file.as_deref().is_some_and(|s| re.is_match(s))
+}
+
+/// Describes each thread of `e` with a frame matching `re`: a header line,
+/// then its whole stack with the matching frames marked by `>`.
+fn matching_threads(e: &LogEntry, re: &Regex) -> Vec<String> {
+    let mut out = Vec::new();
+    for t in &e.threads {
+        let Some(stack) = thread_stack(t) else {
+            continue;
+        };
+        if !stack.iter().any(|f| frame_matches(f, re)) {
+            continue;
+        }
+        let mut text = format!(
+            "{} pid {} ({}) thread {}",
+            e.timestamp, e.pid, e.process_name, t.tid
+        );
+        for f in stack {
+            let mark = if frame_matches(f, re) { '>' } else { ' ' };
+            text += &format!("\n  {} {}", mark, frame_name(f));
+            if let Some(file) = &f.file {
+                text += &format!(" ({}:{})", file, f.line.unwrap_or(0));
+            }
+        }
+        out.push(text);
+    }
+    out
+}
+
+/// Prints the recorded stacks under `args.path` that pass through a frame
+/// whose function or file matches `args.pattern`.
+pub fn grep(args: &GrepArgs) {
+    let range = args.filter.time_range();
+    let mut out = io::stdout().lock();
+    // Set when the reader, such as `head`, went away.
+    let mut closed = false;
+    for f in log_files(Path::new(&args.path)) {
+        let read = visit_log_range(&f, &range, |e| {
+            if closed || !args.filter.matches(&e) {
+                return;
+            }
+            for text in matching_threads(&e, &args.pattern) {
+                closed = closed || writeln!(out, "{}\n", text).is_err();
+            }
+        });
+        if let Err(e) = read {
+            eprintln!("failed to read {}: {}", f.display(), e);
+        }
+        if closed {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matching_frames_marked_in_stack() {
+        let e: LogEntry = serde_json::from_value(serde_json::json!({
+            "timestamp": "2025-06-14T10:00:00Z",
+            "pid": 42,
+            "process_name": "nginx",
+            "cpu_time_percent": 0.0,
+            "memory": {"rss_kb": 0, "vsz_kb": 0, "swap_kb": 0},
+            "threads": [
+                {"tid": 42, "stacktrace": [
+                    {"func": "ssl_do_handshake", "file": "ssl.c", "line": 12},
+                    {"addr": 4096},
+                    {"func": "main"},
+                ]},
+                {"tid": 43, "stacktrace": [{"func": "epoll_wait"}]},
+            ],
+        }))
+        .unwrap();
+        let re = Regex::new("handshake").unwrap();
+        assert_eq!(
+            matching_threads(&e, &re),
+            ["2025-06-14T10:00:00Z pid 42 (nginx) thread 42\n  \
+              > ssl_do_handshake (ssl.c:12)\n    0x1000\n    main"]
+        );
+        assert_eq!(
+            matching_threads(&e, &Regex::new("^ssl\\.c$").unwrap()).len(),
+            1
+        );
+        assert!(matching_threads(&e, &Regex::new("recv").unwrap()).is_empty());
+    }
+}
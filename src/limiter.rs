@@ -0,0 +1,83 @@
+use std::time::Instant;
+
+/// Token bucket shared by all monitored processes that caps how many stack
+/// captures run per second. Processes are visited starting after the one
+/// that got the last capture, so a tight budget rotates through them.
+pub struct CaptureLimiter {
+    rate: f64,
+    tokens: f64,
+    last_refill: Instant,
+    last_pid: Option<u32>,
+}
+
+impl CaptureLimiter {
+    pub fn new(rate: f64, now: Instant) -> Self {
+        CaptureLimiter {
+            rate,
+            tokens: rate.max(1.0),
+            last_refill: now,
+            last_pid: None,
+        }
+    }
+
+    /// Returns `pids` reordered to start right after the last served pid.
+    pub fn order(&self, pids: &[u32]) -> Vec<u32> {
+        let mut sorted = pids.to_vec();
+        sorted.sort_unstable();
+        if let Some(last) = self.last_pid {
+            let start = sorted.partition_point(|p| *p <= last);
+            sorted.rotate_left(start);
+        }
+        sorted
+    }
+
+    /// Consumes one capture for `pid` if the budget allows it.
+    pub fn try_take(&mut self, pid: u32, now: Instant) -> bool {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate.max(1.0));
+        self.last_refill = now;
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        self.last_pid = Some(pid);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn budget_rotates_between_processes() {
+        let start = Instant::now();
+        let mut limiter = CaptureLimiter::new(1.0, start);
+        let pids = [30, 10, 20];
+
+        let order = limiter.order(&pids);
+        assert_eq!(order, vec![10, 20, 30]);
+        assert!(limiter.try_take(10, start));
+        assert!(!limiter.try_take(20, start));
+
+        let later = start + Duration::from_secs(1);
+        assert_eq!(limiter.order(&pids), vec![20, 30, 10]);
+        assert!(limiter.try_take(20, later));
+        assert!(!limiter.try_take(30, later));
+
+        let last = later + Duration::from_secs(1);
+        assert_eq!(limiter.order(&pids), vec![30, 10, 20]);
+        assert!(limiter.try_take(30, last));
+    }
+
+    #[test]
+    fn idle_time_does_not_accumulate_beyond_one_second() {
+        let start = Instant::now();
+        let mut limiter = CaptureLimiter::new(2.0, start);
+        let later = start + Duration::from_secs(60);
+        assert!(limiter.try_take(1, later));
+        assert!(limiter.try_take(2, later));
+        assert!(!limiter.try_take(3, later));
+    }
+}
@@ -1,21 +1,32 @@
 use log::warn;
 use rmp_serde::decode::{Error as MsgpackError, from_read as read_msgpack};
 use rmp_serde::encode::write_named;
+use serde::de::{DeserializeOwned, IgnoredAny};
 use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::{self, OpenOptions};
-use std::io::{self, BufRead, BufReader, Write};
-use std::path::Path;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use zstd::dict::{DecoderDictionary, EncoderDictionary};
 
-use fuzmon::utils::current_date_string;
+use crate::compact::{Interner, Strings};
+use fuzmon::utils::Timezone;
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Version of the log schema written by this build.
+pub const LOG_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct MemoryInfo {
     pub rss_kb: u64,
     pub vsz_kb: u64,
     pub swap_kb: u64,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Frame {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub addr: Option<i64>,
@@ -27,13 +38,83 @@ pub struct Frame {
     pub line: Option<i32>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// The function of `frame`, or its address when it was not symbolized.
+pub fn frame_name(frame: &Frame) -> String {
+    match (&frame.func, frame.addr) {
+        (Some(f), _) => f.clone(),
+        (None, Some(a)) => format!("{:#x}", a),
+        (None, None) => "?".into(),
+    }
+}
+
+/// The most complete stack recorded for `t`, if any.
+pub fn thread_stack(t: &ThreadInfo) -> Option<&Vec<Frame>> {
+    t.merged_stacktrace
+        .as_ref()
+        .or(t.stacktrace.as_ref())
+        .or(t.python_stacktrace.as_ref())
+        .or(t.php_stacktrace.as_ref())
+        .filter(|s| !s.is_empty())
+}
+
+/// General purpose registers of a stopped thread, kept for the hottest
+/// thread so spin loops can be identified even when unwinding fails.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Registers {
+    pub rip: u64,
+    pub rsp: u64,
+    pub rbp: u64,
+    pub rax: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub r8: u64,
+    pub r9: u64,
+}
+
+/// Interpreter-side view of a Python thread as reported by py-spy.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct PythonThreadState {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub owns_gil: bool,
+    /// False when the OS reports the thread as sleeping or blocked.
+    pub active: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct ThreadInfo {
     pub tid: u32,
+    /// Name of a native thread, from its `comm`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// CPU usage of the thread since the previous stack capture.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu_time_percent: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stacktrace: Option<Vec<Frame>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub python_stacktrace: Option<Vec<Frame>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub python_thread: Option<PythonThreadState>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub php_stacktrace: Option<Vec<Frame>>,
+    /// Native stack with Python frames spliced in at their eval-loop calls.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub merged_stacktrace: Option<Vec<Frame>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub truncated: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub registers: Option<Registers>,
+    /// Why the native stack could not be captured, e.g. when ptrace was
+    /// not permitted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capture_error: Option<String>,
+    /// Hash of the stacks above. A thread with an id but no stacks repeats
+    /// the stacks written earlier in the same file under that id.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stack_id: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -45,126 +126,1338 @@ pub struct FdLogEvent {
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct LogEntry {
+    /// Schema version; logs without it are upgraded when read.
+    #[serde(default)]
+    pub version: u32,
     pub timestamp: String,
     pub pid: u32,
     pub process_name: String,
     pub cpu_time_percent: f64,
     pub memory: MemoryInfo,
+    /// Parent pid, recorded with `cmdline`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ppid: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub cmdline: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub env: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub boot_id: Option<String>,
+    /// When the process started, recorded with `hostname`, so a pid reused
+    /// later is told apart.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<String>,
+    /// Why monitoring stopped, on the last entry of each process.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stopped: Option<String>,
+    /// Alert rules with `marker` that started matching at this entry.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alerts: Option<Vec<String>>,
+    /// Set on the entries of fuzmon's own CPU and memory use, which
+    /// reports keep out of their rankings.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub self_overhead: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub fd_events: Option<Vec<FdLogEvent>>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub threads: Vec<ThreadInfo>,
 }
 
-pub fn write_log(dir: &str, entry: &LogEntry, use_msgpack: bool, compress: bool) {
-    let date = current_date_string();
-    let dir = format!("{}/{}", dir.trim_end_matches('/'), date);
-    if let Err(e) = fs::create_dir_all(&dir) {
-        warn!("failed to create {}: {}", dir, e);
+impl LogEntry {
+    /// Milliseconds since the epoch of `timestamp`, or 0 if it does not
+    /// parse. Sorting by this orders old whole-second timestamps correctly
+    /// among millisecond ones.
+    pub fn time_millis(&self) -> i64 {
+        chrono::DateTime::parse_from_rfc3339(&self.timestamp)
+            .map(|t| t.timestamp_millis())
+            .unwrap_or(0)
     }
-    let ext = if use_msgpack { "msgpacks" } else { "jsonl" };
-    let base = format!("{}/{}.{}", dir, entry.pid, ext);
-    let path = if compress {
-        format!("{}.zst", base)
+}
+
+/// Extension of the zstd dictionaries kept at the top of an output
+/// directory.
+pub const DICTIONARY_EXTENSION: &str = "dict";
+
+/// File name of the dictionary with the zstd dictionary ID `id`, which
+/// frames compressed with it record in their header. Keeping each one under
+/// its own name leaves logs written before a new dictionary readable.
+pub fn dictionary_name(id: u32) -> String {
+    format!("fuzmon-{}.{}", id, DICTIONARY_EXTENSION)
+}
+
+/// File stem of the per-day log shared by all pids in the combined layout.
+pub const COMBINED_NAME: &str = "combined";
+
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum Layout {
+    /// One file per pid and day.
+    #[default]
+    PerPid,
+    /// One time-ordered file per day for all pids, to avoid creating a file
+    /// for every short-lived process.
+    Combined,
+}
+
+/// How often a new log file is started for a process.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Rollover {
+    #[default]
+    Day,
+    /// An `HH` directory per hour under the date directory, to keep files
+    /// from high-frequency monitoring small enough to copy and inspect.
+    Hour,
+}
+
+/// How entries are encoded and which file they are written to.
+#[derive(Clone, Default)]
+pub struct LogFormat {
+    pub use_msgpack: bool,
+    /// MessagePack with integer keys and interned strings (`msgpackc`).
+    pub compact: bool,
+    pub compress: bool,
+    pub zstd_level: i32,
+    /// Prepared once at `zstd_level`, as that is costly.
+    pub dictionary: Option<Arc<EncoderDictionary<'static>>>,
+    pub layout: Layout,
+    pub rollover: Rollover,
+    /// Write only the fields that changed since the previous entry.
+    pub delta: bool,
+    /// Memory changes up to this size are not written in delta mode.
+    pub delta_memory_tolerance_kb: u64,
+    /// Time zone the date directories follow.
+    pub timezone: Timezone,
+}
+
+impl LogFormat {
+    pub fn extension(&self) -> &'static str {
+        match (self.use_msgpack, self.compress) {
+            _ if self.compact && self.compress => "msgpackc.zst",
+            _ if self.compact => "msgpackc",
+            (true, true) => "msgpacks.zst",
+            (true, false) => "msgpacks",
+            (false, true) => "jsonl.zst",
+            (false, false) => "jsonl",
+        }
+    }
+}
+
+/// Placeholder of `output.path` replaced by the date directory.
+pub const DATE_PLACEHOLDER: &str = "{date}";
+
+/// `output.path` that streams JSON lines to stdout instead of files.
+pub const STDOUT_PATH: &str = "-";
+
+pub fn is_stdout(dir: &str) -> bool {
+    dir == STDOUT_PATH
+}
+
+/// Directory the date directories are created in: the parent of the path
+/// component holding the `{date}` placeholder, or all of `dir` when the date
+/// is appended.
+pub fn output_root(dir: &str) -> &str {
+    let Some((before, _)) = dir.split_once(DATE_PLACEHOLDER) else {
+        return dir;
+    };
+    match before.rfind('/') {
+        Some(i) => &dir[..i + 1],
+        None => ".",
+    }
+}
+
+pub fn log_path(dir: &str, pid: u32, format: &LogFormat) -> String {
+    let now = format.timezone.now();
+    let date = now.format("%Y%m%d").to_string();
+    let mut dir = if dir.contains(DATE_PLACEHOLDER) {
+        dir.replace(DATE_PLACEHOLDER, &date)
     } else {
-        base
+        format!("{}/{}", dir.trim_end_matches('/'), date)
     };
-    match OpenOptions::new().create(true).append(true).open(&path) {
-        Ok(file) => {
-            if compress {
-                match zstd::Encoder::new(file, 0) {
-                    Ok(mut enc) => {
-                        if use_msgpack {
-                            if let Err(e) = write_named(&mut enc, entry) {
-                                warn!("write msgpack failed: {}", e);
-                            }
-                        } else {
-                            if serde_json::to_writer(&mut enc, entry).is_err() {
-                                warn!("write json failed");
-                            }
-                            if enc.write_all(b"\n").is_err() {
-                                warn!("write newline failed");
-                            }
-                        }
-                        if let Err(e) = enc.finish() {
-                            warn!("finish zstd failed: {}", e);
-                        }
-                    }
-                    Err(e) => warn!("zstd init failed: {}", e),
-                }
-            } else {
-                let mut file = file;
-                if use_msgpack {
-                    if let Err(e) = write_named(&mut file, entry) {
-                        warn!("write msgpack failed: {}", e);
-                    }
-                } else {
-                    if serde_json::to_writer(&mut file, entry).is_err() {
-                        warn!("write json failed");
-                    }
-                    if file.write_all(b"\n").is_err() {
-                        warn!("write newline failed");
-                    }
+    if format.rollover == Rollover::Hour {
+        dir = format!("{}/{}", dir.trim_end_matches('/'), now.format("%H"));
+    }
+    let stem = match format.layout {
+        Layout::PerPid => pid.to_string(),
+        Layout::Combined => COMBINED_NAME.to_string(),
+    };
+    format!(
+        "{}/{}.{}",
+        dir.trim_end_matches('/'),
+        stem,
+        format.extension()
+    )
+}
+
+/// Whether `path` is a log written in the combined layout, which holds the
+/// entries of many pids.
+pub fn is_combined_log(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .and_then(|n| n.split('.').next())
+        == Some(COMBINED_NAME)
+}
+
+/// A process in a log: its pid and, when the log records it, when it
+/// started, so a pid reused within one log is told apart.
+pub type ProcessKey = (u32, Option<String>);
+
+/// Keys the entries of one log. Only the first entry of a process in each
+/// file records its start time, which holds for the entries after it.
+#[derive(Default)]
+pub struct ProcessKeys(HashMap<u32, Option<String>>);
+
+impl ProcessKeys {
+    pub fn key(&mut self, e: &LogEntry) -> ProcessKey {
+        let start = self.0.entry(e.pid).or_default();
+        if e.start_time.is_some() {
+            start.clone_from(&e.start_time);
+        }
+        (e.pid, start.clone())
+    }
+}
+
+/// Groups the entries of one log by process, keeping their order within
+/// each group.
+pub fn split_by_process(entries: Vec<LogEntry>) -> BTreeMap<ProcessKey, Vec<LogEntry>> {
+    let mut keys = ProcessKeys::default();
+    let mut by_process: BTreeMap<ProcessKey, Vec<LogEntry>> = BTreeMap::new();
+    for e in entries {
+        by_process.entry(keys.key(&e)).or_default().push(e);
+    }
+    by_process
+}
+
+fn stacks_of(t: &ThreadInfo) -> [&Option<Vec<Frame>>; 4] {
+    [
+        &t.stacktrace,
+        &t.python_stacktrace,
+        &t.php_stacktrace,
+        &t.merged_stacktrace,
+    ]
+}
+
+/// Replaces stacks already present in `written` by a reference to their id
+/// and records the new ones, so idle threads do not repeat the same frames
+/// every sample.
+pub fn dedup_stacks(entry: &mut LogEntry, written: &mut HashSet<String>) {
+    for t in &mut entry.threads {
+        let stacks = stacks_of(t);
+        if stacks.iter().all(|s| s.is_none()) {
+            continue;
+        }
+        let mut hasher = DefaultHasher::new();
+        stacks.hash(&mut hasher);
+        let id = format!("{:016x}", hasher.finish());
+        if !written.insert(id.clone()) {
+            t.stacktrace = None;
+            t.python_stacktrace = None;
+            t.php_stacktrace = None;
+            t.merged_stacktrace = None;
+        }
+        t.stack_id = Some(id);
+    }
+}
+
+/// Stacks seen so far in a log, by `stack_id`.
+type KnownStacks = HashMap<String, [Option<Vec<Frame>>; 4]>;
+
+/// Fills in the stacks of threads that only name them by `stack_id`, and
+/// remembers the stacks of the others.
+fn resolve_stack_refs(entry: &mut LogEntry, known: &mut KnownStacks) {
+    for t in entry.threads.iter_mut() {
+        let Some(id) = t.stack_id.clone() else {
+            continue;
+        };
+        if stacks_of(t).iter().any(|s| s.is_some()) {
+            known.insert(id, stacks_of(t).map(|s| s.clone()));
+        } else if let Some([c, py, php, merged]) = known.get(&id).cloned() {
+            t.stacktrace = c;
+            t.python_stacktrace = py;
+            t.php_stacktrace = php;
+            t.merged_stacktrace = merged;
+        }
+    }
+}
+
+/// Fields a delta entry leaves out while they stay the same.
+const DELTA_FIELDS: [&str; 3] = ["process_name", "memory", "threads"];
+
+fn delta_field(entry: &serde_json::Map<String, Value>, field: &str) -> Value {
+    // An entry without threads serializes none, but that is a change too.
+    entry.get(field).cloned().unwrap_or_else(|| json!([]))
+}
+
+fn memory_close(a: &Value, b: &Value, tolerance_kb: u64) -> bool {
+    ["rss_kb", "vsz_kb", "swap_kb"].iter().all(|k| {
+        match (
+            a.get(k).and_then(Value::as_u64),
+            b.get(k).and_then(Value::as_u64),
+        ) {
+            (Some(x), Some(y)) => x.abs_diff(y) <= tolerance_kb,
+            _ => false,
+        }
+    })
+}
+
+/// Drops the fields of `value` that did not change since `base`, which
+/// holds what readers reconstruct for the previous entry. `full` is the
+/// entry before its stacks were replaced by references, and memory within
+/// `tolerance_kb` of the base counts as unchanged. Without a base the entry
+/// is written in full and becomes the base.
+pub fn delta_encode(value: &mut Value, full: &Value, base: &mut Option<Value>, tolerance_kb: u64) {
+    let (Some(obj), Some(full)) = (value.as_object_mut(), full.as_object()) else {
+        return;
+    };
+    let Some(base) = base else {
+        *base = Some(Value::Object(
+            DELTA_FIELDS
+                .iter()
+                .map(|f| (f.to_string(), delta_field(full, f)))
+                .collect(),
+        ));
+        return;
+    };
+    obj.insert("delta".into(), Value::Bool(true));
+    for field in DELTA_FIELDS {
+        let cur = delta_field(full, field);
+        let unchanged = match (field, base.get(field)) {
+            ("memory", Some(prev)) => memory_close(&cur, prev, tolerance_kb),
+            (_, prev) => prev == Some(&cur),
+        };
+        if unchanged {
+            obj.remove(field);
+        } else {
+            obj.entry(field).or_insert_with(|| json!([]));
+            base[field] = cur;
+        }
+    }
+}
+
+/// The `DELTA_FIELDS` of the previous entry of a pid, which the delta
+/// entry after it may leave out.
+struct DeltaBase {
+    process_name: String,
+    memory: MemoryInfo,
+    threads: Vec<ThreadInfo>,
+}
+
+impl DeltaBase {
+    fn of(entry: &LogEntry) -> DeltaBase {
+        DeltaBase {
+            process_name: entry.process_name.clone(),
+            memory: entry.memory.clone(),
+            threads: entry.threads.clone(),
+        }
+    }
+}
+
+/// Decodes a record that depends on the entries before it, filling the
+/// fields a delta entry left out from the previous entry of the same pid,
+/// which `bases` holds. Returns `None` for a delta entry whose previous
+/// entry is missing, e.g. because the file was cut, as it cannot be
+/// reconstructed.
+fn decode_delta(mut v: Value, bases: &HashMap<u32, DeltaBase>) -> io::Result<Option<LogEntry>> {
+    let mut threads = None;
+    if let Some(obj) = v.as_object_mut()
+        && obj.remove("delta") == Some(Value::Bool(true))
+    {
+        let pid = obj.get("pid").and_then(Value::as_u64).unwrap_or(0);
+        let Some(base) = u32::try_from(pid).ok().and_then(|p| bases.get(&p)) else {
+            warn!("dropping delta entry of {} without a previous entry", pid);
+            return Ok(None);
+        };
+        obj.entry("process_name")
+            .or_insert_with(|| json!(base.process_name));
+        obj.entry("memory").or_insert_with(|| json!(base.memory));
+        if !obj.contains_key("threads") {
+            threads = Some(base.threads.clone());
+        }
+    }
+    let mut entry: LogEntry =
+        serde_json::from_value(v).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    if let Some(threads) = threads {
+        entry.threads = threads;
+    }
+    Ok(Some(entry))
+}
+
+/// Key of the checksum appended as the last field of every JSON record.
+const CRC_FIELD: &[u8] = b",\"crc32\":\"";
+/// Length of `,"crc32":"xxxxxxxx"}` at the end of a JSON record.
+const CRC_SUFFIX_LEN: usize = CRC_FIELD.len() + 10;
+/// MessagePack marker of the uint32 checksum following every record.
+const MSGPACK_U32: u8 = 0xce;
+
+/// Serializes `entry` as one record of a log file in the given encoding,
+/// followed by a CRC32 of the record so readers can skip damaged ones. JSON
+/// records carry it as a last `crc32` field over the bytes before it, so
+/// each line stays plain JSON.
+pub fn encode_entry(entry: &impl Serialize, use_msgpack: bool) -> Option<Vec<u8>> {
+    if use_msgpack {
+        let mut buf = Vec::new();
+        write_named(&mut buf, entry)
+            .map_err(|e| warn!("write msgpack failed: {}", e))
+            .ok()?;
+        let crc = crc32fast::hash(&buf);
+        buf.push(MSGPACK_U32);
+        buf.extend(crc.to_be_bytes());
+        Some(buf)
+    } else {
+        let mut line = serde_json::to_vec(entry)
+            .map_err(|e| warn!("write json failed: {}", e))
+            .ok()?;
+        let crc = crc32fast::hash(&line);
+        line.pop();
+        line.extend(format!(",\"crc32\":\"{:08x}\"}}\n", crc).as_bytes());
+        Some(line)
+    }
+}
+
+/// Checks the checksum of a JSON record, returning `None` for records
+/// written before checksums were added.
+fn json_crc_ok(line: &[u8]) -> Option<bool> {
+    let split = line.len().checked_sub(CRC_SUFFIX_LEN)?;
+    let (body, suffix) = line.split_at(split);
+    let hex = suffix.strip_prefix(CRC_FIELD)?.strip_suffix(b"\"}")?;
+    let expected = u32::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?;
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(body);
+    hasher.update(b"}");
+    Some(hasher.finalize() == expected)
+}
+
+/// Encodes `entry` as stored in a log of `format`, compressed if needed.
+/// Strings of the compact format are numbered in `interner`.
+pub fn encode_record(
+    entry: &impl Serialize,
+    format: &LogFormat,
+    interner: &mut Interner,
+) -> Option<Vec<u8>> {
+    let data = if format.compact {
+        let value = serde_json::to_value(entry)
+            .map_err(|e| warn!("write msgpack failed: {}", e))
+            .ok()?;
+        encode_entry(&interner.encode(&value), true)?
+    } else {
+        encode_entry(entry, format.use_msgpack)?
+    };
+    if !format.compress {
+        return Some(data);
+    }
+    // Each entry is an independent zstd frame, so a file cut off by an
+    // unclean exit loses at most its last entry, and readers can start at
+    // any entry.
+    match &format.dictionary {
+        Some(dict) => zstd::bulk::Compressor::with_prepared_dictionary(dict),
+        None => zstd::bulk::Compressor::new(format.zstd_level),
+    }
+    .and_then(|mut c| c.compress(&data))
+    .map_err(|e| warn!("zstd compression failed: {}", e))
+    .ok()
+}
+
+/// Opens the log file at `path` for appending, creating parent directories.
+/// Returns the file with its current length.
+pub fn open_log(path: &str) -> Option<(fs::File, u64)> {
+    if let Some(parent) = Path::new(path).parent()
+        && let Err(e) = fs::create_dir_all(parent)
+    {
+        warn!("failed to create {}: {}", parent.display(), e);
+    }
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| warn!("open {} failed: {}", path, e))
+        .ok()?;
+    let len = file.metadata().map(|m| m.len()).unwrap_or(0);
+    Some((file, len))
+}
+
+/// Appends `entry` to the log file at `path`, creating parent directories.
+/// Returns the offset the entry was written at.
+pub fn append_log(
+    path: &str,
+    entry: &impl Serialize,
+    format: &LogFormat,
+    interner: &mut Interner,
+) -> Option<u64> {
+    let data = encode_record(entry, format, interner)?;
+    let (mut file, offset) = open_log(path)?;
+    if let Err(e) = file.write_all(&data) {
+        warn!("write {} failed: {}", path, e);
+        return None;
+    }
+    Some(offset)
+}
+
+/// Extension of the time index kept next to each log file.
+pub const INDEX_EXTENSION: &str = "idx";
+
+fn index_path(log: &Path) -> PathBuf {
+    let mut name = log.as_os_str().to_owned();
+    name.push(format!(".{}", INDEX_EXTENSION));
+    PathBuf::from(name)
+}
+
+/// Records in the time index of the log at `path` that the entry at byte
+/// `offset` is the first one at or after `secs`.
+pub fn append_index(path: &str, secs: i64, offset: u64) {
+    let idx = index_path(Path::new(path));
+    let res = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&idx)
+        .and_then(|mut f| writeln!(f, "{} {}", secs, offset));
+    if let Err(e) = res {
+        warn!("failed to update {}: {}", idx.display(), e);
+    }
+}
+
+/// Reads the `(secs, offset)` marks of the time index of `path`. A missing
+/// index or a damaged line just means more of the log has to be scanned.
+fn read_index(path: &Path) -> Vec<(i64, u64)> {
+    let Ok(text) = fs::read_to_string(index_path(path)) else {
+        return Vec::new();
+    };
+    text.lines()
+        .map_while(|l| {
+            let (secs, offset) = l.split_once(' ')?;
+            Some((secs.parse().ok()?, offset.parse().ok()?))
+        })
+        .collect()
+}
+
+/// Whether `path` holds log entries rather than a dictionary or an index.
+pub fn is_log_file(path: &Path) -> bool {
+    path.file_name().is_some()
+        && path
+            .extension()
+            .is_none_or(|e| e != INDEX_EXTENSION && e != DICTIONARY_EXTENSION)
+}
+
+/// Recursively lists every log file under `dir`.
+pub fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            let p = entry.path();
+            if p.is_dir() {
+                collect_files(&p, files);
+            } else if p.is_file() && is_log_file(&p) {
+                files.push(p);
+            }
+        }
+    }
+}
+
+/// The logs under `path`, which is a directory or a log file, in path
+/// order, which is time order for date directories.
+pub fn log_files(path: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    if path.is_dir() {
+        collect_files(path, &mut files);
+        files.sort();
+    } else {
+        files.push(path.to_path_buf());
+    }
+    files
+}
+
+/// Looks for the dictionary with ID `id` next to the log and at the top of
+/// the output directory, which is one level up from the date directories,
+/// or two with hourly rollover.
+fn find_dictionary(path: &Path, id: u32) -> Option<DecoderDictionary<'static>> {
+    let name = dictionary_name(id);
+    let dict = path
+        .ancestors()
+        .skip(1)
+        .take(3)
+        .find_map(|dir| fs::read(dir.join(&name)).ok());
+    if dict.is_none() {
+        warn!("{} needs {}, which was not found", path.display(), name);
+    }
+    dict.map(|d| DecoderDictionary::copy(&d))
+}
+
+/// Magic number starting every zstd frame.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Bytes of a log held in memory at a time. Records are far smaller.
+const READ_WINDOW: usize = 4 << 20;
+
+/// Reads bytes `start..end` of a log piece by piece.
+struct Window {
+    reader: io::Take<fs::File>,
+    buf: Vec<u8>,
+    pos: usize,
+    eof: bool,
+}
+
+impl Window {
+    fn open(path: &Path, start: u64, end: Option<u64>) -> io::Result<Window> {
+        let mut file = fs::File::open(path)?;
+        file.seek(SeekFrom::Start(start))?;
+        Ok(Window {
+            reader: file.take(end.map_or(u64::MAX, |e| e.saturating_sub(start))),
+            buf: Vec::new(),
+            pos: 0,
+            eof: false,
+        })
+    }
+
+    /// Reads ahead until at least `READ_WINDOW` bytes are unread or the end
+    /// is reached.
+    fn fill(&mut self) -> io::Result<()> {
+        if self.eof || self.buf.len() - self.pos >= READ_WINDOW {
+            return Ok(());
+        }
+        self.buf.drain(..self.pos);
+        self.pos = 0;
+        let want = (2 * READ_WINDOW - self.buf.len()) as u64;
+        let n = (&mut self.reader).take(want).read_to_end(&mut self.buf)?;
+        self.eof = (n as u64) < want;
+        Ok(())
+    }
+
+    fn rest(&self) -> &[u8] {
+        &self.buf[self.pos..]
+    }
+
+    fn consume(&mut self, n: usize) {
+        self.pos += n;
+    }
+}
+
+/// A piece of a log in which records can be told apart.
+#[derive(Clone, Copy)]
+struct Chunk<'a> {
+    data: &'a [u8],
+    /// Whether no chunk follows.
+    last: bool,
+    /// Whether the data was cut off after it.
+    truncated: bool,
+    /// Whether corrupt data was skipped right before it.
+    after_gap: bool,
+}
+
+impl<'a> Chunk<'a> {
+    fn new(data: &'a [u8], last: bool) -> Chunk<'a> {
+        Chunk {
+            data,
+            last,
+            truncated: false,
+            after_gap: false,
+        }
+    }
+}
+
+type ChunkFn<'a> = dyn FnMut(Chunk) -> io::Result<()> + 'a;
+
+/// Passes bytes `start..end` of the log to `f`, decompressed if needed, in
+/// chunks where records can be told apart: lines of JSON, whole MessagePack
+/// records, or one zstd frame. Returns the number of frames that failed to
+/// decompress.
+fn read_log_chunks(
+    path: &Path,
+    start: u64,
+    end: Option<u64>,
+    f: &mut ChunkFn,
+) -> io::Result<usize> {
+    let (is_zst, ext) = log_encoding(path);
+    let mut win = Window::open(path, start, end)?;
+    if is_zst {
+        return read_zst_frames(path, &mut win, f);
+    }
+    let msgpack = ext.starts_with("msgpack");
+    loop {
+        win.fill()?;
+        let rest = win.rest();
+        if rest.is_empty() {
+            return Ok(0);
+        }
+        let complete = if msgpack {
+            Some(msgpack_records_len(rest)).filter(|n| *n > 0)
+        } else {
+            rest.iter().rposition(|b| *b == b'\n').map(|i| i + 1)
+        };
+        // A record larger than the window is passed on as it is.
+        let n = match complete {
+            Some(n) if !win.eof => n,
+            _ => rest.len(),
+        };
+        f(Chunk::new(&rest[..n], win.eof && n == rest.len()))?;
+        win.consume(n);
+    }
+}
+
+/// Every entry is its own zstd frame, so a damaged or cut off frame only
+/// loses that entry; everything after a bad frame header is skipped up to
+/// the next frame magic.
+fn read_zst_frames(path: &Path, win: &mut Window, f: &mut ChunkFn) -> io::Result<usize> {
+    // Frames name the dictionary they need, loaded on first use.
+    let mut dicts: HashMap<u32, Option<DecoderDictionary>> = HashMap::new();
+    let mut corrupt = 0;
+    let mut truncated = false;
+    let mut gap = false;
+    // Held back a frame, as only a later one tells whether it is the last,
+    // with whether a gap came before it.
+    let mut pending: Option<(Vec<u8>, bool)> = None;
+    loop {
+        win.fill()?;
+        let raw = win.rest();
+        if raw.is_empty() {
+            break;
+        }
+        let size = zstd::zstd_safe::find_frame_compressed_size(raw).ok();
+        let decoded = size.and_then(|n| {
+            let frame = &raw[..n];
+            let decoder = match zstd::zstd_safe::get_dict_id_from_frame(frame) {
+                Some(id) => {
+                    let dict = dicts
+                        .entry(id.get())
+                        .or_insert_with(|| find_dictionary(path, id.get()))
+                        .as_ref()?;
+                    zstd::Decoder::with_prepared_dictionary(frame, dict)
                 }
+                None => zstd::Decoder::with_buffer(frame),
+            };
+            let mut data = Vec::new();
+            decoder
+                .and_then(|mut d| d.read_to_end(&mut data))
+                .ok()
+                .map(|_| data)
+        });
+        if let Some(data) = decoded {
+            if let Some((prev, after_gap)) = pending.replace((data, gap)) {
+                f(Chunk {
+                    after_gap,
+                    ..Chunk::new(&prev, false)
+                })?;
             }
+            gap = false;
+            win.consume(size.unwrap_or_default());
+            continue;
         }
-        Err(e) => warn!("open {} failed: {}", path, e),
+        gap = true;
+        let next = raw[1..]
+            .windows(ZSTD_MAGIC.len())
+            .position(|w| w == ZSTD_MAGIC)
+            .map(|i| 1 + i);
+        match next {
+            Some(next) => {
+                corrupt += 1;
+                win.consume(next);
+            }
+            // No frame starts in the window, but one may begin right at
+            // its end.
+            None if !win.eof => {
+                corrupt += 1;
+                let skip = raw.len() - (ZSTD_MAGIC.len() - 1);
+                win.consume(skip);
+            }
+            None => {
+                warn!("{}: truncated zstd data", path.display());
+                truncated = true;
+                break;
+            }
+        }
+    }
+    if let Some((last, after_gap)) = pending {
+        f(Chunk {
+            truncated,
+            after_gap,
+            ..Chunk::new(&last, true)
+        })?;
     }
+    Ok(corrupt)
 }
 
-pub fn read_log_entries(path: &Path) -> io::Result<Vec<LogEntry>> {
-    let file = fs::File::open(path)?;
+/// Whether the log is zstd compressed, and the extension naming its
+/// encoding.
+fn log_encoding(path: &Path) -> (bool, String) {
     let is_zst = path.extension().and_then(|e| e.to_str()) == Some("zst");
-    let reader: Box<dyn std::io::Read> = if is_zst {
-        Box::new(zstd::Decoder::new(file)?)
-    } else {
-        Box::new(file)
+    let mut base = path.to_path_buf();
+    if is_zst {
+        base.set_extension("");
+    }
+    let ext = base
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_string();
+    (is_zst, ext)
+}
+
+/// Whether byte `start` of the log begins a record, as the offsets of a
+/// matching index do.
+fn starts_record(path: &Path, start: u64) -> io::Result<bool> {
+    let (is_zst, ext) = log_encoding(path);
+    let mut file = fs::File::open(path)?;
+    file.seek(SeekFrom::Start(start - 1))?;
+    let mut head = Vec::new();
+    file.take(1 + ZSTD_MAGIC.len() as u64)
+        .read_to_end(&mut head)?;
+    let Some((prev, head)) = head.split_first().filter(|(_, h)| !h.is_empty()) else {
+        return Ok(true);
     };
+    Ok(if is_zst {
+        head.starts_with(&ZSTD_MAGIC)
+    } else if ext.starts_with("msgpack") {
+        // Every record is a map.
+        matches!(head[0], 0x80..=0x8f | 0xde | 0xdf)
+    } else {
+        *prev == b'\n'
+    })
+}
+
+/// Unix time range of the entries to read, open ended where `None`.
+#[derive(Clone, Copy, Default)]
+pub struct TimeRange {
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+}
 
-    let ext = {
-        let mut base = path.to_path_buf();
-        if is_zst {
-            base.set_extension("");
+impl TimeRange {
+    fn is_all(&self) -> bool {
+        self.from.is_none() && self.to.is_none()
+    }
+
+    pub fn contains(&self, entry: &LogEntry) -> bool {
+        if self.is_all() {
+            return true;
         }
-        base.extension()
-            .and_then(|e| e.to_str())
-            .unwrap_or("")
-            .to_string()
+        let Ok(ts) = chrono::DateTime::parse_from_rfc3339(&entry.timestamp) else {
+            return false;
+        };
+        let secs = ts.timestamp();
+        self.from.is_none_or(|f| secs >= f) && self.to.is_none_or(|t| secs <= t)
+    }
+
+    /// Byte range of the log that holds the entries in range according to
+    /// its index. Entries are appended in time order, so everything before
+    /// the last mark at or before `from` and after the first mark past `to`
+    /// can be skipped.
+    fn byte_range(&self, index: &[(i64, u64)]) -> (u64, Option<u64>) {
+        let start = self
+            .from
+            .and_then(|f| index.iter().rev().find(|(secs, _)| *secs <= f))
+            .map_or(0, |(_, offset)| *offset);
+        let end = self
+            .to
+            .and_then(|t| index.iter().find(|(secs, _)| *secs > t))
+            .map(|(_, offset)| *offset)
+            .filter(|e| *e >= start);
+        (start, end)
+    }
+}
+
+pub fn read_log_entries(path: &Path) -> io::Result<Vec<LogEntry>> {
+    read_log_range(path, &TimeRange::default())
+}
+
+/// Reads the entries of `path` within `range`, using the time index to skip
+/// the parts of the file outside it.
+pub fn read_log_range(path: &Path, range: &TimeRange) -> io::Result<Vec<LogEntry>> {
+    let mut entries = Vec::new();
+    visit_log_range(path, range, |e| entries.push(e))?;
+    Ok(entries)
+}
+
+/// Like `read_log_range`, but hands the entries to `f` one at a time as
+/// they are read, so the log never has to fit in memory. Returns how many
+/// corrupt records were skipped, after warning about them.
+pub fn visit_log_range(
+    path: &Path,
+    range: &TimeRange,
+    f: impl FnMut(LogEntry),
+) -> io::Result<usize> {
+    let corrupt = visit_log_checked(path, range, f)?;
+    if corrupt > 0 {
+        warn!("{}: skipped {} corrupt records", path.display(), corrupt);
+    }
+    Ok(corrupt)
+}
+
+/// Like `read_log_range`, but also returns how many records were skipped
+/// because their checksum did not match or they could not be decoded.
+pub fn read_log_checked(path: &Path, range: &TimeRange) -> io::Result<(Vec<LogEntry>, usize)> {
+    let mut entries = Vec::new();
+    let corrupt = visit_log_checked(path, range, |e| entries.push(e))?;
+    Ok((entries, corrupt))
+}
+
+/// What decoding an entry depends on from the entries before it.
+#[derive(Default)]
+struct EntryDecoder {
+    /// Time and cumulative CPU seconds of the previous entry written before
+    /// `version` existed.
+    cpu_prev: Option<(f64, f64)>,
+    delta_bases: HashMap<u32, DeltaBase>,
+    stacks: KnownStacks,
+}
+
+impl EntryDecoder {
+    /// Turns a record into an entry, or `None` if it cannot be
+    /// reconstructed.
+    fn decode(&mut self, record: Record) -> io::Result<Option<LogEntry>> {
+        let mut entry = match record {
+            Record::Entry(entry) => *entry,
+            Record::Value(mut v) => {
+                if v.get("version").and_then(Value::as_u64).unwrap_or(0) == 0 {
+                    upgrade_v0(&mut v, &mut self.cpu_prev);
+                }
+                match decode_delta(v, &self.delta_bases)? {
+                    Some(entry) => entry,
+                    None => return Ok(None),
+                }
+            }
+        };
+        self.delta_bases.insert(entry.pid, DeltaBase::of(&entry));
+        resolve_stack_refs(&mut entry, &mut self.stacks);
+        Ok(Some(entry))
+    }
+}
+
+/// Streams the entries of `path` within `range` to `f` and returns how many
+/// records were skipped as corrupt.
+fn visit_log_checked(
+    path: &Path,
+    range: &TimeRange,
+    mut f: impl FnMut(LogEntry),
+) -> io::Result<usize> {
+    let (mut start, mut end) = if range.is_all() {
+        (0, None)
+    } else {
+        range.byte_range(&read_index(path))
     };
+    if start > 0 && !starts_record(path, start)? {
+        warn!("{}: index does not match, reading all", path.display());
+        (start, end) = (0, None);
+    }
+    let mut decoder = EntryDecoder::default();
+    read_records(path, start, end, &mut |v| {
+        if let Some(e) = decoder.decode(v)?
+            && range.contains(&e)
+        {
+            f(e);
+        }
+        Ok(())
+    })
+}
+
+/// Reads a log again and again as it grows. Each read starts at the last
+/// mark of the time index, where entries stand on their own, and skips the
+/// entries already passed on, so a long log is not read from the start
+/// every time.
+pub struct LogFollower {
+    path: PathBuf,
+    mark: u64,
+    /// Entries passed on since `mark`.
+    seen: usize,
+}
 
-    if ext == "msgpacks" {
-        let mut r = reader;
-        let mut entries = Vec::new();
-        loop {
-            match read_msgpack(&mut r) {
-                Ok(e) => entries.push(e),
-                Err(MsgpackError::InvalidMarkerRead(ref ioe))
-                | Err(MsgpackError::InvalidDataRead(ref ioe))
-                    if ioe.kind() == io::ErrorKind::UnexpectedEof =>
-                {
-                    break;
+impl LogFollower {
+    pub fn new(path: &Path) -> LogFollower {
+        LogFollower {
+            path: path.to_path_buf(),
+            mark: 0,
+            seen: 0,
+        }
+    }
+
+    /// The last mark past the current one whose record is on disk; the
+    /// log may have been cut since it was indexed.
+    fn next_mark(&self) -> Option<u64> {
+        let len = fs::metadata(&self.path).map_or(0, |m| m.len());
+        read_index(&self.path)
+            .last()
+            .map(|&(_, offset)| offset)
+            .filter(|&o| o > self.mark && o < len)
+            .filter(|&o| starts_record(&self.path, o).unwrap_or(false))
+    }
+
+    /// Moves past the entries written so far without reading them all.
+    pub fn skip_existing(&mut self) -> io::Result<()> {
+        if let Some(mark) = self.next_mark() {
+            self.mark = mark;
+            self.seen = 0;
+        }
+        self.read_new(|_| {}).map(|_| ())
+    }
+
+    /// Passes the entries written since the last call to `f` and returns
+    /// how many corrupt records were skipped.
+    pub fn read_new(&mut self, mut f: impl FnMut(LogEntry)) -> io::Result<usize> {
+        let next = self.next_mark();
+        let mut corrupt = self.read_span(next, &mut f)?;
+        if let Some(mark) = next {
+            self.mark = mark;
+            self.seen = 0;
+            corrupt += self.read_span(None, &mut f)?;
+        }
+        Ok(corrupt)
+    }
+
+    fn read_span(&mut self, end: Option<u64>, f: &mut dyn FnMut(LogEntry)) -> io::Result<usize> {
+        let mut decoder = EntryDecoder::default();
+        let mut count = 0;
+        let seen = self.seen;
+        let corrupt = read_records(&self.path, self.mark, end, &mut |v| {
+            if let Some(e) = decoder.decode(v)? {
+                if count >= seen {
+                    f(e);
                 }
-                Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+                count += 1;
             }
+            Ok(())
+        })?;
+        self.seen = self.seen.max(count);
+        Ok(corrupt)
+    }
+}
+
+/// Length of the whole MessagePack records at the start of `data`, each
+/// with its CRC32 if it has one.
+fn msgpack_records_len(data: &[u8]) -> usize {
+    let mut r = data;
+    let mut len = 0;
+    while read_msgpack::<_, IgnoredAny>(&mut r).is_ok() {
+        if r.first() == Some(&MSGPACK_U32) {
+            let Some(rest) = r.get(5..) else {
+                break;
+            };
+            r = rest;
         }
-        Ok(entries)
-    } else {
-        let buf = BufReader::new(reader);
-        let mut entries = Vec::new();
-        for line in buf.lines() {
-            let line = line?;
-            if line.trim().is_empty() {
+        len = data.len() - r.len();
+    }
+    len
+}
+
+/// Takes a MessagePack record with its bytes.
+type RecordFn<'a, T> = dyn FnMut(Option<(T, &[u8])>) -> io::Result<()> + 'a;
+
+/// Parses the records in one chunk of MessagePack data, each optionally
+/// followed by its CRC32, and passes them to `emit` with their bytes, or
+/// `None` where a damaged record was skipped. Decoding cannot resume after
+/// a broken record, so the rest of the chunk counts as one corrupt record.
+fn read_msgpack_chunk<T: DeserializeOwned>(
+    data: &[u8],
+    truncated: bool,
+    emit: &mut RecordFn<T>,
+) -> io::Result<usize> {
+    let mut corrupt = 0;
+    let mut r = data;
+    while !r.is_empty() {
+        let record = r;
+        let v: T = match read_msgpack(&mut r) {
+            Ok(v) => v,
+            Err(MsgpackError::InvalidMarkerRead(ref ioe))
+            | Err(MsgpackError::InvalidDataRead(ref ioe))
+                if ioe.kind() == io::ErrorKind::UnexpectedEof =>
+            {
+                break;
+            }
+            Err(_) if truncated => break,
+            Err(_) => return Ok(corrupt + 1),
+        };
+        let body = &record[..record.len() - r.len()];
+        if r.first() == Some(&MSGPACK_U32) {
+            let Some(crc) = r.get(1..5) else {
+                break;
+            };
+            r = &r[5..];
+            if crc32fast::hash(body).to_be_bytes() != crc {
+                corrupt += 1;
+                emit(None)?;
                 continue;
             }
-            match serde_json::from_str::<LogEntry>(&line) {
-                Ok(e) => entries.push(e),
-                Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        }
+        emit(Some((v, body)))?;
+    }
+    Ok(corrupt)
+}
+
+/// A record as read from a log.
+enum Record {
+    /// An entry that stands on its own, decoded straight from its bytes.
+    Entry(Box<LogEntry>),
+    /// A delta entry or one written before `version` existed, which is
+    /// completed from the entries before it.
+    Value(Value),
+}
+
+/// How the keys of `"delta": true` and of `version` are spelled in a JSON
+/// and in a MessagePack record.
+const JSON_MARKERS: [&[u8]; 2] = [b"\"delta\":true", b"\"version\":"];
+const MSGPACK_MARKERS: [&[u8]; 2] = [b"\xa5delta\xc3", b"\xa7version"];
+
+/// Whether the record in `body` may depend on the entries before it, as a
+/// delta entry or one without a version does. Strings that happen to hold
+/// the markers only send an entry down the slower path.
+fn needs_previous(body: &[u8], [delta, version]: [&[u8]; 2]) -> bool {
+    let has = |marker: &[u8]| body.windows(marker.len()).any(|w| w == marker);
+    has(delta) || !has(version)
+}
+
+/// Decodes a MessagePack record, straight into an entry when it stands on
+/// its own.
+fn msgpack_record(body: &[u8]) -> io::Result<Record> {
+    let record = if needs_previous(body, MSGPACK_MARKERS) {
+        rmp_serde::from_slice(body).map(Record::Value)
+    } else {
+        rmp_serde::from_slice(body).map(|e| Record::Entry(Box::new(e)))
+    };
+    record.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Parses the records stored in bytes `start..end` of `path` and passes
+/// them to `emit` as they are read, returning the number of corrupt records
+/// skipped.
+fn read_records(
+    path: &Path,
+    start: u64,
+    end: Option<u64>,
+    emit: &mut dyn FnMut(Record) -> io::Result<()>,
+) -> io::Result<usize> {
+    let (_, ext) = log_encoding(path);
+    let mut strings = Strings::default();
+    let mut corrupt = 0;
+    let frames = read_log_chunks(path, start, end, &mut |chunk| {
+        let (data, truncated) = (chunk.data, chunk.truncated);
+        corrupt += match ext.as_str() {
+            "msgpacks" => read_msgpack_chunk(data, truncated, &mut |r| match r {
+                Some((IgnoredAny, body)) => emit(msgpack_record(body)?),
+                None => Ok(()),
+            })?,
+            "msgpackc" => {
+                if chunk.after_gap {
+                    strings.resync();
+                }
+                read_msgpack_chunk(data, truncated, &mut |p| match p {
+                    Some((p, _)) => emit(Record::Value(strings.decode(p))),
+                    None => {
+                        strings.resync();
+                        Ok(())
+                    }
+                })?
+            }
+            _ => read_json_chunk(path, chunk, emit)?,
+        };
+        Ok(())
+    })?;
+    Ok(corrupt + frames)
+}
+
+/// Parses the JSON lines in one chunk. A partly written last line, which an
+/// unclean exit leaves, is dropped without counting as corrupt.
+fn read_json_chunk(
+    path: &Path,
+    chunk: Chunk,
+    emit: &mut dyn FnMut(Record) -> io::Result<()>,
+) -> io::Result<usize> {
+    let Chunk {
+        data,
+        last,
+        truncated,
+        ..
+    } = chunk;
+    let mut corrupt = 0;
+    let complete = data.ends_with(b"\n");
+    let mut lines = data.split(|b| *b == b'\n').peekable();
+    while let Some(line) = lines.next() {
+        if line.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+        if json_crc_ok(line) == Some(false) {
+            corrupt += 1;
+            continue;
+        }
+        let record = if needs_previous(line, JSON_MARKERS) {
+            serde_json::from_slice(line).map(|mut v: Value| {
+                if let Some(obj) = v.as_object_mut() {
+                    obj.remove("crc32");
+                }
+                Record::Value(v)
+            })
+        } else {
+            serde_json::from_slice(line).map(|e| Record::Entry(Box::new(e)))
+        };
+        match record {
+            Ok(record) => emit(record)?,
+            Err(_) if last && lines.peek().is_none() && (truncated || !complete) => {
+                warn!("{}: skipping incomplete last entry", path.display());
+            }
+            Err(_) => corrupt += 1,
+        }
+    }
+    Ok(corrupt)
+}
+
+fn timestamp_secs(entry: &serde_json::Map<String, Value>) -> Option<f64> {
+    let ts = chrono::DateTime::parse_from_rfc3339(entry.get("timestamp")?.as_str()?).ok()?;
+    Some(ts.timestamp_millis() as f64 / 1000.0)
+}
+
+/// Rewrites an entry written before `version` existed into the current
+/// schema: cumulative `cpu_time_sec` becomes a percentage over the previous
+/// such entry, which `prev` remembers, per-thread stacks of plain strings
+/// become `threads`, and missing memory fields are zeroed.
+fn upgrade_v0(entry: &mut Value, prev: &mut Option<(f64, f64)>) {
+    let Some(obj) = entry.as_object_mut() else {
+        return;
+    };
+    if let Some(cpu_sec) = obj.remove("cpu_time_sec").and_then(|v| v.as_f64()) {
+        let now = timestamp_secs(obj);
+        let percent = match (*prev, now) {
+            (Some((t0, c0)), Some(t1)) if t1 > t0 => (cpu_sec - c0) * 100.0 / (t1 - t0),
+            _ => 0.0,
+        };
+        obj.entry("cpu_time_percent").or_insert(json!(percent));
+        if let Some(t) = now {
+            *prev = Some((t, cpu_sec));
+        }
+    }
+    obj.entry("cpu_time_percent").or_insert(json!(0.0));
+    let memory = obj.entry("memory").or_insert(json!({}));
+    if let Some(m) = memory.as_object_mut() {
+        for key in ["rss_kb", "vsz_kb", "swap_kb"] {
+            m.entry(key).or_insert(json!(0));
+        }
+    }
+    if let Some(Value::Array(stacks)) = obj.remove("stacktrace") {
+        let pid = obj.get("pid").cloned().unwrap_or(json!(0));
+        let threads: Vec<Value> = stacks
+            .iter()
+            .filter_map(Value::as_array)
+            .map(|frames| {
+                let frames: Vec<Value> = frames
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .map(|f| json!({ "func": f.trim() }))
+                    .collect();
+                json!({ "tid": pid, "stacktrace": frames })
+            })
+            .collect();
+        obj.entry("threads").or_insert(json!(threads));
+    }
+    obj.insert("version".into(), json!(LOG_VERSION));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn logs_larger_than_the_read_window_are_read_in_pieces() {
+        let dir = tempdir().unwrap();
+        let count = 3000;
+        for (ext, use_msgpack) in [("jsonl", false), ("msgpacks", true)] {
+            let path = dir.path().join(format!("7.{ext}"));
+            let mut log = Vec::new();
+            for i in 0..count {
+                let e = json!({
+                    "version": LOG_VERSION,
+                    "timestamp": format!("2025-01-01T00:{:02}:{:02}Z", i / 60 % 60, i % 60),
+                    "pid": 7,
+                    "process_name": format!("{:04}{}", i, "x".repeat(4096)),
+                    "cpu_time_percent": 0.0,
+                    "memory": {"rss_kb": 0, "vsz_kb": 0, "swap_kb": 0},
+                });
+                log.extend(encode_entry(&e, use_msgpack).unwrap());
+            }
+            assert!(log.len() > 3 * READ_WINDOW);
+            fs::write(&path, log).unwrap();
+
+            let (entries, corrupt) = read_log_checked(&path, &TimeRange::default()).unwrap();
+            assert_eq!(corrupt, 0);
+            assert_eq!(entries.len(), count);
+            for (i, e) in entries.iter().enumerate() {
+                assert!(e.process_name.starts_with(&format!("{:04}x", i)));
+            }
+        }
+    }
+
+    fn entry(i: usize, name: &str) -> Value {
+        json!({
+            "version": LOG_VERSION,
+            "timestamp": format!("2025-01-01T00:{:02}:{:02}Z", i / 60 % 60, i % 60),
+            "pid": 1000 + i % 37,
+            "process_name": name,
+            "cpu_time_percent": i % 100,
+            "memory": {"rss_kb": 9000 + i * 7, "vsz_kb": 20000 + i * 13, "swap_kb": 0},
+        })
+    }
+
+    #[test]
+    fn delta_records_completed_in_both_encodings() {
+        let dir = tempdir().unwrap();
+        // Holds the MessagePack marker of delta entries, so it is decoded
+        // the slower way too.
+        let name = "\u{a5}delta\u{c3}";
+        let delta = json!({
+            "version": LOG_VERSION,
+            "timestamp": "2025-01-01T00:00:01Z",
+            "pid": 1000,
+            "cpu_time_percent": 5.0,
+            "delta": true,
+        });
+        for (ext, use_msgpack, markers) in [
+            ("jsonl", false, JSON_MARKERS),
+            ("msgpacks", true, MSGPACK_MARKERS),
+        ] {
+            let path = dir.path().join(format!("1000.{ext}"));
+            let format = LogFormat {
+                use_msgpack,
+                ..Default::default()
+            };
+            let mut log = Vec::new();
+            for e in [entry(0, name), delta.clone()] {
+                log.extend(encode_record(&e, &format, &mut Interner::default()).unwrap());
             }
+            let first = encode_entry(&entry(0, name), use_msgpack).unwrap();
+            assert_eq!(needs_previous(&first, markers), use_msgpack);
+            fs::write(&path, log).unwrap();
+
+            let (entries, corrupt) = read_log_checked(&path, &TimeRange::default()).unwrap();
+            assert_eq!(corrupt, 0);
+            assert_eq!(entries.len(), 2);
+            assert_eq!(entries[1].process_name, name);
+            assert_eq!(entries[1].memory.rss_kb, 9000);
+            assert_eq!(entries[1].cpu_time_percent, 5.0);
         }
-        Ok(entries)
+    }
+
+    fn train(name: &str) -> Vec<u8> {
+        let samples: Vec<Vec<u8>> = (0..2000)
+            .map(|i| serde_json::to_vec(&entry(i, name)).unwrap())
+            .collect();
+        zstd::dict::from_samples(&samples, 4096).unwrap()
+    }
+
+    #[test]
+    fn frames_are_read_with_the_dictionary_they_name() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("7.jsonl.zst");
+        let mut log = Vec::new();
+        for name in ["python3", "nginx"] {
+            let dict = train(name);
+            let id = zstd::zstd_safe::get_dict_id_from_dict(&dict).unwrap();
+            fs::write(dir.path().join(dictionary_name(id.get())), &dict).unwrap();
+            let format = LogFormat {
+                compress: true,
+                dictionary: Some(Arc::new(EncoderDictionary::copy(&dict, 3))),
+                ..Default::default()
+            };
+            let data = encode_record(&entry(0, name), &format, &mut Interner::default());
+            log.extend(data.unwrap());
+        }
+        fs::write(&path, log).unwrap();
+
+        let (entries, corrupt) = read_log_checked(&path, &TimeRange::default()).unwrap();
+        assert_eq!(corrupt, 0);
+        let names: Vec<&str> = entries.iter().map(|e| e.process_name.as_str()).collect();
+        assert_eq!(names, ["python3", "nginx"]);
     }
 }
@@ -1,22 +1,95 @@
+mod alert;
+mod compact;
 mod config;
+mod convert;
+mod daemon;
+mod debuginfod;
+mod dict;
 mod dump;
+mod grep;
+mod limiter;
 mod log;
+mod merge;
+mod otlp;
+mod parquet;
+mod php;
 mod procinfo;
+mod prometheus;
+mod prune;
+mod query;
+mod remote;
 mod report;
 mod run;
+mod spawn;
 mod stacktrace;
+mod statsd;
+mod syslog;
+mod systemd;
+mod tail;
+mod terminal;
+mod top;
+mod upload;
+mod watch;
+mod writer;
 
 use crate::config::{Cli, Commands, parse_cli};
+use ::log::LevelFilter;
 use clap::CommandFactory;
 
+/// Diagnostics go to stderr, which is the log file of a daemon. RUST_LOG
+/// applies unless a flag sets the level, and daemons log from info as
+/// their log file is where they are followed.
+fn init_logger(cli: &Cli) {
+    let level = if cli.quiet {
+        Some(LevelFilter::Error)
+    } else {
+        cli.log_level
+    };
+    let mut builder = match level {
+        Some(level) => {
+            let mut builder = env_logger::Builder::new();
+            builder.filter_level(level);
+            builder
+        }
+        None => {
+            let daemon = matches!(&cli.command, Some(Commands::Run(args)) if args.daemon);
+            let default = if daemon { "info" } else { "error" };
+            env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(default))
+        }
+    };
+    builder.init();
+}
+
 fn main() {
-    env_logger::init();
     let cli = parse_cli();
+    init_logger(&cli);
+    let quiet = cli.quiet;
     if let Some(cmd) = cli.command {
         match cmd {
-            Commands::Run(args) => run::run(args),
-            Commands::Dump(args) => dump::dump(&args.path),
+            Commands::Run(args) => {
+                let code = run::run(args, quiet);
+                if code != 0 {
+                    std::process::exit(code);
+                }
+            }
+            Commands::Dump(args) => dump::dump(&args),
             Commands::Report(args) => report::report(&args),
+            Commands::Prune(args) => prune::prune(&args),
+            Commands::Convert(args) => convert::convert(&args),
+            Commands::Merge(args) => merge::merge(&args),
+            Commands::Dict(args) => dict::train(&args),
+            Commands::Top(args) => {
+                let code = top::top(&args);
+                if code != 0 {
+                    std::process::exit(code);
+                }
+            }
+            Commands::Query(args) => query::query(&args),
+            Commands::Tail(args) => tail::tail(&args),
+            Commands::Grep(args) => grep::grep(&args),
+            Commands::Stats(args) => report::stats(&args),
+            Commands::Config(args) => config::config(&args),
+            Commands::InstallService(args) => systemd::install_service(&args),
         }
     } else {
         Cli::command().print_help().unwrap();
@@ -0,0 +1,106 @@
+use log::warn;
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+use crate::config::MergeArgs;
+use crate::convert::write_entries;
+use crate::log::{LogEntry, LogFormat, collect_files, read_log_entries, split_by_process};
+
+/// A process is identified by its date (and hour) directory, the boot it
+/// ran in, its pid and its start time, or the first timestamp logged for it
+/// in logs that do not record that. Copies of the same log collapse while
+/// reused pids and other hosts stay apart.
+type MergeKey = (String, Option<String>, u32, String);
+
+/// Identifies an entry among the copies of one process.
+type EntryKey = (String, u64, u64);
+
+/// Directory of a log below the output root: its date directory, with the
+/// hour directory of hourly rollover when there is one.
+fn log_subdir(path: &Path) -> String {
+    let name = |p: Option<&Path>| {
+        p.and_then(|p| p.file_name())
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+    let parent = path.parent();
+    let dir = name(parent);
+    let is_hour = dir.len() == 2 && dir.bytes().all(|b| b.is_ascii_digit());
+    if is_hour {
+        format!("{}/{}", name(parent.and_then(Path::parent)), dir)
+    } else {
+        dir
+    }
+}
+
+/// Sorts by timestamp and drops entries that appear in more than one copy.
+fn dedup_entries(entries: Vec<LogEntry>) -> Vec<LogEntry> {
+    let mut entries = entries;
+    entries.sort_by_key(|e| e.time_millis());
+    let mut seen: HashSet<EntryKey> = HashSet::new();
+    entries.retain(|e| {
+        seen.insert((
+            e.timestamp.clone(),
+            e.cpu_time_percent.to_bits(),
+            e.memory.rss_kb,
+        ))
+    });
+    entries
+}
+
+pub fn merge(args: &MergeArgs) {
+    let mut logs: BTreeMap<MergeKey, Vec<LogEntry>> = BTreeMap::new();
+    for dir in &args.dirs {
+        let mut files = Vec::new();
+        collect_files(Path::new(dir), &mut files);
+        files.sort();
+        for f in files {
+            let entries = match read_log_entries(&f) {
+                Ok(e) => e,
+                Err(e) => {
+                    warn!("failed to read {}: {}", f.display(), e);
+                    continue;
+                }
+            };
+            let date = log_subdir(&f);
+            // Combined logs hold several pids, which are merged separately.
+            for ((pid, start_time), entries) in split_by_process(entries) {
+                let Some(start) = start_time.or_else(|| {
+                    let first = entries.iter().min_by_key(|e| e.time_millis())?;
+                    Some(first.timestamp.clone())
+                }) else {
+                    continue;
+                };
+                let boot_id = entries.iter().find_map(|e| e.boot_id.clone());
+                logs.entry((date.clone(), boot_id, pid, start))
+                    .or_default()
+                    .extend(entries);
+            }
+        }
+    }
+
+    let format = LogFormat {
+        use_msgpack: args.format != "jsonl",
+        compact: args.format == "msgpackc",
+        compress: args.compress,
+        ..Default::default()
+    };
+    let ext = format.extension();
+    let mut names = HashSet::new();
+    for ((date, _, pid, _), entries) in logs {
+        let dir = Path::new(&args.output).join(&date);
+        // Distinct processes that reused a pid on the same day get a suffix.
+        let mut name = format!("{}.{}", pid, ext);
+        let mut n = 1;
+        while !names.insert(dir.join(&name)) {
+            name = format!("{}-{}.{}", pid, n, ext);
+            n += 1;
+        }
+        let out = dir.join(&name);
+        let entries = dedup_entries(entries);
+        let count = entries.len();
+        if write_entries(&out, entries, &format) {
+            println!("{} ({} entries)", out.display(), count);
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde_json::{Value, json};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::time::Duration;
+
+use crate::log::{LogEntry, frame_name, thread_stack};
+
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Sends samples to an OpenTelemetry collector over OTLP/HTTP with JSON
+/// encoding. Metrics become gauges and each sampled thread stack becomes a
+/// zero-length span whose name is the innermost function.
+pub struct OtlpExporter {
+    endpoint: String,
+    agent: ureq::Agent,
+    cpu: Vec<Value>,
+    rss: Vec<Value>,
+    fds: Vec<Value>,
+    spans: Vec<Value>,
+}
+
+fn attr(key: &str, value: Value) -> Value {
+    json!({ "key": key, "value": value })
+}
+
+fn string_attr(key: &str, value: &str) -> Value {
+    attr(key, json!({ "stringValue": value }))
+}
+
+fn int_attr(key: &str, value: u64) -> Value {
+    // OTLP JSON encodes 64-bit integers as strings.
+    attr(key, json!({ "intValue": value.to_string() }))
+}
+
+/// Derives a hex id of `words` 64-bit words from `seed`.
+fn hash_hex<T: Hash>(seed: T, words: usize) -> String {
+    (0..words)
+        .map(|i| {
+            let mut h = DefaultHasher::new();
+            (&seed, i).hash(&mut h);
+            format!("{:016x}", h.finish())
+        })
+        .collect()
+}
+
+fn gauge(name: &str, unit: &str, points: Vec<Value>) -> Value {
+    json!({ "name": name, "unit": unit, "gauge": { "dataPoints": points } })
+}
+
+fn resource() -> Value {
+    json!({ "attributes": [string_attr("service.name", "fuzmon")] })
+}
+
+impl OtlpExporter {
+    pub fn new(endpoint: &str) -> Self {
+        OtlpExporter {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            agent: ureq::AgentBuilder::new().timeout(EXPORT_TIMEOUT).build(),
+            cpu: Vec::new(),
+            rss: Vec::new(),
+            fds: Vec::new(),
+            spans: Vec::new(),
+        }
+    }
+
+    /// Queues the metrics and stacks of `entry` until the next `flush`.
+    pub fn record(&mut self, entry: &LogEntry, fd_count: usize) {
+        let ts = DateTime::parse_from_rfc3339(&entry.timestamp)
+            .map(|t| t.with_timezone(&Utc))
+            .unwrap_or_else(|_| Utc::now());
+        let nanos = ts.timestamp_nanos_opt().unwrap_or(0).to_string();
+        let attrs = vec![
+            int_attr("process.pid", entry.pid as u64),
+            string_attr("process.executable.name", &entry.process_name),
+        ];
+        let point = |key: &str, value: Value| {
+            let mut p = json!({ "timeUnixNano": nanos, "attributes": attrs });
+            p[key] = value;
+            p
+        };
+        self.cpu
+            .push(point("asDouble", json!(entry.cpu_time_percent)));
+        self.rss
+            .push(point("asInt", json!(entry.memory.rss_kb.to_string())));
+        self.fds.push(point("asInt", json!(fd_count.to_string())));
+
+        for t in &entry.threads {
+            let Some(frames) = thread_stack(t) else {
+                continue;
+            };
+            let stack: Vec<String> = frames.iter().map(frame_name).collect();
+            let mut span_attrs = attrs.clone();
+            span_attrs.push(int_attr("thread.id", t.tid as u64));
+            span_attrs.push(string_attr("code.stacktrace", &stack.join("\n")));
+            let seed = (entry.pid, t.tid, &nanos);
+            self.spans.push(json!({
+                "traceId": hash_hex(seed, 2),
+                "spanId": hash_hex(seed, 1),
+                "name": stack.first().map(String::as_str).unwrap_or("?"),
+                "kind": 1,
+                "startTimeUnixNano": nanos,
+                "endTimeUnixNano": nanos,
+                "attributes": span_attrs,
+            }));
+        }
+    }
+
+    fn post(&self, path: &str, body: &Value) {
+        let url = format!("{}{}", self.endpoint, path);
+        let res = self
+            .agent
+            .post(&url)
+            .set("Content-Type", "application/json")
+            .send_string(&body.to_string());
+        if let Err(e) = res {
+            warn!("OTLP export to {} failed: {}", url, e);
+        }
+    }
+
+    /// Sends everything queued since the previous flush.
+    pub fn flush(&mut self) {
+        if !self.cpu.is_empty() {
+            let metrics = vec![
+                gauge(
+                    "process.cpu.utilization",
+                    "%",
+                    std::mem::take(&mut self.cpu),
+                ),
+                gauge("process.memory.rss", "KiBy", std::mem::take(&mut self.rss)),
+                gauge(
+                    "process.open_file_descriptors",
+                    "{fd}",
+                    std::mem::take(&mut self.fds),
+                ),
+            ];
+            self.post(
+                "/v1/metrics",
+                &json!({ "resourceMetrics": [{
+                    "resource": resource(),
+                    "scopeMetrics": [{ "scope": { "name": "fuzmon" }, "metrics": metrics }],
+                }]}),
+            );
+        }
+        if !self.spans.is_empty() {
+            let spans = std::mem::take(&mut self.spans);
+            self.post(
+                "/v1/traces",
+                &json!({ "resourceSpans": [{
+                    "resource": resource(),
+                    "scopeSpans": [{ "scope": { "name": "fuzmon" }, "spans": spans }],
+                }]}),
+            );
+        }
+    }
+}
@@ -0,0 +1,323 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::log::LogEntry;
+
+const MAGIC: &[u8] = b"PAR1";
+
+// Parquet enums, as numbered in parquet.thrift.
+const INT64: i32 = 2;
+const DOUBLE: i32 = 5;
+const BYTE_ARRAY: i32 = 6;
+const REQUIRED: i32 = 0;
+const UTF8: i32 = 0;
+const TIMESTAMP_MILLIS: i32 = 9;
+const PLAIN: i32 = 0;
+const RLE: i32 = 3;
+const UNCOMPRESSED: i32 = 0;
+const ZSTD: i32 = 6;
+const DATA_PAGE: i32 = 0;
+
+// Types of the thrift compact protocol.
+const T_I32: u8 = 5;
+const T_I64: u8 = 6;
+const T_BINARY: u8 = 8;
+const T_LIST: u8 = 9;
+const T_STRUCT: u8 = 12;
+
+/// Encodes thrift structs with the compact protocol Parquet metadata uses.
+/// Fields must be written in increasing id order.
+#[derive(Default)]
+struct Thrift {
+    buf: Vec<u8>,
+    /// Id of the last field written in the current struct.
+    field: i16,
+    /// `field` of the structs around the current one.
+    outer: Vec<i16>,
+}
+
+fn zigzag(v: i64) -> u64 {
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+impl Thrift {
+    fn varint(&mut self, mut v: u64) {
+        while v >= 0x80 {
+            self.buf.push(v as u8 | 0x80);
+            v >>= 7;
+        }
+        self.buf.push(v as u8);
+    }
+
+    fn header(&mut self, id: i16, kind: u8) {
+        let delta = id - self.field;
+        if (1..=15).contains(&delta) {
+            self.buf.push((delta as u8) << 4 | kind);
+        } else {
+            self.buf.push(kind);
+            self.varint(zigzag(id.into()));
+        }
+        self.field = id;
+    }
+
+    fn i32(&mut self, id: i16, v: i32) {
+        self.header(id, T_I32);
+        self.varint(zigzag(v.into()));
+    }
+
+    fn i64(&mut self, id: i16, v: i64) {
+        self.header(id, T_I64);
+        self.varint(zigzag(v));
+    }
+
+    fn raw_binary(&mut self, v: &[u8]) {
+        self.varint(v.len() as u64);
+        self.buf.extend_from_slice(v);
+    }
+
+    fn binary(&mut self, id: i16, v: &[u8]) {
+        self.header(id, T_BINARY);
+        self.raw_binary(v);
+    }
+
+    fn list(&mut self, id: i16, kind: u8, len: usize) {
+        self.header(id, T_LIST);
+        if len < 15 {
+            self.buf.push((len as u8) << 4 | kind);
+        } else {
+            self.buf.push(0xf0 | kind);
+            self.varint(len as u64);
+        }
+    }
+
+    /// Starts a struct, as a list element or the top-level value.
+    fn begin(&mut self) {
+        self.outer.push(self.field);
+        self.field = 0;
+    }
+
+    fn begin_field(&mut self, id: i16) {
+        self.header(id, T_STRUCT);
+        self.begin();
+    }
+
+    fn end(&mut self) {
+        self.buf.push(0);
+        self.field = self.outer.pop().unwrap_or(0);
+    }
+}
+
+enum Values {
+    Int64(Vec<i64>),
+    Double(Vec<f64>),
+    Text(Vec<String>),
+}
+
+impl Values {
+    fn physical_type(&self) -> i32 {
+        match self {
+            Values::Int64(_) => INT64,
+            Values::Double(_) => DOUBLE,
+            Values::Text(_) => BYTE_ARRAY,
+        }
+    }
+
+    fn plain(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        match self {
+            Values::Int64(vs) => vs.iter().for_each(|v| out.extend(v.to_le_bytes())),
+            Values::Double(vs) => vs.iter().for_each(|v| out.extend(v.to_le_bytes())),
+            Values::Text(vs) => {
+                for v in vs {
+                    out.extend((v.len() as u32).to_le_bytes());
+                    out.extend(v.as_bytes());
+                }
+            }
+        }
+        out
+    }
+}
+
+struct Column {
+    name: &'static str,
+    converted_type: Option<i32>,
+    values: Values,
+}
+
+fn columns(entries: &[LogEntry]) -> Vec<Column> {
+    let int64 = |name, converted_type, f: fn(&LogEntry) -> i64| Column {
+        name,
+        converted_type,
+        values: Values::Int64(entries.iter().map(f).collect()),
+    };
+    vec![
+        int64("timestamp", Some(TIMESTAMP_MILLIS), LogEntry::time_millis),
+        int64("pid", None, |e| e.pid.into()),
+        Column {
+            name: "process_name",
+            converted_type: Some(UTF8),
+            values: Values::Text(entries.iter().map(|e| e.process_name.clone()).collect()),
+        },
+        Column {
+            name: "cpu_time_percent",
+            converted_type: None,
+            values: Values::Double(entries.iter().map(|e| e.cpu_time_percent).collect()),
+        },
+        int64("rss_kb", None, |e| e.memory.rss_kb as i64),
+        int64("vsz_kb", None, |e| e.memory.vsz_kb as i64),
+        int64("swap_kb", None, |e| e.memory.swap_kb as i64),
+    ]
+}
+
+/// Metadata of a column chunk written to the file.
+struct Chunk {
+    offset: u64,
+    uncompressed: usize,
+    compressed: usize,
+}
+
+fn write_chunk(
+    out: &mut impl Write,
+    offset: u64,
+    column: &Column,
+    rows: usize,
+    compress: bool,
+) -> io::Result<Chunk> {
+    let data = column.values.plain();
+    let size = data.len();
+    let page = if compress {
+        zstd::encode_all(&data[..], 0)?
+    } else {
+        data
+    };
+    let mut header = Thrift::default();
+    header.begin();
+    header.i32(1, DATA_PAGE);
+    header.i32(2, size as i32);
+    header.i32(3, page.len() as i32);
+    header.begin_field(5);
+    header.i32(1, rows as i32);
+    header.i32(2, PLAIN);
+    header.i32(3, RLE);
+    header.i32(4, RLE);
+    header.end();
+    header.end();
+    out.write_all(&header.buf)?;
+    out.write_all(&page)?;
+    Ok(Chunk {
+        offset,
+        uncompressed: header.buf.len() + size,
+        compressed: header.buf.len() + page.len(),
+    })
+}
+
+fn file_metadata(columns: &[Column], chunks: &[Chunk], rows: usize, compress: bool) -> Vec<u8> {
+    let mut meta = Thrift::default();
+    meta.begin();
+    meta.i32(1, 1);
+    meta.list(2, T_STRUCT, columns.len() + 1);
+    meta.begin();
+    meta.binary(4, b"schema");
+    meta.i32(5, columns.len() as i32);
+    meta.end();
+    for c in columns {
+        meta.begin();
+        meta.i32(1, c.values.physical_type());
+        meta.i32(3, REQUIRED);
+        meta.binary(4, c.name.as_bytes());
+        if let Some(t) = c.converted_type {
+            meta.i32(6, t);
+        }
+        meta.end();
+    }
+    meta.i64(3, rows as i64);
+    meta.list(4, T_STRUCT, 1);
+    meta.begin();
+    meta.list(1, T_STRUCT, columns.len());
+    for (c, chunk) in columns.iter().zip(chunks) {
+        meta.begin();
+        meta.i64(2, chunk.offset as i64);
+        meta.begin_field(3);
+        meta.i32(1, c.values.physical_type());
+        meta.list(2, T_I32, 1);
+        meta.varint(zigzag(PLAIN.into()));
+        meta.list(3, T_BINARY, 1);
+        meta.raw_binary(c.name.as_bytes());
+        meta.i32(4, if compress { ZSTD } else { UNCOMPRESSED });
+        meta.i64(5, rows as i64);
+        meta.i64(6, chunk.uncompressed as i64);
+        meta.i64(7, chunk.compressed as i64);
+        meta.i64(9, chunk.offset as i64);
+        meta.end();
+        meta.end();
+    }
+    let total: usize = chunks.iter().map(|c| c.uncompressed).sum();
+    meta.i64(2, total as i64);
+    meta.i64(3, rows as i64);
+    meta.end();
+    meta.binary(6, b"fuzmon");
+    meta.end();
+    meta.buf
+}
+
+/// Writes the sampled metrics of `entries` to `output` as a Parquet table
+/// with one row per entry, for dataframe tools. Stacks and the other
+/// nested details of entries are left out. Pages are compressed with zstd
+/// when `compress`.
+pub fn write_parquet(output: &Path, entries: &[LogEntry], compress: bool) -> io::Result<()> {
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let columns = columns(entries);
+    let mut out = BufWriter::new(File::create(output)?);
+    out.write_all(MAGIC)?;
+    let mut offset = MAGIC.len() as u64;
+    let mut chunks = Vec::new();
+    for c in &columns {
+        let chunk = write_chunk(&mut out, offset, c, entries.len(), compress)?;
+        offset += chunk.compressed as u64;
+        chunks.push(chunk);
+    }
+    let meta = file_metadata(&columns, &chunks, entries.len(), compress);
+    out.write_all(&meta)?;
+    out.write_all(&(meta.len() as u32).to_le_bytes())?;
+    out.write_all(MAGIC)?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thrift_compact_encoding() {
+        let mut t = Thrift::default();
+        t.begin();
+        t.i32(1, -1);
+        t.i64(20, 300);
+        t.binary(21, b"ab");
+        t.list(22, T_I32, 1);
+        t.varint(zigzag(2));
+        t.end();
+        assert_eq!(
+            t.buf,
+            [
+                0x15, 0x01, // field 1 i32, zigzag(-1)
+                0x06, 0x28, 0xd8, 0x04, // field 20 i64 with a long id, zigzag(300)
+                0x18, 0x02, b'a', b'b', // field 21 binary
+                0x19, 0x15, 0x04, // field 22 list of one i32
+                0x00,
+            ]
+        );
+    }
+
+    #[test]
+    fn plain_values() {
+        assert_eq!(Values::Int64(vec![1]).plain(), [1, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(
+            Values::Text(vec!["hi".into()]).plain(),
+            [2, 0, 0, 0, b'h', b'i']
+        );
+    }
+}
@@ -0,0 +1,265 @@
+use nix::sys::uio::{RemoteIoVec, process_vm_readv};
+use nix::unistd::Pid;
+use object::{Object, ObjectKind, ObjectSymbol};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io::IoSliceMut;
+use std::sync::{LazyLock, Mutex};
+use std::time::SystemTime;
+
+use crate::log::Frame;
+use crate::stacktrace::read_exe_infos;
+
+const MAX_FRAMES: usize = 256;
+const MAX_STRING_LEN: u64 = 4096;
+const ZEND_USER_FUNCTION: u8 = 2;
+
+/// Offsets into the non-ZTS x86_64 Zend structures used to walk the
+/// currently executing request, in the same spirit as phpspy.
+struct ZendLayout {
+    eg_current_execute_data: u64,
+    ed_opline: u64,
+    ed_func: u64,
+    ed_prev: u64,
+    func_name: u64,
+    func_scope: u64,
+    op_array_filename: u64,
+    class_name: u64,
+    opline_lineno: u64,
+    string_len: u64,
+    string_val: u64,
+}
+
+fn layout_for(version: (u32, u32)) -> Option<ZendLayout> {
+    let op_array_filename = match version {
+        (7, 4) => 136,
+        (8, 0) | (8, 1) => 144,
+        (8, 2) | (8, 3) => 152,
+        _ => return None,
+    };
+    Some(ZendLayout {
+        eg_current_execute_data: 488,
+        ed_opline: 0,
+        ed_func: 24,
+        ed_prev: 48,
+        func_name: 8,
+        func_scope: 16,
+        op_array_filename,
+        class_name: 8,
+        opline_lineno: 24,
+        string_len: 16,
+        string_val: 24,
+    })
+}
+
+fn parse_php_version(data: &[u8]) -> Option<(u32, u32)> {
+    let marker = b"X-Powered-By: PHP/";
+    let pos = data.windows(marker.len()).position(|w| w == marker)?;
+    let rest = &data[pos + marker.len()..];
+    let end = rest
+        .iter()
+        .position(|c| !(c.is_ascii_digit() || *c == b'.'))
+        .unwrap_or(rest.len());
+    let version = std::str::from_utf8(&rest[..end]).ok()?;
+    let mut parts = version.split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    Some((major, minor))
+}
+
+fn is_php_module(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    name.starts_with("php") || name.starts_with("libphp")
+}
+
+struct Remote {
+    pid: Pid,
+}
+
+impl Remote {
+    fn read(&self, addr: u64, buf: &mut [u8]) -> nix::Result<()> {
+        let remote = [RemoteIoVec {
+            base: addr as usize,
+            len: buf.len(),
+        }];
+        let len = buf.len();
+        let n = process_vm_readv(self.pid, &mut [IoSliceMut::new(buf)], &remote)?;
+        if n != len {
+            return Err(nix::errno::Errno::EFAULT);
+        }
+        Ok(())
+    }
+
+    fn u64(&self, addr: u64) -> nix::Result<u64> {
+        let mut buf = [0u8; 8];
+        self.read(addr, &mut buf)?;
+        Ok(u64::from_ne_bytes(buf))
+    }
+
+    fn u32(&self, addr: u64) -> nix::Result<u32> {
+        let mut buf = [0u8; 4];
+        self.read(addr, &mut buf)?;
+        Ok(u32::from_ne_bytes(buf))
+    }
+
+    fn u8(&self, addr: u64) -> nix::Result<u8> {
+        let mut buf = [0u8; 1];
+        self.read(addr, &mut buf)?;
+        Ok(buf[0])
+    }
+
+    fn zend_string(&self, addr: u64, layout: &ZendLayout) -> nix::Result<String> {
+        let len = self.u64(addr + layout.string_len)?.min(MAX_STRING_LEN);
+        let mut buf = vec![0u8; len as usize];
+        self.read(addr + layout.string_val, &mut buf)?;
+        Ok(String::from_utf8_lossy(&buf).into_owned())
+    }
+}
+
+/// What a PHP binary (or libphp) holds for walking its requests.
+#[derive(Clone, Copy)]
+struct PhpBinary {
+    /// Address of `executor_globals` in the file.
+    executor_globals: u64,
+    is_dynamic: bool,
+    version: Option<(u32, u32)>,
+}
+
+/// A binary by its path and modification time, so upgrades are parsed again.
+type BinaryKey = (String, SystemTime);
+
+/// Binaries already parsed, so every php-fpm worker does not read the whole
+/// binary again at each sample. `None` for binaries without
+/// `executor_globals`.
+static BINARIES: LazyLock<Mutex<HashMap<BinaryKey, Option<PhpBinary>>>> =
+    LazyLock::new(Default::default);
+
+fn parse_binary(data: &[u8]) -> Result<Option<PhpBinary>, Box<dyn Error>> {
+    let obj = object::File::parse(data)?;
+    let sym = obj
+        .dynamic_symbols()
+        .chain(obj.symbols())
+        .find(|s| s.name() == Ok("executor_globals"));
+    Ok(sym.map(|sym| PhpBinary {
+        executor_globals: sym.address(),
+        is_dynamic: obj.kind() == ObjectKind::Dynamic,
+        version: parse_php_version(data),
+    }))
+}
+
+fn load_binary(path: &str) -> Result<Option<PhpBinary>, Box<dyn Error>> {
+    let key = (path.to_string(), fs::metadata(path)?.modified()?);
+    if let Some(binary) = BINARIES.lock().unwrap().get(&key) {
+        return Ok(*binary);
+    }
+    let binary = parse_binary(&fs::read(path)?)?;
+    BINARIES.lock().unwrap().insert(key, binary);
+    Ok(binary)
+}
+
+/// Locates `executor_globals` in the PHP binary (or libphp) mapped into `pid`
+/// and returns its runtime address together with the detected PHP version.
+fn find_executor_globals(pid: i32) -> Result<(u64, (u32, u32)), Box<dyn Error>> {
+    for (path, info) in read_exe_infos(pid) {
+        if !is_php_module(&path) {
+            continue;
+        }
+        let Some(binary) = load_binary(&path)? else {
+            continue;
+        };
+        let version = binary
+            .version
+            .ok_or_else(|| format!("unknown PHP version in {}", path))?;
+        let addr = if binary.is_dynamic {
+            binary
+                .executor_globals
+                .wrapping_sub(info.offset)
+                .wrapping_add(info.start)
+        } else {
+            binary.executor_globals
+        };
+        return Ok((addr, version));
+    }
+    Err("executor_globals not found (ZTS builds are not supported)".into())
+}
+
+/// Reads the PHP request stack of `pid` without stopping the process.
+/// Frames are returned innermost first, like the other stack traces.
+pub fn capture_php_stack_trace(pid: i32) -> Result<Vec<Frame>, Box<dyn Error>> {
+    let (eg, version) = find_executor_globals(pid)?;
+    let layout = layout_for(version)
+        .ok_or_else(|| format!("unsupported PHP version {}.{}", version.0, version.1))?;
+    let mem = Remote {
+        pid: Pid::from_raw(pid),
+    };
+    let mut frames = Vec::new();
+    let mut ed = mem.u64(eg + layout.eg_current_execute_data)?;
+    while ed != 0 && frames.len() < MAX_FRAMES {
+        let func = mem.u64(ed + layout.ed_func)?;
+        if func != 0 {
+            let name_ptr = mem.u64(func + layout.func_name)?;
+            let mut name = if name_ptr == 0 {
+                "{main}".to_string()
+            } else {
+                mem.zend_string(name_ptr, &layout)?
+            };
+            let scope = mem.u64(func + layout.func_scope)?;
+            if scope != 0 {
+                let class = mem.zend_string(mem.u64(scope + layout.class_name)?, &layout)?;
+                name = format!("{}::{}", class, name);
+            }
+            let (file, line) = if mem.u8(func)? == ZEND_USER_FUNCTION {
+                let file_ptr = mem.u64(func + layout.op_array_filename)?;
+                let opline = mem.u64(ed + layout.ed_opline)?;
+                let line = if opline == 0 {
+                    None
+                } else {
+                    Some(mem.u32(opline + layout.opline_lineno)? as i32)
+                };
+                (Some(mem.zend_string(file_ptr, &layout)?), line)
+            } else {
+                (None, None)
+            };
+            frames.push(Frame {
+                addr: None,
+                func: Some(name),
+                file,
+                line,
+            });
+        }
+        ed = mem.u64(ed + layout.ed_prev)?;
+    }
+    Ok(frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn php_version_from_binary() {
+        let data = b"\0\0X-Powered-By: PHP/8.2.7\0more";
+        assert_eq!(parse_php_version(data), Some((8, 2)));
+        assert_eq!(parse_php_version(b"no marker here"), None);
+        assert!(layout_for((8, 2)).is_some());
+        assert!(layout_for((5, 6)).is_none());
+    }
+
+    #[test]
+    fn binaries_are_parsed_once() {
+        let exe = std::env::current_exe().unwrap();
+        let path = exe.to_str().unwrap();
+        assert!(load_binary(path).unwrap().is_none());
+        let modified = fs::metadata(path).unwrap().modified().unwrap();
+        let cached = BINARIES.lock().unwrap();
+        assert!(cached.contains_key(&(path.to_string(), modified)));
+    }
+
+    #[test]
+    fn php_module_names() {
+        assert!(is_php_module("/usr/sbin/php-fpm8.2"));
+        assert!(is_php_module("/usr/lib/apache2/modules/libphp.so"));
+        assert!(!is_php_module("/usr/bin/python3"));
+    }
+}
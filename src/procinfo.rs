@@ -1,5 +1,7 @@
 use log::warn;
-use std::collections::HashMap;
+use nix::unistd::{SysconfVar, sysconf};
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
 
 fn compute_cpu_percent(delta_proc: u64, delta_total: u64, num_cpus: usize) -> f32 {
     if delta_total == 0 {
@@ -9,6 +11,9 @@ fn compute_cpu_percent(delta_proc: u64, delta_total: u64, num_cpus: usize) -> f3
 }
 use std::fs;
 use std::os::unix::fs::MetadataExt;
+use std::time::Instant;
+
+use crate::compact::Interner;
 
 #[derive(Default)]
 pub struct ProcState {
@@ -17,6 +22,59 @@ pub struct ProcState {
     pub fds: HashMap<i32, String>,
     pub pending_fd_events: Vec<FdEvent>,
     pub metadata_written: bool,
+    /// Log file and index mark the ids in `written_stacks` refer to.
+    pub stack_log: Option<(String, u64)>,
+    pub written_stacks: HashSet<String>,
+    /// Strings written to the compact log since `stack_log` last changed.
+    pub interner: Interner,
+    /// What readers reconstruct for the last delta encoded entry.
+    pub delta_base: Option<serde_json::Value>,
+    /// utime + stime of each thread at the previous sample.
+    pub thread_times: HashMap<i32, u64>,
+    /// Total CPU time of the system at the previous thread sample.
+    pub thread_total_time: u64,
+    pub last_stack_capture: Option<Instant>,
+    /// RSS in KB when the last stack was captured, or when the process was
+    /// first seen.
+    pub rss_at_last_stack: Option<u64>,
+    /// Whether the previous sample was above the stack trace CPU threshold.
+    pub above_stack_threshold: bool,
+    /// Consecutive samples above the stack trace CPU threshold.
+    pub samples_above_stack_threshold: u32,
+    /// Samples stacks are still due to CPU for after it dropped.
+    pub stack_hold_left: u32,
+    /// Whether CPU calls for stacks at this sample.
+    pub cpu_stack_triggered: bool,
+    /// Consecutive samples seen in uninterruptible sleep.
+    pub d_state_samples: u32,
+    /// Since when the process has been in D state, if alerts need it.
+    pub d_state_since: Option<Instant>,
+    /// Indexes of the alert rules the process matched at the last sample.
+    pub alerting: HashSet<usize>,
+    /// Whether a failed PHP stack trace was already reported.
+    pub php_trace_warned: bool,
+}
+
+impl ProcState {
+    /// Applies hysteresis to the stack trace CPU threshold: stacks become
+    /// due once CPU was `above` it for `trigger` samples in a row, and stay
+    /// due for `hold` samples after it drops.
+    pub fn update_stack_trigger(&mut self, above: bool, trigger: u32, hold: u32) {
+        self.samples_above_stack_threshold = if above {
+            self.samples_above_stack_threshold + 1
+        } else {
+            0
+        };
+        self.cpu_stack_triggered = if self.samples_above_stack_threshold >= trigger.max(1) {
+            self.stack_hold_left = hold;
+            true
+        } else if self.stack_hold_left > 0 {
+            self.stack_hold_left -= 1;
+            true
+        } else {
+            false
+        };
+    }
 }
 
 pub fn pid_uid(pid: u32) -> Option<u32> {
@@ -46,10 +104,10 @@ pub fn read_pids() -> Vec<u32> {
     let mut pids = Vec::new();
     if let Ok(entries) = fs::read_dir("/proc") {
         for entry in entries.flatten() {
-            if let Ok(name) = entry.file_name().into_string() {
-                if let Ok(pid) = name.parse::<u32>() {
-                    pids.push(pid);
-                }
+            if let Ok(name) = entry.file_name().into_string()
+                && let Ok(pid) = name.parse::<u32>()
+            {
+                pids.push(pid);
             }
         }
     } else {
@@ -58,34 +116,176 @@ pub fn read_pids() -> Vec<u32> {
     pids
 }
 
-fn read_proc_stat(pid: u32) -> Option<(u64, u64)> {
-    let data = match fs::read_to_string(format!("/proc/{}/stat", pid)) {
+/// Extracts utime and stime from a stat line. Fields are counted after the
+/// closing paren because thread names may contain spaces.
+fn parse_stat_ticks(data: &str) -> Option<(u64, u64)> {
+    let rest = &data[data.rfind(')')? + 1..];
+    let mut parts = rest.split_whitespace();
+    let utime = parts.nth(11)?.parse::<u64>().ok()?; // field 14
+    let stime = parts.next()?.parse::<u64>().ok()?; // field 15
+    Some((utime, stime))
+}
+
+/// Extracts the one-letter run state (R, S, D, ...) from a stat line.
+fn parse_stat_state(data: &str) -> Option<char> {
+    data[data.rfind(')')? + 1..]
+        .split_whitespace()
+        .next()?
+        .chars()
+        .next()
+}
+
+/// Extracts the parent pid, the field after the run state.
+fn parse_stat_ppid(data: &str) -> Option<u32> {
+    data[data.rfind(')')? + 1..]
+        .split_whitespace()
+        .nth(1)?
+        .parse()
+        .ok()
+}
+
+/// Extracts the start time in clock ticks after boot, field 22.
+fn parse_stat_start_ticks(data: &str) -> Option<u64> {
+    data[data.rfind(')')? + 1..]
+        .split_whitespace()
+        .nth(19)?
+        .parse()
+        .ok()
+}
+
+/// Seconds since the epoch the system booted at, the `btime` of
+/// /proc/stat.
+pub fn boot_time() -> Option<i64> {
+    let data = fs::read_to_string("/proc/stat").ok()?;
+    data.lines()
+        .find_map(|l| l.strip_prefix("btime "))?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// When `pid` started, given the `boot_time`. Unlike the pid, it tells a
+/// process apart from a later one reusing the pid.
+pub fn start_time(pid: u32, boot_time: i64) -> Option<String> {
+    let ticks = parse_stat_start_ticks(&fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?)?;
+    let hz = sysconf(SysconfVar::CLK_TCK).ok()??;
+    let millis = boot_time * 1000 + (ticks as i64 * 1000) / hz;
+    chrono::DateTime::from_timestamp_millis(millis)
+        .map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::Millis, true))
+}
+
+pub fn parent_pid(pid: u32) -> Option<u32> {
+    parse_stat_ppid(&fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?)
+}
+
+pub fn run_state(pid: u32) -> Option<char> {
+    parse_stat_state(&fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?)
+}
+
+fn read_stat_ticks(path: &str) -> Option<(u64, u64)> {
+    let data = match fs::read_to_string(path) {
         Ok(d) => d,
         Err(e) => {
-            warn!("read stat {} failed: {}", pid, e);
+            warn!("read stat {} failed: {}", path, e);
             return None;
         }
     };
-    let parts: Vec<&str> = data.split_whitespace().collect();
-    let utime = parts.get(13)?.parse::<u64>().ok()?; // field 14
-    let stime = parts.get(14)?.parse::<u64>().ok()?; // field 15
-    Some((utime, stime))
+    parse_stat_ticks(&data)
+}
+
+fn read_proc_stat(pid: u32) -> Option<(u64, u64)> {
+    read_stat_ticks(&format!("/proc/{}/stat", pid))
+}
+
+/// Processes started by `pid`, directly or through others.
+pub fn descendants(pid: u32) -> Vec<u32> {
+    let mut found = Vec::new();
+    let mut queue = vec![pid];
+    while let Some(parent) = queue.pop() {
+        for tid in read_tids(parent) {
+            let Ok(children) =
+                fs::read_to_string(format!("/proc/{}/task/{}/children", parent, tid))
+            else {
+                continue;
+            };
+            for child in children.split_whitespace().filter_map(|c| c.parse().ok()) {
+                found.push(child);
+                queue.push(child);
+            }
+        }
+    }
+    found
+}
+
+pub fn read_tids(pid: u32) -> Vec<i32> {
+    let mut tids: Vec<i32> = match fs::read_dir(format!("/proc/{}/task", pid)) {
+        Ok(d) => d
+            .filter_map(|e| e.ok())
+            .filter_map(|e| e.file_name().into_string().ok())
+            .filter_map(|s| s.parse::<i32>().ok())
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    tids.sort_unstable();
+    tids
+}
+
+/// CPU time a thread used since the previous sample.
+pub struct ThreadCpu {
+    pub tid: i32,
+    /// Ticks used; the total ticks for threads seen for the first time.
+    pub ticks: u64,
+    /// Usage in the same unit as `cpu_time_percent` of the process, when
+    /// the thread was seen before.
+    pub percent: Option<f32>,
+}
+
+/// Returns the CPU time each thread used since the previous call, busiest
+/// first.
+pub fn thread_cpu_deltas(pid: u32, state: &mut ProcState) -> Vec<ThreadCpu> {
+    let system = read_total_cpu_time().unwrap_or(0);
+    let delta_total = match state.thread_total_time {
+        0 => 0,
+        prev => system.saturating_sub(prev),
+    };
+    state.thread_total_time = system;
+    let mut times = HashMap::new();
+    let mut deltas = Vec::new();
+    for tid in read_tids(pid) {
+        if let Some((u, s)) = read_stat_ticks(&format!("/proc/{}/task/{}/stat", pid, tid)) {
+            let total = u + s;
+            let prev = state.thread_times.get(&tid).copied();
+            let ticks = total.saturating_sub(prev.unwrap_or(0));
+            let percent = prev
+                .filter(|_| delta_total > 0)
+                .map(|_| compute_cpu_percent(ticks, delta_total, num_cpus::get()));
+            deltas.push(ThreadCpu {
+                tid,
+                ticks,
+                percent,
+            });
+            times.insert(tid, total);
+        }
+    }
+    state.thread_times = times;
+    deltas.sort_by(|a, b| b.ticks.cmp(&a.ticks).then(a.tid.cmp(&b.tid)));
+    deltas
 }
 
 pub fn read_fd_map(pid: u32) -> HashMap<i32, String> {
     let mut map = HashMap::new();
     if let Ok(entries) = fs::read_dir(format!("/proc/{}/fd", pid)) {
         for entry in entries.flatten() {
-            if let Ok(name) = entry.file_name().into_string() {
-                if let Ok(fd) = name.parse::<i32>() {
-                    match fs::read_link(entry.path()) {
-                        Ok(target) => {
-                            if let Some(path) = target.to_str() {
-                                map.insert(fd, path.to_string());
-                            }
+            if let Ok(name) = entry.file_name().into_string()
+                && let Ok(fd) = name.parse::<i32>()
+            {
+                match fs::read_link(entry.path()) {
+                    Ok(target) => {
+                        if let Some(path) = target.to_str() {
+                            map.insert(fd, path.to_string());
                         }
-                        Err(e) => warn!("read_link for {} fd {} failed: {}", pid, fd, e),
                     }
+                    Err(e) => warn!("read_link for {} fd {} failed: {}", pid, fd, e),
                 }
             }
         }
@@ -158,6 +358,13 @@ pub fn process_name(pid: u32) -> Option<String> {
         .map(|s| s.trim().to_string())
 }
 
+/// Name of thread `tid` of `pid`, as set with `pthread_setname_np`.
+pub fn thread_name(pid: u32, tid: i32) -> Option<String> {
+    fs::read_to_string(format!("/proc/{}/task/{}/comm", pid, tid))
+        .ok()
+        .map(|s| s.trim().to_string())
+}
+
 pub fn vsz_kb(pid: u32) -> Option<u64> {
     read_status_value(pid, "VmSize:")
 }
@@ -186,6 +393,34 @@ pub fn environ(pid: u32) -> Option<String> {
     })
 }
 
+/// Masks the values of the `NAME=value` lines whose name matches one of
+/// `patterns`.
+pub fn redact_env(env: &str, patterns: &[Regex]) -> String {
+    env.lines()
+        .map(|line| match line.split_once('=') {
+            Some((name, _)) if patterns.iter().any(|p| p.is_match(name)) => {
+                format!("{}=***", name)
+            }
+            _ => line.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn read_kernel_value(name: &str) -> Option<String> {
+    let value = fs::read_to_string(format!("/proc/sys/kernel/{}", name)).ok()?;
+    Some(value.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+pub fn hostname() -> Option<String> {
+    read_kernel_value("hostname")
+}
+
+/// Random id the kernel generates at every boot.
+pub fn boot_id() -> Option<String> {
+    read_kernel_value("random/boot_id")
+}
+
 fn read_total_cpu_time() -> Option<u64> {
     let data = match fs::read_to_string("/proc/stat") {
         Ok(d) => d,
@@ -248,11 +483,126 @@ pub fn should_suppress(cpu: f32, rss_kb: u64) -> bool {
 
 #[cfg(test)]
 mod tests {
-    use super::compute_cpu_percent;
+    use super::{
+        ProcState, boot_time, compute_cpu_percent, parse_stat_ppid, parse_stat_start_ticks,
+        parse_stat_state, parse_stat_ticks, start_time,
+    };
+    use super::{descendants, parent_pid, redact_env, thread_cpu_deltas, thread_name};
+
+    #[test]
+    fn stat_ticks_with_spaces_in_name() {
+        let line = "42 (C2 Compiler) S 1 42 42 0 -1 4194560 10 0 0 0 7 3 0 0 20 0 1 0";
+        assert_eq!(parse_stat_ticks(line), Some((7, 3)));
+        assert_eq!(parse_stat_state(line), Some('S'));
+        assert_eq!(parse_stat_state("7 (a) b) D 1"), Some('D'));
+        assert_eq!(parse_stat_ppid(line), Some(1));
+        let full = format!("{} 12345 1000", line);
+        assert_eq!(parse_stat_start_ticks(&full), Some(12345));
+    }
+
+    #[test]
+    fn start_time_is_stable() {
+        let boot = boot_time().unwrap();
+        let pid = std::process::id();
+        let first = start_time(pid, boot).unwrap();
+        assert!(chrono::DateTime::parse_from_rfc3339(&first).is_ok());
+        assert_eq!(start_time(pid, boot), Some(first));
+    }
+
+    #[test]
+    fn secrets_masked_in_env() {
+        let patterns = [regex::RegexBuilder::new("token")
+            .case_insensitive(true)
+            .build()
+            .unwrap()];
+        let env = "HOME=/root\nGITHUB_TOKEN=abc=def\nPATH=/bin";
+        assert_eq!(
+            redact_env(env, &patterns),
+            "HOME=/root\nGITHUB_TOKEN=***\nPATH=/bin"
+        );
+    }
+
+    #[test]
+    fn descendants_include_grandchildren() {
+        let mut child = std::process::Command::new("/bin/sh")
+            .args(["-c", "exec 3<&0; cat <&3 & wait"])
+            .stdin(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        let sh = child.id();
+        // The grandchild shows up once the shell forked it.
+        let found = loop {
+            let found = descendants(std::process::id());
+            if found.iter().any(|&p| parent_pid(p) == Some(sh)) {
+                break found;
+            }
+            std::thread::yield_now();
+        };
+        drop(child.stdin.take());
+        let _ = child.wait();
+        assert!(found.contains(&sh), "{:?}", found);
+    }
+
+    #[test]
+    fn stack_trigger_waits_and_holds() {
+        let mut state = ProcState::default();
+        let samples = [true, true, false, true, true, true, false, false, false];
+        let triggered: Vec<bool> = samples
+            .iter()
+            .map(|&above| {
+                state.update_stack_trigger(above, 3, 2);
+                state.cpu_stack_triggered
+            })
+            .collect();
+        assert_eq!(
+            triggered,
+            [false, false, false, false, false, true, true, true, false]
+        );
+        // The defaults trigger on every sample above the threshold.
+        state.update_stack_trigger(true, 1, 0);
+        assert!(state.cpu_stack_triggered);
+        state.update_stack_trigger(false, 1, 0);
+        assert!(!state.cpu_stack_triggered);
+    }
 
     #[test]
     fn busy_two_threads_reports_200_percent() {
         let percent = compute_cpu_percent(2, 2, 2);
         assert!((percent - 200.0).abs() < f32::EPSILON);
     }
+
+    #[test]
+    fn thread_cpu_known_from_second_sample() {
+        let pid = std::process::id();
+        let mut state = ProcState::default();
+        let first = thread_cpu_deltas(pid, &mut state);
+        assert!(!first.is_empty());
+        assert!(first.iter().all(|t| t.percent.is_none()));
+        // Burn some CPU so the system total moves on.
+        let mut x = 0u64;
+        let start = std::time::Instant::now();
+        while start.elapsed() < std::time::Duration::from_millis(30) {
+            x = x.wrapping_mul(31).wrapping_add(1);
+        }
+        std::hint::black_box(x);
+        let second = thread_cpu_deltas(pid, &mut state);
+        let ours = second.iter().find(|t| t.tid as u32 == gettid()).unwrap();
+        assert!(ours.percent.is_some_and(|p| p >= 0.0));
+    }
+
+    #[test]
+    fn native_thread_named_by_comm() {
+        let pid = std::process::id();
+        let name = std::thread::Builder::new()
+            .name("fuzmon-test".into())
+            .spawn(move || thread_name(pid, gettid() as i32))
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(name.as_deref(), Some("fuzmon-test"));
+    }
+
+    fn gettid() -> u32 {
+        nix::unistd::gettid().as_raw() as u32
+    }
 }
@@ -0,0 +1,183 @@
+use log::{info, warn};
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::log::LogEntry;
+
+const GAUGES: [(&str, &str); 4] = [
+    ("fuzmon_cpu_percent", "CPU usage like top"),
+    ("fuzmon_rss_kb", "Resident set size in KiB"),
+    ("fuzmon_fd_count", "Open file descriptors"),
+    ("fuzmon_thread_count", "Number of threads"),
+];
+
+struct Sample {
+    pid: u32,
+    name: String,
+    /// One value per entry of `GAUGES`.
+    values: [f64; 4],
+}
+
+/// Serves the latest per-process gauges in the Prometheus text format.
+/// Samples recorded during one monitor iteration replace the previous page
+/// on `flush`, so exited processes disappear from the output.
+pub struct PrometheusExporter {
+    page: Arc<Mutex<String>>,
+    pending: Vec<Sample>,
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn render(samples: &[Sample]) -> String {
+    let mut out = String::new();
+    for (i, (name, help)) in GAUGES.iter().enumerate() {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} gauge", name);
+        for s in samples {
+            let _ = writeln!(
+                out,
+                "{}{{pid=\"{}\",name=\"{}\"}} {}",
+                name,
+                s.pid,
+                escape_label(&s.name),
+                s.values[i]
+            );
+        }
+    }
+    out
+}
+
+/// How long a scrape may stall before its connection is dropped.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn serve(mut stream: TcpStream, page: &Mutex<String>) {
+    if let Err(e) = stream
+        .set_read_timeout(Some(CLIENT_TIMEOUT))
+        .and_then(|_| stream.set_write_timeout(Some(CLIENT_TIMEOUT)))
+    {
+        warn!("failed to set timeouts on metrics connection: {}", e);
+        return;
+    }
+    // Drain the request headers; every path returns the metrics page.
+    let mut reader = BufReader::new(&stream);
+    let mut line = String::new();
+    while reader.read_line(&mut line).unwrap_or(0) > 0 && line != "\r\n" {
+        line.clear();
+    }
+    let body = page.lock().unwrap().clone();
+    let resp = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(resp.as_bytes());
+}
+
+impl PrometheusExporter {
+    pub fn bind(addr: &str) -> Option<Self> {
+        let listener = match TcpListener::bind(addr) {
+            Ok(l) => l,
+            Err(e) => {
+                warn!("failed to listen on {}: {}", addr, e);
+                return None;
+            }
+        };
+        info!("serving Prometheus metrics on {}", addr);
+        Some(PrometheusExporter::serve_on(listener))
+    }
+
+    fn serve_on(listener: TcpListener) -> Self {
+        let page = Arc::new(Mutex::new(String::new()));
+        let shared = page.clone();
+        thread::spawn(move || {
+            // Each client on its own thread, so a slow one holds up no other.
+            for stream in listener.incoming().flatten() {
+                let page = shared.clone();
+                thread::spawn(move || serve(stream, &page));
+            }
+        });
+        PrometheusExporter {
+            page,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, entry: &LogEntry, fd_count: usize, thread_count: usize) {
+        self.pending.push(Sample {
+            pid: entry.pid,
+            name: entry.process_name.clone(),
+            values: [
+                entry.cpu_time_percent,
+                entry.memory.rss_kb as f64,
+                fd_count as f64,
+                thread_count as f64,
+            ],
+        });
+    }
+
+    pub fn flush(&mut self) {
+        let page = render(&self.pending);
+        self.pending.clear();
+        *self.page.lock().unwrap() = page;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_gauges_with_escaped_labels() {
+        let page = render(&[Sample {
+            pid: 7,
+            name: "a\"b".into(),
+            values: [12.5, 2048.0, 3.0, 2.0],
+        }]);
+        assert!(page.contains("# TYPE fuzmon_rss_kb gauge\n"), "{}", page);
+        assert!(
+            page.contains("fuzmon_cpu_percent{pid=\"7\",name=\"a\\\"b\"} 12.5\n"),
+            "{}",
+            page
+        );
+        assert!(
+            page.contains("fuzmon_thread_count{pid=\"7\",name=\"a\\\"b\"} 2\n"),
+            "{}",
+            page
+        );
+    }
+
+    #[test]
+    fn idle_client_does_not_block_scrapes() {
+        use std::io::Read;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut exporter = PrometheusExporter::serve_on(listener);
+        exporter.pending.push(Sample {
+            pid: 7,
+            name: "a".into(),
+            values: [1.0, 2.0, 3.0, 4.0],
+        });
+        exporter.flush();
+        let _idle = TcpStream::connect(addr).unwrap();
+        let mut scrape = TcpStream::connect(addr).unwrap();
+        scrape.write_all(b"GET /metrics HTTP/1.1\r\n\r\n").unwrap();
+        let mut resp = String::new();
+        scrape.read_to_string(&mut resp).unwrap();
+        assert!(resp.starts_with("HTTP/1.1 200 OK"), "{}", resp);
+        assert!(
+            resp.contains("fuzmon_rss_kb{pid=\"7\",name=\"a\"} 2\n"),
+            "{}",
+            resp
+        );
+    }
+}
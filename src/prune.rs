@@ -0,0 +1,226 @@
+use chrono::{Days, NaiveDate};
+use log::{info, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::{PruneArgs, apply_output_timezone, expand_output_path, load_config};
+use crate::log::{DATE_PLACEHOLDER, output_root};
+
+pub struct DateDir {
+    pub date: NaiveDate,
+    pub path: PathBuf,
+    pub bytes: u64,
+}
+
+fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|e| match e.metadata() {
+            Ok(m) if m.is_dir() => dir_size(&e.path()),
+            Ok(m) => m.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+/// Splits the output path `dir` into the directory the date directories
+/// are created in and the text around the `YYYYMMDD` date in their names.
+fn date_dir_pattern(dir: &str) -> (&str, &str, &str) {
+    let root = output_root(dir);
+    let Some((before, after)) = dir.split_once(DATE_PLACEHOLDER) else {
+        return (root, "", "");
+    };
+    let prefix = &before[before.rfind('/').map_or(0, |i| i + 1)..];
+    let suffix = after.split('/').next().unwrap_or("");
+    (root, prefix, suffix)
+}
+
+/// Lists the date directories `fuzmon run` writes for the output path
+/// `dir`, oldest first. Without `{date}` in `dir`, they are its bare
+/// `YYYYMMDD` subdirectories.
+pub fn date_dirs(dir: &Path) -> Vec<DateDir> {
+    let dir = dir.to_string_lossy();
+    let (root, prefix, suffix) = date_dir_pattern(&dir);
+    let mut dirs: Vec<DateDir> = fs::read_dir(root)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|e| e.path().is_dir())
+                .filter_map(|e| {
+                    let name = e.file_name().to_string_lossy().into_owned();
+                    let date = name.strip_prefix(prefix)?.strip_suffix(suffix)?;
+                    let date = NaiveDate::parse_from_str(date, "%Y%m%d").ok()?;
+                    Some(DateDir {
+                        date,
+                        bytes: dir_size(&e.path()),
+                        path: e.path(),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    dirs.sort_by_key(|d| d.date);
+    dirs
+}
+
+/// Chooses the directories to delete: everything older than `keep_days`,
+/// then the oldest of the rest while the total exceeds `max_bytes`. The
+/// newest directory is always kept since it is usually still being written.
+fn plan(
+    dirs: &[DateDir],
+    today: NaiveDate,
+    keep_days: Option<u64>,
+    max_bytes: Option<u64>,
+) -> Vec<usize> {
+    let mut doomed = Vec::new();
+    let cutoff = keep_days.and_then(|d| today.checked_sub_days(Days::new(d)));
+    let mut total: u64 = dirs.iter().map(|d| d.bytes).sum();
+    for (i, d) in dirs.iter().enumerate() {
+        if i + 1 == dirs.len() {
+            break;
+        }
+        let expired = cutoff.is_some_and(|c| d.date < c);
+        let over = max_bytes.is_some_and(|m| total > m);
+        if expired || over {
+            doomed.push(i);
+            total -= d.bytes;
+        }
+    }
+    doomed
+}
+
+/// Converts a size in GB from the config or command line to bytes.
+pub fn gb_to_bytes(gb: f64) -> u64 {
+    (gb * 1024.0 * 1024.0 * 1024.0) as u64
+}
+
+/// Total size of the date directories of the output path `dir`, after
+/// removing the oldest while they exceed `max_bytes` when `prune` is set.
+/// The newest is kept, so the total can still exceed `max_bytes`.
+pub fn enforce_budget(dir: &Path, max_bytes: u64, today: NaiveDate, prune: bool) -> u64 {
+    let dirs = date_dirs(dir);
+    let mut total: u64 = dirs.iter().map(|d| d.bytes).sum();
+    if !prune || total <= max_bytes {
+        return total;
+    }
+    for i in plan(&dirs, today, None, Some(max_bytes)) {
+        let d = &dirs[i];
+        match fs::remove_dir_all(&d.path) {
+            Ok(()) => {
+                warn!(
+                    "removed {} ({} bytes) to stay within output.max_total_size_gb",
+                    d.path.display(),
+                    d.bytes
+                );
+                total -= d.bytes;
+            }
+            Err(e) => warn!("failed to remove {}: {}", d.path.display(), e),
+        }
+    }
+    total
+}
+
+pub fn prune(args: &PruneArgs) {
+    let config = args.config.as_deref().map(load_config).unwrap_or_default();
+    let timezone = apply_output_timezone(&config.output);
+    let configured = config.output.path;
+    let path = args
+        .output
+        .clone()
+        .or(configured)
+        .unwrap_or_else(|| "/tmp/fuzmon".into());
+    let path = expand_output_path(&path);
+    if args.keep_days.is_none() && args.max_total_gb.is_none() {
+        println!("nothing to do: pass --keep-days or --max-total-gb");
+        return;
+    }
+    let max_bytes = args.max_total_gb.map(gb_to_bytes);
+    let dirs = date_dirs(Path::new(&path));
+    let today = timezone.today();
+    for i in plan(&dirs, today, args.keep_days, max_bytes) {
+        let d = &dirs[i];
+        if args.dry_run {
+            println!("would remove {} ({} bytes)", d.path.display(), d.bytes);
+            continue;
+        }
+        match fs::remove_dir_all(&d.path) {
+            Ok(()) => {
+                println!("removed {} ({} bytes)", d.path.display(), d.bytes);
+                info!("pruned {}", d.path.display());
+            }
+            Err(e) => warn!("failed to remove {}: {}", d.path.display(), e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dir(date: &str, bytes: u64) -> DateDir {
+        DateDir {
+            date: NaiveDate::parse_from_str(date, "%Y%m%d").unwrap(),
+            path: PathBuf::from(date),
+            bytes,
+        }
+    }
+
+    #[test]
+    fn prune_by_age_and_size() {
+        let dirs = vec![
+            dir("20250101", 100),
+            dir("20250105", 100),
+            dir("20250109", 100),
+            dir("20250110", 100),
+        ];
+        let today = NaiveDate::from_ymd_opt(2025, 1, 10).unwrap();
+        assert_eq!(plan(&dirs, today, Some(7), None), vec![0]);
+        assert_eq!(plan(&dirs, today, None, Some(250)), vec![0, 1]);
+        assert_eq!(plan(&dirs, today, Some(7), Some(150)), vec![0, 1, 2]);
+        assert_eq!(plan(&dirs, today, None, Some(0)), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn budget_removes_oldest_directories() {
+        let root = tempfile::tempdir().unwrap();
+        for (date, bytes) in [("20250101", 100), ("20250102", 100), ("20250103", 300)] {
+            let dir = root.path().join(date);
+            fs::create_dir(&dir).unwrap();
+            fs::write(dir.join("1.jsonl"), vec![b'x'; bytes]).unwrap();
+        }
+        let today = NaiveDate::from_ymd_opt(2025, 1, 3).unwrap();
+        assert_eq!(enforce_budget(root.path(), 400, today, false), 500);
+        assert!(root.path().join("20250101").exists());
+        assert_eq!(enforce_budget(root.path(), 400, today, true), 400);
+        assert!(!root.path().join("20250101").exists());
+        assert!(root.path().join("20250102").exists());
+        // The newest directory stays even when it alone is over.
+        assert_eq!(enforce_budget(root.path(), 200, today, true), 300);
+        assert!(root.path().join("20250103").exists());
+    }
+
+    #[test]
+    fn date_dirs_follow_the_output_path() {
+        let root = tempfile::tempdir().unwrap();
+        for name in [
+            "day-20250102-web",
+            "day-20250101-web",
+            "20250103",
+            "day-x-web",
+        ] {
+            fs::create_dir(root.path().join(name)).unwrap();
+        }
+        let names = |dir: &Path| -> Vec<String> {
+            date_dirs(dir)
+                .iter()
+                .map(|d| d.path.file_name().unwrap().to_string_lossy().into_owned())
+                .collect()
+        };
+        let templated = root.path().join("day-{date}-web").join("logs");
+        assert_eq!(names(&templated), ["day-20250101-web", "day-20250102-web"]);
+        assert_eq!(names(root.path()), ["20250103"]);
+    }
+}
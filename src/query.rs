@@ -0,0 +1,165 @@
+use serde_json::Value;
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::config::QueryArgs;
+use crate::log::{LogEntry, log_files, visit_log_range};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+/// A `--where` test of one field of an entry, such as `rss_kb>1000000`.
+#[derive(Clone, Debug)]
+pub struct Condition {
+    field: String,
+    op: Op,
+    value: String,
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Condition, String> {
+        // The first operator in `s` wins, and of `>=` and `>` the longer.
+        const OPS: [(&str, Op); 6] = [
+            ("<=", Op::Le),
+            (">=", Op::Ge),
+            ("==", Op::Eq),
+            ("!=", Op::Ne),
+            ("<", Op::Lt),
+            (">", Op::Gt),
+        ];
+        let (at, token, op) = OPS
+            .iter()
+            .filter_map(|&(token, op)| s.find(token).map(|at| (at, token, op)))
+            .min_by_key(|&(at, token, _)| (at, std::cmp::Reverse(token.len())))
+            .ok_or_else(|| format!("no comparison in {:?}, expected e.g. rss_kb>1000", s))?;
+        let field = s[..at].trim();
+        let value = s[at + token.len()..].trim();
+        if field.is_empty() || value.is_empty() {
+            return Err(format!("{:?} needs a field and a value", s));
+        }
+        Ok(Condition {
+            field: field.to_string(),
+            op,
+            value: value.to_string(),
+        })
+    }
+}
+
+/// Finds `field` in `entry`, as a dotted path such as `memory.rss_kb`, at
+/// the top or else among the memory fields, so `rss_kb` works as well.
+fn lookup<'a>(entry: &'a Value, field: &str) -> Option<&'a Value> {
+    let path = |root: &'a Value| {
+        field
+            .split('.')
+            .try_fold(root, |v, key| v.as_object()?.get(key))
+    };
+    path(entry).or_else(|| path(entry.get("memory")?))
+}
+
+impl Condition {
+    fn matches(&self, entry: &Value) -> bool {
+        let Some(actual) = lookup(entry, &self.field) else {
+            return false;
+        };
+        let ordering = match (actual, self.value.parse::<f64>()) {
+            (Value::Number(n), Ok(want)) => n.as_f64().and_then(|n| n.partial_cmp(&want)),
+            (Value::String(s), _) => Some(s.as_str().cmp(self.value.as_str())),
+            (other, _) => Some(other.to_string().cmp(&self.value)),
+        };
+        let Some(ordering) = ordering else {
+            return false;
+        };
+        match self.op {
+            Op::Lt => ordering.is_lt(),
+            Op::Le => ordering.is_le(),
+            Op::Gt => ordering.is_gt(),
+            Op::Ge => ordering.is_ge(),
+            Op::Eq => ordering.is_eq(),
+            Op::Ne => ordering.is_ne(),
+        }
+    }
+}
+
+/// Prints the entries of the logs under `args.path` that pass all filters
+/// as JSON lines.
+pub fn query(args: &QueryArgs) {
+    let range = args.filter.time_range();
+    let files = log_files(Path::new(&args.path));
+    let mut out = io::stdout().lock();
+    // Set when the reader, such as `head`, went away.
+    let mut closed = false;
+    for f in &files {
+        let read = visit_log_range(f, &range, |e| {
+            if closed || !matches(args, &e) {
+                return;
+            }
+            let Ok(line) = serde_json::to_string(&e) else {
+                return;
+            };
+            closed = writeln!(out, "{}", line).is_err();
+        });
+        if let Err(e) = read {
+            eprintln!("failed to read {}: {}", f.display(), e);
+        }
+        if closed {
+            return;
+        }
+    }
+}
+
+fn matches(args: &QueryArgs, e: &LogEntry) -> bool {
+    if !args.filter.matches(e) {
+        return false;
+    }
+    args.conditions.is_empty()
+        || serde_json::to_value(e).is_ok_and(|v| args.conditions.iter().all(|c| c.matches(&v)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn entry() -> Value {
+        json!({
+            "timestamp": "2025-06-14T10:00:00Z",
+            "pid": 7,
+            "process_name": "server",
+            "memory": {"rss_kb": 2000, "vsz_kb": 5000, "swap_kb": 0},
+        })
+    }
+
+    fn holds(cond: &str) -> bool {
+        cond.parse::<Condition>().unwrap().matches(&entry())
+    }
+
+    #[test]
+    fn conditions_compare_fields() {
+        assert!(holds("rss_kb>1000"));
+        assert!(holds("memory.rss_kb >= 2000"));
+        assert!(!holds("rss_kb<2000"));
+        assert!(holds("pid==7"));
+        assert!(holds("process_name != client"));
+        assert!(holds("timestamp>=2025-06-14T09:00:00Z"));
+        assert!(!holds("missing>0"));
+    }
+
+    #[test]
+    fn conditions_need_an_operator() {
+        assert!("rss_kb".parse::<Condition>().is_err());
+        assert!(">5".parse::<Condition>().is_err());
+        let c: Condition = "vsz_kb<=5000".parse().unwrap();
+        assert_eq!(c.op, Op::Le);
+        assert_eq!(c.field, "vsz_kb");
+    }
+}
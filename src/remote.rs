@@ -0,0 +1,178 @@
+use log::{info, warn};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::log::LogEntry;
+
+const POST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Oldest spooled batches are dropped beyond this many files.
+const MAX_SPOOLED: usize = 1000;
+/// Batches waiting to be posted; more are spooled right away.
+const QUEUE_BATCHES: usize = 16;
+
+/// Streams entries to an HTTP endpoint as zstd compressed JSON lines, one
+/// POST per monitor iteration. The posts are made by a thread of their own,
+/// so a slow endpoint does not hold up sampling. Batches that cannot be
+/// delivered are spooled to disk and resent, oldest first, once the endpoint
+/// is reachable again.
+pub struct RemoteSink {
+    batch: Vec<u8>,
+    spool: Arc<Spool>,
+    queue: Option<SyncSender<Vec<u8>>>,
+    poster: Option<JoinHandle<()>>,
+}
+
+/// Directory of the batches waiting to be resent.
+struct Spool {
+    dir: PathBuf,
+    /// Held while the directory is changed, which both threads do.
+    lock: Mutex<()>,
+}
+
+impl Spool {
+    fn files(&self) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = fs::read_dir(&self.dir)
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .map(|e| e.path())
+                    .filter(|p| p.extension().is_some_and(|e| e == "zst"))
+                    .collect()
+            })
+            .unwrap_or_default();
+        files.sort();
+        files
+    }
+
+    fn save(&self, payload: &[u8]) {
+        let _guard = self.lock.lock().unwrap();
+        if let Err(e) = self.write(payload) {
+            warn!("failed to spool batch to {}: {}", self.dir.display(), e);
+        }
+    }
+
+    fn write(&self, payload: &[u8]) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let mut files = self.files();
+        while files.len() >= MAX_SPOOLED {
+            let oldest = files.remove(0);
+            warn!("spool full, dropping {}", oldest.display());
+            fs::remove_file(oldest)?;
+        }
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        fs::write(self.dir.join(format!("{:020}.jsonl.zst", nanos)), payload)
+    }
+}
+
+/// Posts the batches of `queue` until the sink is dropped.
+fn post_batches(url: String, spool: &Spool, queue: Receiver<Vec<u8>>) {
+    let agent = ureq::AgentBuilder::new().timeout(POST_TIMEOUT).build();
+    let post = |payload: &[u8]| {
+        agent
+            .post(&url)
+            .set("Content-Type", "application/x-ndjson")
+            .set("Content-Encoding", "zstd")
+            .send_bytes(payload)
+            .map(|_| ())
+            .map_err(Box::new)
+    };
+    for payload in queue {
+        // Resends spooled batches, then sends the current one. Stops at
+        // the first failure so batches keep their order.
+        let mut online = true;
+        for file in spool.files() {
+            let Ok(data) = fs::read(&file) else {
+                continue;
+            };
+            if let Err(e) = post(&data) {
+                warn!("POST to {} failed: {}", url, e);
+                online = false;
+                break;
+            }
+            info!("resent spooled batch {}", file.display());
+            let _guard = spool.lock.lock().unwrap();
+            let _ = fs::remove_file(&file);
+        }
+        if online && let Err(e) = post(&payload) {
+            warn!("POST to {} failed: {}", url, e);
+            online = false;
+        }
+        if !online {
+            spool.save(&payload);
+        }
+    }
+}
+
+impl RemoteSink {
+    /// Starts posting to `url`, spooling to the absolute directory `spool`.
+    pub fn new(url: &str, spool: &Path) -> Self {
+        let spool = Arc::new(Spool {
+            dir: spool.to_path_buf(),
+            lock: Mutex::new(()),
+        });
+        let (queue, batches) = mpsc::sync_channel(QUEUE_BATCHES);
+        let url = url.to_string();
+        let shared = spool.clone();
+        let poster = thread::spawn(move || post_batches(url, &shared, batches));
+        RemoteSink {
+            batch: Vec::new(),
+            spool,
+            queue: Some(queue),
+            poster: Some(poster),
+        }
+    }
+
+    pub fn record(&mut self, entry: &LogEntry) {
+        match serde_json::to_vec(entry) {
+            Ok(line) => {
+                self.batch.extend_from_slice(&line);
+                self.batch.push(b'\n');
+            }
+            Err(e) => warn!("failed to serialize entry: {}", e),
+        }
+    }
+
+    /// Hands the entries recorded since the last flush to the posting
+    /// thread, or spools them when it is too far behind.
+    pub fn flush(&mut self) {
+        if self.batch.is_empty() {
+            return;
+        }
+        let payload = match zstd::stream::encode_all(&self.batch[..], 0) {
+            Ok(p) => p,
+            Err(e) => {
+                warn!("failed to compress batch: {}", e);
+                return;
+            }
+        };
+        self.batch.clear();
+        let Some(queue) = &self.queue else {
+            return;
+        };
+        match queue.try_send(payload) {
+            Ok(()) => {}
+            Err(TrySendError::Full(payload) | TrySendError::Disconnected(payload)) => {
+                warn!("remote endpoint is behind, spooling batch");
+                self.spool.save(&payload);
+            }
+        }
+    }
+}
+
+/// Waits for the queued batches to be posted or spooled.
+impl Drop for RemoteSink {
+    fn drop(&mut self) {
+        drop(self.queue.take());
+        if let Some(poster) = self.poster.take() {
+            let _ = poster.join();
+        }
+    }
+}
@@ -1,740 +1,701 @@
 use chrono::{DateTime, Local};
-use html_escape::encode_text;
 use log::warn;
-use plotters::prelude::*;
-use serde_json::json;
-use std::collections::HashMap;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
-
-use crate::config::{ReportArgs, finalize_report_config, load_config};
-use crate::log::{Frame, LogEntry, read_log_entries};
+use std::time::Duration;
+
+use crate::config::{
+    DEFAULT_LEAK_SLOPE_KB_PER_MIN, DEFAULT_REPORT_TOP, DEFAULT_SPIKE_CPU_PERCENT,
+    DEFAULT_SPIKE_MIN_SEC, EntryFilter, ReportArgs, ReportConfig, StatsArgs,
+    finalize_report_config, load_config, redact_env_patterns,
+};
+use crate::log::{
+    LogEntry, ProcessKey, ProcessKeys, TimeRange, is_combined_log, log_files, visit_log_range,
+};
+use crate::watch::LogWatcher;
+use fuzmon::utils::Timezone;
+
+mod cache;
+mod chart;
+mod diff;
+mod gaps;
+mod graph;
+mod index;
+mod issues;
+mod page;
+mod pdf;
+mod profile;
+mod spikes;
+mod summary;
+mod threads;
+mod trace;
+mod tree;
+mod trend;
+
+use cache::{CACHE_VERSION, ReportCache};
+use chart::{ChartZone, chart_spec, charts_html, write_chart_script};
+use diff::report_diff;
+use gaps::GapFinder;
+use graph::{
+    Downsampled, GraphField, GraphStyle, Series, Theme, local_series, series_label, write_multi_svg,
+};
+use index::{GroupBy, possible_leaks, render_index};
+use issues::{ISSUES_PAGE, render_issues};
+use page::write_pages;
+use pdf::write_pdfs;
+use spikes::{CpuSpike, SpikeFinder};
+use summary::{summary_json, write_summary};
+use trace::COMBINED_TRACE;
+use tree::render_family;
+use trend::report_trend;
 
 const CPU_MIN: f64 = 0.1;
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 struct Stats {
     pid: u32,
+    ppid: Option<u32>,
+    /// Process name of the last entry, after any exec.
+    name: String,
     cmd: String,
     env: Option<String>,
+    host: Option<String>,
+    boot_id: Option<String>,
+    /// When the process started, which with the host and boot tells it
+    /// apart from others with the same pid.
+    start_time: Option<String>,
     start: DateTime<Local>,
     end: DateTime<Local>,
     runtime: i64,
     cpu: f64,
     avg_cpu: f64,
     peak_rss: u64,
-    path: String,
+    /// Fitted RSS growth in KB per minute, when RSS grew steadily.
+    rss_growth: Option<f64>,
+    spikes: Vec<CpuSpike>,
+    /// Logs the entries were read from, several when a process was logged
+    /// in the directories of several days.
+    paths: Vec<String>,
+    python_threads: Vec<PythonThreadStats>,
+    /// Native stack captures that failed, counted by error.
+    capture_errors: BTreeMap<String, usize>,
+    /// Periods in which the process was not sampled.
+    gaps: Vec<(i64, i64)>,
+    /// Points of the CPU and RSS graphs of the index, in milliseconds since
+    /// the epoch.
+    cpu_series: Vec<(i64, f64)>,
+    rss_series: Vec<(i64, f64)>,
+    /// Whether these are the entries of fuzmon's own overhead.
+    self_overhead: bool,
 }
 
-fn calc_stats(path: &Path, entries: &[LogEntry]) -> Option<Stats> {
-    if entries.is_empty() {
-        return None;
-    }
-    let mut sorted: Vec<&LogEntry> = entries.iter().collect();
-    sorted.sort_by_key(|e| e.timestamp.clone());
-    let first = sorted[0];
-    let pid = first.pid;
-    let cmd = first.cmdline.clone().unwrap_or_else(|| "(unknown)".into());
-    let env = first.env.clone();
-    let start = chrono::DateTime::parse_from_rfc3339(&first.timestamp)
-        .map(|t| t.with_timezone(&Local))
-        .unwrap();
-    let end = chrono::DateTime::parse_from_rfc3339(&sorted.last().unwrap().timestamp)
-        .map(|t| t.with_timezone(&Local))
-        .unwrap();
-    let runtime = (end - start).num_seconds();
-    let mut cpu = 0.0f64;
-    let mut peak_rss = 0u64;
-    for win in sorted.windows(2) {
-        if let [a, b] = win {
-            let ta = chrono::DateTime::parse_from_rfc3339(&a.timestamp)
-                .map(|t| t.with_timezone(&Local))
-                .unwrap();
-            let tb = chrono::DateTime::parse_from_rfc3339(&b.timestamp)
-                .map(|t| t.with_timezone(&Local))
-                .unwrap();
-            let dt = (tb - ta).num_seconds() as f64;
-            cpu += a.cpu_time_percent * dt / 100.0;
-        }
+impl Stats {
+    fn key(&self) -> ProcessKey {
+        (self.pid, self.start_time.clone())
     }
-    for e in &sorted {
-        peak_rss = peak_rss.max(e.memory.rss_kb);
-    }
-    let avg_cpu = if runtime > 0 {
-        cpu * 100.0 / runtime as f64
-    } else {
-        0.0
-    };
-    Some(Stats {
-        pid,
-        cmd,
-        env,
-        start,
-        end,
-        runtime,
-        cpu,
-        avg_cpu,
-        peak_rss,
-        path: path.display().to_string(),
-    })
 }
 
-fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) {
-    if let Ok(entries) = fs::read_dir(dir) {
-        for entry in entries.flatten() {
-            let p = entry.path();
-            if p.is_dir() {
-                collect_files(&p, files);
-            } else if p.is_file() {
-                files.push(p);
-            }
-        }
-    }
+/// How often a Python thread was seen holding the GIL or running.
+#[derive(Clone, Serialize, Deserialize)]
+struct PythonThreadStats {
+    tid: u32,
+    name: Option<String>,
+    samples: usize,
+    owns_gil: usize,
+    active: usize,
 }
 
-#[derive(Clone, Copy)]
-enum GraphField {
-    Cpu,
-    Rss,
+/// Least samples before a growing RSS counts as sustained.
+const LEAK_MIN_SAMPLES: usize = 5;
+
+/// Share of the sampling intervals in which RSS must not shrink.
+const LEAK_MIN_GROWING: f64 = 0.8;
+
+/// Fits a line to the RSS of the samples as they come in, with the means
+/// and sums of squares updated one sample at a time.
+#[derive(Default)]
+struct RssTrend {
+    /// Time of the first sample, which times are counted from.
+    t0: Option<i64>,
+    samples: usize,
+    /// Intervals in which RSS did not shrink.
+    growing: usize,
+    last_kb: u64,
+    /// Mean time in minutes and mean RSS in KB.
+    mean_t: f64,
+    mean_v: f64,
+    /// Sums of the squared time deviations and of their products with the
+    /// RSS deviations.
+    var: f64,
+    cov: f64,
 }
 
-fn write_svg(entries: &[LogEntry], out: &Path, field: GraphField) -> io::Result<()> {
-    if entries.is_empty() {
-        return Ok(());
-    }
-    let mut sorted: Vec<&LogEntry> = entries.iter().collect();
-    sorted.sort_by_key(|e| e.timestamp.clone());
-    let start = chrono::DateTime::parse_from_rfc3339(&sorted[0].timestamp)
-        .map(|t| t.with_timezone(&Local))
-        .unwrap();
-    let end = chrono::DateTime::parse_from_rfc3339(&sorted.last().unwrap().timestamp)
-        .map(|t| t.with_timezone(&Local))
-        .unwrap();
-
-    let mut max_val = 0.0f64;
-    let mut series = Vec::new();
-    for e in &sorted {
-        let t = chrono::DateTime::parse_from_rfc3339(&e.timestamp)
-            .map(|tt| tt.with_timezone(&Local))
-            .unwrap();
-        let v = match field {
-            GraphField::Cpu => e.cpu_time_percent,
-            GraphField::Rss => e.memory.rss_kb as f64,
-        };
-        max_val = max_val.max(v);
-        series.push((t, v));
-    }
-    if max_val <= 0.0 {
-        max_val = 1.0;
-    }
-
-    let root = SVGBackend::new(out, (600, 300)).into_drawing_area();
-    root.fill(&WHITE)
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-    let (y_desc, caption, scale) = match field {
-        GraphField::Cpu => ("CPU %", "CPU usage (%)", 1.0),
-        GraphField::Rss => {
-            if max_val >= 1024.0 * 1024.0 {
-                ("RSS GB", "Resident set size (GB)", 1024.0 * 1024.0)
-            } else {
-                ("RSS MB", "Resident set size (MB)", 1024.0)
-            }
+impl RssTrend {
+    fn add(&mut self, time: i64, rss_kb: u64) {
+        let t0 = *self.t0.get_or_insert(time);
+        if self.samples > 0 && rss_kb >= self.last_kb {
+            self.growing += 1;
         }
-    };
-    let y_max = (max_val / scale).max(1.0);
-    if matches!(field, GraphField::Cpu) {
-        let mut chart = ChartBuilder::on(&root)
-            .caption(caption, ("sans-serif", 20))
-            .margin(5)
-            .x_label_area_size(40)
-            .y_label_area_size(40)
-            .build_cartesian_2d(start..end, (CPU_MIN..y_max).log_scale())
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-        chart
-            .configure_mesh()
-            .x_desc("time")
-            .y_desc(y_desc)
-            .x_labels(5)
-            .y_labels(5)
-            .x_label_formatter(&|dt| dt.format("%H:%M:%S").to_string())
-            .draw()
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-        chart
-            .draw_series(LineSeries::new(
-                series.into_iter().map(|(x, v)| {
-                    let val = v / scale;
-                    let val = if val < CPU_MIN { CPU_MIN } else { val };
-                    (x, val)
-                }),
-                &BLUE,
-            ))
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-        root.present()
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
-    } else {
-        let mut chart = ChartBuilder::on(&root)
-            .caption(caption, ("sans-serif", 20))
-            .margin(5)
-            .x_label_area_size(40)
-            .y_label_area_size(40)
-            .build_cartesian_2d(start..end, 0f64..y_max)
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-        chart
-            .configure_mesh()
-            .x_desc("time")
-            .y_desc(y_desc)
-            .x_labels(5)
-            .y_labels(5)
-            .x_label_formatter(&|dt| dt.format("%H:%M:%S").to_string())
-            .draw()
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-        chart
-            .draw_series(LineSeries::new(
-                series.into_iter().map(|(x, v)| (x, v / scale)),
-                &BLUE,
-            ))
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-        root.present()
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+        self.last_kb = rss_kb;
+        self.samples += 1;
+        let n = self.samples as f64;
+        let t = (time - t0) as f64 / 60_000.0;
+        let dt = t - self.mean_t;
+        self.mean_t += dt / n;
+        self.mean_v += (rss_kb as f64 - self.mean_v) / n;
+        self.var += dt * (t - self.mean_t);
+        self.cov += dt * (rss_kb as f64 - self.mean_v);
+    }
+
+    /// The slope of the fitted line in KB per minute, if RSS rose overall
+    /// and hardly ever went down.
+    fn slope(&self) -> Option<f64> {
+        if self.samples < LEAK_MIN_SAMPLES
+            || (self.growing as f64) < LEAK_MIN_GROWING * (self.samples - 1) as f64
+            || self.var <= 0.0
+        {
+            return None;
+        }
+        Some(self.cov / self.var).filter(|slope| *slope > 0.0)
     }
 }
 
-fn collect_series(
-    entries: &[LogEntry],
-    field: GraphField,
-) -> (
-    Vec<(DateTime<Local>, f64)>,
-    DateTime<Local>,
-    DateTime<Local>,
-) {
-    if entries.is_empty() {
-        let now = Local::now();
-        return (Vec::new(), now, now);
-    }
-    let mut sorted: Vec<&LogEntry> = entries.iter().collect();
-    sorted.sort_by_key(|e| e.timestamp.clone());
-    let start = chrono::DateTime::parse_from_rfc3339(&sorted[0].timestamp)
-        .map(|t| t.with_timezone(&Local))
-        .unwrap();
-    let end = chrono::DateTime::parse_from_rfc3339(&sorted.last().unwrap().timestamp)
-        .map(|t| t.with_timezone(&Local))
-        .unwrap();
-    let mut series = Vec::new();
-    for e in &sorted {
-        let t = chrono::DateTime::parse_from_rfc3339(&e.timestamp)
-            .map(|tt| tt.with_timezone(&Local))
-            .unwrap();
-        let v = match field {
-            GraphField::Cpu => e.cpu_time_percent,
-            GraphField::Rss => e.memory.rss_kb as f64,
-        };
-        series.push((t, v));
+/// Accumulates the stats of one process from its entries as they are read
+/// in time order, without keeping them. Fields taken from the first or last
+/// entry remember the time they were seen at.
+#[derive(Default)]
+struct StatsBuilder {
+    pid: u32,
+    /// Time and CPU usage of the previous entry, which lasted until this one.
+    prev: Option<(i64, f64)>,
+    /// CPU seconds.
+    cpu: f64,
+    peak_rss: u64,
+    rss_trend: RssTrend,
+    spikes: SpikeFinder,
+    gaps: GapFinder,
+    cpu_series: Downsampled<f64>,
+    rss_series: Downsampled<f64>,
+    /// Timestamp, command line and environment of the first entry.
+    first: Option<(i64, String, Option<String>, Option<String>)>,
+    /// Timestamp and process name of the last entry.
+    last: Option<(i64, String, String)>,
+    host: Option<(i64, String)>,
+    boot_id: Option<(i64, String)>,
+    start_time: Option<(i64, String)>,
+    ppid: Option<(i64, u32)>,
+    /// With the time the thread name was last seen at.
+    python_threads: HashMap<u32, (PythonThreadStats, i64)>,
+    capture_errors: BTreeMap<String, usize>,
+    self_overhead: bool,
+}
+
+/// Keeps `value` seen at `time` if it is earlier than the one in `slot`.
+fn keep_first<T>(slot: &mut Option<(i64, T)>, time: i64, value: Option<T>) {
+    if let Some(v) = value
+        && slot.as_ref().is_none_or(|(t, _)| time < *t)
+    {
+        *slot = Some((time, v));
     }
-    (series, start, end)
 }
 
-fn write_multi_svg(stats: &[Stats], out: &Path, field: GraphField) {
-    let mut data = Vec::new();
-    let mut start_all: Option<DateTime<Local>> = None;
-    let mut end_all: Option<DateTime<Local>> = None;
-    let mut max_val = 0.0f64;
-    for s in stats {
-        if let Ok(entries) = read_log_entries(Path::new(&s.path)) {
-            let (series, start, end) = collect_series(&entries, field);
-            if series.is_empty() {
-                continue;
-            }
-            start_all = Some(start_all.map_or(start, |cur| cur.min(start)));
-            end_all = Some(end_all.map_or(end, |cur| cur.max(end)));
-            for &(_, v) in &series {
-                max_val = max_val.max(v);
-            }
-            let token = s.cmd.split_whitespace().next().unwrap_or("");
-            let base = Path::new(token)
-                .file_name()
-                .map(|b| b.to_string_lossy().into_owned())
-                .unwrap_or_else(|| token.to_string());
-            let label = format!("{} {}", s.pid, base);
-            data.push((label, series));
+impl StatsBuilder {
+    fn new(cfg: &ReportConfig) -> StatsBuilder {
+        StatsBuilder {
+            spikes: SpikeFinder::new(
+                cfg.spike_cpu_percent.unwrap_or(DEFAULT_SPIKE_CPU_PERCENT),
+                cfg.spike_min_sec.unwrap_or(DEFAULT_SPIKE_MIN_SEC) as i64 * 1000,
+            ),
+            ..Default::default()
         }
     }
-    if data.is_empty() {
-        return;
-    }
-    if max_val <= 0.0 {
-        max_val = 1.0;
-    }
-    let start = match start_all {
-        Some(s) => s,
-        None => return,
-    };
-    let end = end_all.unwrap_or(start + chrono::Duration::seconds(1));
-    let root = SVGBackend::new(out, (600, 300)).into_drawing_area();
-    if root.fill(&WHITE).is_err() {
-        return;
-    }
-    let (y_desc, caption, scale) = match field {
-        GraphField::Cpu => ("CPU %", "Top CPU usage", 1.0),
-        GraphField::Rss => {
-            if max_val >= 1024.0 * 1024.0 {
-                ("RSS GB", "Top RSS (GB)", 1024.0 * 1024.0)
-            } else {
-                ("RSS MB", "Top RSS (MB)", 1024.0)
-            }
-        }
-    };
-    let y_max = (max_val / scale).max(1.0);
-    if matches!(field, GraphField::Cpu) {
-        let mut chart = match ChartBuilder::on(&root)
-            .caption(caption, ("sans-serif", 20))
-            .margin(5)
-            .x_label_area_size(40)
-            .y_label_area_size(40)
-            .build_cartesian_2d(start..end, (CPU_MIN..y_max).log_scale())
-        {
-            Ok(c) => c,
-            Err(_) => return,
-        };
-        if chart
-            .configure_mesh()
-            .x_desc("time")
-            .y_desc(y_desc)
-            .x_labels(5)
-            .y_labels(5)
-            .x_label_formatter(&|dt| dt.format("%H:%M:%S").to_string())
-            .draw()
-            .is_err()
-        {
-            return;
-        }
-        for (i, (label, series)) in data.into_iter().enumerate() {
-            let color = Palette99::pick(i).mix(0.9);
-            if chart
-                .draw_series(LineSeries::new(
-                    series.into_iter().map(|(x, v)| {
-                        let val = v / scale;
-                        let val = if val < CPU_MIN { CPU_MIN } else { val };
-                        (x, val)
-                    }),
-                    &color,
-                ))
-                .map(|l| {
-                    l.label(label).legend(move |(x, y)| {
-                        PathElement::new(vec![(x, y), (x + 20, y)], color.clone())
-                    })
-                })
-                .is_err()
-            {
-                return;
-            }
-        }
-        let _ = chart.configure_series_labels().border_style(&BLACK).draw();
-        let _ = root.present();
-    } else {
-        let mut chart = match ChartBuilder::on(&root)
-            .caption(caption, ("sans-serif", 20))
-            .margin(5)
-            .x_label_area_size(40)
-            .y_label_area_size(40)
-            .build_cartesian_2d(start..end, 0f64..y_max)
-        {
-            Ok(c) => c,
-            Err(_) => return,
-        };
-        if chart
-            .configure_mesh()
-            .x_desc("time")
-            .y_desc(y_desc)
-            .x_labels(5)
-            .y_labels(5)
-            .x_label_formatter(&|dt| dt.format("%H:%M:%S").to_string())
-            .draw()
-            .is_err()
+
+    fn add(&mut self, e: &LogEntry) {
+        let time = e.time_millis();
+        let (cpu, rss) = (e.cpu_time_percent, e.memory.rss_kb);
+        self.pid = e.pid;
+        // An entry from before the previous one, as after the clock was
+        // set back, adds no interval.
+        if let Some((prev, prev_cpu)) = self.prev
+            && time >= prev
         {
-            return;
+            self.cpu += prev_cpu * (time - prev) as f64 / 100_000.0;
+            self.gaps.add(prev, time);
         }
-        for (i, (label, series)) in data.into_iter().enumerate() {
-            let color = Palette99::pick(i).mix(0.9);
-            if chart
-                .draw_series(LineSeries::new(
-                    series.into_iter().map(|(x, v)| (x, v / scale)),
-                    &color,
-                ))
-                .map(|l| {
-                    l.label(label).legend(move |(x, y)| {
-                        PathElement::new(vec![(x, y), (x + 20, y)], color.clone())
-                    })
-                })
-                .is_err()
-            {
-                return;
-            }
+        self.prev = Some((time, cpu));
+        self.peak_rss = self.peak_rss.max(rss);
+        self.rss_trend.add(time, rss);
+        self.spikes.add(time, cpu);
+        self.cpu_series.push(time, cpu);
+        self.rss_series.push(time, rss as f64);
+        if self.first.as_ref().is_none_or(|f| time < f.0) {
+            self.first = Some((time, e.timestamp.clone(), e.cmdline.clone(), e.env.clone()));
         }
-        let _ = chart.configure_series_labels().border_style(&BLACK).draw();
-        let _ = root.present();
-    }
-}
-
-fn write_chrome_trace(entries: &[LogEntry], out: &Path) -> io::Result<()> {
-    if entries.is_empty() {
-        return Ok(());
-    }
-    let mut sorted: Vec<&LogEntry> = entries.iter().collect();
-    sorted.sort_by_key(|e| e.timestamp.clone());
-    let mut events = Vec::new();
-    use std::collections::HashMap;
-    let mut active: HashMap<(u32, usize), (String, serde_json::Value, i64, u32)> = HashMap::new();
-
-    fn handle_frames(
-        tid: u32,
-        frames: &[&Frame],
-        pid: u32,
-        ts: i64,
-        active: &mut HashMap<(u32, usize), (String, serde_json::Value, i64, u32)>,
-        events: &mut Vec<serde_json::Value>,
-    ) {
-        if frames.is_empty() {
-            return;
+        if self.last.as_ref().is_none_or(|l| time >= l.0) {
+            self.last = Some((time, e.timestamp.clone(), e.process_name.clone()));
         }
-
-        // handle existing events beyond current depth
-        let mut depth = frames.len();
-        loop {
-            let key = (tid, depth);
-            if let Some((name, args, start, pid_saved)) = active.remove(&key) {
-                let dur = ts - start;
-                events.push(json!({
-                    "name": name,
-                    "ph": "X",
-                    "pid": pid_saved,
-                    "tid": tid,
-                    "ts": start,
-                    "dur": if dur <= 0 { 1 } else { dur },
-                    "args": args,
-                }));
-                depth += 1;
-            } else {
-                break;
+        keep_first(&mut self.host, time, e.hostname.clone());
+        keep_first(&mut self.boot_id, time, e.boot_id.clone());
+        keep_first(&mut self.start_time, time, e.start_time.clone());
+        keep_first(&mut self.ppid, time, e.ppid);
+        self.self_overhead |= e.self_overhead == Some(true);
+        for t in &e.threads {
+            if let Some(err) = &t.capture_error {
+                *self.capture_errors.entry(err.clone()).or_default() += 1;
             }
-        }
-
-        for (idx, frame) in frames.iter().enumerate() {
-            let name = if let Some(f) = &frame.func {
-                f.clone()
-            } else if let Some(a) = frame.addr {
-                format!("{:#x}", a)
-            } else {
-                "?".to_string()
+            let Some(state) = &t.python_thread else {
+                continue;
             };
-            let args = json!({
-                "addr": frame.addr,
-                "file": frame.file,
-                "line": frame.line,
+            let (s, named_at) = self.python_threads.entry(t.tid).or_insert_with(|| {
+                let s = PythonThreadStats {
+                    tid: t.tid,
+                    name: None,
+                    samples: 0,
+                    owns_gil: 0,
+                    active: 0,
+                };
+                (s, i64::MIN)
             });
-            let key = (tid, idx);
-            match active.get_mut(&key) {
-                Some((cur, cur_args, _start, _pid)) if cur == &name => {
-                    *cur_args = args;
-                }
-                Some((cur, cur_args, start, pid_saved)) => {
-                    let dur = ts - *start;
-                    events.push(json!({
-                        "name": cur,
-                        "ph": "X",
-                        "pid": *pid_saved,
-                        "tid": tid,
-                        "ts": *start,
-                        "dur": if dur <= 0 { 1 } else { dur },
-                        "args": cur_args.clone(),
-                    }));
-                    *cur = name;
-                    *cur_args = args;
-                    *start = ts;
-                    *pid_saved = pid;
-                }
-                None => {
-                    active.insert(key, (name, args, ts, pid));
-                }
+            if state.name.is_some() && time >= *named_at {
+                s.name = state.name.clone();
+                *named_at = time;
             }
+            s.samples += 1;
+            s.owns_gil += state.owns_gil as usize;
+            s.active += state.active as usize;
         }
     }
 
-    for (i, e) in sorted.iter().enumerate() {
-        if e.threads.is_empty() {
-            continue;
-        }
-        let dt = chrono::DateTime::parse_from_rfc3339(&e.timestamp)
+    fn finish(self, paths: Vec<String>) -> Option<Stats> {
+        let (_, first_ts, cmd, env) = self.first?;
+        let (_, last_ts, name) = self.last?;
+        let start = chrono::DateTime::parse_from_rfc3339(&first_ts)
             .map(|t| t.with_timezone(&Local))
-            .map_err(|er| io::Error::new(io::ErrorKind::InvalidData, er))?;
-        let ts = dt.timestamp_micros();
-
-        for t in &e.threads {
-            if let Some(st) = &t.stacktrace {
-                let frames: Vec<&Frame> = st.iter().collect();
-                handle_frames(t.tid << 1, &frames, e.pid, ts, &mut active, &mut events);
-            }
-            if let Some(py) = &t.python_stacktrace {
-                let frames: Vec<&Frame> = py.iter().collect();
-                handle_frames(
-                    (t.tid << 1) | 1,
-                    &frames,
-                    e.pid,
-                    ts,
-                    &mut active,
-                    &mut events,
-                );
-            }
-        }
+            .unwrap();
+        let end = chrono::DateTime::parse_from_rfc3339(&last_ts)
+            .map(|t| t.with_timezone(&Local))
+            .unwrap();
+        let runtime_ms = (end - start).num_milliseconds();
+        let runtime = runtime_ms / 1000;
+        let cpu = self.cpu;
+        let avg_cpu = if runtime_ms > 0 {
+            cpu * 100_000.0 / runtime_ms as f64
+        } else {
+            0.0
+        };
+        let mut python_threads: Vec<PythonThreadStats> =
+            self.python_threads.into_values().map(|(s, _)| s).collect();
+        python_threads.sort_by_key(|s| s.tid);
+        Some(Stats {
+            pid: self.pid,
+            ppid: self.ppid.map(|(_, p)| p),
+            name,
+            cmd: cmd.unwrap_or_else(|| "(unknown)".into()),
+            env,
+            host: self.host.map(|(_, h)| h),
+            boot_id: self.boot_id.map(|(_, b)| b),
+            start_time: self.start_time.map(|(_, t)| t),
+            start,
+            end,
+            runtime,
+            cpu,
+            avg_cpu,
+            peak_rss: self.peak_rss,
+            rss_growth: self.rss_trend.slope(),
+            spikes: self.spikes.finish(),
+            paths,
+            python_threads,
+            capture_errors: self.capture_errors,
+            gaps: self.gaps.finish(),
+            cpu_series: self.cpu_series.points,
+            rss_series: self.rss_series.points,
+            self_overhead: self.self_overhead,
+        })
+    }
+}
 
-        if i == sorted.len() - 1 {
-            let final_ts = ts;
-            for ((tid, _idx), (name, args, start, pid)) in active.drain() {
-                let dur = final_ts - start;
-                events.push(json!({
-                    "name": name,
-                    "ph": "X",
-                    "pid": pid,
-                    "tid": tid,
-                    "ts": start,
-                    "dur": if dur <= 0 { 1 } else { dur },
-                    "args": args,
-                }));
+/// Calls `f` with the entries of the process `s` in each of its paths.
+/// Combined logs also hold the entries of other processes, which are
+/// dropped.
+fn visit_process(s: &Stats, range: &TimeRange, mut f: impl FnMut(LogEntry)) -> io::Result<()> {
+    let key = s.key();
+    for path in &s.paths {
+        let mut keys = ProcessKeys::default();
+        visit_log_range(Path::new(path), range, |e| {
+            if keys.key(&e) == key {
+                f(e);
             }
-        }
-    }
-    if events.is_empty() {
-        return Ok(());
+        })?;
     }
-    let obj = json!({ "traceEvents": events });
-    fs::write(out, serde_json::to_vec(&obj)?)
+    Ok(())
 }
 
-fn write_graphs(entries: &[LogEntry], out_dir: &Path, pid: u32) {
-    let cpu_path = out_dir.join(format!("{}_cpu.svg", pid));
-    if let Err(e) = write_svg(entries, &cpu_path, GraphField::Cpu) {
-        warn!("failed to write {}: {}", cpu_path.display(), e);
+fn report_file(
+    path: &Path,
+    out_dir: &Path,
+    cfg: &ReportConfig,
+    range: &TimeRange,
+    opts: &ReportOptions,
+) {
+    let (stats, issues) = file_stats(path, cfg, range, &opts.filter);
+    if issues.error.is_some() {
+        return;
     }
-    let rss_path = out_dir.join(format!("{}_rss.svg", pid));
-    if let Err(e) = write_svg(entries, &rss_path, GraphField::Rss) {
-        warn!("failed to write {}: {}", rss_path.display(), e);
+    let stats = stats.into_iter().find(|s| reported(&opts.filter, s));
+    let leak_slope = cfg
+        .leak_slope_kb_per_min
+        .unwrap_or(DEFAULT_LEAK_SLOPE_KB_PER_MIN);
+    write_summary(out_dir, stats.as_slice(), leak_slope, opts.summary_md);
+    let index = out_dir.join("index.html");
+    if let Some(s) = stats {
+        let family = render_family(&s, std::slice::from_ref(&s), &HashSet::new());
+        write_pages(&[(&s, index.clone(), family)], out_dir, range, opts, None);
+        if let Some(browser) = &opts.pdf_browser {
+            write_pdfs(&[index], browser);
+        }
+    } else if let Err(e) = fs::write(&index, "<p>No entries</p>") {
+        warn!("failed to write {}: {}", index.display(), e);
     }
 }
 
-fn write_trace(entries: &[LogEntry], out_dir: &Path, pid: u32) -> bool {
-    let path = out_dir.join(format!("{}_trace.json", pid));
-    if let Err(e) = write_chrome_trace(entries, &path) {
-        warn!("failed to write {}: {}", path.display(), e);
-        return false;
-    }
-    path.exists()
+/// Orders processes by average CPU usage, most first, treating usage up to
+/// `CPU_MIN` as idle so that idle processes are ordered by peak RSS.
+fn busiest_first(a: &Stats, b: &Stats) -> std::cmp::Ordering {
+    let cpu = |s: &Stats| if s.avg_cpu <= CPU_MIN { 0.0 } else { s.avg_cpu };
+    cpu(b)
+        .total_cmp(&cpu(a))
+        .then_with(|| b.peak_rss.cmp(&a.peak_rss))
 }
 
-fn truncate(s: &str, len: usize) -> String {
-    let mut out = String::new();
-    for (i, c) in s.chars().enumerate() {
-        if i >= len {
-            out.push_str("...");
-            break;
-        }
-        out.push(c);
-    }
-    out
+/// Whether `filter` keeps the process of `s` in a report.
+fn reported(filter: &EntryFilter, s: &Stats) -> bool {
+    filter.matches_pid(s.pid) && filter.matches_name(&s.name)
 }
 
-fn render_single(s: &Stats, has_trace: bool) -> String {
-    let mut out = String::new();
-    out.push_str("<html><body>\n");
-    out.push_str(&format!("<h1>Report for PID {}</h1>\n", s.pid));
-    out.push_str(&format!("<p>Command: {}</p>\n", encode_text(&s.cmd)));
-    out.push_str("<ul>\n");
-    out.push_str(&format!("<li>Total runtime: {} sec</li>\n", s.runtime));
-    out.push_str(&format!("<li>Total CPU time: {:.1} sec</li>\n", s.cpu));
-    out.push_str(&format!("<li>Average CPU usage: {:.1}%</li>\n", s.avg_cpu));
-    out.push_str(&format!("<li>Peak RSS: {} KB</li>\n", s.peak_rss));
-    out.push_str("</ul>\n");
-    if let Some(e) = &s.env {
-        if !e.is_empty() {
-            out.push_str(&format!(
-                "<details><summary>Environment</summary><pre>{}</pre></details>\n",
-                encode_text(e)
-            ));
-        }
-    } else {
-        out.push_str("<p>Environment: unknown</p>\n");
-    }
-    out.push_str(&format!(
-        "<p>CPU usage<br><img src=\"{}_cpu.svg\" alt=\"CPU usage graph\" /></p>\n",
-        s.pid
-    ));
-    out.push_str(&format!(
-        "<p>RSS<br><img src=\"{}_rss.svg\" alt=\"RSS graph\" /></p>\n",
-        s.pid
-    ));
-    if has_trace {
-        out.push_str(&format!(
-            "<p><a href=\"{}_trace.json\">Trace JSON</a></p>\n",
-            s.pid
-        ));
-    }
-    out.push_str("</body></html>\n");
-    out
+/// What to report besides the pages, from the command line.
+struct ReportOptions {
+    speedscope: bool,
+    combined_trace: bool,
+    group_by: Option<GroupBy>,
+    summary_md: bool,
+    chart_zone: ChartZone,
+    filter: EntryFilter,
+    style: GraphStyle,
+    /// Names of the environment variables whose values are masked, also in
+    /// logs captured before they were configured.
+    redact_env: Vec<Regex>,
+    /// Browser printing the pages to PDF, with `--pdf`.
+    pdf_browser: Option<String>,
 }
 
-fn render_index(stats: &[Stats], link: bool) -> String {
-    let mut out = String::new();
-    out.push_str("<html><head><style>table,th,td{border:1px solid black;border-collapse:collapse;}pre{margin:0;}</style></head><body>\n");
-    out.push_str("<p>CPU usage<br><img src=\"top_cpu.svg\" alt=\"Top CPU usage graph\" /></p>\n");
-    out.push_str("<p>Peak RSS<br><img src=\"top_rss.svg\" alt=\"Top RSS graph\" /></p>\n");
-    if let (Some(start), Some(end)) = (
-        stats.iter().map(|s| s.start).min(),
-        stats.iter().map(|s| s.end).max(),
-    ) {
-        out.push_str(&format!("<p>Start: {}</p>\n", start));
-        out.push_str(&format!("<p>End: {}</p>\n", end));
-    }
-    out.push_str("<table>\n");
-    out.push_str(
-        "<tr><th>PID</th><th>Command</th><th>Total runtime</th><th>Total CPU time</th><th>Avg CPU (%)</th><th>Peak RSS</th><th>Start</th><th>End</th></tr>\n",
-    );
-    for s in stats {
-        let pid_cell = if link {
-            format!("<a href=\"{}.html\">{}</a>", s.pid, s.pid)
-        } else {
-            s.pid.to_string()
-        };
-        let summary = truncate(&s.cmd, 30);
-        let cmd_cell = format!(
-            "<details><summary>{}</summary><pre>{}</pre></details>",
-            encode_text(&summary),
-            encode_text(&s.cmd)
-        );
-        out.push_str(&format!(
-            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{:.1}</td><td>{:.1}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
-            pid_cell,
-            cmd_cell,
-            s.runtime,
-            s.cpu,
-            s.avg_cpu,
-            s.peak_rss,
-            s.start,
-            s.end
-        ));
-    }
-    out.push_str("</table></body></html>\n");
-    out
+/// What went wrong reading one log.
+#[derive(Default, Serialize, Deserialize)]
+struct FileIssues {
+    /// Why the log could not be read at all.
+    error: Option<String>,
+    /// Records skipped because they were corrupt.
+    corrupt: usize,
 }
 
-fn report_file(path: &Path, out_dir: &Path) {
-    match read_log_entries(path) {
-        Ok(entries) => {
-            if let Some(s) = calc_stats(path, &entries) {
-                write_graphs(&entries, out_dir, s.pid);
-                let has_trace = write_trace(&entries, out_dir, s.pid);
-                let html = render_single(&s, has_trace);
-                let index = out_dir.join("index.html");
-                if let Err(e) = fs::write(&index, html) {
-                    warn!("failed to write {}: {}", index.display(), e);
-                }
-            } else {
-                let index = out_dir.join("index.html");
-                if let Err(e) = fs::write(&index, "<p>No entries</p>") {
-                    warn!("failed to write {}: {}", index.display(), e);
-                }
-            }
+/// Computes the stats of the processes in the log `f` whose pid passes
+/// `filter`, and what kept any of it from being read.
+fn file_stats(
+    f: &Path,
+    cfg: &ReportConfig,
+    range: &TimeRange,
+    filter: &EntryFilter,
+) -> (Vec<Stats>, FileIssues) {
+    // One pass over the log, keeping none of the entries.
+    let mut builders: BTreeMap<ProcessKey, StatsBuilder> = BTreeMap::new();
+    let mut keys = ProcessKeys::default();
+    let read = visit_log_range(f, range, |e| {
+        let key = keys.key(&e);
+        if filter.matches_pid(e.pid) {
+            builders
+                .entry(key)
+                .or_insert_with(|| StatsBuilder::new(cfg))
+                .add(&e);
+        }
+    });
+    match read {
+        Ok(corrupt) => {
+            let stats = builders
+                .into_values()
+                .filter_map(|b| b.finish(vec![f.display().to_string()]))
+                .collect();
+            let issues = FileIssues {
+                error: None,
+                corrupt,
+            };
+            (stats, issues)
+        }
+        Err(e) => {
+            warn!("failed to read {}: {}", f.display(), e);
+            let issues = FileIssues {
+                error: Some(e.to_string()),
+                corrupt: 0,
+            };
+            (Vec::new(), issues)
         }
-        Err(e) => warn!("failed to read {}: {}", path.display(), e),
     }
 }
 
-fn report_dir(path: &Path, out_dir: &Path, top_cpu: usize, top_rss: usize) {
-    let mut files = Vec::new();
-    collect_files(path, &mut files);
-    let mut stats = Vec::new();
-    for f in files {
-        match read_log_entries(&f) {
-            Ok(entries) => {
-                if let Some(s) = calc_stats(&f, &entries) {
-                    stats.push(s);
-                }
+/// Computes the stats of every process found under `path`, which is a
+/// directory or a log file, that passes `filter`, leaving out fuzmon's own
+/// overhead.
+fn collect_stats(
+    path: &Path,
+    cfg: &ReportConfig,
+    range: &TimeRange,
+    filter: &EntryFilter,
+) -> Vec<Stats> {
+    let stats = log_files(path)
+        .iter()
+        .flat_map(|f| file_stats(f, cfg, range, filter).0)
+        .collect();
+    stitch(stats, cfg, range)
+        .into_iter()
+        .filter(|s| reported(filter, s) && !s.self_overhead)
+        .collect()
+}
+
+/// Joins the stats of each process logged in more than one file, as when
+/// it ran past midnight into the directory of the next day. Processes are
+/// told apart by when they started, so a reused pid stays separate; stats
+/// without a start time are left alone.
+fn stitch(stats: Vec<Stats>, cfg: &ReportConfig, range: &TimeRange) -> Vec<Stats> {
+    let mut groups: Vec<Vec<Stats>> = Vec::new();
+    let mut index: HashMap<_, usize> = HashMap::new();
+    for s in stats {
+        let key = s
+            .start_time
+            .clone()
+            .map(|t| (s.host.clone(), s.boot_id.clone(), s.pid, t));
+        match key.map(|k| index.entry(k)) {
+            Some(Entry::Occupied(i)) => groups[*i.get()].push(s),
+            Some(Entry::Vacant(v)) => {
+                v.insert(groups.len());
+                groups.push(vec![s]);
             }
-            Err(e) => warn!("failed to read {}: {}", f.display(), e),
+            None => groups.push(vec![s]),
         }
     }
+    groups
+        .into_iter()
+        .filter_map(|mut group| {
+            if group.len() == 1 {
+                return group.pop();
+            }
+            let mut paths: Vec<String> = group.iter().flat_map(|s| s.paths.clone()).collect();
+            paths.sort();
+            let joined = Stats {
+                paths,
+                ..group.swap_remove(0)
+            };
+            let mut builder = StatsBuilder::new(cfg);
+            if let Err(e) = visit_process(&joined, range, |e| builder.add(&e)) {
+                warn!("failed to read {}: {}", joined.paths.join(", "), e);
+            }
+            builder.finish(joined.paths)
+        })
+        .collect()
+}
+
+/// Reports every process found under `path`, which is a directory or a
+/// combined log.
+fn report_dir(
+    path: &Path,
+    out_dir: &Path,
+    cfg: &ReportConfig,
+    range: &TimeRange,
+    opts: &ReportOptions,
+) {
+    // Everything the cached stats and pages depend on besides the logs.
+    let params = format!(
+        "{} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {} {:?} {:?}",
+        CACHE_VERSION,
+        range.from,
+        range.to,
+        cfg.spike_cpu_percent,
+        cfg.spike_min_sec,
+        cfg.theme,
+        cfg.timezone,
+        (
+            cfg.graph_width,
+            cfg.graph_height,
+            cfg.graph_dpi,
+            cfg.graph_x_labels,
+            cfg.graph_y_labels
+        ),
+        opts.speedscope,
+        opts.redact_env,
+        opts.pdf_browser
+    );
+    let mut cache = ReportCache::load(out_dir, params);
+    let (stats, fresh) = cache.collect_stats(path, cfg, range, &opts.filter);
+    // Shown apart, as it is not part of what was monitored.
+    let (overhead, stats): (Vec<Stats>, Vec<Stats>) =
+        stats.into_iter().partition(|s| s.self_overhead);
+    let leak_slope = cfg
+        .leak_slope_kb_per_min
+        .unwrap_or(DEFAULT_LEAK_SLOPE_KB_PER_MIN);
+    write_summary(out_dir, &stats, leak_slope, opts.summary_md);
+    let mut files: Vec<(&String, &FileIssues)> =
+        cache.files.iter().map(|(k, f)| (k, &f.issues)).collect();
+    files.sort_by_key(|(k, _)| *k);
+    let (issues, issues_html) = render_issues(&files, &stats, opts.style.theme);
+    let issues_path = out_dir.join(ISSUES_PAGE);
+    if let Err(e) = fs::write(&issues_path, issues_html) {
+        warn!("failed to write {}: {}", issues_path.display(), e);
+    }
+    let issues_link = (ISSUES_PAGE, format!("Issues ({})", issues));
     if stats.is_empty() {
         let index = out_dir.join("index.html");
-        if let Err(e) = fs::write(&index, "<p>No entries</p>") {
+        let html = format!(
+            "<p>No entries</p>\n<p><a href=\"{}\">{}</a></p>\n",
+            issues_link.0, issues_link.1
+        );
+        if let Err(e) = fs::write(&index, html) {
             warn!("failed to write {}: {}", index.display(), e);
         }
+        cache.save(out_dir);
         return;
     }
 
     let mut by_cpu = stats.clone();
-    by_cpu.sort_by(|a, b| {
-        let a_cpu = if a.avg_cpu <= 0.1 { 0.0 } else { a.avg_cpu };
-        let b_cpu = if b.avg_cpu <= 0.1 { 0.0 } else { b.avg_cpu };
-        b_cpu
-            .partial_cmp(&a_cpu)
-            .unwrap()
-            .then_with(|| b.peak_rss.cmp(&a.peak_rss))
-    });
+    by_cpu.sort_by(busiest_first);
     let mut by_rss = stats.clone();
     by_rss.sort_by_key(|s| std::cmp::Reverse(s.peak_rss));
 
-    let cpu_top: Vec<_> = by_cpu.iter().take(top_cpu).cloned().collect();
-    let rss_top: Vec<_> = by_rss.iter().take(top_rss).cloned().collect();
-
-    let mut map: HashMap<String, Stats> = HashMap::new();
-    for s in cpu_top.clone() {
-        map.entry(s.path.clone()).or_insert(s);
-    }
-    for s in rss_top.clone() {
-        map.entry(s.path.clone()).or_insert(s);
+    let cpu_top: Vec<_> = by_cpu
+        .iter()
+        .take(cfg.top_cpu.unwrap_or(DEFAULT_REPORT_TOP))
+        .cloned()
+        .collect();
+    let rss_top: Vec<_> = by_rss
+        .iter()
+        .take(cfg.top_rss.unwrap_or(DEFAULT_REPORT_TOP))
+        .cloned()
+        .collect();
+    // Possible leaks get their pages too, to look at their RSS graphs.
+    let leaking = possible_leaks(&stats, leak_slope)
+        .into_iter()
+        .map(|(s, _)| s);
+
+    let mut map: HashMap<(Vec<String>, ProcessKey), Stats> = HashMap::new();
+    for s in cpu_top.iter().chain(&rss_top).chain(leaking) {
+        map.entry((s.paths.clone(), s.key()))
+            .or_insert_with(|| s.clone());
     }
     let mut selected: Vec<_> = map.into_values().collect();
-    selected.sort_by(|a, b| {
-        let a_cpu = if a.avg_cpu <= 0.1 { 0.0 } else { a.avg_cpu };
-        let b_cpu = if b.avg_cpu <= 0.1 { 0.0 } else { b.avg_cpu };
-        b_cpu
-            .partial_cmp(&a_cpu)
-            .unwrap()
-            .then_with(|| b.peak_rss.cmp(&a.peak_rss))
-    });
-
-    write_multi_svg(&cpu_top, &out_dir.join("top_cpu.svg"), GraphField::Cpu);
-    write_multi_svg(&rss_top, &out_dir.join("top_rss.svg"), GraphField::Rss);
+    selected.sort_by(busiest_first);
+
+    // The stats carry the points of their graphs, so no log is read again.
+    let series = |stats: &[Stats], points: fn(&Stats) -> &[(i64, f64)]| -> Vec<(String, Series)> {
+        stats
+            .iter()
+            .map(|s| (series_label(s), local_series(points(s))))
+            .collect()
+    };
+    let cpu_data = series(&cpu_top, |s| &s.cpu_series);
+    let rss_data = series(&rss_top, |s| &s.rss_series);
+    let charts = charts_html(
+        &[
+            ("top_cpu_chart", chart_spec(GraphField::Cpu, &cpu_data)),
+            ("top_rss_chart", chart_spec(GraphField::Rss, &rss_data)),
+        ],
+        &opts.chart_zone,
+    );
+    write_multi_svg(
+        cpu_data,
+        &out_dir.join("top_cpu.svg"),
+        GraphField::Cpu,
+        opts.style,
+    );
+    write_multi_svg(
+        rss_data,
+        &out_dir.join("top_rss.svg"),
+        GraphField::Rss,
+        opts.style,
+    );
+    write_chart_script(out_dir);
 
     // write index.html
-    let index_html = render_index(&selected, true);
+    let mut links = vec![issues_link];
+    if opts.combined_trace {
+        links.push((COMBINED_TRACE, "Trace of all listed processes".into()));
+    }
+    let index_html = render_index(
+        &stats, &selected, leak_slope, &overhead, &charts, &links, opts,
+    );
     let index_path = out_dir.join("index.html");
     if let Err(e) = fs::write(&index_path, index_html) {
         warn!("failed to write {}: {}", index_path.display(), e);
     }
 
-    // write per pid files
+    // Write per pid files, except those whose log did not change. The
+    // combined trace needs the entries of all of them, so then every page
+    // is written from the same pass.
+    let linked: HashSet<u32> = selected.iter().map(|s| s.pid).collect();
+    let mut pages = Vec::new();
+    let mut rendered = Vec::new();
     for s in &selected {
-        match read_log_entries(Path::new(&s.path)) {
-            Ok(entries) => {
-                if let Some(stats) = calc_stats(Path::new(&s.path), &entries) {
-                    write_graphs(&entries, out_dir, s.pid);
-                    let has_trace = write_trace(&entries, out_dir, s.pid);
-                    let html = render_single(&stats, has_trace);
-                    let out = out_dir.join(format!("{}.html", s.pid));
-                    if let Err(e) = fs::write(&out, html) {
-                        warn!("failed to write {}: {}", out.display(), e);
-                    }
-                }
-            }
-            Err(e) => warn!("failed to read {}: {}", s.path, e),
+        let out = out_dir.join(format!("{}.html", s.pid));
+        // Pages also change when a parent or child comes or goes.
+        let page = (s.paths.join("\n"), render_family(s, &stats, &linked));
+        let unchanged =
+            !s.paths.iter().any(|p| fresh.contains(p)) && cache.pages.get(&s.pid) == Some(&page);
+        let printed = opts.pdf_browser.is_none() || out.with_extension("pdf").exists();
+        if unchanged && out.exists() && printed && !opts.combined_trace {
+            continue;
+        }
+        pages.push((s, out, page.1.clone()));
+        rendered.push((s, page));
+    }
+    let combined = opts.combined_trace.then(|| out_dir.join(COMBINED_TRACE));
+    let failed = write_pages(&pages, out_dir, range, opts, combined.as_deref());
+    // Only the pages written now need their PDFs printed again.
+    let mut printed = vec![index_path];
+    for ((s, page), (_, out, _)) in rendered.into_iter().zip(pages) {
+        if !s.paths.iter().any(|p| failed.contains(p)) {
+            cache.pages.insert(s.pid, page);
+            printed.push(out);
         }
     }
+    cache.save(out_dir);
+    if let Some(browser) = &opts.pdf_browser {
+        write_pdfs(&printed, browser);
+    }
 }
 
+/// Least time between two reports with `--watch`.
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
 pub fn report(args: &ReportArgs) {
-    let cfg = if let Some(ref path) = args.config {
-        finalize_report_config(load_config(path).report)
-    } else {
-        finalize_report_config(Default::default())
+    let config = args.config.as_deref().map(load_config).unwrap_or_default();
+    let mut cfg = finalize_report_config(config.report, args);
+    // Labels are in local time unless a time zone is named.
+    if cfg.timezone.is_none() {
+        cfg.timezone = config.output.timezone.clone();
+    }
+    // Applied to the whole process, as labels are taken in local time.
+    let mut named = None;
+    if let Some(name) = cfg.timezone.as_deref() {
+        let applied = Timezone::apply(name);
+        if applied.is_none() {
+            warn!("unknown timezone {}, using local time", name);
+        }
+        named = applied.and(Some(name)).filter(|n| *n != "local");
+    }
+    let chart_zone = match named {
+        Some("utc") => ChartZone::Named("UTC".into()),
+        Some(name) => ChartZone::Named(name.into()),
+        None => ChartZone::Offset(Local::now().offset().local_minus_utc() / 60),
     };
     let input = Path::new(&args.path);
     let out_dir = if let Some(ref o) = args.output {
@@ -749,15 +710,110 @@ pub fn report(args: &ReportArgs) {
     if let Err(e) = fs::create_dir_all(&out_dir) {
         warn!("failed to create {}: {}", out_dir.display(), e);
     }
-    if input.is_dir() {
-        report_dir(
-            input,
-            &out_dir,
-            cfg.top_cpu.unwrap_or(10),
-            cfg.top_rss.unwrap_or(10),
+    let range = args.filter.time_range();
+    let opts = ReportOptions {
+        speedscope: args.speedscope,
+        combined_trace: args.combined_trace,
+        group_by: args.group_by.as_deref().map(|g| match g {
+            "cmdline" => GroupBy::Cmdline,
+            _ => GroupBy::Name,
+        }),
+        summary_md: args.summary_md,
+        chart_zone,
+        filter: args.filter.clone(),
+        redact_env: redact_env_patterns(&config.output),
+        pdf_browser: cfg.pdf_browser.clone().filter(|_| args.pdf),
+        style: GraphStyle {
+            theme: match cfg.theme.as_deref() {
+                Some("dark") => Theme::Dark,
+                Some("light") | None => Theme::Light,
+                Some(other) => {
+                    warn!("unknown theme {}, using light", other);
+                    Theme::Light
+                }
+            },
+            width: cfg.graph_width.unwrap_or(600),
+            height: cfg.graph_height.unwrap_or(300),
+            dpi: cfg.graph_dpi.unwrap_or(96),
+            x_labels: cfg.graph_x_labels.unwrap_or(5),
+            y_labels: cfg.graph_y_labels.unwrap_or(5),
+        },
+    };
+    let generate = || {
+        if args.trend {
+            report_trend(input, &out_dir, &cfg, &range, &opts);
+        } else if let Some(baseline) = &args.baseline {
+            report_diff(Path::new(baseline), input, &out_dir, &cfg, &range, &opts);
+        } else if input.is_dir() || is_combined_log(input) {
+            report_dir(input, &out_dir, &cfg, &range, &opts);
+        } else {
+            report_file(input, &out_dir, &cfg, &range, &opts);
+        }
+        println!("{}", out_dir.display());
+    };
+    if !args.watch {
+        generate();
+        return;
+    }
+    // Watching starts first so that changes made while the report is
+    // written are not missed.
+    let mut watcher = match LogWatcher::new(input, Some(&out_dir), WATCH_INTERVAL) {
+        Ok(w) => w,
+        Err(e) => {
+            warn!("failed to watch {}: {}", input.display(), e);
+            generate();
+            return;
+        }
+    };
+    loop {
+        generate();
+        if let Err(e) = watcher.wait() {
+            warn!("failed to watch {}: {}", input.display(), e);
+            return;
+        }
+    }
+}
+
+/// Lines up the stats of each process, most CPU first, for a terminal.
+fn stats_table(stats: &[Stats]) -> String {
+    let mut sorted: Vec<&Stats> = stats.iter().collect();
+    sorted.sort_by(|a, b| b.cpu.total_cmp(&a.cpu).then(a.pid.cmp(&b.pid)));
+    let mut out = format!(
+        "{:>7} {:>10} {:>10} {:>8} {:>12}  COMMAND\n",
+        "PID", "RUNTIME s", "CPU s", "AVG CPU%", "PEAK RSS KB"
+    );
+    for s in sorted {
+        out.push_str(&format!(
+            "{:>7} {:>10} {:>10.1} {:>8.1} {:>12}  {}\n",
+            s.pid, s.runtime, s.cpu, s.avg_cpu, s.peak_rss, s.cmd
+        ));
+    }
+    out
+}
+
+/// Prints the stats `report` computes for the processes under `args.path`,
+/// without writing any pages.
+pub fn stats(args: &StatsArgs) {
+    let config = args.config.as_deref().map(load_config).unwrap_or_default();
+    let report_args = ReportArgs {
+        path: args.path.clone(),
+        filter: args.filter.clone(),
+        config: args.config.clone(),
+        ..Default::default()
+    };
+    let cfg = finalize_report_config(config.report, &report_args);
+    let range = args.filter.time_range();
+    let stats = collect_stats(Path::new(&args.path), &cfg, &range, &args.filter);
+    if args.json {
+        let leak_slope = cfg
+            .leak_slope_kb_per_min
+            .unwrap_or(DEFAULT_LEAK_SLOPE_KB_PER_MIN);
+        let summary = summary_json(&stats, leak_slope);
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&summary).unwrap_or_default()
         );
     } else {
-        report_file(input, &out_dir);
+        print!("{}", stats_table(&stats));
     }
-    println!("{}", out_dir.display());
 }
@@ -0,0 +1,111 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+use super::{FileIssues, Stats, file_stats, reported, stitch};
+use crate::config::{EntryFilter, ReportConfig};
+use crate::log::{TimeRange, log_files};
+
+/// File in the report directory remembering the stats of earlier runs.
+const CACHE_NAME: &str = ".report-cache.json";
+
+/// Changed whenever the cached stats change; older caches are dropped.
+pub(super) const CACHE_VERSION: u32 = 6;
+
+/// Stats and pages of an earlier run over the same directory, so that a
+/// report of a growing directory only reads the logs that changed.
+#[derive(Default, Serialize, Deserialize)]
+pub(super) struct ReportCache {
+    /// Settings the stats and pages were made with.
+    params: String,
+    pub(super) files: HashMap<String, CachedFile>,
+    /// Log files and parent and children section each process page was last
+    /// rendered with.
+    pub(super) pages: HashMap<u32, (String, String)>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(super) struct CachedFile {
+    modified: SystemTime,
+    size: u64,
+    /// Stats of all processes in the file, before any filter.
+    stats: Vec<Stats>,
+    pub(super) issues: FileIssues,
+}
+
+impl ReportCache {
+    /// Reads the cache of `out_dir`, or starts an empty one when there is
+    /// none or it was made with other `params`.
+    pub(super) fn load(out_dir: &Path, params: String) -> ReportCache {
+        let cache = fs::read(out_dir.join(CACHE_NAME))
+            .ok()
+            .and_then(|data| serde_json::from_slice::<ReportCache>(&data).ok());
+        match cache {
+            Some(c) if c.params == params => c,
+            _ => ReportCache {
+                params,
+                ..Default::default()
+            },
+        }
+    }
+
+    pub(super) fn save(&self, out_dir: &Path) {
+        let path = out_dir.join(CACHE_NAME);
+        let data = serde_json::to_vec(self).unwrap_or_default();
+        if let Err(e) = fs::write(&path, data) {
+            warn!("failed to write {}: {}", path.display(), e);
+        }
+    }
+
+    /// Like `collect_stats`, but only reads the logs whose size or
+    /// modification time changed since they were cached, and keeps the
+    /// overhead of fuzmon. Also returns the logs that were read.
+    pub(super) fn collect_stats(
+        &mut self,
+        path: &Path,
+        cfg: &ReportConfig,
+        range: &TimeRange,
+        filter: &EntryFilter,
+    ) -> (Vec<Stats>, HashSet<String>) {
+        let mut files = HashMap::new();
+        let mut fresh = HashSet::new();
+        let mut stats = Vec::new();
+        for f in log_files(path) {
+            let key = f.display().to_string();
+            let (modified, size) = match fs::metadata(&f).and_then(|m| Ok((m.modified()?, m.len())))
+            {
+                Ok(m) => m,
+                Err(e) => {
+                    warn!("failed to stat {}: {}", key, e);
+                    continue;
+                }
+            };
+            let cached = self
+                .files
+                .remove(&key)
+                .filter(|c| c.modified == modified && c.size == size);
+            let file = cached.unwrap_or_else(|| {
+                fresh.insert(key.clone());
+                let (stats, issues) = file_stats(&f, cfg, range, &EntryFilter::default());
+                CachedFile {
+                    modified,
+                    size,
+                    stats,
+                    issues,
+                }
+            });
+            stats.extend(file.stats.iter().cloned());
+            files.insert(key, file);
+        }
+        // Logs that were removed since are forgotten.
+        self.files = files;
+        let stats = stitch(stats, cfg, range)
+            .into_iter()
+            .filter(|s| reported(filter, s))
+            .collect();
+        (stats, fresh)
+    }
+}
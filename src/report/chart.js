@@ -0,0 +1,167 @@
+// Interactive line charts for fuzmon reports. Drag to zoom into a time
+// range, double click to zoom out, hover for values and click a legend
+// entry to hide or show its series. Optional `bands` of [from, to] times
+// are shaded, e.g. CPU spikes. Times are labeled as days when `dates` is set,
+// in the zone `timezone` names, or else `utc_offset_min` minutes east of UTC,
+// so they match the rest of the report wherever it is opened.
+function fuzmonChart(id, spec) {
+  var colors = ["#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd",
+                "#8c564b", "#e377c2", "#7f7f7f", "#bcbd22", "#17becf"];
+  var root = document.getElementById(id);
+  var canvas = document.createElement("canvas");
+  var tip = document.createElement("div");
+  var legend = document.createElement("div");
+  // Text and the tooltip follow the colors of the page theme.
+  var fg = getComputedStyle(root).color;
+  root.style.position = "relative";
+  tip.style.cssText = "position:absolute;display:none;pointer-events:none;" +
+    "border:1px solid #888;padding:2px 4px;font:12px sans-serif;";
+  tip.style.background = getComputedStyle(document.body).backgroundColor;
+  root.appendChild(canvas);
+  root.appendChild(tip);
+  root.appendChild(legend);
+
+  var width = Math.max(root.clientWidth, 600), height = 300;
+  var left = 60, right = 10, top = 24, bottom = 30;
+  canvas.width = width;
+  canvas.height = height;
+  var ctx = canvas.getContext("2d");
+  var series = spec.series.filter(function (s) { return s.data.length > 0; });
+  var hidden = {};
+  var tmin = Infinity, tmax = -Infinity;
+  series.forEach(function (s) {
+    tmin = Math.min(tmin, s.data[0][0]);
+    tmax = Math.max(tmax, s.data[s.data.length - 1][0]);
+  });
+  if (tmax <= tmin) tmax = tmin + 1000;
+  var view = [tmin, tmax];
+  var drag = null;
+
+  function yRange() {
+    var max = 0;
+    series.forEach(function (s, i) {
+      if (hidden[i]) return;
+      s.data.forEach(function (p) {
+        if (p[0] >= view[0] && p[0] <= view[1]) max = Math.max(max, p[1]);
+      });
+    });
+    return spec.log ? [0.1, Math.max(max, 1)] : [0, max > 0 ? max : 1];
+  }
+  function xPos(t) {
+    return left + (t - view[0]) / (view[1] - view[0]) * (width - left - right);
+  }
+  function xTime(x) {
+    return view[0] + (x - left) / (width - left - right) * (view[1] - view[0]);
+  }
+  function yPos(v, yr) {
+    var h = height - top - bottom;
+    if (spec.log) {
+      var lv = Math.log10(Math.max(v, yr[0]));
+      var lo = Math.log10(yr[0]), hi = Math.log10(yr[1]);
+      return height - bottom - (lv - lo) / (hi - lo || 1) * h;
+    }
+    return height - bottom - (v - yr[0]) / (yr[1] - yr[0]) * h;
+  }
+  var shift = spec.timezone ? 0 : (spec.utc_offset_min || 0) * 60000;
+  var format = new Intl.DateTimeFormat(undefined, spec.dates
+    ? { timeZone: spec.timezone || "UTC", year: "numeric", month: "2-digit", day: "2-digit" }
+    : { timeZone: spec.timezone || "UTC", hour: "2-digit", minute: "2-digit", second: "2-digit" });
+  function label(t) {
+    return format.format(new Date(t + shift));
+  }
+  function draw() {
+    var yr = yRange();
+    ctx.clearRect(0, 0, width, height);
+    ctx.font = "12px sans-serif";
+    ctx.fillStyle = fg;
+    ctx.fillText(spec.title + " (" + spec.unit + ")", left, 14);
+    ctx.fillStyle = "rgba(255,0,0,0.15)";
+    (spec.bands || []).forEach(function (b) {
+      var from = xPos(Math.max(b[0], view[0])), to = xPos(Math.min(b[1], view[1]));
+      if (to > from) ctx.fillRect(from, top, to - from, height - top - bottom);
+    });
+    ctx.fillStyle = fg;
+    ctx.strokeStyle = "rgba(128,128,128,0.3)";
+    for (var i = 0; i <= 4; i++) {
+      var v = spec.log ? yr[0] * Math.pow(yr[1] / yr[0], i / 4)
+                       : yr[0] + (yr[1] - yr[0]) * i / 4;
+      var y = yPos(v, yr);
+      ctx.beginPath(); ctx.moveTo(left, y); ctx.lineTo(width - right, y); ctx.stroke();
+      ctx.fillText(v.toPrecision(3), 2, y + 4);
+      var t = view[0] + (view[1] - view[0]) * i / 4;
+      ctx.fillText(label(t), Math.min(xPos(t), width - 70), height - 10);
+    }
+    series.forEach(function (s, i) {
+      if (hidden[i]) return;
+      ctx.strokeStyle = colors[i % colors.length];
+      ctx.beginPath();
+      var started = false;
+      s.data.forEach(function (p) {
+        if (p[0] < view[0] || p[0] > view[1]) return;
+        var x = xPos(p[0]), y = yPos(p[1], yr);
+        if (started) ctx.lineTo(x, y); else ctx.moveTo(x, y);
+        started = true;
+      });
+      ctx.stroke();
+    });
+    if (drag && drag.to !== undefined) {
+      ctx.fillStyle = "rgba(0,0,255,0.1)";
+      ctx.fillRect(Math.min(drag.from, drag.to), top, Math.abs(drag.to - drag.from),
+                   height - top - bottom);
+    }
+  }
+  function nearest(s, t) {
+    var best = null;
+    s.data.forEach(function (p) {
+      if (best === null || Math.abs(p[0] - t) < Math.abs(best[0] - t)) best = p;
+    });
+    return best;
+  }
+  function offsetX(e) {
+    return e.clientX - canvas.getBoundingClientRect().left;
+  }
+
+  canvas.addEventListener("mousedown", function (e) { drag = { from: offsetX(e) }; });
+  canvas.addEventListener("mousemove", function (e) {
+    var x = offsetX(e);
+    if (drag) {
+      drag.to = x;
+      draw();
+      return;
+    }
+    var t = xTime(x);
+    var lines = [label(t)];
+    series.forEach(function (s, i) {
+      var p = hidden[i] ? null : nearest(s, t);
+      if (p) lines.push(s.label + ": " + p[1].toFixed(1));
+    });
+    tip.innerText = lines.join("\n");
+    tip.style.display = "block";
+    tip.style.left = Math.min(x + 12, width - 160) + "px";
+    tip.style.top = top + "px";
+  });
+  canvas.addEventListener("mouseleave", function () { tip.style.display = "none"; });
+  window.addEventListener("mouseup", function () {
+    if (drag && drag.to !== undefined && Math.abs(drag.to - drag.from) > 5) {
+      var a = xTime(Math.min(drag.from, drag.to)), b = xTime(Math.max(drag.from, drag.to));
+      view = [Math.max(a, tmin), Math.min(b, tmax)];
+    }
+    drag = null;
+    draw();
+  });
+  canvas.addEventListener("dblclick", function () { view = [tmin, tmax]; draw(); });
+
+  series.forEach(function (s, i) {
+    var item = document.createElement("span");
+    item.style.cssText = "cursor:pointer;margin-right:12px;font:12px sans-serif;";
+    item.innerHTML = "<span style=\"color:" + colors[i % colors.length] + "\">&#9632;</span> ";
+    item.appendChild(document.createTextNode(s.label));
+    item.addEventListener("click", function () {
+      hidden[i] = !hidden[i];
+      item.style.opacity = hidden[i] ? 0.4 : 1;
+      draw();
+    });
+    legend.appendChild(item);
+  });
+  draw();
+}
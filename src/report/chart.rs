@@ -0,0 +1,67 @@
+use log::warn;
+use serde_json::json;
+use std::fs;
+use std::path::Path;
+
+use super::graph::{GraphField, Series};
+
+/// Script drawing the interactive charts, written next to the pages.
+const CHART_SCRIPT: &str = include_str!("chart.js");
+
+const CHART_SCRIPT_NAME: &str = "chart.js";
+
+pub(super) fn write_chart_script(out_dir: &Path) {
+    let path = out_dir.join(CHART_SCRIPT_NAME);
+    if let Err(e) = fs::write(&path, CHART_SCRIPT) {
+        warn!("failed to write {}: {}", path.display(), e);
+    }
+}
+
+/// Describes an interactive chart of `data` for `chart.js`, with times in
+/// milliseconds since the epoch.
+pub(super) fn chart_spec(field: GraphField, data: &[(String, Series)]) -> serde_json::Value {
+    let title = field.names().1;
+    let (unit, scale, log) = match field {
+        GraphField::Cpu => ("%", 1.0, true),
+        _ => ("MB", 1024.0, false),
+    };
+    let series: Vec<serde_json::Value> = data
+        .iter()
+        .map(|(label, points)| {
+            let points: Vec<(i64, f64)> = points
+                .iter()
+                .map(|(t, v)| (t.timestamp_millis(), v / scale))
+                .collect();
+            json!({ "label": label, "data": points })
+        })
+        .collect();
+    json!({ "title": title, "unit": unit, "log": log, "series": series })
+}
+
+/// Time zone the interactive charts label times in, which is the one of
+/// the rest of the report rather than that of the browser.
+pub(super) enum ChartZone {
+    /// A zone name the browser knows, such as `Asia/Tokyo`.
+    Named(String),
+    /// Minutes east of UTC of local time when the report was written.
+    Offset(i32),
+}
+
+/// Embeds the charts with the script drawing them.
+pub(super) fn charts_html(charts: &[(&str, serde_json::Value)], zone: &ChartZone) -> String {
+    let mut out = format!("<script src=\"{}\"></script>\n", CHART_SCRIPT_NAME);
+    for (id, spec) in charts {
+        let mut spec = spec.clone();
+        match zone {
+            ChartZone::Named(name) => spec["timezone"] = json!(name),
+            ChartZone::Offset(minutes) => spec["utc_offset_min"] = json!(minutes),
+        }
+        // A "</" in a label would end the script element early.
+        let spec = spec.to_string().replace("</", "<\\/");
+        out.push_str(&format!(
+            "<div id=\"{}\"></div>\n<script>fuzmonChart(\"{}\", {});</script>\n",
+            id, id, spec
+        ));
+    }
+    out
+}
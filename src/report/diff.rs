@@ -0,0 +1,77 @@
+use html_escape::encode_text;
+use log::warn;
+use std::fs;
+use std::path::Path;
+
+use super::graph::Theme;
+use super::index::{GroupTotals, totals_by};
+use super::{ReportOptions, Stats, collect_stats};
+use crate::config::ReportConfig;
+use crate::log::TimeRange;
+
+fn render_diff(baseline: &[Stats], current: &[Stats], theme: Theme) -> String {
+    let base = totals_by(baseline, |s| s.name.clone());
+    let cur = totals_by(current, |s| s.name.clone());
+    let mut names: Vec<&String> = base.keys().chain(cur.keys()).collect();
+    names.sort();
+    names.dedup();
+    let none = GroupTotals::default();
+    let cpu_delta = |n: &String| cur.get(n).unwrap_or(&none).cpu - base.get(n).unwrap_or(&none).cpu;
+    // Largest changes in CPU time first.
+    names.sort_by(|a, b| cpu_delta(b).abs().total_cmp(&cpu_delta(a).abs()));
+
+    let mut out = theme.head();
+    out.push_str("<h1>Comparison with baseline</h1>\n<table>\n");
+    out.push_str(
+        "<tr><th>Name</th><th>Processes</th><th>Total CPU time</th><th>CPU delta</th><th>Peak RSS</th><th>RSS delta</th><th>Total runtime</th><th>Runtime delta</th></tr>\n",
+    );
+    for name in names {
+        let (class, b, c) = match (base.get(name), cur.get(name)) {
+            (None, Some(c)) => (" class=\"new\"", &none, c),
+            (Some(b), None) => (" class=\"removed\"", b, &none),
+            (Some(b), Some(c)) => ("", b, c),
+            (None, None) => continue,
+        };
+        out.push_str(&format!(
+            "<tr{}><td>{}</td><td>{} &rarr; {}</td><td>{:.1} &rarr; {:.1}</td><td>{:+.1}</td><td>{} &rarr; {}</td><td>{:+}</td><td>{} &rarr; {}</td><td>{:+}</td></tr>\n",
+            class,
+            encode_text(name),
+            b.processes,
+            c.processes,
+            b.cpu,
+            c.cpu,
+            c.cpu - b.cpu,
+            b.peak_rss,
+            c.peak_rss,
+            c.peak_rss as i64 - b.peak_rss as i64,
+            b.runtime,
+            c.runtime,
+            c.runtime - b.runtime
+        ));
+    }
+    out.push_str("</table></body></html>\n");
+    out
+}
+
+/// Writes a page comparing the processes under `path` with those under
+/// `baseline`, grouped by process name.
+pub(super) fn report_diff(
+    baseline: &Path,
+    path: &Path,
+    out_dir: &Path,
+    cfg: &ReportConfig,
+    range: &TimeRange,
+    opts: &ReportOptions,
+) {
+    // The baseline usually ran at another time, so `range` would leave
+    // nothing of it; it is compared as a whole.
+    let html = render_diff(
+        &collect_stats(baseline, cfg, &TimeRange::default(), &opts.filter),
+        &collect_stats(path, cfg, range, &opts.filter),
+        opts.style.theme,
+    );
+    let index = out_dir.join("index.html");
+    if let Err(e) = fs::write(&index, html) {
+        warn!("failed to write {}: {}", index.display(), e);
+    }
+}
@@ -0,0 +1,66 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+
+/// How many times the usual interval between samples a pause must last to
+/// count as a gap in the data.
+const GAP_FACTOR: i64 = 5;
+
+/// Most gaps kept of one process, the longest ones.
+const MAX_GAPS: usize = 1000;
+
+/// Finds the pauses between samples longer than `GAP_FACTOR` times their
+/// median interval, such as when the monitor stalled or was stopped. The
+/// median is taken of the intervals rounded to two significant digits, and
+/// only the `MAX_GAPS` longest intervals are kept to be checked against it.
+#[derive(Default)]
+pub(super) struct GapFinder {
+    /// How many intervals were seen of each rounded length.
+    lengths: BTreeMap<i64, usize>,
+    intervals: usize,
+    /// Length, start and end of the longest intervals.
+    longest: BinaryHeap<Reverse<(i64, i64, i64)>>,
+}
+
+/// `ms` rounded down to two significant digits.
+fn two_digits(ms: i64) -> i64 {
+    let mut scale = 1;
+    while ms / scale >= 100 {
+        scale *= 10;
+    }
+    ms / scale * scale
+}
+
+impl GapFinder {
+    pub(super) fn add(&mut self, from: i64, to: i64) {
+        let len = to - from;
+        *self.lengths.entry(two_digits(len)).or_default() += 1;
+        self.intervals += 1;
+        self.longest.push(Reverse((len, from, to)));
+        if self.longest.len() > MAX_GAPS {
+            self.longest.pop();
+        }
+    }
+
+    pub(super) fn finish(self) -> Vec<(i64, i64)> {
+        if self.intervals < 3 {
+            return Vec::new();
+        }
+        let mut rank = self.intervals / 2;
+        let mut usual = 1;
+        for (len, count) in self.lengths {
+            if rank < count {
+                usual = len.max(1);
+                break;
+            }
+            rank -= count;
+        }
+        let mut gaps: Vec<(i64, i64)> = self
+            .longest
+            .into_iter()
+            .filter(|Reverse((len, _, _))| *len > usual * GAP_FACTOR)
+            .map(|Reverse((_, from, to))| (from, to))
+            .collect();
+        gaps.sort_unstable();
+        gaps
+    }
+}
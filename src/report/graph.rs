@@ -0,0 +1,486 @@
+use chrono::{DateTime, Local};
+use plotters::chart::{MeshStyle, SeriesLabelStyle};
+use plotters::coord::{CoordTranslate, Shift};
+use plotters::prelude::*;
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
+use super::{CPU_MIN, Stats};
+use crate::log::LogEntry;
+
+/// Colors of the report pages and graphs.
+#[derive(Clone, Copy)]
+pub(super) enum Theme {
+    Light,
+    /// For dashboards on dark screens.
+    Dark,
+}
+
+/// Page style shared by the themes.
+const BASE_CSS: &str = "table,th,td{border:1px solid;border-collapse:collapse;}pre{margin:0;}tr.new{background:#dfd;}tr.removed{background:#fdd;}";
+
+impl Theme {
+    pub(super) fn background(self) -> RGBColor {
+        match self {
+            Theme::Light => WHITE,
+            Theme::Dark => RGBColor(0x1e, 0x1e, 0x1e),
+        }
+    }
+
+    fn foreground(self) -> RGBColor {
+        match self {
+            Theme::Light => BLACK,
+            Theme::Dark => RGBColor(0xdd, 0xdd, 0xdd),
+        }
+    }
+
+    /// Color of the single line of a process graph.
+    fn line(self) -> RGBColor {
+        match self {
+            Theme::Light => BLUE,
+            Theme::Dark => RGBColor(0x6c, 0xb4, 0xff),
+        }
+    }
+
+    fn font<'a>(self, size: u32) -> TextStyle<'a> {
+        ("sans-serif", size).into_font().color(&self.foreground())
+    }
+
+    fn css(self) -> &'static str {
+        match self {
+            Theme::Light => "body{background:#fff;color:#000;}",
+            Theme::Dark => {
+                "body{background:#1e1e1e;color:#ddd;}a{color:#8cf;}table,th,td{border-color:#666;}tr.new{background:#234523;}tr.removed{background:#4a2323;}"
+            }
+        }
+    }
+
+    /// Start of a page up to the opening body tag.
+    pub(super) fn head(self) -> String {
+        format!(
+            "<html><head><style>{}{}</style></head><body>\n",
+            BASE_CSS,
+            self.css()
+        )
+    }
+}
+
+/// Size and layout of the SVG graphs, and the theme they are drawn in.
+#[derive(Clone, Copy)]
+pub(super) struct GraphStyle {
+    pub(super) theme: Theme,
+    /// Size in pixels at 96 DPI.
+    pub(super) width: u32,
+    pub(super) height: u32,
+    /// Everything is scaled up from 96 DPI, so higher values keep the
+    /// layout but add detail, e.g. for print.
+    pub(super) dpi: u32,
+    pub(super) x_labels: usize,
+    pub(super) y_labels: usize,
+}
+
+impl GraphStyle {
+    /// Pixels at the graph resolution for `v` pixels at 96 DPI.
+    fn px(self, v: u32) -> u32 {
+        v * self.dpi / 96
+    }
+
+    pub(super) fn size(self) -> (u32, u32) {
+        (self.px(self.width), self.px(self.height))
+    }
+
+    fn font<'a>(self, size: u32) -> TextStyle<'a> {
+        self.theme.font(self.px(size))
+    }
+
+    fn line(self, color: impl Color) -> ShapeStyle {
+        color.stroke_width(self.px(1))
+    }
+
+    /// Starts a chart on `root` with a caption and room for axis labels.
+    pub(super) fn chart<'a, 'b, DB: DrawingBackend>(
+        self,
+        root: &'a DrawingArea<DB, Shift>,
+        caption: &str,
+    ) -> ChartBuilder<'a, 'b, DB> {
+        let mut builder = ChartBuilder::on(root);
+        builder
+            .caption(caption, self.font(20))
+            .margin(self.px(5))
+            .x_label_area_size(self.px(40))
+            .y_label_area_size(self.px(40));
+        builder
+    }
+}
+
+pub(super) fn themed_mesh<'a, 'b, X: Ranged, Y: Ranged, DB: DrawingBackend>(
+    mut mesh: MeshStyle<'a, 'b, X, Y, DB>,
+    style: GraphStyle,
+) -> MeshStyle<'a, 'b, X, Y, DB> {
+    let fg = style.theme.foreground();
+    mesh.axis_style(fg)
+        .bold_line_style(fg.mix(0.2))
+        .light_line_style(fg.mix(0.05))
+        .label_style(style.font(12))
+        .axis_desc_style(style.font(12))
+        .x_labels(style.x_labels)
+        .y_labels(style.y_labels);
+    mesh
+}
+
+pub(super) fn themed_legend<'a, 'b, DB: DrawingBackend + 'a, CT: CoordTranslate>(
+    mut legend: SeriesLabelStyle<'a, 'b, DB, CT>,
+    style: GraphStyle,
+) -> SeriesLabelStyle<'a, 'b, DB, CT> {
+    legend
+        .border_style(style.theme.foreground())
+        .background_style(style.theme.background().mix(0.8))
+        .label_font(style.font(12));
+    legend
+}
+
+#[derive(Clone, Copy)]
+pub(super) enum GraphField {
+    Cpu,
+    Rss,
+    Vsz,
+    Swap,
+}
+
+impl GraphField {
+    /// Graphs of the process pages, in order.
+    pub(super) const ALL: [GraphField; 4] = [
+        GraphField::Cpu,
+        GraphField::Rss,
+        GraphField::Vsz,
+        GraphField::Swap,
+    ];
+    /// Memory graphs of the process pages, after the CPU graph.
+    pub(super) const MEMORY: [GraphField; 3] = [GraphField::Rss, GraphField::Vsz, GraphField::Swap];
+
+    /// Short and long names of the value.
+    pub(super) fn names(self) -> (&'static str, &'static str) {
+        match self {
+            GraphField::Cpu => ("CPU", "CPU usage"),
+            GraphField::Rss => ("RSS", "Resident set size"),
+            GraphField::Vsz => ("VSZ", "Virtual memory size"),
+            GraphField::Swap => ("Swap", "Swap usage"),
+        }
+    }
+
+    /// Names the graph files and charts of the value.
+    pub(super) fn stem(self) -> String {
+        self.names().0.to_lowercase()
+    }
+
+    /// The value of `e`, in percent or KB.
+    pub(super) fn value(self, e: &LogEntry) -> f64 {
+        match self {
+            GraphField::Cpu => e.cpu_time_percent,
+            GraphField::Rss => e.memory.rss_kb as f64,
+            GraphField::Vsz => e.memory.vsz_kb as f64,
+            GraphField::Swap => e.memory.swap_kb as f64,
+        }
+    }
+}
+
+/// Unit memory of up to `max_kb` is drawn in, with its size in KB.
+fn memory_unit(max_kb: f64) -> (&'static str, f64) {
+    if max_kb >= 1024.0 * 1024.0 {
+        ("GB", 1024.0 * 1024.0)
+    } else {
+        ("MB", 1024.0)
+    }
+}
+
+/// Writes the graph of `field` of `s` from `series`. The spikes of `s` are
+/// shaded on the CPU graph.
+pub(super) fn write_svg(
+    series: Series,
+    s: &Stats,
+    out: &Path,
+    field: GraphField,
+    style: GraphStyle,
+) -> io::Result<()> {
+    if series.is_empty() {
+        return Ok(());
+    }
+    let (start, end) = (s.start, s.end);
+    let spikes = match field {
+        GraphField::Cpu => &s.spikes[..],
+        _ => &[],
+    };
+    let mut max_val = series.iter().map(|&(_, v)| v).fold(0.0f64, f64::max);
+    if max_val <= 0.0 {
+        max_val = 1.0;
+    }
+
+    let root = SVGBackend::new(out, style.size()).into_drawing_area();
+    root.fill(&style.theme.background())
+        .map_err(io::Error::other)?;
+    let (short, long) = field.names();
+    let (unit, scale) = match field {
+        GraphField::Cpu => ("%", 1.0),
+        _ => memory_unit(max_val),
+    };
+    let (y_desc, caption) = (
+        format!("{} {}", short, unit),
+        format!("{} ({})", long, unit),
+    );
+    let y_max = (max_val / scale).max(1.0);
+    if matches!(field, GraphField::Cpu) {
+        let mut chart = style
+            .chart(&root, &caption)
+            .build_cartesian_2d(start..end, (CPU_MIN..y_max).log_scale())
+            .map_err(io::Error::other)?;
+        themed_mesh(chart.configure_mesh(), style)
+            .x_desc("time")
+            .y_desc(y_desc)
+            .x_label_formatter(&|dt| dt.format("%H:%M:%S").to_string())
+            .draw()
+            .map_err(io::Error::other)?;
+        let local = |ms: i64| DateTime::from_timestamp_millis(ms).map(|t| t.with_timezone(&Local));
+        chart
+            .draw_series(spikes.iter().filter_map(|spike| {
+                let (from, to) = (local(spike.start)?, local(spike.end)?);
+                Some(Rectangle::new(
+                    [(from, CPU_MIN), (to, y_max)],
+                    RED.mix(0.15).filled(),
+                ))
+            }))
+            .map_err(io::Error::other)?;
+        chart
+            .draw_series(LineSeries::new(
+                series.into_iter().map(|(x, v)| {
+                    let val = v / scale;
+                    let val = if val < CPU_MIN { CPU_MIN } else { val };
+                    (x, val)
+                }),
+                style.line(style.theme.line()),
+            ))
+            .map_err(io::Error::other)?;
+        root.present().map_err(io::Error::other)
+    } else {
+        let mut chart = style
+            .chart(&root, &caption)
+            .build_cartesian_2d(start..end, 0f64..y_max)
+            .map_err(io::Error::other)?;
+        themed_mesh(chart.configure_mesh(), style)
+            .x_desc("time")
+            .y_desc(y_desc)
+            .x_label_formatter(&|dt| dt.format("%H:%M:%S").to_string())
+            .draw()
+            .map_err(io::Error::other)?;
+        chart
+            .draw_series(LineSeries::new(
+                series.into_iter().map(|(x, v)| (x, v / scale)),
+                style.line(style.theme.line()),
+            ))
+            .map_err(io::Error::other)?;
+        root.present().map_err(io::Error::other)
+    }
+}
+
+pub(super) type Series = Vec<(DateTime<Local>, f64)>;
+
+/// Most points kept of a graph; those of a longer run are merged.
+const GRAPH_POINTS: usize = 300;
+
+/// Combines two neighbouring points of a graph.
+pub(super) trait Merge {
+    fn merge(&mut self, other: Self);
+}
+
+/// Keeps the peak, which is what the graphs are looked at for.
+impl Merge for f64 {
+    fn merge(&mut self, other: f64) {
+        *self = self.max(other);
+    }
+}
+
+/// Keeps the peak of each thread.
+impl Merge for HashMap<u32, f64> {
+    fn merge(&mut self, other: HashMap<u32, f64>) {
+        for (tid, v) in other {
+            let peak = self.entry(tid).or_insert(v);
+            *peak = peak.max(v);
+        }
+    }
+}
+
+/// Points of a graph added in time order, in milliseconds since the epoch.
+/// Whenever there are `GRAPH_POINTS`, neighbours are merged in pairs, so a
+/// graph of any length takes bounded memory and each point stands for the
+/// same number of samples. A point keeps the time of its first sample.
+#[derive(Default)]
+pub(super) struct Downsampled<V> {
+    pub(super) points: Vec<(i64, V)>,
+    /// Samples merged into each point.
+    stride: usize,
+    /// Samples merged into the last point so far.
+    filled: usize,
+}
+
+impl<V: Merge> Downsampled<V> {
+    pub(super) fn push(&mut self, time: i64, value: V) {
+        if let Some((_, last)) = self.points.last_mut()
+            && self.filled < self.stride
+        {
+            last.merge(value);
+            self.filled += 1;
+            return;
+        }
+        if self.points.len() == GRAPH_POINTS {
+            let mut pairs = std::mem::take(&mut self.points).into_iter();
+            while let Some((time, mut v)) = pairs.next() {
+                if let Some((_, w)) = pairs.next() {
+                    v.merge(w);
+                }
+                self.points.push((time, v));
+            }
+            self.stride = self.stride.max(1) * 2;
+        }
+        self.points.push((time, value));
+        self.filled = 1;
+    }
+}
+
+/// The points of a graph in local time.
+pub(super) fn local_series(points: &[(i64, f64)]) -> Series {
+    points
+        .iter()
+        .filter_map(|&(ms, v)| {
+            Some((
+                DateTime::from_timestamp_millis(ms)?.with_timezone(&Local),
+                v,
+            ))
+        })
+        .collect()
+}
+
+/// Labels a series with the pid and the program name.
+pub(super) fn series_label(s: &Stats) -> String {
+    let token = s.cmd.split_whitespace().next().unwrap_or("");
+    let base = Path::new(token)
+        .file_name()
+        .map(|b| b.to_string_lossy().into_owned())
+        .unwrap_or_else(|| token.to_string());
+    format!("{} {}", s.pid, base)
+}
+
+pub(super) fn write_multi_svg(
+    data: Vec<(String, Series)>,
+    out: &Path,
+    field: GraphField,
+    style: GraphStyle,
+) {
+    let points = || data.iter().flat_map(|(_, series)| series.iter());
+    let (Some(start), Some(end)) = (
+        points().map(|&(t, _)| t).min(),
+        points().map(|&(t, _)| t).max(),
+    ) else {
+        return;
+    };
+    let mut max_val = points().map(|&(_, v)| v).fold(0.0f64, f64::max);
+    if max_val <= 0.0 {
+        max_val = 1.0;
+    }
+    let root = SVGBackend::new(out, style.size()).into_drawing_area();
+    if root.fill(&style.theme.background()).is_err() {
+        return;
+    }
+    let short = field.names().0;
+    let (y_desc, caption, scale) = match field {
+        GraphField::Cpu => ("CPU %".to_string(), "Top CPU usage".to_string(), 1.0),
+        _ => {
+            let (unit, scale) = memory_unit(max_val);
+            (
+                format!("{} {}", short, unit),
+                format!("Top {} ({})", short, unit),
+                scale,
+            )
+        }
+    };
+    let y_max = (max_val / scale).max(1.0);
+    if matches!(field, GraphField::Cpu) {
+        let mut chart = match style
+            .chart(&root, &caption)
+            .build_cartesian_2d(start..end, (CPU_MIN..y_max).log_scale())
+        {
+            Ok(c) => c,
+            Err(_) => return,
+        };
+        if themed_mesh(chart.configure_mesh(), style)
+            .x_desc("time")
+            .y_desc(y_desc)
+            .x_label_formatter(&|dt| dt.format("%H:%M:%S").to_string())
+            .draw()
+            .is_err()
+        {
+            return;
+        }
+        for (i, (label, series)) in data.into_iter().enumerate() {
+            let color = Palette99::pick(i).mix(0.9);
+            if chart
+                .draw_series(LineSeries::new(
+                    series.into_iter().map(|(x, v)| {
+                        let val = v / scale;
+                        let val = if val < CPU_MIN { CPU_MIN } else { val };
+                        (x, val)
+                    }),
+                    style.line(color),
+                ))
+                .map(|l| {
+                    l.label(label)
+                        .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color))
+                })
+                .is_err()
+            {
+                return;
+            }
+        }
+        let _ = themed_legend(chart.configure_series_labels(), style).draw();
+        let _ = root.present();
+    } else {
+        let mut chart = match style
+            .chart(&root, &caption)
+            .build_cartesian_2d(start..end, 0f64..y_max)
+        {
+            Ok(c) => c,
+            Err(_) => return,
+        };
+        if themed_mesh(chart.configure_mesh(), style)
+            .x_desc("time")
+            .y_desc(y_desc)
+            .x_label_formatter(&|dt| dt.format("%H:%M:%S").to_string())
+            .draw()
+            .is_err()
+        {
+            return;
+        }
+        for (i, (label, series)) in data.into_iter().enumerate() {
+            let color = Palette99::pick(i).mix(0.9);
+            if chart
+                .draw_series(LineSeries::new(
+                    series.into_iter().map(|(x, v)| (x, v / scale)),
+                    style.line(color),
+                ))
+                .map(|l| {
+                    l.label(label)
+                        .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color))
+                })
+                .is_err()
+            {
+                return;
+            }
+        }
+        let _ = themed_legend(chart.configure_series_labels(), style).draw();
+        let _ = root.present();
+    }
+}
+
+/// File of the graph of the memory `field` on the page of `pid`.
+pub(super) fn memory_graph_name(pid: u32, field: GraphField) -> String {
+    format!("{}_{}.svg", pid, field.stem())
+}
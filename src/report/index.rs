@@ -0,0 +1,293 @@
+use chrono::{DateTime, Local};
+use html_escape::encode_text;
+use std::collections::{BTreeMap, HashSet};
+
+use super::graph::series_label;
+use super::page::truncate;
+use super::tree::render_tree;
+use super::{ReportOptions, Stats};
+
+/// The pid, linked to its page when it has one.
+pub(super) fn pid_cell(pid: u32, linked: &HashSet<u32>) -> String {
+    if linked.contains(&pid) {
+        format!("<a href=\"{}.html\">{}</a>", pid, pid)
+    } else {
+        pid.to_string()
+    }
+}
+
+pub(super) fn pid_table(stats: &[&Stats], linked: &HashSet<u32>) -> String {
+    let mut out = String::new();
+    out.push_str("<table>\n");
+    out.push_str(
+        "<tr><th>PID</th><th>Command</th><th>Total runtime</th><th>Total CPU time</th><th>Avg CPU (%)</th><th>Peak RSS</th><th>Start</th><th>End</th></tr>\n",
+    );
+    for s in stats {
+        let summary = truncate(&s.cmd, 30);
+        let cmd_cell = format!(
+            "<details><summary>{}</summary><pre>{}</pre></details>",
+            encode_text(&summary),
+            encode_text(&s.cmd)
+        );
+        out.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{:.1}</td><td>{:.1}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            pid_cell(s.pid, linked),
+            cmd_cell,
+            s.runtime,
+            s.cpu,
+            s.avg_cpu,
+            s.peak_rss,
+            s.start,
+            s.end
+        ));
+    }
+    out.push_str("</table>");
+    out
+}
+
+/// Lists the groups of `stats` by CPU time, each expandable into its
+/// processes.
+fn group_table(stats: &[Stats], by: GroupBy, linked: &HashSet<u32>) -> String {
+    let mut groups: Vec<(String, GroupTotals)> =
+        totals_by(stats, |s| by.key(s)).into_iter().collect();
+    groups.sort_by(|a, b| b.1.cpu.total_cmp(&a.1.cpu));
+    let mut out = String::new();
+    out.push_str("<table>\n");
+    out.push_str(
+        "<tr><th>Group</th><th>Processes</th><th>Total runtime</th><th>Total CPU time</th><th>Summed peak RSS</th></tr>\n",
+    );
+    for (key, t) in &groups {
+        out.push_str(&format!(
+            "<tr><td><details><summary>{}</summary>{}</details></td><td>{}</td><td>{}</td><td>{:.1}</td><td>{}</td></tr>\n",
+            encode_text(key),
+            pid_table(&t.members, linked),
+            t.processes,
+            t.runtime,
+            t.cpu,
+            t.summed_rss
+        ));
+    }
+    out.push_str("</table>\n");
+    out
+}
+
+/// The processes of `stats` whose RSS grew by at least `slope` KB per
+/// minute, fastest first.
+pub(super) fn possible_leaks(stats: &[Stats], slope: f64) -> Vec<(&Stats, f64)> {
+    let mut leaks: Vec<(&Stats, f64)> = stats
+        .iter()
+        .filter_map(|s| s.rss_growth.filter(|g| *g >= slope).map(|g| (s, g)))
+        .collect();
+    leaks.sort_by(|a, b| b.1.total_cmp(&a.1));
+    leaks
+}
+
+fn render_leaks(stats: &[Stats], slope: f64, linked: &HashSet<u32>) -> String {
+    let leaks = possible_leaks(stats, slope);
+    if leaks.is_empty() {
+        return String::new();
+    }
+    let mut out = String::from("<h2>Possible leaks</h2>\n<table>\n");
+    out.push_str(
+        "<tr><th>PID</th><th>Command</th><th>RSS growth (KB/min)</th><th>Peak RSS</th><th>Total runtime</th></tr>\n",
+    );
+    for (s, growth) in leaks {
+        out.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{:.1}</td><td>{}</td><td>{}</td></tr>\n",
+            pid_cell(s.pid, linked),
+            encode_text(&truncate(&s.cmd, 30)),
+            growth,
+            s.peak_rss,
+            s.runtime
+        ));
+    }
+    out.push_str("</table>\n");
+    out
+}
+
+const GANTT_WIDTH: f64 = 800.0;
+
+const GANTT_LABEL_WIDTH: f64 = 160.0;
+
+const GANTT_ROW_HEIGHT: usize = 18;
+
+const GANTT_AXIS_HEIGHT: usize = 20;
+
+/// Draws when each process in `shown` was first and last seen, one row per
+/// process in order of appearance, to show what ran concurrently.
+fn render_gantt(shown: &[Stats]) -> String {
+    let (Some(start), Some(end)) = (
+        shown.iter().map(|s| s.start).min(),
+        shown.iter().map(|s| s.end).max(),
+    ) else {
+        return String::new();
+    };
+    let span = (end - start).num_milliseconds().max(1) as f64;
+    let plot_width = GANTT_WIDTH - GANTT_LABEL_WIDTH;
+    let x_at = |t: DateTime<Local>| {
+        GANTT_LABEL_WIDTH + (t - start).num_milliseconds() as f64 / span * plot_width
+    };
+    let mut rows: Vec<&Stats> = shown.iter().collect();
+    rows.sort_by_key(|s| (s.start, s.pid));
+    let height = rows.len() * GANTT_ROW_HEIGHT + GANTT_AXIS_HEIGHT;
+
+    let mut out = format!(
+        "<p>Process lifetimes<br><svg id=\"lifetimes\" xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" font-family=\"sans-serif\" font-size=\"12\" fill=\"currentColor\">\n",
+        GANTT_WIDTH, height
+    );
+    for (i, s) in rows.iter().enumerate() {
+        let y = i * GANTT_ROW_HEIGHT;
+        let x = x_at(s.start);
+        let width = (x_at(s.end) - x).max(1.0);
+        let label = encode_text(&series_label(s)).into_owned();
+        out.push_str(&format!(
+            "<a href=\"{}.html\"><text x=\"4\" y=\"{}\">{}</text></a>\n",
+            s.pid,
+            y + GANTT_ROW_HEIGHT - 5,
+            label
+        ));
+        out.push_str(&format!(
+            "<rect x=\"{:.1}\" y=\"{}\" width=\"{:.1}\" height=\"{}\" fill=\"steelblue\"><title>{}: {} - {} ({} s)</title></rect>\n",
+            x,
+            y + 2,
+            width,
+            GANTT_ROW_HEIGHT - 4,
+            label,
+            s.start.format("%H:%M:%S"),
+            s.end.format("%H:%M:%S"),
+            s.runtime
+        ));
+    }
+    let axis_y = rows.len() * GANTT_ROW_HEIGHT;
+    for i in 0..=4 {
+        let t = start + chrono::Duration::milliseconds((span * i as f64 / 4.0) as i64);
+        // The last label ends at the right edge instead of starting there.
+        let anchor = if i == 4 { "end" } else { "start" };
+        out.push_str(&format!(
+            "<text x=\"{:.1}\" y=\"{}\" text-anchor=\"{}\">{}</text>\n",
+            x_at(t),
+            axis_y + GANTT_AXIS_HEIGHT - 5,
+            anchor,
+            t.format("%H:%M:%S")
+        ));
+    }
+    out.push_str("</svg></p>\n");
+    out
+}
+
+/// Renders the index of the processes in `shown`, which get their own
+/// pages. With `group_by`, all processes are listed in their groups instead.
+/// The process tree and the possible leaks always cover all of them, and
+/// the `overhead` of fuzmon is listed last.
+pub(super) fn render_index(
+    all: &[Stats],
+    shown: &[Stats],
+    leak_slope: f64,
+    overhead: &[Stats],
+    charts: &str,
+    links: &[(&str, String)],
+    opts: &ReportOptions,
+) -> String {
+    let linked: HashSet<u32> = shown.iter().map(|s| s.pid).collect();
+    let mut out = opts.style.theme.head();
+    out.push_str(&render_leaks(all, leak_slope, &linked));
+    out.push_str("<p>CPU usage<br><img src=\"top_cpu.svg\" alt=\"Top CPU usage graph\" /></p>\n");
+    out.push_str("<p>Peak RSS<br><img src=\"top_rss.svg\" alt=\"Top RSS graph\" /></p>\n");
+    out.push_str(charts);
+    for (href, text) in links {
+        out.push_str(&format!("<p><a href=\"{}\">{}</a></p>\n", href, text));
+    }
+    out.push_str(&render_gantt(shown));
+    if let (Some(start), Some(end)) = (
+        shown.iter().map(|s| s.start).min(),
+        shown.iter().map(|s| s.end).max(),
+    ) {
+        out.push_str(&format!("<p>Start: {}</p>\n", start));
+        out.push_str(&format!("<p>End: {}</p>\n", end));
+    }
+    match opts.group_by {
+        Some(by) => out.push_str(&group_table(all, by, &linked)),
+        None => {
+            let rows: Vec<&Stats> = shown.iter().collect();
+            out.push_str(&pid_table(&rows, &linked));
+        }
+    }
+    out.push_str(&render_tree(all, &linked));
+    if !overhead.is_empty() {
+        let rows: Vec<&Stats> = overhead.iter().collect();
+        out.push_str("<h2>fuzmon overhead</h2>\n");
+        out.push_str(&pid_table(&rows, &HashSet::new()));
+    }
+    out.push_str("</body></html>\n");
+    out
+}
+
+/// Totals of all processes in one group, such as those sharing a name.
+#[derive(Default)]
+pub(super) struct GroupTotals<'a> {
+    pub(super) processes: usize,
+    pub(super) cpu: f64,
+    pub(super) peak_rss: u64,
+    /// Peak RSS of the processes added up, the memory a pool of identical
+    /// workers needs at most.
+    summed_rss: u64,
+    pub(super) runtime: i64,
+    members: Vec<&'a Stats>,
+}
+
+pub(super) fn totals_by<'a>(
+    stats: &'a [Stats],
+    key: impl Fn(&Stats) -> String,
+) -> BTreeMap<String, GroupTotals<'a>> {
+    let mut totals: BTreeMap<String, GroupTotals> = BTreeMap::new();
+    for s in stats {
+        let t = totals.entry(key(s)).or_default();
+        t.processes += 1;
+        t.cpu += s.cpu;
+        t.peak_rss = t.peak_rss.max(s.peak_rss);
+        t.summed_rss += s.peak_rss;
+        t.runtime += s.runtime;
+        t.members.push(s);
+    }
+    totals
+}
+
+/// What `report --group-by` aggregates processes by.
+#[derive(Clone, Copy)]
+pub(super) enum GroupBy {
+    Name,
+    /// The command line with numbers replaced, so workers started with
+    /// their index or port still fall into one group.
+    Cmdline,
+}
+
+impl GroupBy {
+    fn key(self, s: &Stats) -> String {
+        match self {
+            GroupBy::Name => s.name.clone(),
+            GroupBy::Cmdline => normalize_cmdline(&s.cmd),
+        }
+    }
+}
+
+fn normalize_cmdline(cmd: &str) -> String {
+    let mut out = String::new();
+    for word in cmd.split_whitespace() {
+        if !out.is_empty() {
+            out.push(' ');
+        }
+        let mut in_number = false;
+        for c in word.chars() {
+            if c.is_ascii_digit() {
+                if !in_number {
+                    out.push('N');
+                }
+                in_number = true;
+            } else {
+                out.push(c);
+                in_number = false;
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,106 @@
+use chrono::{DateTime, Local};
+use html_escape::encode_text;
+
+use super::graph::Theme;
+use super::{FileIssues, Stats};
+
+/// Page of the directory report listing what is missing from it.
+pub(super) const ISSUES_PAGE: &str = "issues.html";
+
+/// Lists the logs that could not be read in full, and the processes of
+/// `stats` whose stacks could not be captured or that went unsampled for a
+/// while. Also returns how many problems were found.
+pub(super) fn render_issues(
+    files: &[(&String, &FileIssues)],
+    stats: &[Stats],
+    theme: Theme,
+) -> (usize, String) {
+    let time = |ms: i64| {
+        DateTime::from_timestamp_millis(ms)
+            .map(|t| t.with_timezone(&Local).to_string())
+            .unwrap_or_default()
+    };
+    let mut count = 0;
+    let mut out = theme.head();
+    out.push_str("<h1>Issues</h1>\n");
+    let mut section = |title: &str, header: &str, rows: Vec<String>| {
+        if rows.is_empty() {
+            return;
+        }
+        count += rows.len();
+        out.push_str(&format!(
+            "<h2>{}</h2>\n<table>\n<tr>{}</tr>\n",
+            title, header
+        ));
+        for row in rows {
+            out.push_str(&format!("<tr>{}</tr>\n", row));
+        }
+        out.push_str("</table>\n");
+    };
+    section(
+        "Unreadable logs",
+        "<th>Log</th><th>Error</th>",
+        files
+            .iter()
+            .filter_map(|(path, i)| {
+                let error = i.error.as_ref()?;
+                Some(format!(
+                    "<td>{}</td><td>{}</td>",
+                    encode_text(path),
+                    encode_text(error)
+                ))
+            })
+            .collect(),
+    );
+    section(
+        "Corrupt records",
+        "<th>Log</th><th>Skipped records</th>",
+        files
+            .iter()
+            .filter(|(_, i)| i.corrupt > 0)
+            .map(|(path, i)| format!("<td>{}</td><td>{}</td>", encode_text(path), i.corrupt))
+            .collect(),
+    );
+    section(
+        "Failed stack captures",
+        "<th>PID</th><th>Command</th><th>Error</th><th>Captures</th>",
+        stats
+            .iter()
+            .flat_map(|s| {
+                s.capture_errors.iter().map(move |(error, n)| {
+                    format!(
+                        "<td>{}</td><td>{}</td><td>{}</td><td>{}</td>",
+                        s.pid,
+                        encode_text(&s.cmd),
+                        encode_text(error),
+                        n
+                    )
+                })
+            })
+            .collect(),
+    );
+    section(
+        "Sampling gaps",
+        "<th>PID</th><th>Command</th><th>From</th><th>To</th><th>Length (sec)</th>",
+        stats
+            .iter()
+            .flat_map(|s| {
+                s.gaps.iter().map(move |&(from, to)| {
+                    format!(
+                        "<td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:.1}</td>",
+                        s.pid,
+                        encode_text(&s.cmd),
+                        time(from),
+                        time(to),
+                        (to - from) as f64 / 1000.0
+                    )
+                })
+            })
+            .collect(),
+    );
+    if count == 0 {
+        out.push_str("<p>No issues found</p>\n");
+    }
+    out.push_str("</body></html>\n");
+    (count, out)
+}
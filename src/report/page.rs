@@ -0,0 +1,398 @@
+use chrono::Local;
+use html_escape::encode_text;
+use log::warn;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::chart::{chart_spec, charts_html, write_chart_script};
+use super::graph::{
+    Downsampled, GraphField, local_series, memory_graph_name, series_label, write_svg,
+};
+use super::profile::{
+    HotFunctions, Speedscope, fold_stack, render_hot_functions, stack_samples, write_flamegraph,
+};
+use super::spikes::{SpikeStack, nearest_stacks, render_cpu_spikes};
+use super::threads::{ThreadCpu, render_thread_cpu, write_thread_svg};
+use super::trace::{TraceBuilder, TraceWriter, write_trace};
+use super::{ReportOptions, Stats};
+use crate::log::{LogEntry, ProcessKeys, TimeRange, visit_log_range};
+use crate::procinfo::redact_env;
+
+/// Writes a file of the process page with `write`, which skips it when
+/// there is nothing to show, and returns whether it exists.
+fn write_page_file(path: &Path, write: impl FnOnce(&Path) -> io::Result<()>) -> bool {
+    if let Err(e) = write(path) {
+        warn!("failed to write {}: {}", path.display(), e);
+        return false;
+    }
+    path.exists()
+}
+
+/// Files linked from a process page.
+struct PageLinks {
+    trace: bool,
+    flame: bool,
+    speedscope: bool,
+}
+
+/// Rows of the fd event table; a process opening files in a loop would
+/// otherwise produce a page too large to open.
+const FD_TABLE_MAX_ROWS: usize = 1000;
+
+/// The files and sockets opened and closed, in order.
+#[derive(Default)]
+struct FdTable {
+    /// The first `FD_TABLE_MAX_ROWS` events as table rows.
+    rows: String,
+    events: usize,
+}
+
+impl FdTable {
+    fn add(&mut self, e: &LogEntry) {
+        for ev in e.fd_events.iter().flatten() {
+            self.events += 1;
+            if self.events > FD_TABLE_MAX_ROWS {
+                continue;
+            }
+            let time = chrono::DateTime::parse_from_rfc3339(&e.timestamp)
+                .map(|t| t.with_timezone(&Local).format("%H:%M:%S%.3f").to_string())
+                .unwrap_or_else(|_| e.timestamp.clone());
+            self.rows.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                time,
+                ev.fd,
+                encode_text(&ev.event),
+                encode_text(&ev.path)
+            ));
+        }
+    }
+
+    fn render(&self) -> String {
+        if self.events == 0 {
+            return String::new();
+        }
+        let mut out = String::new();
+        out.push_str("<h2>FD events</h2>\n");
+        if self.events > FD_TABLE_MAX_ROWS {
+            out.push_str(&format!(
+                "<p>Showing the first {} of {} events.</p>\n",
+                FD_TABLE_MAX_ROWS, self.events
+            ));
+        }
+        out.push_str("<table>\n<tr><th>Time</th><th>FD</th><th>Event</th><th>Path</th></tr>\n");
+        out.push_str(&self.rows);
+        out.push_str("</table>\n");
+        out
+    }
+}
+
+/// What the page of one process shows, accumulated from its entries as
+/// they are read in time order, without keeping them.
+struct PageBuilder<'a> {
+    stats: &'a Stats,
+    /// An entry counts for the time until the next one, so each is taken
+    /// in when the next arrives. The last counts as long as the one before.
+    pending: Option<LogEntry>,
+    interval: i64,
+    /// Time of the first entry, which the speedscope profiles start at.
+    first: Option<i64>,
+    /// Points of the graphs, in the order of `GraphField::ALL`.
+    graphs: [Downsampled<f64>; 4],
+    threads: ThreadCpu,
+    hot: HotFunctions,
+    folded: HashMap<String, i64>,
+    speedscope: Option<Speedscope>,
+    spike_stacks: Vec<Option<SpikeStack>>,
+    fd: FdTable,
+    trace: TraceBuilder,
+    /// Trace file of the page, until writing it fails.
+    trace_file: Option<TraceWriter>,
+}
+
+impl<'a> PageBuilder<'a> {
+    fn new(s: &'a Stats, out_dir: &Path, opts: &ReportOptions) -> PageBuilder<'a> {
+        let trace_path = out_dir.join(format!("{}_trace.json", s.pid));
+        let trace_file = TraceWriter::create(trace_path.clone())
+            .inspect_err(|e| warn!("failed to write {}: {}", trace_path.display(), e))
+            .ok();
+        PageBuilder {
+            stats: s,
+            pending: None,
+            interval: 0,
+            first: None,
+            graphs: Default::default(),
+            threads: ThreadCpu::default(),
+            hot: HotFunctions::default(),
+            folded: HashMap::new(),
+            speedscope: opts.speedscope.then(Speedscope::default),
+            spike_stacks: vec![None; s.spikes.len()],
+            fd: FdTable::default(),
+            trace: TraceBuilder::default(),
+            trace_file,
+        }
+    }
+
+    /// Takes in the entry before `e`, also adding its trace events to
+    /// `combined`.
+    fn add(&mut self, e: LogEntry, combined: &mut Option<TraceWriter>) {
+        let time = e.time_millis();
+        if let Some(prev) = self.pending.replace(e) {
+            self.interval = time - prev.time_millis();
+            self.take(prev, combined);
+        }
+    }
+
+    fn take(&mut self, e: LogEntry, combined: &mut Option<TraceWriter>) {
+        let weight = self.interval.max(1);
+        let time = e.time_millis();
+        let first = *self.first.get_or_insert(time);
+        for (points, field) in self.graphs.iter_mut().zip(GraphField::ALL) {
+            points.push(time, field.value(&e));
+        }
+        self.threads.add(&e, weight);
+        for sample in stack_samples(&e, time - first, weight) {
+            self.hot.add(&sample);
+            fold_stack(&mut self.folded, &sample);
+            if let Some(speedscope) = &mut self.speedscope {
+                speedscope.add(&sample);
+            }
+        }
+        nearest_stacks(&mut self.spike_stacks, &self.stats.spikes, &e);
+        self.fd.add(&e);
+        let mut events = Vec::new();
+        self.trace.add(&e, &mut events);
+        write_trace(&mut self.trace_file, &events);
+        write_trace(combined, &events);
+    }
+
+    /// Writes the page to `html` with its graphs and stack views next to
+    /// it, and `family` linking its parent and children.
+    fn write(
+        mut self,
+        out_dir: &Path,
+        html: &Path,
+        family: &str,
+        opts: &ReportOptions,
+        combined: &mut Option<TraceWriter>,
+    ) {
+        if let Some(last) = self.pending.take() {
+            self.take(last, combined);
+        }
+        let mut events = Vec::new();
+        self.trace.finish(&mut events);
+        write_trace(&mut self.trace_file, &events);
+        write_trace(combined, &events);
+
+        let s = self.stats;
+        let pid = s.pid;
+        for (points, field) in self.graphs.iter().zip(GraphField::ALL) {
+            let path = match field {
+                GraphField::Cpu => out_dir.join(format!("{}_cpu.svg", pid)),
+                _ => out_dir.join(memory_graph_name(pid, field)),
+            };
+            if let Err(e) = write_svg(local_series(&points.points), s, &path, field, opts.style) {
+                warn!("failed to write {}: {}", path.display(), e);
+            }
+        }
+        let trace_path = out_dir.join(format!("{}_trace.json", pid));
+        let trace = match self.trace_file.take().map(TraceWriter::finish) {
+            Some(Ok(0)) => fs::remove_file(&trace_path).is_err(),
+            Some(Ok(_)) => true,
+            Some(Err(e)) => {
+                warn!("failed to write {}: {}", trace_path.display(), e);
+                false
+            }
+            None => false,
+        };
+        let speedscope = self.speedscope.take();
+        let links = PageLinks {
+            trace,
+            flame: write_page_file(&out_dir.join(format!("{}_flame.svg", pid)), |p| {
+                write_flamegraph(self.folded, p)
+            }),
+            speedscope: speedscope.is_some_and(|profile| {
+                write_page_file(&out_dir.join(format!("{}.speedscope.json", pid)), |p| {
+                    profile.write(pid, p)
+                })
+            }),
+        };
+        let label = series_label(s);
+        let spec = |field: GraphField| {
+            let points = &self.graphs[field as usize].points;
+            chart_spec(field, &[(label.clone(), local_series(points))])
+        };
+        let mut cpu_spec = spec(GraphField::Cpu);
+        cpu_spec["bands"] = s.spikes.iter().map(|s| json!([s.start, s.end])).collect();
+        let ids: Vec<String> = GraphField::MEMORY
+            .iter()
+            .map(|f| format!("{}_{}_chart", pid, f.stem()))
+            .collect();
+        let cpu_id = format!("{}_cpu_chart", pid);
+        let mut specs = vec![(cpu_id.as_str(), cpu_spec)];
+        for (id, field) in ids.iter().zip(GraphField::MEMORY) {
+            specs.push((id, spec(field)));
+        }
+        let charts = charts_html(&specs, &opts.chart_zone);
+        write_chart_script(out_dir);
+        let threads = self.threads.ranked();
+        let has_thread_svg = !threads.is_empty()
+            && write_page_file(&out_dir.join(format!("{}_threads.svg", pid)), |p| {
+                write_thread_svg(&self.threads.points.points, &threads, p, opts.style)
+            });
+        let extra = family.to_string()
+            + &charts
+            + &render_cpu_spikes(&s.spikes, &self.spike_stacks)
+            + &render_thread_cpu(pid, &threads, has_thread_svg)
+            + &render_hot_functions(self.hot)
+            + &self.fd.render();
+        if let Err(e) = fs::write(html, render_single(s, &links, &extra, opts)) {
+            warn!("failed to write {}: {}", html.display(), e);
+        }
+    }
+}
+
+/// Writes the pages of `pages`, each a process with the file of its page
+/// and its family section, from one pass over the logs they were computed
+/// from. Also writes the trace of them all to `combined`, if given. Returns
+/// the logs that could not be read.
+pub(super) fn write_pages(
+    pages: &[(&Stats, PathBuf, String)],
+    out_dir: &Path,
+    range: &TimeRange,
+    opts: &ReportOptions,
+    combined: Option<&Path>,
+) -> HashSet<String> {
+    let mut builders: Vec<PageBuilder> = pages
+        .iter()
+        .map(|(s, _, _)| PageBuilder::new(s, out_dir, opts))
+        .collect();
+    let mut combined = combined.and_then(|path| {
+        TraceWriter::create(path.to_path_buf())
+            .inspect_err(|e| warn!("failed to write {}: {}", path.display(), e))
+            .ok()
+    });
+    // Logs in order of their paths, which is the time order of the logs
+    // of each process.
+    let mut files: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    for (i, (s, _, _)) in pages.iter().enumerate() {
+        for path in &s.paths {
+            files.entry(path).or_default().push(i);
+        }
+    }
+    let mut failed = HashSet::new();
+    for (path, readers) in files {
+        let mut keys = ProcessKeys::default();
+        let read = visit_log_range(Path::new(path), range, |e| {
+            let key = keys.key(&e);
+            if let Some(&i) = readers.iter().find(|&&i| pages[i].0.key() == key) {
+                builders[i].add(e, &mut combined);
+            }
+        });
+        if let Err(e) = read {
+            warn!("failed to read {}: {}", path, e);
+            failed.insert(path.to_string());
+        }
+    }
+    for (builder, (_, html, family)) in builders.into_iter().zip(pages) {
+        builder.write(out_dir, html, family, opts, &mut combined);
+    }
+    if let Some(trace) = combined {
+        let path = trace.path.clone();
+        if let Err(e) = trace.finish() {
+            warn!("failed to write {}: {}", path.display(), e);
+        }
+    }
+    failed
+}
+
+pub(super) fn truncate(s: &str, len: usize) -> String {
+    let mut out = String::new();
+    for (i, c) in s.chars().enumerate() {
+        if i >= len {
+            out.push_str("...");
+            break;
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Renders the page of one process; `sections` is appended after the
+/// graphs.
+fn render_single(s: &Stats, links: &PageLinks, sections: &str, opts: &ReportOptions) -> String {
+    let mut out = opts.style.theme.head();
+    out.push_str(&format!("<h1>Report for PID {}</h1>\n", s.pid));
+    out.push_str(&format!("<p>Command: {}</p>\n", encode_text(&s.cmd)));
+    if let Some(host) = &s.host {
+        out.push_str(&format!("<p>Host: {}</p>\n", encode_text(host)));
+    }
+    out.push_str("<ul>\n");
+    out.push_str(&format!("<li>Total runtime: {} sec</li>\n", s.runtime));
+    out.push_str(&format!("<li>Total CPU time: {:.1} sec</li>\n", s.cpu));
+    out.push_str(&format!("<li>Average CPU usage: {:.1}%</li>\n", s.avg_cpu));
+    out.push_str(&format!("<li>Peak RSS: {} KB</li>\n", s.peak_rss));
+    out.push_str("</ul>\n");
+    if let Some(e) = &s.env {
+        if !e.is_empty() {
+            out.push_str(&format!(
+                "<details><summary>Environment</summary><pre>{}</pre></details>\n",
+                encode_text(&redact_env(e, &opts.redact_env))
+            ));
+        }
+    } else {
+        out.push_str("<p>Environment: unknown</p>\n");
+    }
+    out.push_str(&format!(
+        "<p>CPU usage<br><img src=\"{}_cpu.svg\" alt=\"CPU usage graph\" /></p>\n",
+        s.pid
+    ));
+    for field in GraphField::MEMORY {
+        out.push_str(&format!(
+            "<p>{0}<br><img src=\"{1}\" alt=\"{0} graph\" /></p>\n",
+            field.names().0,
+            memory_graph_name(s.pid, field)
+        ));
+    }
+    out.push_str(sections);
+    if links.trace {
+        out.push_str(&format!(
+            "<p><a href=\"{}_trace.json\">Trace JSON</a></p>\n",
+            s.pid
+        ));
+    }
+    if links.flame {
+        out.push_str(&format!(
+            "<p><a href=\"{}_flame.svg\">Flame graph</a></p>\n",
+            s.pid
+        ));
+    }
+    if links.speedscope {
+        out.push_str(&format!(
+            "<p><a href=\"{}.speedscope.json\">Speedscope profile</a></p>\n",
+            s.pid
+        ));
+    }
+    if !s.python_threads.is_empty() {
+        out.push_str("<h2>Python threads</h2>\n<table>\n");
+        out.push_str(
+            "<tr><th>TID</th><th>Name</th><th>Samples</th><th>Holding GIL (%)</th><th>Active (%)</th></tr>\n",
+        );
+        for t in &s.python_threads {
+            let pct = |n: usize| n as f64 * 100.0 / t.samples as f64;
+            out.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{:.1}</td><td>{:.1}</td></tr>\n",
+                t.tid,
+                encode_text(t.name.as_deref().unwrap_or("")),
+                t.samples,
+                pct(t.owns_gil),
+                pct(t.active)
+            ));
+        }
+        out.push_str("</table>\n");
+    }
+    out.push_str("</body></html>\n");
+    out
+}
@@ -0,0 +1,39 @@
+use log::warn;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use crate::upload::uri_encode;
+
+/// Prints each of `pages` to a PDF file next to it with `browser` running
+/// headless, for reports that have to be attached as static files.
+pub(super) fn write_pdfs(pages: &[PathBuf], browser: &str) {
+    for page in pages {
+        let page = match fs::canonicalize(page) {
+            Ok(p) => p,
+            Err(e) => {
+                warn!("failed to find {}: {}", page.display(), e);
+                continue;
+            }
+        };
+        let pdf = page.with_extension("pdf");
+        let status = Command::new(browser)
+            .args(["--headless", "--disable-gpu", "--no-pdf-header-footer"])
+            .arg(format!("--print-to-pdf={}", pdf.display()))
+            .arg(format!(
+                "file://{}",
+                uri_encode(&page.to_string_lossy(), true)
+            ))
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        match status {
+            Ok(s) if s.success() => {}
+            Ok(s) => warn!("{} failed to print {}: {}", browser, page.display(), s),
+            Err(e) => {
+                warn!("failed to run {}: {}", browser, e);
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use html_escape::encode_text;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::log::{Frame, LogEntry, frame_name, thread_stack};
+
+/// One recorded thread stack, innermost frame first.
+pub(super) struct StackSample<'a> {
+    tid: u32,
+    /// Milliseconds since the first entry.
+    offset: i64,
+    /// Milliseconds until the next entry.
+    weight: i64,
+    frames: &'a [Frame],
+}
+
+/// The stacks of all threads of `e`, which is `offset` milliseconds after
+/// the first entry and counts for `weight` milliseconds.
+pub(super) fn stack_samples(
+    e: &LogEntry,
+    offset: i64,
+    weight: i64,
+) -> impl Iterator<Item = StackSample<'_>> {
+    e.threads.iter().filter_map(move |t| {
+        Some(StackSample {
+            tid: t.tid,
+            offset,
+            weight,
+            frames: thread_stack(t)?,
+        })
+    })
+}
+
+/// Sampled time of a function over all threads, in milliseconds.
+struct FunctionTime {
+    name: String,
+    /// Time as the innermost frame.
+    self_ms: i64,
+    /// Time anywhere on the stack.
+    total_ms: i64,
+}
+
+/// Sums of the sampled stacks by innermost function and by whole stack.
+#[derive(Default)]
+pub(super) struct HotFunctions {
+    /// Self and total time of each function.
+    functions: HashMap<String, (i64, i64)>,
+    stacks: HashMap<String, i64>,
+    total: i64,
+}
+
+impl HotFunctions {
+    pub(super) fn add(&mut self, sample: &StackSample) {
+        let names: Vec<String> = sample.frames.iter().map(frame_name).collect();
+        self.total += sample.weight;
+        self.functions.entry(names[0].clone()).or_default().0 += sample.weight;
+        // Recursion counts once per sample.
+        let mut seen = HashSet::new();
+        for name in &names {
+            if seen.insert(name) {
+                self.functions.entry(name.clone()).or_default().1 += sample.weight;
+            }
+        }
+        *self.stacks.entry(names.join(" < ")).or_default() += sample.weight;
+    }
+
+    /// The functions and the stacks, hottest first.
+    fn ranked(self) -> (Vec<FunctionTime>, Vec<(String, i64)>) {
+        let mut functions: Vec<FunctionTime> = self
+            .functions
+            .into_iter()
+            .map(|(name, (self_ms, total_ms))| FunctionTime {
+                name,
+                self_ms,
+                total_ms,
+            })
+            .collect();
+        functions.sort_by(|a, b| {
+            (b.self_ms, b.total_ms)
+                .cmp(&(a.self_ms, a.total_ms))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        let mut stacks: Vec<(String, i64)> = self.stacks.into_iter().collect();
+        stacks.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        (functions, stacks)
+    }
+}
+
+/// Rows of the hottest function and stack tables.
+const HOT_TABLE_ROWS: usize = 20;
+
+/// Tables of where the sampled time went, the gist of a profile without
+/// opening the trace.
+pub(super) fn render_hot_functions(hot: HotFunctions) -> String {
+    let total = hot.total;
+    if total == 0 {
+        return String::new();
+    }
+    let (functions, stacks) = hot.ranked();
+    let percent = |ms: i64| ms as f64 * 100.0 / total as f64;
+    let mut out = String::new();
+    out.push_str("<h2>Hottest functions</h2>\n");
+    out.push_str("<table>\n<tr><th>Function</th><th>Self (sec)</th><th>Self (%)</th><th>Total (%)</th></tr>\n");
+    for f in functions.iter().take(HOT_TABLE_ROWS) {
+        out.push_str(&format!(
+            "<tr><td>{}</td><td>{:.1}</td><td>{:.1}</td><td>{:.1}</td></tr>\n",
+            encode_text(&f.name),
+            f.self_ms as f64 / 1000.0,
+            percent(f.self_ms),
+            percent(f.total_ms)
+        ));
+    }
+    out.push_str("</table>\n");
+    out.push_str("<h2>Hottest stacks</h2>\n");
+    out.push_str("<table>\n<tr><th>Stack</th><th>Time (sec)</th><th>Time (%)</th></tr>\n");
+    for (stack, ms) in stacks.iter().take(HOT_TABLE_ROWS) {
+        out.push_str(&format!(
+            "<tr><td>{}</td><td>{:.1}</td><td>{:.1}</td></tr>\n",
+            encode_text(stack),
+            *ms as f64 / 1000.0,
+            percent(*ms)
+        ));
+    }
+    out.push_str("</table>\n");
+    out
+}
+
+/// Adds `sample` to the folded stacks, whose lines start at the root.
+pub(super) fn fold_stack(folded: &mut HashMap<String, i64>, sample: &StackSample) {
+    let names: Vec<String> = sample
+        .frames
+        .iter()
+        .rev()
+        .map(|f| frame_name(f).replace(';', ":"))
+        .collect();
+    *folded.entry(names.join(";")).or_default() += sample.weight;
+}
+
+pub(super) fn write_flamegraph(folded: HashMap<String, i64>, out: &Path) -> io::Result<()> {
+    if folded.is_empty() {
+        return Ok(());
+    }
+    let mut lines: Vec<String> = folded
+        .into_iter()
+        .map(|(stack, ms)| format!("{} {}", stack, ms))
+        .collect();
+    lines.sort();
+    let mut opts = inferno::flamegraph::Options::default();
+    // Samples weigh the wall time until the next one, busy or not.
+    opts.title = "Sampled time by stack".into();
+    opts.count_name = "ms".into();
+    let file = io::BufWriter::new(fs::File::create(out)?);
+    inferno::flamegraph::from_lines(&mut opts, lines.iter().map(String::as_str), file)
+        .map_err(io::Error::other)
+}
+
+/// Stacks of one thread as frame indices, root first, with their weights.
+#[derive(Default)]
+struct ThreadProfile {
+    samples: Vec<Vec<usize>>,
+    weights: Vec<i64>,
+    start: i64,
+    end: i64,
+}
+
+/// The stacks in the speedscope file format, one sampled profile per thread
+/// so its time order view follows each thread.
+#[derive(Default)]
+pub(super) struct Speedscope {
+    frames: Vec<serde_json::Value>,
+    frame_ids: HashMap<(String, Option<String>, Option<i32>), usize>,
+    threads: BTreeMap<u32, ThreadProfile>,
+}
+
+impl Speedscope {
+    pub(super) fn add(&mut self, sample: &StackSample) {
+        let stack: Vec<usize> = sample
+            .frames
+            .iter()
+            .rev()
+            .map(|f| {
+                let key = (frame_name(f), f.file.clone(), f.line);
+                *self
+                    .frame_ids
+                    .entry(key)
+                    .or_insert_with_key(|(name, file, line)| {
+                        let mut frame = json!({ "name": name });
+                        if let Some(file) = file {
+                            frame["file"] = json!(file);
+                        }
+                        if let Some(line) = line {
+                            frame["line"] = json!(line);
+                        }
+                        self.frames.push(frame);
+                        self.frames.len() - 1
+                    })
+            })
+            .collect();
+        let profile = self
+            .threads
+            .entry(sample.tid)
+            .or_insert_with(|| ThreadProfile {
+                start: sample.offset,
+                ..Default::default()
+            });
+        // A stack that did not change is one longer sample.
+        match profile.weights.last_mut() {
+            Some(weight) if profile.samples.last() == Some(&stack) => *weight += sample.weight,
+            _ => {
+                profile.samples.push(stack);
+                profile.weights.push(sample.weight);
+            }
+        }
+        profile.end = sample.offset + sample.weight;
+    }
+
+    pub(super) fn write(self, pid: u32, out: &Path) -> io::Result<()> {
+        if self.threads.is_empty() {
+            return Ok(());
+        }
+        let profiles: Vec<serde_json::Value> = self
+            .threads
+            .into_iter()
+            .map(|(tid, p)| {
+                json!({
+                    "type": "sampled",
+                    "name": format!("tid {}", tid),
+                    "unit": "milliseconds",
+                    "startValue": p.start,
+                    "endValue": p.end,
+                    "samples": p.samples,
+                    "weights": p.weights,
+                })
+            })
+            .collect();
+        let file = json!({
+            "$schema": "https://www.speedscope.app/file-format-schema.json",
+            "name": format!("pid {}", pid),
+            "exporter": "fuzmon",
+            "shared": { "frames": self.frames },
+            "profiles": profiles,
+        });
+        fs::write(out, serde_json::to_vec(&file)?)
+    }
+}
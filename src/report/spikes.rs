@@ -0,0 +1,137 @@
+use chrono::{DateTime, Local};
+use html_escape::encode_text;
+use serde::{Deserialize, Serialize};
+
+use crate::log::{LogEntry, frame_name, thread_stack};
+
+/// A period in which CPU usage stayed at or above the spike threshold, in
+/// milliseconds since the epoch.
+#[derive(Clone, Serialize, Deserialize)]
+pub(super) struct CpuSpike {
+    pub(super) start: i64,
+    pub(super) end: i64,
+    pub(super) peak: f64,
+}
+
+/// Finds the periods of at least `min_ms` in which every sample used
+/// `threshold` percent CPU or more. A sample covers the time until the next
+/// one, as in the CPU time totals.
+#[derive(Default)]
+pub(super) struct SpikeFinder {
+    threshold: f64,
+    min_ms: i64,
+    current: Option<CpuSpike>,
+    spikes: Vec<CpuSpike>,
+}
+
+impl SpikeFinder {
+    /// Finds the periods of at least `min_ms` at or above `threshold`.
+    pub(super) fn new(threshold: f64, min_ms: i64) -> SpikeFinder {
+        SpikeFinder {
+            threshold,
+            min_ms,
+            ..Default::default()
+        }
+    }
+
+    pub(super) fn add(&mut self, time: i64, cpu_percent: f64) {
+        if let Some(spike) = &mut self.current {
+            spike.end = spike.end.max(time);
+        }
+        if cpu_percent >= self.threshold {
+            let spike = self.current.get_or_insert(CpuSpike {
+                start: time,
+                end: time,
+                peak: 0.0,
+            });
+            spike.peak = spike.peak.max(cpu_percent);
+        } else {
+            self.close();
+        }
+    }
+
+    fn close(&mut self) {
+        if let Some(spike) = self.current.take()
+            && spike.end - spike.start >= self.min_ms
+        {
+            self.spikes.push(spike);
+        }
+    }
+
+    pub(super) fn finish(mut self) -> Vec<CpuSpike> {
+        self.close();
+        self.spikes
+    }
+}
+
+/// Stack recorded nearest to a CPU spike: how far from it, when, and the
+/// stacks of all threads as HTML.
+pub(super) type SpikeStack = (i64, i64, String);
+
+/// Keeps the entry with a recorded stack closest to each of `spikes`,
+/// preferring ones inside it.
+pub(super) fn nearest_stacks(
+    nearest: &mut [Option<SpikeStack>],
+    spikes: &[CpuSpike],
+    e: &LogEntry,
+) {
+    if !e.threads.iter().any(|t| thread_stack(t).is_some()) {
+        return;
+    }
+    let t = e.time_millis();
+    for (spike, nearest) in spikes.iter().zip(nearest) {
+        let distance = (spike.start - t).max(t - spike.end).max(0);
+        if nearest.as_ref().is_some_and(|(d, _, _)| *d <= distance) {
+            continue;
+        }
+        let mut stacks = String::new();
+        for th in &e.threads {
+            if let Some(frames) = thread_stack(th) {
+                stacks.push_str(&format!("thread {}\n", th.tid));
+                for f in frames {
+                    stacks.push_str(&format!("  {}\n", encode_text(&frame_name(f))));
+                }
+            }
+        }
+        *nearest = Some((distance, t, stacks));
+    }
+}
+
+/// Lists the CPU spikes with the stacks captured nearest to each.
+pub(super) fn render_cpu_spikes(spikes: &[CpuSpike], nearest: &[Option<SpikeStack>]) -> String {
+    if spikes.is_empty() {
+        return String::new();
+    }
+    let time = |ms: i64| {
+        DateTime::from_timestamp_millis(ms)
+            .map(|t| t.with_timezone(&Local).format("%H:%M:%S").to_string())
+            .unwrap_or_default()
+    };
+    let mut table = String::from(
+        "<h2>CPU spikes</h2>\n<table>\n<tr><th>Start</th><th>Duration (sec)</th><th>Peak CPU (%)</th><th>Nearest stack</th></tr>\n",
+    );
+    let mut stacks = String::new();
+    for (i, (spike, nearest)) in spikes.iter().zip(nearest).enumerate() {
+        let link = match nearest {
+            Some((_, at, pre)) => {
+                stacks.push_str(&format!(
+                    "<h3 id=\"spike_stack_{}\">Stack at {}</h3>\n<pre>{}</pre>\n",
+                    i,
+                    time(*at),
+                    pre
+                ));
+                format!("<a href=\"#spike_stack_{}\">{}</a>", i, time(*at))
+            }
+            None => String::new(),
+        };
+        table.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{:.1}</td><td>{}</td></tr>\n",
+            time(spike.start),
+            (spike.end - spike.start) / 1000,
+            spike.peak,
+            link
+        ));
+    }
+    table.push_str("</table>\n");
+    table + &stacks
+}
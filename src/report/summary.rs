@@ -0,0 +1,174 @@
+use chrono::{DateTime, SecondsFormat};
+use log::warn;
+use serde_json::json;
+use std::fs;
+use std::path::Path;
+
+use super::Stats;
+use super::index::possible_leaks;
+
+fn utc_time(ms: i64) -> String {
+    DateTime::from_timestamp_millis(ms)
+        .map(|t| t.to_rfc3339_opts(SecondsFormat::Millis, true))
+        .unwrap_or_default()
+}
+
+/// The stats of every process and the anomalies found, for CI jobs to
+/// check instead of the HTML.
+pub(super) fn summary_json(stats: &[Stats], leak_slope: f64) -> serde_json::Value {
+    let mut sorted: Vec<&Stats> = stats.iter().collect();
+    sorted.sort_by_key(|s| (s.pid, s.start));
+    let processes: Vec<serde_json::Value> = sorted
+        .iter()
+        .map(|s| {
+            json!({
+                "pid": s.pid,
+                "ppid": s.ppid,
+                "name": s.name,
+                "cmdline": s.cmd,
+                "host": s.host,
+                "start": utc_time(s.start.timestamp_millis()),
+                "end": utc_time(s.end.timestamp_millis()),
+                "runtime_sec": s.runtime,
+                "cpu_time_sec": s.cpu,
+                "avg_cpu_percent": s.avg_cpu,
+                "peak_rss_kb": s.peak_rss,
+                "rss_growth_kb_per_min": s.rss_growth,
+            })
+        })
+        .collect();
+    let leaks: Vec<serde_json::Value> = possible_leaks(stats, leak_slope)
+        .into_iter()
+        .map(|(s, growth)| json!({ "pid": s.pid, "name": s.name, "rss_growth_kb_per_min": growth }))
+        .collect();
+    let spikes: Vec<serde_json::Value> = sorted
+        .iter()
+        .flat_map(|s| {
+            s.spikes.iter().map(|spike| {
+                json!({
+                    "pid": s.pid,
+                    "name": s.name,
+                    "start": utc_time(spike.start),
+                    "end": utc_time(spike.end),
+                    "duration_sec": (spike.end - spike.start) / 1000,
+                    "peak_cpu_percent": spike.peak,
+                })
+            })
+        })
+        .collect();
+    json!({
+        "processes": processes,
+        "anomalies": { "possible_leaks": leaks, "cpu_spikes": spikes },
+    })
+}
+
+/// Renders `summary` as Markdown, e.g. for a CI job summary.
+fn summary_markdown(summary: &serde_json::Value) -> String {
+    let cell = |v: &serde_json::Value| match v {
+        serde_json::Value::String(s) => s.replace('|', "\\|"),
+        serde_json::Value::Number(n) => match n.as_f64() {
+            Some(f) if !n.is_u64() && !n.is_i64() => format!("{:.1}", f),
+            _ => n.to_string(),
+        },
+        _ => String::new(),
+    };
+    let mut out = String::from("# fuzmon report summary\n\n");
+    out.push_str("| PID | Name | Runtime (sec) | CPU time (sec) | Avg CPU (%) | Peak RSS (KB) |\n");
+    out.push_str("|---|---|---|---|---|---|\n");
+    for p in summary["processes"].as_array().into_iter().flatten() {
+        let row: Vec<String> = [
+            "pid",
+            "name",
+            "runtime_sec",
+            "cpu_time_sec",
+            "avg_cpu_percent",
+            "peak_rss_kb",
+        ]
+        .iter()
+        .map(|k| cell(&p[*k]))
+        .collect();
+        out.push_str(&format!("| {} |\n", row.join(" | ")));
+    }
+    let anomalies = &summary["anomalies"];
+    if let Some(leaks) = anomalies["possible_leaks"].as_array()
+        && !leaks.is_empty()
+    {
+        out.push_str("\n## Possible leaks\n\n");
+        for l in leaks {
+            out.push_str(&format!(
+                "- PID {} ({}): RSS grows by {} KB/min\n",
+                cell(&l["pid"]),
+                cell(&l["name"]),
+                cell(&l["rss_growth_kb_per_min"])
+            ));
+        }
+    }
+    if let Some(spikes) = anomalies["cpu_spikes"].as_array()
+        && !spikes.is_empty()
+    {
+        out.push_str("\n## CPU spikes\n\n");
+        for s in spikes {
+            out.push_str(&format!(
+                "- PID {} ({}): {} for {} sec, peak {}%\n",
+                cell(&s["pid"]),
+                cell(&s["name"]),
+                cell(&s["start"]),
+                cell(&s["duration_sec"]),
+                cell(&s["peak_cpu_percent"])
+            ));
+        }
+    }
+    out
+}
+
+/// Columns of the index process table, as keys of the summary processes.
+const CSV_COLUMNS: [(&str, &str); 8] = [
+    ("pid", "pid"),
+    ("cmd", "cmdline"),
+    ("runtime_sec", "runtime_sec"),
+    ("cpu_time_sec", "cpu_time_sec"),
+    ("avg_cpu_percent", "avg_cpu_percent"),
+    ("peak_rss_kb", "peak_rss_kb"),
+    ("start", "start"),
+    ("end", "end"),
+];
+
+/// Renders the processes of the summary as CSV for spreadsheets.
+fn summary_csv(summary: &serde_json::Value) -> String {
+    let cell = |v: &serde_json::Value| match v {
+        serde_json::Value::String(s) if s.contains([',', '"', '\n', '\r']) => {
+            format!("\"{}\"", s.replace('"', "\"\""))
+        }
+        serde_json::Value::String(s) => s.clone(),
+        serde_json::Value::Null => String::new(),
+        v => v.to_string(),
+    };
+    let header: Vec<&str> = CSV_COLUMNS.iter().map(|(name, _)| *name).collect();
+    let mut out = format!("{}\n", header.join(","));
+    for p in summary["processes"].as_array().into_iter().flatten() {
+        let row: Vec<String> = CSV_COLUMNS.iter().map(|(_, k)| cell(&p[*k])).collect();
+        out.push_str(&format!("{}\n", row.join(",")));
+    }
+    out
+}
+
+/// Writes `summary.json` and `summary.csv` next to the index, and
+/// `summary.md` with `markdown`.
+pub(super) fn write_summary(out_dir: &Path, stats: &[Stats], leak_slope: f64, markdown: bool) {
+    let summary = summary_json(stats, leak_slope);
+    let path = out_dir.join("summary.json");
+    let json = serde_json::to_string_pretty(&summary).unwrap_or_default();
+    if let Err(e) = fs::write(&path, json) {
+        warn!("failed to write {}: {}", path.display(), e);
+    }
+    let path = out_dir.join("summary.csv");
+    if let Err(e) = fs::write(&path, summary_csv(&summary)) {
+        warn!("failed to write {}: {}", path.display(), e);
+    }
+    if markdown {
+        let path = out_dir.join("summary.md");
+        if let Err(e) = fs::write(&path, summary_markdown(&summary)) {
+            warn!("failed to write {}: {}", path.display(), e);
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use chrono::{DateTime, Local};
+use html_escape::encode_text;
+use plotters::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::path::Path;
+
+use super::graph::{Downsampled, GraphStyle, Series, themed_legend, themed_mesh};
+use crate::log::{LogEntry, frame_name, thread_stack};
+
+/// How much CPU one thread used over the run.
+pub(super) struct ThreadCpuStats {
+    tid: u32,
+    name: Option<String>,
+    /// CPU seconds.
+    cpu: f64,
+    peak: f64,
+    /// Innermost frames of the stack seen for the longest time.
+    top_stack: Option<String>,
+}
+
+/// Frames of the most common stack shown in the thread table.
+const TOP_STACK_FRAMES: usize = 3;
+
+/// CPU usage of the threads with recorded usage, for the thread table and
+/// the stacked chart.
+#[derive(Default)]
+pub(super) struct ThreadCpu {
+    /// With the time each stack was seen for.
+    by_tid: BTreeMap<u32, (ThreadCpuStats, HashMap<String, i64>)>,
+    /// Usage of every thread at each point of the chart.
+    pub(super) points: Downsampled<HashMap<u32, f64>>,
+}
+
+impl ThreadCpu {
+    pub(super) fn add(&mut self, e: &LogEntry, weight: i64) {
+        let mut usage = HashMap::new();
+        for t in &e.threads {
+            let Some(percent) = t.cpu_time_percent else {
+                continue;
+            };
+            *usage.entry(t.tid).or_default() += percent;
+            let (s, stacks) = self.by_tid.entry(t.tid).or_insert_with(|| {
+                let s = ThreadCpuStats {
+                    tid: t.tid,
+                    name: None,
+                    cpu: 0.0,
+                    peak: 0.0,
+                    top_stack: None,
+                };
+                (s, HashMap::new())
+            });
+            s.cpu += percent * weight as f64 / 100_000.0;
+            s.peak = s.peak.max(percent);
+            if let Some(name) = t
+                .python_thread
+                .as_ref()
+                .and_then(|p| p.name.clone())
+                .or_else(|| t.name.clone())
+            {
+                s.name = Some(name);
+            }
+            if let Some(stack) = thread_stack(t) {
+                let names: Vec<String> = stack
+                    .iter()
+                    .take(TOP_STACK_FRAMES)
+                    .map(frame_name)
+                    .collect();
+                *stacks.entry(names.join(" < ")).or_default() += weight;
+            }
+        }
+        if !usage.is_empty() {
+            self.points.push(e.time_millis(), usage);
+        }
+    }
+
+    /// The threads, busiest first.
+    pub(super) fn ranked(&mut self) -> Vec<ThreadCpuStats> {
+        let mut stats: Vec<ThreadCpuStats> = std::mem::take(&mut self.by_tid)
+            .into_values()
+            .map(|(mut s, stacks)| {
+                s.top_stack = stacks
+                    .into_iter()
+                    .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)))
+                    .map(|(stack, _)| stack);
+                s
+            })
+            .collect();
+        stats.sort_by(|a, b| b.cpu.total_cmp(&a.cpu).then(a.tid.cmp(&b.tid)));
+        stats
+    }
+}
+
+/// Threads drawn separately in the stacked chart; the rest are summed up.
+const CHART_THREADS: usize = 8;
+
+/// Draws the CPU usage of the busiest threads stacked on each other.
+pub(super) fn write_thread_svg(
+    points: &[(i64, HashMap<u32, f64>)],
+    threads: &[ThreadCpuStats],
+    out: &Path,
+    style: GraphStyle,
+) -> io::Result<()> {
+    let tids: Vec<u32> = threads.iter().take(CHART_THREADS).map(|t| t.tid).collect();
+    let mut layers: Vec<Series> = vec![Vec::new(); tids.len() + 1];
+    for (ms, usage) in points {
+        let Some(t) = DateTime::from_timestamp_millis(*ms) else {
+            continue;
+        };
+        let t = t.with_timezone(&Local);
+        let mut values = vec![0.0; tids.len() + 1];
+        for (tid, v) in usage {
+            let i = tids.iter().position(|t| t == tid).unwrap_or(tids.len());
+            values[i] += v;
+        }
+        // Each layer is drawn from zero up to the sum of the layers below.
+        let mut sum = 0.0;
+        for (layer, v) in layers.iter_mut().zip(values) {
+            sum += v;
+            layer.push((t, sum));
+        }
+    }
+    let top = layers.last().cloned().unwrap_or_default();
+    let (Some(start), Some(end)) = (top.first().map(|p| p.0), top.last().map(|p| p.0)) else {
+        return Ok(());
+    };
+    let y_max = top.iter().map(|p| p.1).fold(1.0f64, f64::max);
+    let root = SVGBackend::new(out, style.size()).into_drawing_area();
+    root.fill(&style.theme.background())
+        .map_err(io::Error::other)?;
+    let mut chart = style
+        .chart(&root, "CPU usage by thread (%)")
+        .build_cartesian_2d(start..end, 0f64..y_max)
+        .map_err(io::Error::other)?;
+    themed_mesh(chart.configure_mesh(), style)
+        .x_desc("time")
+        .y_desc("CPU %")
+        .x_label_formatter(&|dt| dt.format("%H:%M:%S").to_string())
+        .draw()
+        .map_err(io::Error::other)?;
+    // Highest layer first so the lower ones are painted over it.
+    for (i, layer) in layers.into_iter().enumerate().rev() {
+        let label = tids
+            .get(i)
+            .map_or_else(|| "other".to_string(), |tid| tid.to_string());
+        let color = Palette99::pick(i).mix(0.8);
+        chart
+            .draw_series(AreaSeries::new(layer, 0.0, color.filled()))
+            .map_err(io::Error::other)?
+            .label(label)
+            .legend(move |(x, y)| Rectangle::new([(x, y - 4), (x + 12, y + 4)], color.filled()));
+    }
+    themed_legend(chart.configure_series_labels(), style)
+        .draw()
+        .map_err(io::Error::other)?;
+    root.present().map_err(io::Error::other)
+}
+
+/// Rows of the hottest thread table.
+const THREAD_TABLE_ROWS: usize = 10;
+
+pub(super) fn render_thread_cpu(pid: u32, threads: &[ThreadCpuStats], has_svg: bool) -> String {
+    if threads.is_empty() {
+        return String::new();
+    }
+    let mut out = String::new();
+    out.push_str("<h2>Threads by CPU</h2>\n");
+    if has_svg {
+        out.push_str(&format!(
+            "<p><img src=\"{}_threads.svg\" alt=\"CPU usage by thread graph\" /></p>\n",
+            pid
+        ));
+    }
+    out.push_str("<table>\n<tr><th>TID</th><th>Name</th><th>CPU time (sec)</th><th>Peak CPU (%)</th><th>Most common stack</th></tr>\n");
+    for t in threads.iter().take(THREAD_TABLE_ROWS) {
+        out.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{:.1}</td><td>{:.1}</td><td>{}</td></tr>\n",
+            t.tid,
+            encode_text(t.name.as_deref().unwrap_or("")),
+            t.cpu,
+            t.peak,
+            encode_text(t.top_stack.as_deref().unwrap_or(""))
+        ));
+    }
+    out.push_str("</table>\n");
+    out
+}
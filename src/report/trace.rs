@@ -0,0 +1,225 @@
+use log::warn;
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use crate::log::{Frame, LogEntry, frame_name};
+
+/// File name of the trace of all processes in a directory report.
+pub(super) const COMBINED_TRACE: &str = "trace.json";
+
+/// Trace row of the fd events. Thread rows are derived from the tid, which
+/// is never 0.
+const FD_LANE_TID: u32 = 0;
+
+/// A Chrome trace file written one event at a time, so that a long trace is
+/// never held in memory.
+pub(super) struct TraceWriter {
+    pub(super) path: PathBuf,
+    file: io::BufWriter<fs::File>,
+    events: usize,
+}
+
+impl TraceWriter {
+    pub(super) fn create(path: PathBuf) -> io::Result<TraceWriter> {
+        let mut file = io::BufWriter::new(fs::File::create(&path)?);
+        file.write_all(b"{\"traceEvents\":[")?;
+        Ok(TraceWriter {
+            path,
+            file,
+            events: 0,
+        })
+    }
+
+    fn write(&mut self, events: &[serde_json::Value]) -> io::Result<()> {
+        for event in events {
+            if self.events > 0 {
+                self.file.write_all(b",")?;
+            }
+            serde_json::to_writer(&mut self.file, event)?;
+            self.events += 1;
+        }
+        Ok(())
+    }
+
+    /// Ends the trace and returns how many events it has.
+    pub(super) fn finish(mut self) -> io::Result<usize> {
+        self.file.write_all(b"]}")?;
+        self.file.flush()?;
+        Ok(self.events)
+    }
+}
+
+/// Adds `events` to the trace in `slot`, which is dropped once writing it
+/// fails.
+pub(super) fn write_trace(slot: &mut Option<TraceWriter>, events: &[serde_json::Value]) {
+    if let Some(trace) = slot
+        && let Err(e) = trace.write(events)
+    {
+        warn!("failed to write {}: {}", trace.path.display(), e);
+        *slot = None;
+    }
+}
+
+/// Stack frame open on a trace row: its name, its arguments, when it was
+/// entered and the pid it belongs to.
+type OpenFrame = (String, serde_json::Value, i64, u32);
+
+/// Makes the Chrome trace events of the stacks and fd events of one process
+/// from its entries, in time order.
+#[derive(Default)]
+pub(super) struct TraceBuilder {
+    active: HashMap<(u32, usize), OpenFrame>,
+    named: HashSet<(u32, u32)>,
+    named_pids: HashSet<u32>,
+    /// Time of the last entry, where the frames still open end.
+    last_ts: i64,
+}
+
+/// The complete event of a frame that was open from `start` until `ts`.
+fn frame_event(tid: u32, (name, args, start, pid): OpenFrame, ts: i64) -> serde_json::Value {
+    let dur = ts - start;
+    json!({
+        "name": name,
+        "ph": "X",
+        "pid": pid,
+        "tid": tid,
+        "ts": start,
+        "dur": if dur <= 0 { 1 } else { dur },
+        "args": args,
+    })
+}
+
+impl TraceBuilder {
+    /// Emits a `thread_name` metadata event the first time a row is used.
+    fn name_row(&mut self, events: &mut Vec<serde_json::Value>, pid: u32, tid: u32, name: String) {
+        if self.named.insert((pid, tid)) {
+            events.push(json!({
+                "name": "thread_name",
+                "ph": "M",
+                "pid": pid,
+                "tid": tid,
+                "args": { "name": name },
+            }));
+        }
+    }
+
+    fn handle_frames(
+        &mut self,
+        tid: u32,
+        frames: &[Frame],
+        pid: u32,
+        ts: i64,
+        events: &mut Vec<serde_json::Value>,
+    ) {
+        if frames.is_empty() {
+            return;
+        }
+
+        // handle existing events beyond current depth
+        let mut depth = frames.len();
+        while let Some(open) = self.active.remove(&(tid, depth)) {
+            events.push(frame_event(tid, open, ts));
+            depth += 1;
+        }
+
+        for (idx, frame) in frames.iter().enumerate() {
+            let name = frame_name(frame);
+            let args = json!({
+                "addr": frame.addr,
+                "file": frame.file,
+                "line": frame.line,
+            });
+            match self.active.get_mut(&(tid, idx)) {
+                Some((cur, cur_args, _start, _pid)) if cur == &name => {
+                    *cur_args = args;
+                }
+                Some(open) => {
+                    let ended = std::mem::replace(open, (name, args, ts, pid));
+                    events.push(frame_event(tid, ended, ts));
+                }
+                None => {
+                    self.active.insert((tid, idx), (name, args, ts, pid));
+                }
+            }
+        }
+    }
+
+    pub(super) fn add(&mut self, e: &LogEntry, events: &mut Vec<serde_json::Value>) {
+        let Ok(dt) = chrono::DateTime::parse_from_rfc3339(&e.timestamp) else {
+            return;
+        };
+        let ts = dt.timestamp_micros();
+        self.last_ts = ts;
+
+        for ev in e.fd_events.iter().flatten() {
+            let name = "fd events".to_string();
+            self.name_row(events, e.pid, FD_LANE_TID, name);
+            // Socket and other events recorded later land here as well,
+            // under their own event name.
+            events.push(json!({
+                "name": format!("{} {}", ev.event, ev.path),
+                "cat": "fd",
+                "ph": "i",
+                "s": "t",
+                "pid": e.pid,
+                "tid": FD_LANE_TID,
+                "ts": ts,
+                "args": { "fd": ev.fd, "event": ev.event, "path": ev.path },
+            }));
+        }
+        if e.threads.is_empty() {
+            return;
+        }
+
+        if self.named_pids.insert(e.pid) {
+            events.push(json!({
+                "name": "process_name",
+                "ph": "M",
+                "pid": e.pid,
+                "args": { "name": format!("{} ({})", e.process_name, e.pid) },
+            }));
+        }
+        for t in &e.threads {
+            // Each thread has a native row at tid << 1 and a Python (or PHP)
+            // row next to it; both are labeled so they can be told apart.
+            let label = match t.python_thread.as_ref().and_then(|p| p.name.as_ref()) {
+                Some(name) => name.clone(),
+                None => format!("tid {}", t.tid),
+            };
+            if t.merged_stacktrace.is_some() || t.stacktrace.is_some() {
+                let name = format!("{} (native)", label);
+                self.name_row(events, e.pid, t.tid << 1, name);
+            }
+            if t.python_thread.is_some() || t.python_stacktrace.is_some() {
+                let name = format!("{} (python)", label);
+                self.name_row(events, e.pid, (t.tid << 1) | 1, name);
+            } else if t.php_stacktrace.is_some() {
+                let name = format!("{} (php)", label);
+                self.name_row(events, e.pid, (t.tid << 1) | 1, name);
+            }
+            if let Some(merged) = &t.merged_stacktrace {
+                self.handle_frames(t.tid << 1, merged, e.pid, ts, events);
+                continue;
+            }
+            if let Some(st) = &t.stacktrace {
+                self.handle_frames(t.tid << 1, st, e.pid, ts, events);
+            }
+            if let Some(py) = t.python_stacktrace.as_ref().or(t.php_stacktrace.as_ref()) {
+                self.handle_frames((t.tid << 1) | 1, py, e.pid, ts, events);
+            }
+        }
+    }
+
+    /// Ends the frames still open at the last entry.
+    pub(super) fn finish(&mut self, events: &mut Vec<serde_json::Value>) {
+        let ts = self.last_ts;
+        events.extend(
+            self.active
+                .drain()
+                .map(|((tid, _), open)| frame_event(tid, open, ts)),
+        );
+    }
+}
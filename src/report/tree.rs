@@ -0,0 +1,139 @@
+use html_escape::encode_text;
+use std::collections::{HashMap, HashSet};
+
+use super::Stats;
+use super::index::{pid_cell, pid_table};
+use super::page::truncate;
+
+/// A process of the tree with the totals of its whole subtree.
+struct TreeNode<'a> {
+    stats: &'a Stats,
+    children: Vec<TreeNode<'a>>,
+    processes: usize,
+    cpu: f64,
+    summed_rss: u64,
+}
+
+fn tree_node<'a>(
+    stats: &'a [Stats],
+    i: usize,
+    children: &HashMap<usize, Vec<usize>>,
+    visited: &mut HashSet<usize>,
+) -> TreeNode<'a> {
+    visited.insert(i);
+    let mut node = TreeNode {
+        stats: &stats[i],
+        children: Vec::new(),
+        processes: 1,
+        cpu: stats[i].cpu,
+        summed_rss: stats[i].peak_rss,
+    };
+    for &c in children.get(&i).into_iter().flatten() {
+        if visited.contains(&c) {
+            continue;
+        }
+        let child = tree_node(stats, c, children, visited);
+        node.processes += child.processes;
+        node.cpu += child.cpu;
+        node.summed_rss += child.summed_rss;
+        node.children.push(child);
+    }
+    node.children.sort_by(|a, b| b.cpu.total_cmp(&a.cpu));
+    node
+}
+
+/// Arranges `stats` by their parent pids. Processes whose parent was not
+/// monitored become roots.
+fn process_tree(stats: &[Stats]) -> Vec<TreeNode<'_>> {
+    let by_pid: HashMap<u32, usize> = stats.iter().enumerate().map(|(i, s)| (s.pid, i)).collect();
+    let mut children: HashMap<usize, Vec<usize>> = HashMap::new();
+    let mut roots = Vec::new();
+    for (i, s) in stats.iter().enumerate() {
+        match s.ppid.and_then(|p| by_pid.get(&p)) {
+            Some(&parent) if parent != i => children.entry(parent).or_default().push(i),
+            _ => roots.push(i),
+        }
+    }
+    let mut visited = HashSet::new();
+    let mut tree: Vec<TreeNode> = roots
+        .into_iter()
+        .map(|i| tree_node(stats, i, &children, &mut visited))
+        .collect();
+    // Reused pids can make parents form a cycle without any root.
+    for i in 0..stats.len() {
+        if !visited.contains(&i) {
+            tree.push(tree_node(stats, i, &children, &mut visited));
+        }
+    }
+    tree.sort_by(|a, b| b.cpu.total_cmp(&a.cpu));
+    tree
+}
+
+fn render_tree_node(node: &TreeNode, linked: &HashSet<u32>, out: &mut String) {
+    let s = node.stats;
+    let pid = pid_cell(s.pid, linked);
+    let label = format!(
+        "{} {}: CPU {:.1} s, RSS {} KB",
+        pid,
+        encode_text(&truncate(&s.cmd, 30)),
+        node.cpu,
+        node.summed_rss
+    );
+    if node.children.is_empty() {
+        out.push_str(&format!("<li>{}</li>\n", label));
+        return;
+    }
+    out.push_str(&format!(
+        "<li><details open><summary>{} ({} processes)</summary><ul>\n",
+        label, node.processes
+    ));
+    for child in &node.children {
+        render_tree_node(child, linked, out);
+    }
+    out.push_str("</ul></details></li>\n");
+}
+
+/// Names the parent of `s` and lists its children among `stats`, linking
+/// the pages in `linked`. Empty when its parent is unknown and it has no
+/// children.
+pub(super) fn render_family(s: &Stats, stats: &[Stats], linked: &HashSet<u32>) -> String {
+    let mut out = String::new();
+    if let Some(ppid) = s.ppid {
+        let parent = stats.iter().find(|p| p.pid == ppid && p.pid != s.pid);
+        let cmd = parent.map_or(String::new(), |p| {
+            format!(" {}", encode_text(&truncate(&p.cmd, 30)))
+        });
+        out.push_str(&format!(
+            "<p>Parent: {}{}</p>\n",
+            pid_cell(ppid, linked),
+            cmd
+        ));
+    }
+    let mut children: Vec<&Stats> = stats
+        .iter()
+        .filter(|c| c.ppid == Some(s.pid) && c.pid != s.pid)
+        .collect();
+    if !children.is_empty() {
+        children.sort_by_key(|c| (c.start, c.pid));
+        out.push_str("<h2>Children</h2>\n");
+        out.push_str(&pid_table(&children, linked));
+        out.push('\n');
+    }
+    out
+}
+
+/// Lists `stats` as a tree of parents and children, with the CPU time and
+/// summed peak RSS of every subtree. Empty when no process has a monitored
+/// parent.
+pub(super) fn render_tree(stats: &[Stats], linked: &HashSet<u32>) -> String {
+    let tree = process_tree(stats);
+    if tree.iter().all(|n| n.children.is_empty()) {
+        return String::new();
+    }
+    let mut out = String::from("<h2>Process tree</h2>\n<ul class=\"tree\">\n");
+    for node in &tree {
+        render_tree_node(node, linked, &mut out);
+    }
+    out.push_str("</ul>\n");
+    out
+}
@@ -0,0 +1,115 @@
+use html_escape::encode_text;
+use log::warn;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+use super::chart::{ChartZone, charts_html, write_chart_script};
+use super::graph::Theme;
+use super::index::totals_by;
+use super::{ReportOptions, collect_stats};
+use crate::config::ReportConfig;
+use crate::log::TimeRange;
+use crate::prune::date_dirs;
+
+/// CPU seconds and peak RSS of each process name on one day.
+type DayTotals = BTreeMap<String, (f64, u64)>;
+
+/// Renders how the processes of each name developed over the date
+/// directories under `path`, for capacity planning across days.
+pub(super) fn report_trend(
+    path: &Path,
+    out_dir: &Path,
+    cfg: &ReportConfig,
+    range: &TimeRange,
+    opts: &ReportOptions,
+) {
+    let daily: Vec<(String, DayTotals)> = date_dirs(path)
+        .into_iter()
+        .map(|day| {
+            let stats = collect_stats(&day.path, cfg, range, &opts.filter);
+            let totals = totals_by(&stats, |s| s.name.clone())
+                .into_iter()
+                .map(|(name, t)| (name, (t.cpu, t.peak_rss)))
+                .collect();
+            (day.date.format("%Y%m%d").to_string(), totals)
+        })
+        .collect();
+    write_chart_script(out_dir);
+    let index = out_dir.join("index.html");
+    if let Err(e) = fs::write(&index, render_trend(&daily, opts.style.theme)) {
+        warn!("failed to write {}: {}", index.display(), e);
+    }
+}
+
+/// Names drawn in the trend charts; the table lists all.
+const TREND_CHART_NAMES: usize = 10;
+
+fn render_trend(daily: &[(String, DayTotals)], theme: Theme) -> String {
+    let mut cpu_by_name: HashMap<&String, f64> = HashMap::new();
+    for (name, (cpu, _)) in daily.iter().flat_map(|(_, totals)| totals) {
+        *cpu_by_name.entry(name).or_default() += cpu;
+    }
+    let mut names: Vec<&String> = cpu_by_name.keys().copied().collect();
+    // Busiest names over all days first.
+    names.sort_by(|a, b| cpu_by_name[b].total_cmp(&cpu_by_name[a]).then(a.cmp(b)));
+    let midnight = |day: &str| {
+        chrono::NaiveDate::parse_from_str(day, "%Y%m%d")
+            .ok()
+            .and_then(|d| d.and_hms_opt(0, 0, 0))
+            .map(|t| t.and_utc().timestamp_millis())
+    };
+    let series = |value: &dyn Fn(&(f64, u64)) -> f64| -> Vec<serde_json::Value> {
+        names
+            .iter()
+            .take(TREND_CHART_NAMES)
+            .map(|name| {
+                let points: Vec<(i64, f64)> = daily
+                    .iter()
+                    .filter_map(|(day, totals)| Some((midnight(day)?, value(totals.get(*name)?))))
+                    .collect();
+                json!({ "label": name, "data": points })
+            })
+            .collect()
+    };
+    let trend_spec = |title: &str, unit: &str, series: Vec<serde_json::Value>| json!({ "title": title, "unit": unit, "log": false, "dates": true, "series": series });
+    let mut out = theme.head();
+    out.push_str("<h1>Daily trends</h1>\n");
+    // Days are placed at midnight UTC of their date.
+    let utc = ChartZone::Named("UTC".into());
+    out.push_str(&charts_html(
+        &[
+            (
+                "trend_cpu_chart",
+                trend_spec("CPU time per day", "sec", series(&|t| t.0)),
+            ),
+            (
+                "trend_rss_chart",
+                trend_spec("Peak RSS per day", "MB", series(&|t| t.1 as f64 / 1024.0)),
+            ),
+        ],
+        &utc,
+    ));
+    let table = |title: &str, cell: &dyn Fn(&(f64, u64)) -> String| {
+        let mut out = format!("<h2>{}</h2>\n<table>\n<tr><th>Name</th>", title);
+        for (day, _) in daily {
+            out.push_str(&format!("<th>{}</th>", day));
+        }
+        out.push_str("</tr>\n");
+        for name in &names {
+            out.push_str(&format!("<tr><td>{}</td>", encode_text(name)));
+            for (_, totals) in daily {
+                let value = totals.get(*name).map(cell).unwrap_or_default();
+                out.push_str(&format!("<td>{}</td>", value));
+            }
+            out.push_str("</tr>\n");
+        }
+        out.push_str("</table>\n");
+        out
+    };
+    out.push_str(&table("CPU time (sec)", &|t| format!("{:.1}", t.0)));
+    out.push_str(&table("Peak RSS (KB)", &|t| t.1.to_string()));
+    out.push_str("</body></html>\n");
+    out
+}
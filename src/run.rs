@@ -1,93 +1,348 @@
 use chrono::Utc;
 use log::{info, warn};
+use nix::errno::Errno;
+use nix::sys::signal::Signal;
 use regex::Regex;
 use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::path::Path;
 use std::sync::{
     Arc,
     atomic::{AtomicBool, Ordering},
 };
-use std::thread::sleep;
-use std::time::Duration;
+use std::thread::{self, sleep};
+use std::time::{Duration, Instant};
+use zstd::dict::EncoderDictionary;
 
-use crate::config::{Config, RunArgs, load_config, merge_config, uid_from_name};
-use crate::log::{FdLogEvent, LogEntry, MemoryInfo, ThreadInfo, write_log};
+use crate::alert::{Alerts, Sample};
+use crate::config::{
+    Config, DEFAULT_CAPTURE_WORKERS, DEFAULT_MAX_FRAMES, DEFAULT_STACKTRACE_HOLD_SAMPLES,
+    DEFAULT_STACKTRACE_TRIGGER_SAMPLES, FilterConfig, RunArgs, StacktraceConfig,
+    apply_output_timezone, load_config, merge_config, parse_log_format, redact_env_patterns,
+    uid_from_name, user_from_name,
+};
+use crate::daemon::daemonize;
+use crate::limiter::CaptureLimiter;
+use crate::log::{
+    FdLogEvent, LOG_VERSION, Layout, LogEntry, LogFormat, MemoryInfo, Rollover, ThreadInfo,
+    dictionary_name, is_stdout, output_root,
+};
+use crate::otlp::OtlpExporter;
+use crate::php::capture_php_stack_trace;
 use crate::procinfo::{
-    ProcState, cmdline, detect_fd_events, environ, get_proc_usage, pid_uid, proc_exists,
-    process_name, read_pids, rss_kb, should_suppress, swap_kb, vsz_kb,
+    ProcState, cmdline, descendants, detect_fd_events, environ, get_proc_usage, parent_pid,
+    pid_uid, proc_exists, process_name, read_pids, read_tids, redact_env, rss_kb, run_state,
+    should_suppress, swap_kb, thread_cpu_deltas, thread_name, vsz_kb,
+};
+use crate::prometheus::PrometheusExporter;
+use crate::prune::{enforce_budget, gb_to_bytes};
+use crate::remote::RemoteSink;
+use crate::spawn::{block_signals, spawn};
+use crate::stacktrace::{
+    CaptureOptions, UnwindBackend, capture_c_stack_traces, capture_python_stack_traces,
+    has_python_module, merge_python_stack,
 };
-use crate::stacktrace::{capture_c_stack_traces, capture_python_stack_traces};
+use crate::statsd::{DEFAULT_TEMPLATE, StatsdSink};
+use crate::syslog::{Severity, SyslogSink};
+use crate::systemd::Notifier;
+use crate::upload::Uploader;
+use crate::writer::{FlushPolicy, LogWriter, WriteStats};
+
+struct MonitorOptions {
+    target_pid: Option<u32>,
+    target_uid: Option<u32>,
+    target_name: Option<Regex>,
+    /// Whether fuzmon and its children are monitored like the others.
+    include_self: bool,
+    filter: ProcessFilter,
+    record_cpu_percent_threshold: f64,
+    stacktrace_cpu_percent_threshold: f64,
+    /// Samples above the threshold before, and after it, stacks are due.
+    stacktrace_trigger_samples: u32,
+    stacktrace_hold_samples: u32,
+    /// RSS in KB from which stacks are captured regardless of CPU.
+    stacktrace_rss_kb_threshold: Option<u64>,
+    /// RSS growth in KB since the last stack that triggers a capture.
+    stacktrace_rss_growth_kb: Option<u64>,
+    output_dir: Option<String>,
+    verbose: bool,
+    capture: CaptureOptions,
+    max_thread_frames: Option<usize>,
+    capture_registers: bool,
+    /// Whether threads get their CPU usage and name recorded.
+    thread_cpu: bool,
+    top_threads: Option<usize>,
+    stacktrace_interval: Option<Duration>,
+    /// Names of the environment variables whose values are masked.
+    redact_env: Vec<Regex>,
+    alerts: Option<Alerts>,
+}
+
+impl MonitorOptions {
+    fn streaming(&self) -> bool {
+        self.output_dir.as_deref().is_some_and(is_stdout)
+    }
+}
+
+/// Prints a status message, on stderr when entries are streamed to stdout.
+fn print_status(streaming: bool, msg: &str) {
+    if streaming {
+        eprintln!("{}", msg);
+    } else {
+        println!("{}", msg);
+    }
+}
+
+/// Destinations that receive every sample in addition to the log files.
+struct Sinks {
+    otlp: Option<OtlpExporter>,
+    prometheus: Option<PrometheusExporter>,
+    statsd: Option<StatsdSink>,
+    remote: Option<RemoteSink>,
+    syslog: Option<SyslogSink>,
+}
+
+/// Samples a process must spend in D state before a stall is reported.
+const STALL_SAMPLES: u32 = 2;
+
+/// How often the size of the output directory is checked against
+/// `output.max_total_size_gb`, since it means walking the whole tree.
+const BUDGET_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+impl Sinks {
+    fn record(&mut self, entry: &LogEntry, state: &ProcState) {
+        if let Some(otlp) = &mut self.otlp {
+            otlp.record(entry, state.fds.len());
+        }
+        if let Some(prom) = &mut self.prometheus {
+            prom.record(entry, state.fds.len(), read_tids(entry.pid).len());
+        }
+        if let Some(statsd) = &mut self.statsd {
+            statsd.record(
+                entry.pid,
+                &entry.process_name,
+                entry.cpu_time_percent,
+                entry.memory.rss_kb,
+            );
+        }
+        if let Some(remote) = &mut self.remote {
+            remote.record(entry);
+        }
+    }
+
+    /// Whether any sink takes the entries, needing them built even when no
+    /// log is written.
+    fn records_enabled(&self) -> bool {
+        self.otlp.is_some()
+            || self.prometheus.is_some()
+            || self.statsd.is_some()
+            || self.remote.is_some()
+    }
+
+    fn events_enabled(&self) -> bool {
+        self.syslog.is_some()
+    }
+
+    fn event(&self, severity: Severity, msg: &str) {
+        if let Some(syslog) = &self.syslog {
+            syslog.event(severity, msg);
+        }
+    }
+
+    fn flush(&mut self) {
+        if let Some(otlp) = &mut self.otlp {
+            otlp.flush();
+        }
+        if let Some(prom) = &mut self.prometheus {
+            prom.flush();
+        }
+        if let Some(statsd) = &mut self.statsd {
+            statsd.flush();
+        }
+        if let Some(remote) = &mut self.remote {
+            remote.flush();
+        }
+    }
+}
 
-pub fn run(args: RunArgs) {
+/// Reads the zstd dictionary at `path`, prepared for compressing at
+/// `level`, and copies it to the top of the output directory under its
+/// dictionary ID, where the readers of the logs look for it.
+fn load_dictionary(
+    path: &str,
+    level: i32,
+    output_dir: Option<&str>,
+) -> Option<Arc<EncoderDictionary<'static>>> {
+    let dict = fs::read(path)
+        .map_err(|e| warn!("failed to read zstd dictionary {}: {}", path, e))
+        .ok()?;
+    let Some(id) = zstd::zstd_safe::get_dict_id_from_dict(&dict) else {
+        warn!(
+            "{} has no dictionary ID, train one with `fuzmon dict`",
+            path
+        );
+        return None;
+    };
+    if let Some(dir) = output_dir.filter(|d| !is_stdout(d)) {
+        let dest = Path::new(output_root(dir)).join(dictionary_name(id.get()));
+        if !dest.exists()
+            && let Err(e) = fs::write(&dest, &dict)
+        {
+            warn!("failed to write {}: {}", dest.display(), e);
+        }
+    }
+    Some(Arc::new(EncoderDictionary::copy(&dict, level)))
+}
+
+/// Monitors until interrupted or the target exits, then prints a summary
+/// unless `quiet`. Returns the exit code of fuzmon: that of the spawned
+/// command, if any.
+pub fn run(args: RunArgs, quiet: bool) -> i32 {
     let config = match args.config.as_deref() {
         Some(path) => load_config(path),
         None => Config::default(),
     };
     let config = merge_config(config, &args);
 
-    let ignore_patterns: Vec<Regex> = config
-        .filter
-        .ignore_process_name
-        .unwrap_or_default()
-        .into_iter()
-        .filter_map(|p| Regex::new(&p).ok())
-        .collect();
+    let filter = ProcessFilter::new(&config.filter);
 
-    let mut format = config.output.format.as_deref().unwrap_or("jsonl.zst");
-    format = match format {
-        "json" => "jsonl",
-        "json.zst" => "jsonl.zst",
-        "msgpack" => "msgpacks",
-        "msgpack.zst" => "msgpacks.zst",
-        other => other,
+    let format = match config.output.format.as_deref().map(parse_log_format) {
+        Some(Ok(format)) => format,
+        Some(Err(e)) => {
+            warn!("{}, using jsonl.zst", e);
+            "jsonl.zst".to_string()
+        }
+        None => "jsonl.zst".to_string(),
     };
-    let use_msgpack = matches!(format, "msgpacks" | "msgpacks.zst");
+    let compact = format.starts_with("msgpackc");
+    let use_msgpack = format.starts_with("msgpack");
     let compress = config
         .output
         .compress
         .unwrap_or_else(|| format.ends_with(".zst"));
+    let layout = match config.output.layout.as_deref() {
+        Some("combined") => Layout::Combined,
+        Some("per_pid") | None => Layout::PerPid,
+        Some(other) => {
+            warn!("unknown output layout {}, using per_pid", other);
+            Layout::PerPid
+        }
+    };
+    let rollover = match config.output.rollover.as_deref() {
+        Some("hour") => Rollover::Hour,
+        Some("day") | None => Rollover::Day,
+        Some(other) => {
+            warn!("unknown output rollover {}, using day", other);
+            Rollover::Day
+        }
+    };
+    let timezone = apply_output_timezone(&config.output);
     let verbose = args.verbose;
 
     let output_dir = config.output.path.as_deref();
-    if let Some(dir) = output_dir {
-        if let Err(e) = fs::create_dir_all(dir) {
-            warn!("failed to create {}: {}", dir, e);
+    let streaming = output_dir.is_some_and(is_stdout);
+    let target_uid = config.filter.target_user.as_deref().and_then(uid_from_name);
+    let include_self = config.filter.include_self.unwrap_or(false);
+    let target_name = match target_name_pattern(&config.filter) {
+        Ok(re) => re,
+        Err(e) => {
+            let msg = format!("invalid target_process_name: {}", e);
+            print_status(streaming, &msg);
+            warn!("{}", msg);
+            return 1;
         }
+    };
+
+    let run_as = match args.run_as.as_deref() {
+        Some(name) => match user_from_name(name) {
+            Some(user) => Some(user),
+            None => {
+                let msg = format!("unknown user {}", name);
+                print_status(streaming, &msg);
+                warn!("{}", msg);
+                return 1;
+            }
+        },
+        None => None,
+    };
+
+    if args.dry_run {
+        preview_targets(
+            args.pid.map(|p| p as u32),
+            target_uid,
+            target_name.as_ref(),
+            include_self,
+            &filter,
+            config
+                .monitor
+                .record_cpu_time_percent_threshold
+                .unwrap_or(0.0),
+        );
+        return 0;
     }
+    if let Some(dir) = output_dir.filter(|d| !is_stdout(d)).map(output_root)
+        && let Err(e) = fs::create_dir_all(dir)
+    {
+        warn!("failed to create {}: {}", dir, e);
+    }
+    let zstd_level = config.output.zstd_level.unwrap_or(3);
+    let dictionary = config
+        .output
+        .zstd_dictionary
+        .as_deref()
+        .and_then(|p| load_dictionary(p, zstd_level, output_dir));
+
+    // Forked before any thread is started, and before the command so that
+    // it runs under the daemon.
+    let mut daemon = if args.daemon {
+        let Some(log) = args.log_file.clone().or_else(|| {
+            output_dir
+                .filter(|d| !is_stdout(d))
+                .map(|d| format!("{}.log", output_root(d).trim_end_matches('/')))
+        }) else {
+            eprintln!("--daemon needs an output directory, not -o -");
+            return 1;
+        };
+        match daemonize(Path::new(&log), args.pidfile.as_deref().map(Path::new)) {
+            Ok(daemon) => Some(daemon),
+            Err(e) => {
+                // Logged at info with --daemon, so this is seen once.
+                warn!("failed to start daemon: {}", e);
+                return 1;
+            }
+        }
+    } else {
+        None
+    };
 
     let mut child = None;
     let mut target_pid = args.pid.map(|p| p as u32);
     if target_pid.is_none() && !args.command.is_empty() {
-        let mut cmd = std::process::Command::new(&args.command[0]);
-        if args.command.len() > 1 {
-            cmd.args(&args.command[1..]);
-        }
-        match cmd.spawn() {
+        match spawn(&args.command, args.pty, streaming, run_as.as_ref()) {
             Ok(c) => {
-                target_pid = Some(c.id());
+                target_pid = Some(c.child.id());
                 child = Some(c);
                 info!("spawned {} as pid {}", args.command[0], target_pid.unwrap());
             }
             Err(e) => {
                 let msg = format!("failed to spawn {}: {}", args.command[0], e);
-                println!("{}", msg);
+                print_status(streaming, &msg);
                 warn!("{}", msg);
-                return;
+                // As shells do for commands that cannot be run.
+                return 127;
             }
         }
     }
 
-    if let Some(pid) = target_pid {
-        if fs::metadata(format!("/proc/{}", pid)).is_err() {
-            let msg = format!("pid {} not found", pid);
-            println!("{}", msg);
-            warn!("{}", msg);
-            return;
-        }
+    if let Some(pid) = target_pid
+        && fs::metadata(format!("/proc/{}", pid)).is_err()
+    {
+        let msg = format!("pid {} not found", pid);
+        print_status(streaming, &msg);
+        warn!("{}", msg);
+        return 1;
     }
 
-    let target_uid = config.filter.target_user.as_deref().and_then(uid_from_name);
-
     let interval = config.monitor.interval_sec.unwrap_or(0);
     let sleep_dur = if interval == 0 {
         Duration::from_millis(200)
@@ -105,54 +360,185 @@ pub fn run(args: RunArgs) {
         .unwrap_or(1.0);
 
     let term = Arc::new(AtomicBool::new(false));
-    {
-        let t = term.clone();
-        ctrlc::set_handler(move || {
-            t.store(true, Ordering::SeqCst);
-            info!("SIGINT received, shutting down");
-        })
-        .expect("set SIGINT handler");
+    // A command gets the signals instead, and fuzmon stops when it exits.
+    if child.is_none() {
+        handle_termination(term.clone()).expect("handle termination signals");
+    }
+    if let Some(d) = &mut daemon {
+        d.started();
     }
+    let mut notifier = Notifier::from_env();
+    if let Some(n) = &notifier {
+        n.ready();
+    }
+
+    let opts = MonitorOptions {
+        target_pid,
+        target_uid,
+        target_name,
+        include_self,
+        filter,
+        redact_env: redact_env_patterns(&config.output),
+        alerts: Alerts::new(&config.alert, args.allow_actions),
+        record_cpu_percent_threshold,
+        stacktrace_cpu_percent_threshold,
+        stacktrace_trigger_samples: config
+            .monitor
+            .stacktrace_trigger_samples
+            .unwrap_or(DEFAULT_STACKTRACE_TRIGGER_SAMPLES),
+        stacktrace_hold_samples: config
+            .monitor
+            .stacktrace_hold_samples
+            .unwrap_or(DEFAULT_STACKTRACE_HOLD_SAMPLES),
+        stacktrace_rss_kb_threshold: config
+            .monitor
+            .stacktrace_rss_mb_threshold
+            .map(|mb| mb * 1024),
+        stacktrace_rss_growth_kb: config.monitor.stacktrace_rss_growth_mb.map(|mb| mb * 1024),
+        output_dir: output_dir.map(|d| d.to_string()),
+        verbose,
+        capture: capture_options(&config.stacktrace),
+        max_thread_frames: config.stacktrace.max_thread_frames,
+        capture_registers: config.stacktrace.capture_registers.unwrap_or(false),
+        thread_cpu: config.stacktrace.thread_cpu.unwrap_or(false),
+        top_threads: config.stacktrace.top_threads_per_process,
+        stacktrace_interval: config
+            .monitor
+            .stacktrace_interval_sec
+            .map(Duration::from_secs),
+    };
 
     let mut states: HashMap<u32, ProcState> = HashMap::new();
+    let log_format = LogFormat {
+        use_msgpack,
+        compact,
+        compress,
+        zstd_level,
+        dictionary,
+        layout,
+        rollover,
+        delta: config.output.delta.unwrap_or(false),
+        delta_memory_tolerance_kb: config.output.delta_memory_tolerance_kb.unwrap_or(0),
+        timezone,
+    };
+    let flush = FlushPolicy {
+        interval_secs: config.output.flush_interval_sec.unwrap_or(0),
+        fsync: config.output.fsync.unwrap_or(false),
+    };
+    let mut writer = LogWriter::new(log_format, flush);
+    let mut uploader = config
+        .output
+        .s3_bucket
+        .as_deref()
+        .zip(output_dir.filter(|_| !streaming))
+        .and_then(|(bucket, dir)| {
+            Uploader::start(&config.output, bucket, Path::new(dir), timezone)
+        });
+    let mut limiter = config
+        .stacktrace
+        .max_captures_per_sec
+        .map(|rate| CaptureLimiter::new(rate, Instant::now()));
+    let mut sinks = Sinks {
+        otlp: config.output.otlp.as_deref().map(OtlpExporter::new),
+        prometheus: config
+            .output
+            .prometheus_listen
+            .as_deref()
+            .and_then(PrometheusExporter::bind),
+        statsd: config.output.statsd.as_deref().and_then(|addr| {
+            let template = config.output.statsd_template.as_deref();
+            StatsdSink::connect(addr, template.unwrap_or(DEFAULT_TEMPLATE))
+        }),
+        // The spool is always set by `merge_config`.
+        remote: config
+            .output
+            .remote_url
+            .as_deref()
+            .zip(config.output.remote_spool.as_deref())
+            .map(|(url, spool)| RemoteSink::new(url, Path::new(spool))),
+        syslog: config
+            .output
+            .syslog
+            .as_deref()
+            .and_then(SyslogSink::connect),
+    };
+    let budget = config
+        .output
+        .max_total_size_gb
+        .map(gb_to_bytes)
+        .zip(output_dir.filter(|_| !streaming));
+    let prune_over_budget = match config.output.max_total_size_action.as_deref() {
+        Some("stop") => false,
+        Some("prune") | None => true,
+        Some(other) => {
+            warn!("unknown max_total_size_action {}, using prune", other);
+            true
+        }
+    };
+    let started = Instant::now();
+    let mut next_budget_check = Instant::now();
+    let deadline = config
+        .monitor
+        .max_duration_sec
+        .map(|secs| Instant::now() + Duration::from_secs(secs));
     loop {
-        if let Some(pid) = target_pid {
-            if !proc_exists(pid) {
-                let name = process_name(pid).unwrap_or_else(|| "?".to_string());
-                let msg = format!("Process {pid} ({name}) disappeared, exiting");
-                println!("{}", msg);
-                info!("{}", msg);
-                break;
+        if let Some(pid) = target_pid
+            && !proc_exists(pid)
+        {
+            let name = process_name(pid).unwrap_or_else(|| "?".to_string());
+            let msg = format!("Process {pid} ({name}) disappeared, exiting");
+            if !quiet {
+                print_status(streaming, &msg);
             }
+            info!("{}", msg);
+            break;
         }
-        monitor_iteration(
-            &mut states,
-            target_pid,
-            target_uid,
-            &ignore_patterns,
-            record_cpu_percent_threshold,
-            stacktrace_cpu_percent_threshold,
-            output_dir,
-            use_msgpack,
-            compress,
-            verbose,
-        );
-        if let Some(ref mut c) = child {
-            if c.try_wait().ok().flatten().is_some() {
+        monitor_iteration(&mut states, &mut limiter, &mut sinks, &mut writer, &opts);
+        if let Some(u) = &mut uploader {
+            u.tick();
+        }
+        if let Some((max_bytes, dir)) = budget
+            && Instant::now() >= next_budget_check
+        {
+            next_budget_check = Instant::now() + BUDGET_CHECK_INTERVAL;
+            let today = timezone.today();
+            let total = enforce_budget(Path::new(dir), max_bytes, today, prune_over_budget);
+            if total > max_bytes {
+                let msg = format!(
+                    "{} takes {} bytes, over output.max_total_size_gb, stopping",
+                    output_root(dir),
+                    total
+                );
+                print_status(streaming, &msg);
+                warn!("{}", msg);
+                sinks.event(Severity::Warning, &msg);
+                writer.stop("output.max_total_size_gb exceeded");
+                term.store(true, Ordering::SeqCst);
                 break;
             }
-        } else if let Some(pid) = target_pid {
-            if fs::metadata(format!("/proc/{}", pid)).is_err() {
+        }
+        if let Some(ref mut c) = child {
+            if c.child.try_wait().ok().flatten().is_some() {
                 break;
             }
+        } else if let Some(pid) = target_pid
+            && fs::metadata(format!("/proc/{}", pid)).is_err()
+        {
+            break;
         }
-        if term.load(Ordering::SeqCst) {
+        if term.load(Ordering::SeqCst) || writer.stdout_closed() {
             break;
         }
         let mut elapsed = Duration::from_millis(0);
         while elapsed < sleep_dur {
+            if deadline.is_some_and(|d| Instant::now() >= d) && !term.swap(true, Ordering::SeqCst) {
+                info!("max_duration_sec reached, shutting down");
+            }
             if term.load(Ordering::SeqCst) {
-                return;
+                break;
+            }
+            if let Some(n) = &mut notifier {
+                n.watchdog(Instant::now());
             }
             let step = std::cmp::min(Duration::from_millis(100), sleep_dur - elapsed);
             sleep(step);
@@ -162,59 +548,249 @@ pub fn run(args: RunArgs) {
             break;
         }
     }
+    if let Some(n) = &notifier {
+        n.stopping();
+    }
     if term.load(Ordering::SeqCst) {
-        monitor_iteration(
-            &mut states,
-            target_pid,
-            target_uid,
-            &ignore_patterns,
-            record_cpu_percent_threshold,
-            stacktrace_cpu_percent_threshold,
+        monitor_iteration(&mut states, &mut limiter, &mut sinks, &mut writer, &opts);
+        // Monitoring stopped before the command did, after --duration or
+        // over the disk budget.
+        if let Some(c) = &mut child {
+            c.terminate();
+        }
+    }
+    let format_name = if streaming {
+        "jsonl".to_string()
+    } else {
+        let base = format.trim_end_matches(".zst");
+        if compress {
+            format!("{}.zst", base)
+        } else {
+            base.to_string()
+        }
+    };
+    if !quiet {
+        print_summary(
+            writer.stats(),
+            started.elapsed(),
+            &format_name,
             output_dir,
-            use_msgpack,
-            compress,
-            verbose,
+            streaming,
         );
     }
-    if let Some(mut c) = child {
-        let _ = c.wait();
+    child.map_or(0, |c| match c.wait() {
+        Ok(status) => exit_code(status),
+        Err(e) => {
+            warn!("failed to wait for {}: {}", args.command[0], e);
+            1
+        }
+    })
+}
+
+/// Tells what the run recorded, once monitoring stops.
+fn print_summary(
+    stats: &WriteStats,
+    elapsed: Duration,
+    format: &str,
+    output_dir: Option<&str>,
+    streaming: bool,
+) {
+    let output = match output_dir {
+        Some(d) if is_stdout(d) => " to stdout".to_string(),
+        Some(d) => format!(" to {}", output_root(d)),
+        None => String::new(),
+    };
+    let msg = format!(
+        "Monitored {} processes for {:.1}s\n\
+         Wrote {} entries ({} bytes of {}){}\n\
+         Captured {} thread stacks, {} failed",
+        stats.pids.len(),
+        elapsed.as_secs_f64(),
+        stats.entries,
+        stats.bytes,
+        format,
+        output,
+        stats.stack_captures,
+        stats.stack_failures,
+    );
+    print_status(streaming, &msg);
+}
+
+/// Signals that stop monitoring, after the final sample and flush.
+const TERMINATION: [Signal; 4] = [
+    Signal::SIGINT,
+    Signal::SIGTERM,
+    Signal::SIGHUP,
+    Signal::SIGQUIT,
+];
+
+/// Sets `term` once fuzmon gets one of `TERMINATION`. Must be called
+/// before any other thread is started.
+fn handle_termination(term: Arc<AtomicBool>) -> std::io::Result<()> {
+    let mut signals = block_signals(&TERMINATION)?;
+    thread::spawn(move || {
+        while let Ok(Some(info)) = signals.read_signal() {
+            term.store(true, Ordering::SeqCst);
+            let name = Signal::try_from(info.ssi_signo as i32).map_or("signal", |s| s.as_str());
+            info!("{} received, shutting down", name);
+        }
+    });
+    Ok(())
+}
+
+/// The exit code of a command, or 128 + N for one killed by signal N, as
+/// shells report it.
+fn exit_code(status: std::process::ExitStatus) -> i32 {
+    use std::os::unix::process::ExitStatusExt;
+    status
+        .code()
+        .or_else(|| status.signal().map(|sig| 128 + sig))
+        .unwrap_or(1)
+}
+
+/// The process name and command line patterns of `[filter]`.
+pub struct ProcessFilter {
+    ignore_name: Vec<Regex>,
+    ignore_cmdline: Vec<Regex>,
+    include_cmdline: Vec<Regex>,
+}
+
+/// Compiles `patterns`, skipping invalid ones.
+fn compile_patterns(patterns: &Option<Vec<String>>) -> Vec<Regex> {
+    patterns
+        .iter()
+        .flatten()
+        .filter_map(|p| {
+            Regex::new(p)
+                .map_err(|e| warn!("invalid filter pattern {}: {}", p, e))
+                .ok()
+        })
+        .collect()
+}
+
+impl ProcessFilter {
+    pub fn new(filter: &FilterConfig) -> ProcessFilter {
+        ProcessFilter {
+            ignore_name: compile_patterns(&filter.ignore_process_name),
+            ignore_cmdline: compile_patterns(&filter.ignore_cmdline),
+            include_cmdline: compile_patterns(&filter.include_cmdline),
+        }
+    }
+
+    /// Whether `pid`, named `name`, is left out.
+    pub fn skips(&self, pid: u32, name: &str) -> bool {
+        self.skip_reason(pid, name).is_some()
+    }
+
+    /// The setting leaving `pid`, named `name`, out. The command line is
+    /// only read when there are patterns for it.
+    fn skip_reason(&self, pid: u32, name: &str) -> Option<&'static str> {
+        if self.ignore_name.iter().any(|re| re.is_match(name)) {
+            return Some("ignore_process_name");
+        }
+        if self.ignore_cmdline.is_empty() && self.include_cmdline.is_empty() {
+            return None;
+        }
+        let cmd = cmdline(pid).unwrap_or_default();
+        if self.ignore_cmdline.iter().any(|re| re.is_match(&cmd)) {
+            return Some("ignore_cmdline");
+        }
+        if !(self.include_cmdline.is_empty()
+            || self.include_cmdline.iter().any(|re| re.is_match(&cmd)))
+        {
+            return Some("include_cmdline");
+        }
+        None
+    }
+}
+
+pub fn capture_options(config: &StacktraceConfig) -> CaptureOptions {
+    let backend = match config.backend.as_deref() {
+        Some("libunwind") => UnwindBackend::Libunwind,
+        Some("frame_pointer") | None => UnwindBackend::FramePointer,
+        Some(other) => {
+            warn!("unknown stacktrace backend {}, using frame_pointer", other);
+            UnwindBackend::FramePointer
+        }
+    };
+    CaptureOptions {
+        debuginfod: config.debuginfod.unwrap_or(false),
+        max_pause: config.max_pause_ms.map(Duration::from_millis),
+        max_frames: config.max_frames.unwrap_or(DEFAULT_MAX_FRAMES),
+        shorten_cpp_names: config.shorten_cpp_names.unwrap_or(false),
+        backend,
+        workers: config.capture_workers.unwrap_or(DEFAULT_CAPTURE_WORKERS),
     }
 }
 
 fn monitor_iteration(
     states: &mut HashMap<u32, ProcState>,
-    target_pid: Option<u32>,
-    target_uid: Option<u32>,
-    ignore_patterns: &[Regex],
-    record_cpu_percent_threshold: f64,
-    stacktrace_cpu_percent_threshold: f64,
-    output_dir: Option<&str>,
-    use_msgpack: bool,
-    compress: bool,
-    verbose: bool,
+    limiter: &mut Option<CaptureLimiter>,
+    sinks: &mut Sinks,
+    writer: &mut LogWriter,
+    opts: &MonitorOptions,
 ) {
-    let pids = collect_pids(target_pid, target_uid);
-    if verbose {
-        println!("Found {} PIDs", pids.len());
+    let mut pids = collect_pids(
+        opts.target_pid,
+        opts.target_uid,
+        opts.target_name.as_ref(),
+        opts.include_self,
+    );
+    if opts.verbose {
+        print_status(opts.streaming(), &format!("Found {} PIDs", pids.len()));
+    }
+    // Left out of the others by collect_pids, but its overhead is recorded.
+    let overhead = (opts.target_pid.is_none() && !opts.include_self).then(std::process::id);
+    pids.extend(overhead);
+    prune_states(states, &pids, sinks, writer, opts);
+    if let Some(l) = limiter {
+        pids = l.order(&pids);
     }
-    prune_states(states, &pids, output_dir, use_msgpack, compress);
     for pid in &pids {
-        process_pid(
-            *pid,
-            states,
-            target_pid,
-            ignore_patterns,
-            record_cpu_percent_threshold,
-            stacktrace_cpu_percent_threshold,
-            output_dir,
-            use_msgpack,
-            compress,
-            verbose,
-        );
+        if Some(*pid) == overhead {
+            record_overhead(*pid, states, sinks, writer, opts);
+        } else {
+            process_pid(*pid, states, limiter, sinks, writer, opts);
+        }
     }
+    sinks.flush();
+    writer.expire(Utc::now().timestamp());
+}
+
+/// Compiles `filter.target_process_name`.
+pub fn target_name_pattern(filter: &FilterConfig) -> Result<Option<Regex>, regex::Error> {
+    filter
+        .target_process_name
+        .as_deref()
+        .map(Regex::new)
+        .transpose()
 }
 
-fn collect_pids(target_pid: Option<u32>, target_uid: Option<u32>) -> Vec<u32> {
+/// Whether the name or command line of `pid` matches `re`. fuzmon itself
+/// is never matched, as the pattern is on its own command line.
+fn name_matches(pid: u32, re: &Regex) -> bool {
+    pid != std::process::id()
+        && (process_name(pid).is_some_and(|n| re.is_match(&n))
+            || cmdline(pid).is_some_and(|c| re.is_match(&c)))
+}
+
+/// fuzmon and the processes it started, unless `include_self`.
+fn own_pids(include_self: bool) -> HashSet<u32> {
+    if include_self {
+        return HashSet::new();
+    }
+    let me = std::process::id();
+    let mut own: HashSet<u32> = descendants(me).into_iter().collect();
+    own.insert(me);
+    own
+}
+
+pub fn collect_pids(
+    target_pid: Option<u32>,
+    target_uid: Option<u32>,
+    target_name: Option<&Regex>,
+    include_self: bool,
+) -> Vec<u32> {
     let mut pids = if let Some(pid) = target_pid {
         if fs::metadata(format!("/proc/{}", pid)).is_ok() {
             vec![pid]
@@ -225,57 +801,130 @@ fn collect_pids(target_pid: Option<u32>, target_uid: Option<u32>) -> Vec<u32> {
         read_pids()
     };
     if target_pid.is_none() {
-        if let Some(uid) = target_uid {
-            pids.retain(|p| pid_uid(*p) == Some(uid));
-        }
+        let own = own_pids(include_self);
+        pids.retain(|p| target_mismatch(*p, target_uid, target_name, &own).is_none());
     }
     pids
 }
 
+/// The setting `pid` fails to match when monitoring all processes.
+fn target_mismatch(
+    pid: u32,
+    target_uid: Option<u32>,
+    target_name: Option<&Regex>,
+    own: &HashSet<u32>,
+) -> Option<&'static str> {
+    if own.contains(&pid) {
+        return Some("include_self");
+    }
+    if target_uid.is_some_and(|uid| pid_uid(pid) != Some(uid)) {
+        return Some("target_user");
+    }
+    if target_name.is_some_and(|re| !name_matches(pid, re)) {
+        return Some("target_process_name");
+    }
+    None
+}
+
+/// Prints which processes the filters of `config` keep and which they
+/// leave out, and why, after one pass over `/proc`.
+fn preview_targets(
+    target_pid: Option<u32>,
+    target_uid: Option<u32>,
+    target_name: Option<&Regex>,
+    include_self: bool,
+    filter: &ProcessFilter,
+    record_cpu_percent_threshold: f64,
+) {
+    let pids = match target_pid {
+        Some(pid) => vec![pid],
+        None => read_pids(),
+    };
+    let own = own_pids(include_self);
+    let mut monitored = 0;
+    for pid in &pids {
+        let name = process_name(*pid).unwrap_or_else(|| "?".into());
+        let reason = if target_pid.is_some() {
+            // -p bypasses the other filters.
+            (!proc_exists(*pid)).then_some("not running")
+        } else {
+            target_mismatch(*pid, target_uid, target_name, &own)
+                .or_else(|| filter.skip_reason(*pid, &name))
+        };
+        match reason {
+            Some(reason) => println!("skip     {:>7}  {}  ({})", pid, name, reason),
+            None => {
+                monitored += 1;
+                println!("monitor  {:>7}  {}", pid, name);
+            }
+        }
+    }
+    println!(
+        "{} monitored, {} skipped",
+        monitored,
+        pids.len() - monitored
+    );
+    if target_pid.is_none() && record_cpu_percent_threshold > 0.0 {
+        println!(
+            "processes below {:.1}% CPU are also skipped at each sample \
+             (record_cpu_time_percent_threshold)",
+            record_cpu_percent_threshold
+        );
+    }
+}
+
 fn prune_states(
     states: &mut HashMap<u32, ProcState>,
     pids: &[u32],
-    output_dir: Option<&str>,
-    use_msgpack: bool,
-    compress: bool,
+    sinks: &Sinks,
+    writer: &mut LogWriter,
+    opts: &MonitorOptions,
 ) {
     let existing: Vec<u32> = states.keys().copied().collect();
     let pid_set: HashSet<u32> = pids.iter().copied().collect();
     for old in &existing {
         if !pid_set.contains(old) {
-            if let Some(mut state) = states.remove(old) {
-                if let Some(dir) = output_dir {
-                    let events: Vec<FdLogEvent> = state
-                        .fds
-                        .drain()
-                        .map(|(fd, path)| FdLogEvent {
-                            fd,
-                            event: "close".into(),
-                            path,
-                        })
-                        .collect();
-                    if !events.is_empty() {
-                        let entry = LogEntry {
-                            timestamp: Utc::now()
-                                .to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
-                            pid: *old,
-                            process_name: process_name(*old).unwrap_or_else(|| "?".into()),
-                            cpu_time_percent: 0.0,
-                            memory: MemoryInfo {
-                                rss_kb: 0,
-                                vsz_kb: 0,
-                                swap_kb: 0,
-                            },
-                            cmdline: None,
-                            env: None,
-                            fd_events: Some(events),
-                            threads: Vec::new(),
-                        };
-                        write_log(dir, &entry, use_msgpack, compress);
-                    }
+            if let Some(mut state) = states.remove(old)
+                && let Some(dir) = opts.output_dir.as_deref()
+            {
+                let events: Vec<FdLogEvent> = state
+                    .fds
+                    .drain()
+                    .map(|(fd, path)| FdLogEvent {
+                        fd,
+                        event: "close".into(),
+                        path,
+                    })
+                    .collect();
+                if !events.is_empty() {
+                    let mut entry = LogEntry {
+                        version: LOG_VERSION,
+                        timestamp: Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+                        pid: *old,
+                        process_name: process_name(*old).unwrap_or_else(|| "?".into()),
+                        cpu_time_percent: 0.0,
+                        memory: MemoryInfo {
+                            rss_kb: 0,
+                            vsz_kb: 0,
+                            swap_kb: 0,
+                        },
+                        ppid: None,
+                        cmdline: None,
+                        env: None,
+                        hostname: None,
+                        boot_id: None,
+                        start_time: None,
+                        stopped: None,
+                        alerts: None,
+                        self_overhead: None,
+                        fd_events: Some(events),
+                        threads: Vec::new(),
+                    };
+                    writer.write(dir, &mut entry, &mut state);
                 }
             }
             info!("process {} disappeared", old);
+            sinks.event(Severity::Info, &format!("process {} disappeared", old));
         }
     }
 }
@@ -283,31 +932,30 @@ fn prune_states(
 fn process_pid(
     pid: u32,
     states: &mut HashMap<u32, ProcState>,
-    target_pid: Option<u32>,
-    ignore_patterns: &[Regex],
-    record_cpu_percent_threshold: f64,
-    stacktrace_cpu_percent_threshold: f64,
-    output_dir: Option<&str>,
-    use_msgpack: bool,
-    compress: bool,
-    verbose: bool,
+    limiter: &mut Option<CaptureLimiter>,
+    sinks: &mut Sinks,
+    writer: &mut LogWriter,
+    opts: &MonitorOptions,
 ) {
     let is_new = !states.contains_key(&pid);
     let state = states.entry(pid).or_default();
     let usage = get_proc_usage(pid, state);
     let cpu = usage.map(|u| u.0).unwrap_or(0.0);
-    if should_skip_pid(
-        pid,
-        target_pid,
-        ignore_patterns,
-        record_cpu_percent_threshold,
-        cpu,
-    ) {
+    // Counted on every sample, including ones not recorded.
+    state.update_stack_trigger(
+        cpu >= opts.stacktrace_cpu_percent_threshold as f32,
+        opts.stacktrace_trigger_samples,
+        opts.stacktrace_hold_samples,
+    );
+    if should_skip_pid(pid, opts, cpu) {
         return;
     }
     if is_new {
         info!("new process {}", pid);
     }
+    if sinks.events_enabled() {
+        report_events(pid, state, cpu, is_new, sinks, opts);
+    }
     let raw_events = detect_fd_events(pid, state);
     state.pending_fd_events.extend(raw_events);
     let rss = usage
@@ -336,58 +984,129 @@ fn process_pid(
         })
         .collect();
 
-    if verbose && !should_suppress(cpu, rss) {
-        println!("PID {:>5}: {:>5.1}% CPU, {:>8} KB RSS", pid, cpu, rss);
+    if opts.verbose && !should_suppress(cpu, rss) {
+        let msg = format!("PID {:>5}: {:>5.1}% CPU, {:>8} KB RSS", pid, cpu, rss);
+        print_status(opts.streaming(), &msg);
     }
 
-    if let Some(dir) = output_dir {
-        let entry = build_log_entry(
+    let markers = opts.alerts.as_ref().map(|alerts| {
+        let name = process_name(pid).unwrap_or_else(|| "?".into());
+        let sample = Sample {
             pid,
-            state,
-            cpu,
-            rss,
-            fd_log_events,
-            stacktrace_cpu_percent_threshold,
+            process_name: &name,
+            cpu_percent: cpu,
+            rss_kb: rss,
+            fd_count: state.fds.len(),
+        };
+        alerts.check(&sample, state, Instant::now())
+    });
+
+    if opts.output_dir.is_none() && !sinks.records_enabled() {
+        return;
+    }
+    let mut entry = build_log_entry(pid, state, cpu, rss, fd_log_events, limiter, opts);
+    entry.alerts = markers.filter(|m| !m.is_empty());
+    if opts.verbose
+        && let Ok(line) = serde_json::to_string(&entry)
+    {
+        print_status(opts.streaming(), &line);
+    }
+    sinks.record(&entry, state);
+    if let Some(dir) = opts.output_dir.as_deref() {
+        writer.write(dir, &mut entry, state);
+    }
+}
+
+/// Sends appearance, CPU threshold crossings and D-state stalls of `pid` to
+/// the event sinks.
+fn report_events(
+    pid: u32,
+    state: &mut ProcState,
+    cpu: f32,
+    is_new: bool,
+    sinks: &Sinks,
+    opts: &MonitorOptions,
+) {
+    let name = || process_name(pid).unwrap_or_else(|| "?".into());
+    if is_new {
+        sinks.event(
+            Severity::Info,
+            &format!("process {} ({}) appeared", pid, name()),
         );
-        if verbose {
-            if let Ok(line) = serde_json::to_string(&entry) {
-                println!("{}", line);
-            }
+    }
+    let threshold = opts.stacktrace_cpu_percent_threshold as f32;
+    let above = cpu >= threshold;
+    if above && !state.above_stack_threshold {
+        sinks.event(
+            Severity::Notice,
+            &format!(
+                "process {} ({}) at {:.1}% CPU crossed {:.1}%",
+                pid,
+                name(),
+                cpu,
+                threshold
+            ),
+        );
+    }
+    state.above_stack_threshold = above;
+    if run_state(pid) == Some('D') {
+        state.d_state_samples += 1;
+        if state.d_state_samples == STALL_SAMPLES {
+            sinks.event(
+                Severity::Warning,
+                &format!(
+                    "process {} ({}) stalled in uninterruptible sleep",
+                    pid,
+                    name()
+                ),
+            );
         }
-        write_log(dir, &entry, use_msgpack, compress);
+    } else {
+        state.d_state_samples = 0;
     }
 }
 
-fn should_skip_pid(
-    pid: u32,
-    target_pid: Option<u32>,
-    ignore_patterns: &[Regex],
-    record_cpu_percent_threshold: f64,
-    cpu_percent: f32,
-) -> bool {
-    if target_pid.is_none() {
-        if let Some(name) = process_name(pid) {
-            if ignore_patterns.iter().any(|re| re.is_match(&name)) {
-                return true;
-            }
+fn should_skip_pid(pid: u32, opts: &MonitorOptions, cpu_percent: f32) -> bool {
+    if opts.target_pid.is_none() {
+        if let Some(name) = process_name(pid)
+            && opts.filter.skips(pid, &name)
+        {
+            return true;
         }
-        if cpu_percent < record_cpu_percent_threshold as f32 {
+        if cpu_percent < opts.record_cpu_percent_threshold as f32 {
             return true;
         }
     }
     false
 }
 
-fn build_log_entry(
-    pid: u32,
-    state: &mut ProcState,
-    cpu_percent: f32,
-    rss: u64,
-    fd_events: Vec<FdLogEvent>,
-    stacktrace_cpu_percent_threshold: f64,
-) -> LogEntry {
-    let mut entry = LogEntry {
-        timestamp: Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+/// Trims every stack of `thread` to `cap` frames, keeping the innermost ones.
+fn cap_thread_frames(thread: &mut ThreadInfo, cap: usize) {
+    let mut cut = false;
+    for frames in [
+        &mut thread.stacktrace,
+        &mut thread.python_stacktrace,
+        &mut thread.php_stacktrace,
+        &mut thread.merged_stacktrace,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        if frames.len() > cap {
+            frames.truncate(cap);
+            cut = true;
+        }
+    }
+    if cut && thread.truncated.is_none() {
+        thread.truncated = Some("max_thread_frames reached".into());
+    }
+}
+
+/// An entry with only the CPU and memory of `pid`.
+fn metrics_entry(pid: u32, cpu_percent: f32, rss: u64) -> LogEntry {
+    LogEntry {
+        version: LOG_VERSION,
+        timestamp: Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
         pid,
         process_name: process_name(pid).unwrap_or_else(|| "?".into()),
         cpu_time_percent: cpu_percent as f64,
@@ -396,24 +1115,106 @@ fn build_log_entry(
             vsz_kb: vsz_kb(pid).unwrap_or(0),
             swap_kb: swap_kb(pid).unwrap_or(0),
         },
+        ppid: None,
         cmdline: None,
         env: None,
-        fd_events: if fd_events.is_empty() {
-            None
-        } else {
-            Some(fd_events)
-        },
+        hostname: None,
+        boot_id: None,
+        start_time: None,
+        stopped: None,
+        alerts: None,
+        self_overhead: None,
+        fd_events: None,
         threads: Vec::new(),
+    }
+}
+
+/// Records the CPU and memory fuzmon itself uses, without stacks or any of
+/// the other details of monitored processes.
+fn record_overhead(
+    pid: u32,
+    states: &mut HashMap<u32, ProcState>,
+    sinks: &mut Sinks,
+    writer: &mut LogWriter,
+    opts: &MonitorOptions,
+) {
+    let state = states.entry(pid).or_default();
+    let Some((cpu, rss)) = get_proc_usage(pid, state) else {
+        return;
     };
+    if let Some(dir) = opts.output_dir.as_deref() {
+        let mut entry = LogEntry {
+            self_overhead: Some(true),
+            ..metrics_entry(pid, cpu, rss)
+        };
+        sinks.record(&entry, state);
+        writer.write(dir, &mut entry, state);
+    }
+}
+
+fn build_log_entry(
+    pid: u32,
+    state: &mut ProcState,
+    cpu_percent: f32,
+    rss: u64,
+    fd_events: Vec<FdLogEvent>,
+    limiter: &mut Option<CaptureLimiter>,
+    opts: &MonitorOptions,
+) -> LogEntry {
+    let mut entry = metrics_entry(pid, cpu_percent, rss);
+    if !fd_events.is_empty() {
+        entry.fd_events = Some(fd_events);
+    }
     if !state.metadata_written {
+        entry.ppid = parent_pid(pid);
         entry.cmdline = cmdline(pid);
-        entry.env = environ(pid);
+        entry.env = environ(pid).map(|env| redact_env(&env, &opts.redact_env));
         state.metadata_written = true;
     }
-    if cpu_percent >= stacktrace_cpu_percent_threshold as f32 {
+    let stack_due = match (opts.stacktrace_interval, state.last_stack_capture) {
+        (Some(interval), Some(last)) => last.elapsed() >= interval,
+        _ => true,
+    };
+    // Leaks tend to be in nearly idle processes, so memory triggers too.
+    let rss_at_last_stack = *state.rss_at_last_stack.get_or_insert(rss);
+    let memory_triggered = opts.stacktrace_rss_kb_threshold.is_some_and(|t| rss >= t)
+        || opts
+            .stacktrace_rss_growth_kb
+            .is_some_and(|g| rss >= rss_at_last_stack + g);
+    if stack_due
+        && (state.cpu_stack_triggered || memory_triggered)
+        && limiter
+            .as_mut()
+            .is_none_or(|l| l.try_take(pid, Instant::now()))
+    {
+        state.last_stack_capture = Some(Instant::now());
+        state.rss_at_last_stack = Some(rss);
         let name = &entry.process_name;
-        let mut c_traces = capture_c_stack_traces(pid as i32);
-        let mut py_traces = if name.starts_with("python") {
+        let deltas = if opts.thread_cpu || opts.capture_registers || opts.top_threads.is_some() {
+            thread_cpu_deltas(pid, state)
+        } else {
+            Vec::new()
+        };
+        let hottest = if opts.capture_registers {
+            deltas.first().filter(|d| d.ticks > 0).map(|d| d.tid)
+        } else {
+            None
+        };
+        let thread_cpu: HashMap<i32, f32> = deltas
+            .iter()
+            .filter(|_| opts.thread_cpu)
+            .filter_map(|d| Some((d.tid, d.percent?)))
+            .collect();
+        let tids = match opts.top_threads {
+            Some(n) => {
+                let mut tids: Vec<i32> = deltas.iter().take(n).map(|d| d.tid).collect();
+                tids.sort_unstable();
+                tids
+            }
+            None => read_tids(pid),
+        };
+        let mut c_traces = capture_c_stack_traces(&tids, &opts.capture, hottest);
+        let mut py_traces = if has_python_module(pid as i32) {
             match capture_python_stack_traces(pid as i32) {
                 Ok(t) => t,
                 Err(e) => {
@@ -424,21 +1225,85 @@ fn build_log_entry(
         } else {
             HashMap::new()
         };
+        if opts.top_threads.is_some() {
+            py_traces.retain(|tid, _| tids.contains(&(*tid as i32)));
+        }
+        // php-fpm workers serve one request at a time on their main thread.
+        let mut php_trace = if name.starts_with("php") {
+            match capture_php_stack_trace(pid as i32) {
+                Ok(t) => Some(t),
+                Err(e) => {
+                    // Fails the same way at every sample, e.g. for ZTS builds.
+                    if !state.php_trace_warned {
+                        warn!("php trace of pid {} failed: {}", pid, e);
+                        state.php_trace_warned = true;
+                    }
+                    None
+                }
+            }
+        } else {
+            None
+        };
         for (tid, c) in c_traces.drain(..) {
             let py = py_traces.remove(&(tid as u32));
+            let php = if tid as u32 == pid {
+                php_trace.take()
+            } else {
+                None
+            };
+            let (stacktrace, truncated, registers, capture_error) = match c {
+                Ok(t) => (Some(t.frames), t.truncated, t.registers, None),
+                // The thread exited since it was listed.
+                Err(Errno::ESRCH) => (None, None, None, None),
+                Err(e) => (None, None, None, Some(e.to_string())),
+            };
+            let merged = match (&stacktrace, &py) {
+                (Some(c), Some(p)) => merge_python_stack(c, p),
+                _ => None,
+            };
+            let python_thread = py.as_ref().map(|p| p.state.clone());
+            // Python threads are named by py-spy.
+            let thread_name = if opts.thread_cpu && python_thread.is_none() {
+                thread_name(pid, tid)
+            } else {
+                None
+            };
             entry.threads.push(ThreadInfo {
                 tid: tid as u32,
-                stacktrace: c,
-                python_stacktrace: py,
+                name: thread_name,
+                cpu_time_percent: thread_cpu.get(&tid).map(|&p| p as f64),
+                stacktrace,
+                python_stacktrace: py.map(|p| p.frames()),
+                python_thread,
+                php_stacktrace: php,
+                merged_stacktrace: merged,
+                truncated,
+                registers,
+                capture_error,
+                ..Default::default()
             });
         }
         for (tid, py) in py_traces.into_iter() {
             entry.threads.push(ThreadInfo {
                 tid,
-                stacktrace: None,
-                python_stacktrace: Some(py),
+                cpu_time_percent: thread_cpu.get(&(tid as i32)).map(|&p| p as f64),
+                python_stacktrace: Some(py.frames()),
+                python_thread: Some(py.state),
+                ..Default::default()
             });
         }
+        if let Some(php) = php_trace {
+            entry.threads.push(ThreadInfo {
+                tid: pid,
+                php_stacktrace: Some(php),
+                ..Default::default()
+            });
+        }
+        if let Some(cap) = opts.max_thread_frames {
+            for t in &mut entry.threads {
+                cap_thread_frames(t, cap);
+            }
+        }
     }
     entry
 }
@@ -0,0 +1,249 @@
+use log::{info, warn};
+use nix::fcntl::{FcntlArg, FdFlag, fcntl};
+use nix::libc;
+use nix::pty::openpty;
+use nix::sys::signal::{SigSet, Signal, kill, killpg};
+use nix::sys::signalfd::SignalFd;
+use nix::unistd::{Gid, Pid, Uid, getgrouplist, setgid, setgroups, setsid, setuid};
+use std::ffi::CString;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::fd::{AsFd, AsRawFd, OwnedFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, Stdio};
+use std::thread::{self, JoinHandle};
+
+use crate::config::User;
+use crate::terminal::{RawMode, set_window_size, window_size};
+
+/// Signals fuzmon passes on to the command instead of stopping.
+const FORWARDED: [Signal; 7] = [
+    Signal::SIGINT,
+    Signal::SIGTERM,
+    Signal::SIGHUP,
+    Signal::SIGQUIT,
+    Signal::SIGUSR1,
+    Signal::SIGUSR2,
+    Signal::SIGWINCH,
+];
+
+/// A command run under fuzmon.
+pub struct Spawned {
+    pub child: Child,
+    /// Copies the output of the command from its pty, if it has one.
+    output: Option<JoinHandle<()>>,
+    /// Keeps the terminal of fuzmon raw while the command owns the pty.
+    raw: Option<RawMode>,
+    /// Whether the command leads a session on a pty of its own.
+    pty: bool,
+}
+
+/// Sends `sig` to the command, or to its whole process group when it
+/// leads one, as the terminal would.
+fn signal_command(pid: Pid, group: bool, sig: Signal) -> nix::Result<()> {
+    if group {
+        killpg(pid, sig)
+    } else {
+        kill(pid, sig)
+    }
+}
+
+impl Spawned {
+    /// Asks the command to stop, once it is no longer monitored. Does
+    /// nothing if it has already exited.
+    pub fn terminate(&mut self) {
+        if !matches!(self.child.try_wait(), Ok(None)) {
+            return;
+        }
+        let pid = Pid::from_raw(self.child.id() as i32);
+        info!("sending SIGTERM to pid {}", pid);
+        if let Err(e) = signal_command(pid, self.pty, Signal::SIGTERM) {
+            warn!("failed to send SIGTERM to pid {}: {}", pid, e);
+        }
+    }
+
+    /// Waits for the command, then for the rest of its output.
+    pub fn wait(mut self) -> io::Result<std::process::ExitStatus> {
+        let status = self.child.wait();
+        if let Some(output) = self.output.take() {
+            let _ = output.join();
+        }
+        drop(self.raw.take());
+        status
+    }
+}
+
+/// Gives the pty the size of the terminal of fuzmon, if it has one.
+fn resize(pty: &OwnedFd) {
+    if let Some(size) = window_size(libc::STDIN_FILENO) {
+        let _ = set_window_size(pty.as_raw_fd(), &size);
+    }
+}
+
+/// Copies `from` to `to` until either side is closed. Reads from a pty end
+/// with EIO once the command and its children are gone.
+fn copy(mut from: File, mut to: impl Write) {
+    let mut buf = [0u8; 4096];
+    loop {
+        let n = match from.read(&mut buf) {
+            Ok(0) | Err(_) => return,
+            Ok(n) => n,
+        };
+        if to.write_all(&buf[..n]).and_then(|_| to.flush()).is_err() {
+            return;
+        }
+    }
+}
+
+/// Passes signals sent to fuzmon on to `pid`, or to its process group on
+/// `pty`, so jobs it started stop with it rather than keep the pty open.
+/// Signals of the terminal are left alone when the command shares it, as
+/// they reach it directly.
+fn forward_signals(mut signals: SignalFd, pid: Pid, pty: Option<OwnedFd>) {
+    while let Ok(Some(info)) = signals.read_signal() {
+        let Ok(sig) = Signal::try_from(info.ssi_signo as i32) else {
+            continue;
+        };
+        if sig == Signal::SIGWINCH
+            && let Some(pty) = &pty
+        {
+            // The pty sends SIGWINCH to the command itself.
+            resize(pty);
+            continue;
+        }
+        if info.ssi_code == libc::SI_KERNEL && pty.is_none() {
+            continue;
+        }
+        info!("forwarding {} to pid {}", sig, pid);
+        if let Err(e) = signal_command(pid, pty.is_some(), sig) {
+            warn!("failed to forward {} to pid {}: {}", sig, pid, e);
+        }
+    }
+}
+
+/// Blocks `signals` in this thread and the ones it starts, so they can be
+/// read from the returned fd instead. Must be called before any other
+/// thread is started.
+pub fn block_signals(signals: &[Signal]) -> io::Result<SignalFd> {
+    let mut mask = SigSet::empty();
+    for &sig in signals {
+        mask.add(sig);
+    }
+    mask.thread_block()?;
+    Ok(SignalFd::new(&mask)?)
+}
+
+/// Lets `cmd` get the signals blocked in fuzmon, which it would inherit.
+pub fn unblock_signals(cmd: &mut Command) {
+    // SAFETY: pthread_sigmask is async-signal-safe.
+    unsafe {
+        cmd.pre_exec(|| Ok(SigSet::all().thread_unblock()?));
+    }
+}
+
+fn set_cloexec(fd: &OwnedFd) -> nix::Result<()> {
+    fcntl(fd.as_raw_fd(), FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC)).map(|_| ())
+}
+
+/// Runs `command`, passing the signals fuzmon gets on to it, as `run_as`
+/// if given. With `pty`, the command gets a terminal of its own, relayed
+/// to fuzmon's in raw mode, with its output written to stderr instead of
+/// stdout when `to_stderr`. Must be called before any other thread is
+/// started.
+pub fn spawn(
+    command: &[String],
+    pty: bool,
+    to_stderr: bool,
+    run_as: Option<&User>,
+) -> io::Result<Spawned> {
+    let mut cmd = Command::new(&command[0]);
+    cmd.args(&command[1..]);
+    // Leave systemd to fuzmon rather than the command.
+    cmd.env_remove("NOTIFY_SOCKET");
+    if let Some(user) = run_as {
+        // Read before forking, as NSS lookups are not async-signal-safe.
+        let name = CString::new(user.name.as_str())?;
+        let gid = Gid::from_raw(user.gid);
+        let uid = Uid::from_raw(user.uid);
+        let groups = getgrouplist(&name, gid)?;
+        // std only drops the groups of root, so the command would miss
+        // those of the user that `sudo -u` gives it. They are set first,
+        // while it may still change them.
+        // SAFETY: setgroups, setgid and setuid are async-signal-safe.
+        unsafe {
+            cmd.pre_exec(move || {
+                setgroups(&groups)?;
+                setgid(gid)?;
+                setuid(uid)?;
+                Ok(())
+            });
+        }
+        cmd.env("HOME", &user.home)
+            .env("USER", &user.name)
+            .env("LOGNAME", &user.name);
+    }
+    let master = if pty {
+        let size = window_size(libc::STDIN_FILENO);
+        let pair = openpty(size.as_ref(), None)?;
+        set_cloexec(&pair.master)?;
+        cmd.stdin(Stdio::from(pair.slave.try_clone()?));
+        cmd.stdout(Stdio::from(pair.slave.try_clone()?));
+        cmd.stderr(Stdio::from(pair.slave));
+        // SAFETY: setsid and ioctl are async-signal-safe.
+        unsafe {
+            cmd.pre_exec(|| {
+                setsid()?;
+                if libc::ioctl(libc::STDIN_FILENO, libc::TIOCSCTTY, 0) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        Some(pair.master)
+    } else {
+        // Keep the output of the command out of the stream of entries.
+        if to_stderr {
+            cmd.stdout(io::stderr());
+        }
+        None
+    };
+
+    // Blocked before the command starts, so none arrives unhandled.
+    let signals = block_signals(&FORWARDED)?;
+    unblock_signals(&mut cmd);
+    let child = cmd.spawn()?;
+    // Closes the pty of the command in fuzmon, so reads end with it.
+    drop(cmd);
+    let pid = Pid::from_raw(child.id() as i32);
+
+    let mut spawned = Spawned {
+        child,
+        output: None,
+        raw: None,
+        pty,
+    };
+    let relay = match master {
+        Some(master) => {
+            spawned.raw = RawMode::enter().ok();
+            let stdin = File::from(io::stdin().as_fd().try_clone_to_owned()?);
+            let input = File::from(master.try_clone()?);
+            thread::spawn(move || {
+                copy(stdin, &input);
+                // Ends the input of the command as Ctrl-D would.
+                let _ = (&input).write_all(&[4]);
+            });
+            let output = File::from(master.try_clone()?);
+            spawned.output = Some(thread::spawn(move || {
+                if to_stderr {
+                    copy(output, io::stderr())
+                } else {
+                    copy(output, io::stdout())
+                }
+            }));
+            Some(master)
+        }
+        None => None,
+    };
+    thread::spawn(move || forward_signals(signals, pid, relay));
+    Ok(spawned)
+}
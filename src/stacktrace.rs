@@ -1,34 +1,237 @@
 use addr2line::Loader;
 use log::{info, warn};
-use nix::sys::{ptrace, wait::waitpid};
+use nix::errno::Errno;
+use nix::libc::user_regs_struct;
+use nix::sys::ptrace;
+use nix::sys::signal::Signal;
+use nix::sys::wait::{WaitPidFlag, WaitStatus, waitpid};
 use nix::unistd::Pid;
 use object::{Object, ObjectKind};
 use py_spy::{Config as PySpyConfig, PythonSpy};
 use std::borrow::Cow;
-use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fs;
 use std::io::Read;
-use std::rc::Rc;
-use std::time::SystemTime;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
-use crate::log::Frame;
+use crate::config::{DEFAULT_CAPTURE_WORKERS, DEFAULT_MAX_FRAMES};
+use crate::debuginfod::{Lookup, build_id_hex, find_debuginfo, is_fetching};
+use crate::log::{Frame, PythonThreadState, Registers};
+
+const DEBUG_ROOT: &str = "/usr/lib/debug";
+
+/// How native stacks are unwound.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum UnwindBackend {
+    /// Follow the rbp chain; cheap but needs frame pointers.
+    FramePointer,
+    /// libunwind-ptrace, which uses .eh_frame and works without frame
+    /// pointers at a higher cost per frame.
+    Libunwind,
+}
+
+/// Settings that affect how native stacks are captured and symbolized.
+#[derive(Clone)]
+pub struct CaptureOptions {
+    pub debuginfod: bool,
+    /// Longest time a thread may stay stopped while its stack is unwound.
+    pub max_pause: Option<Duration>,
+    /// Maximum number of native frames unwound per thread.
+    pub max_frames: usize,
+    /// Collapse C++ template arguments in function names.
+    pub shorten_cpp_names: bool,
+    pub backend: UnwindBackend,
+    /// Number of threads of one process captured concurrently.
+    pub workers: usize,
+}
+
+impl Default for CaptureOptions {
+    fn default() -> Self {
+        CaptureOptions {
+            debuginfod: false,
+            max_pause: None,
+            max_frames: DEFAULT_MAX_FRAMES,
+            shorten_cpp_names: false,
+            backend: UnwindBackend::FramePointer,
+            workers: DEFAULT_CAPTURE_WORKERS,
+        }
+    }
+}
+
+/// Native stack of one thread. `truncated` explains why the walk stopped
+/// early, if it did.
+pub struct NativeTrace {
+    pub frames: Vec<Frame>,
+    pub truncated: Option<String>,
+    pub registers: Option<Registers>,
+}
+
+/// Upper bound on cached modules; the least recently used entry is evicted
+/// once it is exceeded so long-running daemons do not grow without bound.
+const MODULE_CACHE_LIMIT: usize = 512;
 
 struct CachedModule {
-    module: Option<Rc<ModuleData>>,
+    module: Option<Arc<ModuleData>>,
     mtime: Option<SystemTime>,
+    /// Build-id of the debug info still being downloaded, with which the
+    /// module is loaded again once it is there.
+    fetching: Option<String>,
+    last_used: u64,
 }
 
-thread_local! {
-    static MODULE_CACHE: RefCell<HashMap<String, CachedModule>> = RefCell::new(HashMap::new());
+struct ModuleCache {
+    entries: HashMap<String, CachedModule>,
+    limit: usize,
+    tick: u64,
+}
+
+impl ModuleCache {
+    fn new(limit: usize) -> Self {
+        ModuleCache {
+            entries: HashMap::new(),
+            limit,
+            tick: 0,
+        }
+    }
+
+    /// Returns the cached result for `path` if it was loaded with the same
+    /// mtime; a stale entry is dropped so the caller reloads it.
+    fn get(&mut self, path: &str, mtime: Option<SystemTime>) -> Option<Option<Arc<ModuleData>>> {
+        self.tick += 1;
+        let entry = self.entries.get_mut(path)?;
+        if entry.mtime != mtime {
+            info!(
+                "mmaped file {} mtime changed, reloading: old_mtime={:?} new_mtime={:?}",
+                path, entry.mtime, mtime
+            );
+        } else if entry.fetching.as_deref().is_some_and(|id| !is_fetching(id)) {
+            info!("debug info of {} fetched, reloading", path);
+        } else {
+            entry.last_used = self.tick;
+            return Some(entry.module.clone());
+        }
+        self.entries.remove(path);
+        None
+    }
+
+    fn insert(&mut self, path: &str, mtime: Option<SystemTime>, loaded: LoadedModule) {
+        self.tick += 1;
+        if self.entries.len() >= self.limit
+            && !self.entries.contains_key(path)
+            && let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, e)| e.last_used)
+                .map(|(p, _)| p.clone())
+        {
+            self.entries.remove(&oldest);
+        }
+        self.entries.insert(
+            path.to_string(),
+            CachedModule {
+                module: loaded.module,
+                mtime,
+                fetching: loaded.fetching,
+                last_used: self.tick,
+            },
+        );
+    }
 }
 
+static MODULE_CACHE: LazyLock<Mutex<ModuleCache>> =
+    LazyLock::new(|| Mutex::new(ModuleCache::new(MODULE_CACHE_LIMIT)));
+
 pub struct ModuleData {
-    loader: Rc<Loader>,
+    loader: Arc<Mutex<Loader>>,
+    is_pic: bool,
+}
+
+/// What `inspect_elf` found out about an ELF file.
+#[derive(Default)]
+struct ElfInfo {
     is_pic: bool,
+    /// Separate debug info, for files without DWARF.
+    debug: Option<PathBuf>,
+    /// Build-id of debug info debuginfod is still downloading.
+    fetching: Option<String>,
+}
+
+/// Returns whether the ELF file is position independent and, when it lacks
+/// DWARF, where its separate debug info is. With debuginfod enabled, debug
+/// info that is not installed is fetched in the background; until it
+/// arrives, only the symbols of the file itself are used.
+fn inspect_elf(path: &str, opts: &CaptureOptions) -> ElfInfo {
+    let data = match fs::read(path) {
+        Ok(d) => d,
+        Err(e) => {
+            warn!("read {} failed: {}", path, e);
+            return ElfInfo::default();
+        }
+    };
+    let obj = match object::File::parse(&*data) {
+        Ok(o) => o,
+        Err(e) => {
+            warn!("parse {} failed: {}", path, e);
+            return ElfInfo::default();
+        }
+    };
+    let mut info = ElfInfo {
+        is_pic: matches!(obj.kind(), ObjectKind::Dynamic),
+        ..Default::default()
+    };
+    if obj.section_by_name(".debug_info").is_none() {
+        info.debug = find_separate_debug_file(path, &obj, Path::new(DEBUG_ROOT));
+        if info.debug.is_none()
+            && opts.debuginfod
+            && let Ok(Some(id)) = obj.build_id()
+        {
+            match find_debuginfo(id) {
+                Lookup::Found(debug) => info.debug = Some(debug),
+                Lookup::Fetching => info.fetching = Some(build_id_hex(id)),
+                Lookup::Missing => {}
+            }
+        }
+    }
+    info
 }
 
-fn get_module(path: &str) -> Option<Rc<ModuleData>> {
+/// Finds installed debug info for a stripped ELF the way gdb does: first by
+/// build-id under `debug_root/.build-id`, then by following .gnu_debuglink
+/// next to the file, in its .debug directory and under `debug_root`.
+fn find_separate_debug_file(path: &str, obj: &object::File, debug_root: &Path) -> Option<PathBuf> {
+    if let Ok(Some(id)) = obj.build_id()
+        && id.len() > 1
+    {
+        let hex = build_id_hex(id);
+        let candidate = debug_root
+            .join(".build-id")
+            .join(&hex[..2])
+            .join(format!("{}.debug", &hex[2..]));
+        if candidate.is_file() {
+            return Some(candidate);
+        }
+    }
+    let (name, crc) = obj.gnu_debuglink().ok()??;
+    let name = std::str::from_utf8(name).ok()?;
+    let dir = Path::new(path).parent()?;
+    let candidates = [
+        dir.join(name),
+        dir.join(".debug").join(name),
+        debug_root
+            .join(dir.strip_prefix("/").unwrap_or(dir))
+            .join(name),
+    ];
+    candidates.into_iter().find(|c| {
+        c.as_path() != Path::new(path)
+            && fs::read(c).is_ok_and(|data| crc32fast::hash(&data) == crc)
+    })
+}
+
+fn get_module(path: &str, opts: &CaptureOptions) -> Option<Arc<ModuleData>> {
     if path.starts_with("[") {
         return None;
     }
@@ -40,83 +243,52 @@ fn get_module(path: &str) -> Option<Rc<ModuleData>> {
         return None;
     }
     let mtime = meta.modified().ok();
-    MODULE_CACHE.with(|cache| {
-        let mut map = cache.borrow_mut();
-        if let Some(entry) = map.get(path) {
-            if entry.mtime == mtime {
-                return entry.module.clone();
-            }
-            info!(
-                "mmaped file {} mtime changed, reloading: old_mtime={:?} new_mtime={:?}",
-                path, entry.mtime, mtime
-            );
-            map.remove(path);
-        }
-        let mut header = [0u8; 4];
-        match fs::File::open(path).and_then(|mut f| f.read_exact(&mut header)) {
-            Ok(_) => {
-                if header != [0x7f, b'E', b'L', b'F'] {
-                    map.insert(
-                        path.to_string(),
-                        CachedModule {
-                            module: None,
-                            mtime,
-                        },
-                    );
-                    return None;
-                }
-            }
-            Err(e) => {
-                warn!("read {} failed: {}", path, e);
-                map.insert(
-                    path.to_string(),
-                    CachedModule {
-                        module: None,
-                        mtime,
-                    },
-                );
-                return None;
-            }
+    if let Some(module) = MODULE_CACHE.lock().unwrap().get(path, mtime) {
+        return module;
+    }
+    // Parsing DWARF can be slow, so load without holding the cache lock.
+    let loaded = load_module(path, opts);
+    let module = loaded.module.clone();
+    MODULE_CACHE.lock().unwrap().insert(path, mtime, loaded);
+    module
+}
+
+/// A module as loaded, with the build-id of debug info still being
+/// fetched for it.
+#[derive(Default)]
+struct LoadedModule {
+    module: Option<Arc<ModuleData>>,
+    fetching: Option<String>,
+}
+
+fn load_module(path: &str, opts: &CaptureOptions) -> LoadedModule {
+    let mut header = [0u8; 4];
+    if let Err(e) = fs::File::open(path).and_then(|mut f| f.read_exact(&mut header)) {
+        warn!("read {} failed: {}", path, e);
+        return LoadedModule::default();
+    }
+    if header != [0x7f, b'E', b'L', b'F'] {
+        return LoadedModule::default();
+    }
+    let elf = inspect_elf(path, opts);
+    let symbols = elf.debug.as_deref().unwrap_or(Path::new(path));
+    let module = match Loader::new(symbols) {
+        Ok(loader) => {
+            info!("load debug symbols from {}", symbols.display());
+            Some(Arc::new(ModuleData {
+                loader: Arc::new(Mutex::new(loader)),
+                is_pic: elf.is_pic,
+            }))
         }
-        match Loader::new(path) {
-            Ok(loader) => {
-                info!("load debug symbols from {}", path);
-                let mut is_pic = false;
-                match fs::read(path) {
-                    Ok(data) => match object::File::parse(&*data) {
-                        Ok(obj) => {
-                            is_pic = matches!(obj.kind(), ObjectKind::Dynamic);
-                        }
-                        Err(e) => warn!("parse {} failed: {}", path, e),
-                    },
-                    Err(e) => warn!("read {} failed: {}", path, e),
-                }
-                let rc = Rc::new(ModuleData {
-                    loader: Rc::new(loader),
-                    is_pic,
-                });
-                map.insert(
-                    path.to_string(),
-                    CachedModule {
-                        module: Some(rc.clone()),
-                        mtime,
-                    },
-                );
-                Some(rc)
-            }
-            Err(e) => {
-                warn!("Loader::new {} failed: {}", path, e);
-                map.insert(
-                    path.to_string(),
-                    CachedModule {
-                        module: None,
-                        mtime,
-                    },
-                );
-                None
-            }
+        Err(e) => {
+            warn!("Loader::new {} failed: {}", path, e);
+            None
         }
-    })
+    };
+    LoadedModule {
+        module,
+        fetching: elf.fetching,
+    }
 }
 
 pub struct ExeInfo {
@@ -126,17 +298,17 @@ pub struct ExeInfo {
 }
 
 pub struct Module {
-    pub loader: Rc<Loader>,
+    pub loader: Arc<Mutex<Loader>>,
     pub info: ExeInfo,
     pub is_pic: bool,
 }
 
-pub fn load_loaders(pid: i32) -> Vec<Module> {
+pub fn read_exe_infos(pid: i32) -> HashMap<String, ExeInfo> {
     let maps = match fs::read_to_string(format!("/proc/{}/maps", pid)) {
         Ok(m) => m,
         Err(e) => {
             warn!("read maps {} failed: {}", pid, e);
-            return Vec::new();
+            return HashMap::new();
         }
     };
     let mut infos: HashMap<String, ExeInfo> = HashMap::new();
@@ -160,30 +332,34 @@ pub fn load_loaders(pid: i32) -> Vec<Module> {
             Some(v) => v,
             None => continue,
         };
-        if let Some((start, end)) = range.split_once('-') {
-            if let (Ok(start_addr), Ok(end_addr), Ok(off)) = (
+        if let Some((start, end)) = range.split_once('-')
+            && let (Ok(start_addr), Ok(end_addr), Ok(off)) = (
                 u64::from_str_radix(start, 16),
                 u64::from_str_radix(end, 16),
                 u64::from_str_radix(offset, 16),
-            ) {
-                let entry = infos.entry(path.to_string()).or_insert(ExeInfo {
-                    start: start_addr,
-                    end: end_addr,
-                    offset: off,
-                });
-                if start_addr < entry.start {
-                    entry.start = start_addr;
-                    entry.offset = off;
-                }
-                if end_addr > entry.end {
-                    entry.end = end_addr;
-                }
+            )
+        {
+            let entry = infos.entry(path.to_string()).or_insert(ExeInfo {
+                start: start_addr,
+                end: end_addr,
+                offset: off,
+            });
+            if start_addr < entry.start {
+                entry.start = start_addr;
+                entry.offset = off;
+            }
+            if end_addr > entry.end {
+                entry.end = end_addr;
             }
         }
     }
+    infos
+}
+
+pub fn load_loaders(pid: i32, opts: &CaptureOptions) -> Vec<Module> {
     let mut modules = Vec::new();
-    for (path, info) in infos {
-        if let Some(data) = get_module(&path) {
+    for (path, info) in read_exe_infos(pid) {
+        if let Some(data) = get_module(&path, opts) {
             modules.push(Module {
                 loader: data.loader.clone(),
                 info,
@@ -194,10 +370,54 @@ pub fn load_loaders(pid: i32) -> Vec<Module> {
     modules
 }
 
-fn describe_addr(loader: &Rc<Loader>, info: &ExeInfo, addr: u64, is_pic: bool) -> Option<Frame> {
+/// Makes demangled C++ names readable: drops inline ABI namespaces, spells
+/// `std::basic_string<char, ...>` as `std::string` and collapses the
+/// remaining template arguments to `<...>`.
+pub fn shorten_cpp_name(name: &str) -> String {
+    let name = name
+        .replace("std::__cxx11::", "std::")
+        .replace("std::__1::", "std::")
+        .replace(
+            "std::basic_string<char, std::char_traits<char>, std::allocator<char> >",
+            "std::string",
+        );
+    let mut out = String::with_capacity(name.len());
+    let mut depth = 0;
+    for c in name.chars() {
+        match c {
+            '<' if depth == 0 && (out.ends_with("operator") || out.ends_with("operator<")) => {
+                out.push(c)
+            }
+            '<' => {
+                if depth == 0 {
+                    out.push_str("<...");
+                }
+                depth += 1;
+            }
+            '>' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    out.push('>');
+                }
+            }
+            _ if depth > 0 => {}
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Demangles a symbol table name, which carries no language information.
+/// Rust names lose their trailing `::h<hash>` suffix.
+fn demangle_symbol(name: &str) -> String {
+    addr2line::demangle_auto(Cow::from(name), None).into_owned()
+}
+
+fn describe_addr(loader: &Mutex<Loader>, info: &ExeInfo, addr: u64, is_pic: bool) -> Option<Frame> {
     if addr < info.start || addr >= info.end {
         return None;
     }
+    let loader = loader.lock().unwrap();
     let mut probe = addr;
     if is_pic {
         probe = addr.wrapping_sub(info.start).wrapping_add(info.offset);
@@ -211,10 +431,10 @@ fn describe_addr(loader: &Rc<Loader>, info: &ExeInfo, addr: u64, is_pic: bool) -
     if let Ok(mut frames) = loader.find_frames(probe) {
         while let Ok(Some(frame)) = frames.next() {
             found_frames = true;
-            if func.is_none() {
-                if let Some(f) = &frame.function {
-                    func = Some(f.demangle().unwrap_or_else(|_| Cow::from("??")).into());
-                }
+            if func.is_none()
+                && let Some(f) = &frame.function
+            {
+                func = Some(f.demangle().unwrap_or_else(|_| Cow::from("??")).into());
             }
             if let Some(loc) = frame.location {
                 if file.is_none() {
@@ -226,10 +446,8 @@ fn describe_addr(loader: &Rc<Loader>, info: &ExeInfo, addr: u64, is_pic: bool) -
             }
         }
     }
-    if !found_frames {
-        if let Some(sym) = loader.find_symbol(probe) {
-            func = Some(sym.to_string());
-        }
+    if !found_frames && let Some(sym) = loader.find_symbol(probe) {
+        func = Some(demangle_symbol(sym));
     }
     Some(Frame {
         addr: Some(addr as i64),
@@ -239,108 +457,300 @@ fn describe_addr(loader: &Rc<Loader>, info: &ExeInfo, addr: u64, is_pic: bool) -
     })
 }
 
-fn get_stack_trace(pid: Pid, max_frames: usize) -> nix::Result<Vec<u64>> {
-    let regs = ptrace::getregs(pid)?;
-    let mut rbp = regs.rbp as u64;
+fn get_stack_trace(
+    pid: Pid,
+    regs: &user_regs_struct,
+    max_frames: usize,
+    deadline: Option<Instant>,
+) -> (Vec<u64>, Option<String>) {
+    let mut rbp = regs.rbp;
     let mut addrs = Vec::new();
-    addrs.push(regs.rip as u64);
+    addrs.push(regs.rip);
 
-    for _ in 0..max_frames {
+    while addrs.len() < max_frames {
+        if deadline.is_some_and(|d| Instant::now() >= d) {
+            return (addrs, Some("max_pause_ms exceeded".into()));
+        }
         if rbp == 0 || rbp >= 0xfffffffffffffff8 {
-            break;
+            return (addrs, None);
         }
-        let next_rip = ptrace::read(pid, (rbp + 8) as ptrace::AddressType)? as u64;
-        addrs.push(next_rip);
-        let next_rbp = ptrace::read(pid, rbp as ptrace::AddressType)? as u64;
+        // Code built without frame pointers leaves garbage in rbp; keep the
+        // frames collected so far instead of dropping the whole trace.
+        let (Ok(next_rip), Ok(next_rbp)) = (
+            ptrace::read(pid, (rbp + 8) as ptrace::AddressType),
+            ptrace::read(pid, rbp as ptrace::AddressType),
+        ) else {
+            return (addrs, None);
+        };
+        addrs.push(next_rip as u64);
+        let next_rbp = next_rbp as u64;
         if next_rbp == 0 {
-            break;
+            return (addrs, None);
         }
         rbp = next_rbp;
     }
 
-    Ok(addrs)
+    (addrs, Some("max_frames reached".into()))
+}
+
+/// Unwinds a stopped thread with libunwind-ptrace. A failure after the first
+/// frame keeps what was collected and is reported as the truncation reason.
+fn get_libunwind_stack_trace(
+    tid: i32,
+    max_frames: usize,
+    deadline: Option<Instant>,
+) -> Result<(Vec<u64>, Option<String>), remoteprocess::Error> {
+    let unwinder = remoteprocess::Unwinder::new()?;
+    let thread = remoteprocess::Thread::new(tid)?;
+    let mut addrs = Vec::new();
+    for ip in unwinder.cursor(&thread)? {
+        if addrs.len() >= max_frames {
+            return Ok((addrs, Some("max_frames reached".into())));
+        }
+        if deadline.is_some_and(|d| Instant::now() >= d) {
+            return Ok((addrs, Some("max_pause_ms exceeded".into())));
+        }
+        match ip {
+            Ok(ip) => addrs.push(ip),
+            Err(e) if !addrs.is_empty() => return Ok((addrs, Some(format!("libunwind: {}", e)))),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok((addrs, None))
+}
+
+/// Waits for the SIGSTOP sent by PTRACE_ATTACH. A freshly spawned child may
+/// report its post-exec SIGTRAP first; detaching at that point would leave
+/// the SIGSTOP pending and the target stopped forever.
+fn wait_attach_stop(target: Pid) -> nix::Result<()> {
+    loop {
+        match waitpid(target, Some(WaitPidFlag::__WALL))? {
+            WaitStatus::Stopped(_, Signal::SIGSTOP) => return Ok(()),
+            WaitStatus::Stopped(_, Signal::SIGTRAP) => ptrace::cont(target, None)?,
+            WaitStatus::Stopped(_, sig) => ptrace::cont(target, sig)?,
+            WaitStatus::Exited(..) | WaitStatus::Signaled(..) => return Err(Errno::ESRCH),
+            _ => ptrace::cont(target, None)?,
+        }
+    }
+}
+
+/// Registers recorded for the hottest thread.
+fn registers_of(regs: &user_regs_struct) -> Registers {
+    Registers {
+        rip: regs.rip,
+        rsp: regs.rsp,
+        rbp: regs.rbp,
+        rax: regs.rax,
+        rdi: regs.rdi,
+        rsi: regs.rsi,
+        rdx: regs.rdx,
+        rcx: regs.rcx,
+        r8: regs.r8,
+        r9: regs.r9,
+    }
 }
 
-pub fn capture_stack_trace(pid: i32) -> nix::Result<Vec<Frame>> {
+/// Stops the thread only while its registers and frame chain are read;
+/// symbolization happens after detaching so DWARF parsing never extends the
+/// pause.
+pub fn capture_stack_trace(
+    pid: i32,
+    opts: &CaptureOptions,
+    with_registers: bool,
+) -> nix::Result<NativeTrace> {
     let target = Pid::from_raw(pid);
+    let deadline = opts.max_pause.map(|d| Instant::now() + d);
     ptrace::attach(target)?;
-    waitpid(target, None)?;
-
-    let res = (|| {
-        let stack = get_stack_trace(target, 32)?;
-        let modules = load_loaders(pid);
-        let mut frames = Vec::new();
-        for addr in stack {
-            let mut added = false;
-            for m in &modules {
-                if let Some(info) = describe_addr(&m.loader, &m.info, addr, m.is_pic) {
-                    frames.push(info);
-                    added = true;
-                    break;
+    let res = wait_attach_stop(target).and_then(|_| {
+        let regs = ptrace::getregs(target)?;
+        let (stack, truncated) = match opts.backend {
+            UnwindBackend::FramePointer => {
+                get_stack_trace(target, &regs, opts.max_frames, deadline)
+            }
+            UnwindBackend::Libunwind => {
+                match get_libunwind_stack_trace(pid, opts.max_frames, deadline) {
+                    Ok(r) => r,
+                    Err(e) => (vec![regs.rip], Some(format!("libunwind: {}", e))),
                 }
             }
-            if !added {
-                frames.push(Frame {
-                    addr: Some(addr as i64),
-                    func: None,
-                    file: None,
-                    line: None,
-                });
+        };
+        Ok((stack, truncated, regs))
+    });
+    if let Err(e) = ptrace::detach(target, None) {
+        warn!("detach failed: {}", e);
+    }
+    let (stack, truncated, regs) = res?;
+    let registers = with_registers.then(|| registers_of(&regs));
+
+    let modules = load_loaders(pid, opts);
+    let mut frames = Vec::new();
+    for addr in stack {
+        let mut added = false;
+        for m in &modules {
+            if let Some(info) = describe_addr(&m.loader, &m.info, addr, m.is_pic) {
+                frames.push(info);
+                added = true;
+                break;
             }
         }
-        Ok(frames)
-    })();
+        if !added {
+            frames.push(Frame {
+                addr: Some(addr as i64),
+                func: None,
+                file: None,
+                line: None,
+            });
+        }
+    }
+    if opts.shorten_cpp_names {
+        for f in &mut frames {
+            f.func = f.func.as_deref().map(shorten_cpp_name);
+        }
+    }
+    Ok(NativeTrace {
+        frames,
+        truncated,
+        registers,
+    })
+}
 
-    if let Err(e) = ptrace::detach(target, None) {
-        warn!("detach failed: {}", e);
+/// Captures the threads `tids`; `registers_tid` also gets its register set
+/// recorded. Up to `opts.workers` threads are stopped at once, each by its own
+/// tracer thread, so a pass over N threads pauses the process for about
+/// N / workers times `max_pause` rather than N times.
+pub fn capture_c_stack_traces(
+    tids: &[i32],
+    opts: &CaptureOptions,
+    registers_tid: Option<i32>,
+) -> Vec<(i32, nix::Result<NativeTrace>)> {
+    let capture = |tid: i32| capture_stack_trace(tid, opts, registers_tid == Some(tid));
+    let workers = opts.workers.clamp(1, tids.len().max(1));
+    if workers == 1 {
+        return tids.iter().map(|&tid| (tid, capture(tid))).collect();
     }
-    res
+    // ptrace requests must come from the thread that attached, so every
+    // worker attaches, unwinds and detaches its own tids.
+    let next = AtomicUsize::new(0);
+    let mut traces: Vec<(usize, nix::Result<NativeTrace>)> = thread::scope(|s| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let idx = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(&tid) = tids.get(idx) else {
+                            return done;
+                        };
+                        done.push((idx, capture(tid)));
+                    }
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap_or_default())
+            .collect()
+    });
+    traces.sort_by_key(|(idx, _)| *idx);
+    traces.into_iter().map(|(idx, t)| (tids[idx], t)).collect()
 }
 
-pub fn capture_c_stack_traces(pid: i32) -> Vec<(i32, Option<Vec<Frame>>)> {
-    let mut tids: Vec<i32> = match fs::read_dir(format!("/proc/{}/task", pid)) {
-        Ok(d) => d
-            .filter_map(|e| e.ok())
-            .filter_map(|e| e.file_name().into_string().ok())
-            .filter_map(|s| s.parse::<i32>().ok())
-            .collect(),
-        Err(_) => Vec::new(),
-    };
-    tids.sort_unstable();
-    let mut traces = Vec::new();
-    for tid in tids {
-        match capture_stack_trace(tid) {
-            Ok(t) => traces.push((tid, Some(t))),
-            Err(_) => traces.push((tid, None)),
-        }
+/// Python frames of one thread grouped by the native eval-loop call that runs
+/// them, innermost first.
+pub struct PythonTrace {
+    pub calls: Vec<Vec<Frame>>,
+    pub state: PythonThreadState,
+}
+
+impl PythonTrace {
+    pub fn frames(&self) -> Vec<Frame> {
+        self.calls.concat()
     }
-    traces
+}
+
+fn is_python_module(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    name.starts_with("libpython") || name.starts_with("python")
+}
+
+/// Whether `pid` maps a CPython interpreter or libpython, which also covers
+/// launchers like gunicorn or celery and embedded interpreters.
+pub fn has_python_module(pid: i32) -> bool {
+    read_exe_infos(pid).keys().any(|p| is_python_module(p))
 }
 
 pub fn capture_python_stack_traces(
     pid: i32,
-) -> Result<HashMap<u32, Vec<Frame>>, Box<dyn std::error::Error>> {
-    let config = PySpyConfig::default();
+) -> Result<HashMap<u32, PythonTrace>, Box<dyn std::error::Error>> {
+    // Idle threads are kept so the log shows which ones wait for the GIL.
+    let config = PySpyConfig {
+        include_idle: true,
+        ..Default::default()
+    };
     let mut spy = PythonSpy::new(pid as py_spy::Pid, &config)?;
     let traces = spy.get_stack_traces()?;
     let mut result = HashMap::new();
     for t in traces {
         if let Some(tid) = t.os_thread_id {
-            let mut frames = Vec::new();
+            let mut calls = Vec::new();
+            let mut current = Vec::new();
             for f in t.frames {
-                frames.push(Frame {
+                current.push(Frame {
                     addr: None,
                     func: Some(f.name),
                     file: Some(f.filename),
-                    line: Some(f.line as i32),
+                    line: Some(f.line),
                 });
+                if f.is_entry {
+                    calls.push(std::mem::take(&mut current));
+                }
+            }
+            if !current.is_empty() {
+                calls.push(current);
             }
-            result.insert(tid as u32, frames);
+            let state = PythonThreadState {
+                name: t.thread_name,
+                owns_gil: t.owns_gil,
+                active: t.active,
+            };
+            result.insert(tid as u32, PythonTrace { calls, state });
         }
     }
     Ok(result)
 }
 
+fn is_eval_frame(frame: &Frame) -> bool {
+    frame
+        .func
+        .as_deref()
+        .is_some_and(|f| f.contains("PyEval_EvalFrame"))
+}
+
+/// Replaces each native PyEval_EvalFrame* frame with the Python frames it
+/// was executing. Returns `None` when no eval frame was symbolized, since the
+/// result would only repeat the native stack.
+pub fn merge_python_stack(native: &[Frame], python: &PythonTrace) -> Option<Vec<Frame>> {
+    if !native.iter().any(is_eval_frame) {
+        return None;
+    }
+    let mut calls = python.calls.iter();
+    let mut merged = Vec::new();
+    for frame in native {
+        if is_eval_frame(frame)
+            && let Some(call) = calls.next()
+        {
+            merged.extend(call.iter().cloned());
+        } else {
+            merged.push(frame.clone());
+        }
+    }
+    // Frames the native walk did not reach are the outermost ones.
+    for call in calls {
+        merged.extend(call.iter().cloned());
+    }
+    Some(merged)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -348,19 +758,214 @@ mod tests {
     use tempfile::tempdir;
 
     fn clear_cache() {
-        MODULE_CACHE.with(|c| c.borrow_mut().clear());
+        MODULE_CACHE.lock().unwrap().entries.clear();
+    }
+
+    #[test]
+    fn module_cache_evicts_least_recently_used() {
+        let mut cache = ModuleCache::new(2);
+        cache.insert("a", None, LoadedModule::default());
+        cache.insert("b", None, LoadedModule::default());
+        assert!(cache.get("a", None).is_some());
+        cache.insert("c", None, LoadedModule::default());
+        assert!(cache.get("a", None).is_some());
+        assert!(cache.get("b", None).is_none());
+        assert!(cache.get("c", None).is_some());
+        assert_eq!(cache.entries.len(), 2);
+    }
+
+    #[test]
+    fn module_reloaded_once_debug_info_fetched() {
+        let mut cache = ModuleCache::new(2);
+        let loaded = LoadedModule {
+            module: None,
+            fetching: Some("feed".into()),
+        };
+        cache.insert("a", None, loaded);
+        assert!(cache.get("a", None).is_none());
+    }
+
+    #[test]
+    fn zero_pause_budget_truncates() {
+        let mut child = Command::new("sleep").arg("10").spawn().expect("spawn");
+        let opts = CaptureOptions {
+            max_pause: Some(Duration::ZERO),
+            ..Default::default()
+        };
+        let trace = capture_stack_trace(child.id() as i32, &opts, false);
+        let _ = child.kill();
+        let _ = child.wait();
+        let trace = trace.expect("trace");
+        assert_eq!(trace.frames.len(), 1);
+        assert_eq!(trace.truncated.as_deref(), Some("max_pause_ms exceeded"));
+    }
+
+    fn frame(func: &str) -> Frame {
+        Frame {
+            addr: None,
+            func: Some(func.into()),
+            file: None,
+            line: None,
+        }
+    }
+
+    fn funcs(frames: &[Frame]) -> Vec<&str> {
+        frames.iter().filter_map(|f| f.func.as_deref()).collect()
+    }
+
+    #[test]
+    fn merge_python_stack_replaces_eval_frames() {
+        let native = vec![
+            frame("read"),
+            frame("_PyEval_EvalFrameDefault"),
+            frame("PyObject_Call"),
+            frame("_PyEval_EvalFrameDefault"),
+            frame("main"),
+        ];
+        let python = PythonTrace {
+            calls: vec![
+                vec![frame("inner"), frame("middle")],
+                vec![frame("outer")],
+                vec![frame("<module>")],
+            ],
+            state: PythonThreadState::default(),
+        };
+        let merged = merge_python_stack(&native, &python).expect("merged");
+        assert_eq!(
+            funcs(&merged),
+            vec![
+                "read",
+                "inner",
+                "middle",
+                "PyObject_Call",
+                "outer",
+                "main",
+                "<module>"
+            ]
+        );
+        assert!(merge_python_stack(&[frame("main")], &python).is_none());
+    }
+
+    #[test]
+    fn python_module_names() {
+        assert!(is_python_module("/usr/bin/python3.11"));
+        assert!(is_python_module(
+            "/usr/lib/x86_64-linux-gnu/libpython3.11.so.1.0"
+        ));
+        assert!(!is_python_module("/usr/bin/gunicorn"));
+    }
+
+    #[test]
+    fn registers_recorded_on_request() {
+        let mut child = Command::new("sleep").arg("10").spawn().expect("spawn");
+        let trace = capture_stack_trace(child.id() as i32, &CaptureOptions::default(), true);
+        let _ = child.kill();
+        let _ = child.wait();
+        let trace = trace.expect("trace");
+        let regs = trace.registers.expect("registers");
+        assert_eq!(Some(regs.rip as i64), trace.frames[0].addr);
+    }
+
+    /// Spawns `sleep` and waits until it blocks in nanosleep, so unwinding
+    /// does not race with the dynamic loader.
+    fn spawn_sleeping() -> std::process::Child {
+        let child = Command::new("sleep").arg("10").spawn().expect("spawn");
+        let stat = format!("/proc/{}/stat", child.id());
+        while !fs::read_to_string(&stat).unwrap().contains(") S ") {}
+        child
+    }
+
+    #[test]
+    fn parallel_capture_keeps_thread_order() {
+        let mut children: Vec<_> = (0..3).map(|_| spawn_sleeping()).collect();
+        let tids: Vec<i32> = children.iter().map(|c| c.id() as i32).collect();
+        let opts = CaptureOptions {
+            workers: 2,
+            ..Default::default()
+        };
+        let traces = capture_c_stack_traces(&tids, &opts, Some(tids[1]));
+        for c in &mut children {
+            let _ = c.kill();
+            let _ = c.wait();
+        }
+        let order: Vec<i32> = traces.iter().map(|(tid, _)| *tid).collect();
+        assert_eq!(order, tids);
+        for (tid, t) in &traces {
+            let t = t.as_ref().expect("trace");
+            assert_eq!(t.registers.is_some(), *tid == tids[1]);
+        }
+    }
+
+    #[test]
+    fn libunwind_backend_unwinds_without_frame_pointers() {
+        let mut child = spawn_sleeping();
+        let opts = CaptureOptions {
+            backend: UnwindBackend::Libunwind,
+            ..Default::default()
+        };
+        let trace = capture_stack_trace(child.id() as i32, &opts, true);
+        let _ = child.kill();
+        let _ = child.wait();
+        let trace = trace.expect("trace");
+        assert!(trace.frames.len() > 1, "truncated: {:?}", trace.truncated);
+        let rip = trace.registers.expect("registers").rip;
+        assert_eq!(trace.frames[0].addr, Some(rip as i64));
+    }
+
+    #[test]
+    fn frame_limit_truncates() {
+        let mut child = Command::new("sleep").arg("10").spawn().expect("spawn");
+        let opts = CaptureOptions {
+            max_frames: 1,
+            ..Default::default()
+        };
+        let trace = capture_stack_trace(child.id() as i32, &opts, false);
+        let _ = child.kill();
+        let _ = child.wait();
+        let trace = trace.expect("trace");
+        assert_eq!(trace.frames.len(), 1);
+        assert_eq!(trace.truncated.as_deref(), Some("max_frames reached"));
+    }
+
+    #[test]
+    fn demangle_symbol_names() {
+        assert_eq!(
+            demangle_symbol("_ZN4core3fmt5write17h0123456789abcdefE"),
+            "core::fmt::write"
+        );
+        assert_eq!(demangle_symbol("_RNvCs1234_7mycrate4main"), "mycrate::main");
+        assert_eq!(demangle_symbol("_Z3fooi"), "foo(int)");
+        assert_eq!(demangle_symbol("main"), "main");
+    }
+
+    #[test]
+    fn shorten_cpp_names() {
+        assert_eq!(
+            shorten_cpp_name(
+                "foo(std::__cxx11::basic_string<char, std::char_traits<char>, std::allocator<char> > const&)"
+            ),
+            "foo(std::string const&)"
+        );
+        assert_eq!(
+            shorten_cpp_name("std::vector<int, std::allocator<int> >::push_back(int const&)"),
+            "std::vector<...>::push_back(int const&)"
+        );
+        assert_eq!(
+            shorten_cpp_name("operator<<(std::ostream&, Foo const&)"),
+            "operator<<(std::ostream&, Foo const&)"
+        );
     }
 
     #[test]
     fn loader_none_for_nonexistent() {
         clear_cache();
-        assert!(get_module("/no/such/file").is_none());
+        assert!(get_module("/no/such/file", &CaptureOptions::default()).is_none());
     }
 
     #[test]
     fn loader_none_for_non_regular() {
         clear_cache();
-        assert!(get_module("/dev/null").is_none());
+        assert!(get_module("/dev/null", &CaptureOptions::default()).is_none());
     }
 
     #[test]
@@ -369,8 +974,8 @@ mod tests {
         let dir = tempdir().unwrap();
         let file = dir.path().join("plain.txt");
         std::fs::write(&file, b"plain").unwrap();
-        assert!(get_module(file.to_str().unwrap()).is_none());
-        assert!(get_module(file.to_str().unwrap()).is_none());
+        assert!(get_module(file.to_str().unwrap(), &CaptureOptions::default()).is_none());
+        assert!(get_module(file.to_str().unwrap(), &CaptureOptions::default()).is_none());
     }
 
     #[test]
@@ -379,8 +984,8 @@ mod tests {
         let dir = tempdir().unwrap();
         let exe = dir.path().join("tprog");
         std::fs::write(&exe, b"bad").unwrap();
-        assert!(get_module(exe.to_str().unwrap()).is_none());
-        assert!(get_module(exe.to_str().unwrap()).is_none());
+        assert!(get_module(exe.to_str().unwrap(), &CaptureOptions::default()).is_none());
+        assert!(get_module(exe.to_str().unwrap(), &CaptureOptions::default()).is_none());
 
         let src = dir.path().join("t.c");
         std::fs::write(&src, "int main(){return 0;}").unwrap();
@@ -389,6 +994,6 @@ mod tests {
             .status()
             .expect("compile");
         assert!(status.success());
-        assert!(get_module(exe.to_str().unwrap()).is_some());
+        assert!(get_module(exe.to_str().unwrap(), &CaptureOptions::default()).is_some());
     }
 }
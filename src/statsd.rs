@@ -0,0 +1,119 @@
+use log::warn;
+use std::net::UdpSocket;
+
+/// Keeps datagrams below the common Ethernet MTU minus headers.
+const MAX_PACKET: usize = 1432;
+
+pub const DEFAULT_TEMPLATE: &str = "fuzmon.{name}.{pid}.{metric}";
+
+/// Sends per-process gauges to a statsd (or Graphite via statsd) daemon over
+/// UDP. Metric names come from a template with `{name}`, `{pid}` and
+/// `{metric}` placeholders.
+pub struct StatsdSink {
+    socket: UdpSocket,
+    template: String,
+    lines: Vec<String>,
+}
+
+/// Replaces characters that statsd or Graphite treat as separators.
+fn sanitize(part: &str) -> String {
+    part.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn metric_name(template: &str, name: &str, pid: u32, metric: &str) -> String {
+    template
+        .replace("{name}", &sanitize(name))
+        .replace("{pid}", &pid.to_string())
+        .replace("{metric}", metric)
+}
+
+/// Joins `lines` into newline separated packets of at most `MAX_PACKET`
+/// bytes each.
+fn packets(lines: &[String]) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut cur = String::new();
+    for line in lines {
+        if !cur.is_empty() && cur.len() + 1 + line.len() > MAX_PACKET {
+            out.push(std::mem::take(&mut cur));
+        }
+        if !cur.is_empty() {
+            cur.push('\n');
+        }
+        cur.push_str(line);
+    }
+    if !cur.is_empty() {
+        out.push(cur);
+    }
+    out
+}
+
+impl StatsdSink {
+    pub fn connect(addr: &str, template: &str) -> Option<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0")
+            .and_then(|s| s.connect(addr).map(|_| s))
+            .map_err(|e| warn!("failed to set up statsd to {}: {}", addr, e))
+            .ok()?;
+        Some(StatsdSink {
+            socket,
+            template: template.to_string(),
+            lines: Vec::new(),
+        })
+    }
+
+    pub fn record(&mut self, pid: u32, name: &str, cpu_percent: f64, rss_kb: u64) {
+        for (metric, value) in [
+            ("cpu_percent", format!("{:.1}", cpu_percent)),
+            ("rss_kb", rss_kb.to_string()),
+        ] {
+            let key = metric_name(&self.template, name, pid, metric);
+            self.lines.push(format!("{}:{}|g", key, value));
+        }
+    }
+
+    pub fn flush(&mut self) {
+        for packet in packets(&self.lines) {
+            if let Err(e) = self.socket.send(packet.as_bytes()) {
+                warn!("statsd send failed: {}", e);
+                break;
+            }
+        }
+        self.lines.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metric_names_follow_template() {
+        assert_eq!(
+            metric_name(DEFAULT_TEMPLATE, "kworker/0:1", 42, "rss_kb"),
+            "fuzmon.kworker_0_1.42.rss_kb"
+        );
+        assert_eq!(
+            metric_name("procs.{metric}.{name}", "nginx", 1, "cpu_percent"),
+            "procs.cpu_percent.nginx"
+        );
+    }
+
+    #[test]
+    fn packets_stay_below_limit() {
+        let lines: Vec<String> = (0..100).map(|i| format!("m{:040}:{}|g", i, i)).collect();
+        let packets = packets(&lines);
+        assert!(packets.len() > 1);
+        assert!(packets.iter().all(|p| p.len() <= MAX_PACKET));
+        assert_eq!(
+            packets.iter().map(|p| p.lines().count()).sum::<usize>(),
+            100
+        );
+    }
+}
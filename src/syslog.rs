@@ -0,0 +1,57 @@
+use log::warn;
+use std::os::unix::net::UnixDatagram;
+
+const FACILITY_DAEMON: u8 = 3;
+
+#[derive(Clone, Copy)]
+pub enum Severity {
+    Warning = 4,
+    Notice = 5,
+    Info = 6,
+}
+
+/// Sends notable events to the local syslog socket, which journald also
+/// listens on. Regular samples stay in the log files only.
+pub struct SyslogSink {
+    socket: UnixDatagram,
+    path: String,
+}
+
+fn format_message(severity: Severity, pid: u32, msg: &str) -> String {
+    let pri = FACILITY_DAEMON * 8 + severity as u8;
+    format!("<{}>fuzmon[{}]: {}", pri, pid, msg)
+}
+
+impl SyslogSink {
+    pub fn connect(path: &str) -> Option<Self> {
+        let socket = UnixDatagram::unbound()
+            .and_then(|s| s.connect(path).map(|_| s))
+            .map_err(|e| warn!("failed to connect to syslog {}: {}", path, e))
+            .ok()?;
+        Some(SyslogSink {
+            socket,
+            path: path.to_string(),
+        })
+    }
+
+    pub fn event(&self, severity: Severity, msg: &str) {
+        let line = format_message(severity, std::process::id(), msg);
+        if let Err(e) = self.socket.send(line.as_bytes()) {
+            warn!("syslog send to {} failed: {}", self.path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_carry_daemon_priority() {
+        assert_eq!(
+            format_message(Severity::Warning, 12, "stall"),
+            "<28>fuzmon[12]: stall"
+        );
+        assert_eq!(format_message(Severity::Info, 1, "x"), "<30>fuzmon[1]: x");
+    }
+}
@@ -0,0 +1,199 @@
+use log::{info, warn};
+use std::env;
+use std::fs;
+use std::io;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::config::InstallServiceArgs;
+
+/// Tells systemd about the state of the monitor through `$NOTIFY_SOCKET`,
+/// as `sd_notify` does.
+pub struct Notifier {
+    socket: UnixDatagram,
+    addr: SocketAddr,
+    /// How often to send `WATCHDOG=1`, half the `WatchdogSec` of the unit.
+    watchdog: Option<Duration>,
+    last_watchdog: Option<Instant>,
+}
+
+impl Notifier {
+    /// Connects to the socket systemd passed, if fuzmon runs as a
+    /// `Type=notify` service.
+    pub fn from_env() -> Option<Notifier> {
+        let path = env::var("NOTIFY_SOCKET").ok()?;
+        let watchdog = watchdog_interval(
+            env::var("WATCHDOG_USEC").ok().as_deref(),
+            env::var("WATCHDOG_PID").ok().as_deref(),
+        );
+        Notifier::new(&path, watchdog)
+            .map_err(|e| warn!("failed to connect to NOTIFY_SOCKET {}: {}", path, e))
+            .ok()
+    }
+
+    fn new(path: &str, watchdog: Option<Duration>) -> io::Result<Notifier> {
+        let addr = match path.strip_prefix('@') {
+            Some(name) => SocketAddr::from_abstract_name(name)?,
+            None => SocketAddr::from_pathname(path)?,
+        };
+        Ok(Notifier {
+            socket: UnixDatagram::unbound()?,
+            addr,
+            watchdog,
+            last_watchdog: None,
+        })
+    }
+
+    fn notify(&self, state: &str) {
+        if let Err(e) = self.socket.send_to_addr(state.as_bytes(), &self.addr) {
+            warn!("failed to notify systemd: {}", e);
+        }
+    }
+
+    pub fn ready(&self) {
+        info!("notifying systemd that fuzmon is ready");
+        self.notify("READY=1");
+    }
+
+    pub fn stopping(&self) {
+        self.notify("STOPPING=1");
+    }
+
+    /// Sends `WATCHDOG=1` when it is due at `now`.
+    pub fn watchdog(&mut self, now: Instant) {
+        let Some(interval) = self.watchdog else {
+            return;
+        };
+        if self
+            .last_watchdog
+            .is_some_and(|last| now.duration_since(last) < interval)
+        {
+            return;
+        }
+        self.last_watchdog = Some(now);
+        self.notify("WATCHDOG=1");
+    }
+}
+
+/// Half of `WATCHDOG_USEC`, when the watchdog is meant for this process.
+fn watchdog_interval(usec: Option<&str>, pid: Option<&str>) -> Option<Duration> {
+    if let Some(pid) = pid
+        && pid.parse::<u32>().ok() != Some(std::process::id())
+    {
+        return None;
+    }
+    let usec: u64 = usec?.parse().ok().filter(|&u| u > 0)?;
+    Some(Duration::from_micros(usec) / 2)
+}
+
+/// Unit file running `exe` as a notify service with the config at `config`.
+fn unit_file(exe: &Path, config: Option<&Path>) -> String {
+    let mut command = format!("{} run", exe.display());
+    if let Some(config) = config {
+        command += &format!(" -c {}", config.display());
+    }
+    format!(
+        "[Unit]\n\
+         Description=fuzmon process monitor\n\
+         After=network.target\n\
+         \n\
+         [Service]\n\
+         Type=notify\n\
+         ExecStart={}\n\
+         Restart=on-failure\n\
+         WatchdogSec=30\n\
+         \n\
+         [Install]\n\
+         WantedBy=multi-user.target\n",
+        command
+    )
+}
+
+/// Writes a unit file that runs this fuzmon binary with `args.config`.
+pub fn install_service(args: &InstallServiceArgs) {
+    let exe = match env::current_exe() {
+        Ok(exe) => exe,
+        Err(e) => {
+            eprintln!("failed to find the fuzmon binary: {}", e);
+            return;
+        }
+    };
+    // systemd starts the service from /, so the config needs a full path.
+    let config = match args.config.as_deref().map(fs::canonicalize).transpose() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("failed to find {}: {}", args.config.as_deref().unwrap(), e);
+            return;
+        }
+    };
+    let unit = unit_file(&exe, config.as_deref());
+    match fs::write(&args.output, unit) {
+        Ok(()) => println!(
+            "Wrote {}, enable it with: systemctl daemon-reload && systemctl enable --now {}",
+            args.output,
+            Path::new(&args.output)
+                .file_name()
+                .map_or_else(|| args.output.clone(), |n| n.to_string_lossy().into()),
+        ),
+        Err(e) => eprintln!("failed to write {}: {}", args.output, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn notifier_sends_ready_and_watchdog() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("notify");
+        let systemd = UnixDatagram::bind(&path).unwrap();
+        let mut notifier =
+            Notifier::new(path.to_str().unwrap(), Some(Duration::from_secs(10))).unwrap();
+        let recv = || {
+            let mut buf = [0u8; 64];
+            let n = systemd.recv(&mut buf).unwrap();
+            String::from_utf8_lossy(&buf[..n]).into_owned()
+        };
+        notifier.ready();
+        assert_eq!(recv(), "READY=1");
+        let start = Instant::now();
+        notifier.watchdog(start);
+        // Not due yet, so the next message is the second watchdog.
+        notifier.watchdog(start + Duration::from_secs(5));
+        notifier.watchdog(start + Duration::from_secs(10));
+        assert_eq!(recv(), "WATCHDOG=1");
+        assert_eq!(recv(), "WATCHDOG=1");
+        notifier.stopping();
+        assert_eq!(recv(), "STOPPING=1");
+    }
+
+    #[test]
+    fn watchdog_only_for_this_process() {
+        let me = std::process::id().to_string();
+        assert_eq!(
+            watchdog_interval(Some("30000000"), None),
+            Some(Duration::from_secs(15))
+        );
+        assert_eq!(
+            watchdog_interval(Some("30000000"), Some(&me)),
+            Some(Duration::from_secs(15))
+        );
+        assert_eq!(watchdog_interval(Some("30000000"), Some("1")), None);
+        assert_eq!(watchdog_interval(Some("0"), None), None);
+        assert_eq!(watchdog_interval(None, None), None);
+    }
+
+    #[test]
+    fn unit_runs_binary_with_config() {
+        let unit = unit_file(
+            Path::new("/usr/bin/fuzmon"),
+            Some(Path::new("/etc/fuzmon.toml")),
+        );
+        assert!(unit.contains("ExecStart=/usr/bin/fuzmon run -c /etc/fuzmon.toml\n"));
+        assert!(unit.contains("Type=notify\n"));
+    }
+}
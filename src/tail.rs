@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::config::TailArgs;
+use crate::log::{LogEntry, LogFollower, frame_name, log_files, thread_stack};
+use crate::watch::LogWatcher;
+
+/// Innermost frames printed of each stack.
+const FRAMES: usize = 5;
+
+/// One line per entry, then its stacks and file events indented below.
+fn format_entry(e: &LogEntry) -> String {
+    let mut out = format!(
+        "{} {:>7} {}  CPU {:.1}%  RSS {} KB",
+        e.timestamp, e.pid, e.process_name, e.cpu_time_percent, e.memory.rss_kb
+    );
+    for t in &e.threads {
+        let Some(stack) = thread_stack(t) else {
+            continue;
+        };
+        let frames: Vec<String> = stack.iter().take(FRAMES).map(frame_name).collect();
+        let more = if stack.len() > FRAMES { " <- ..." } else { "" };
+        out += &format!("\n    {}: {}{}", t.tid, frames.join(" <- "), more);
+    }
+    for ev in e.fd_events.iter().flatten() {
+        out += &format!("\n    fd {} {} {}", ev.fd, ev.event, ev.path);
+    }
+    out
+}
+
+/// Passes the new entries of the logs under `path` to `f`, following logs
+/// that appeared since the last call from their start.
+fn read_new(
+    path: &Path,
+    followers: &mut HashMap<PathBuf, LogFollower>,
+    f: &mut dyn FnMut(LogEntry),
+) {
+    for file in log_files(path) {
+        let follower = followers
+            .entry(file.clone())
+            .or_insert_with(|| LogFollower::new(&file));
+        if let Err(e) = follower.read_new(&mut *f) {
+            eprintln!("failed to read {}: {}", file.display(), e);
+        }
+    }
+}
+
+pub fn tail(args: &TailArgs) {
+    let path = Path::new(&args.path);
+    // Entries are shown as soon as they are written.
+    let mut watcher = match LogWatcher::new(path, None, Duration::ZERO) {
+        Ok(w) => w,
+        Err(e) => {
+            eprintln!("failed to watch {}: {}", path.display(), e);
+            return;
+        }
+    };
+    let range = args.filter.time_range();
+    // Like `tail -f`, only what is written from now on is shown.
+    let mut followers = HashMap::new();
+    for file in log_files(path) {
+        let mut follower = LogFollower::new(&file);
+        if let Err(e) = follower.skip_existing() {
+            eprintln!("failed to read {}: {}", file.display(), e);
+        }
+        followers.insert(file, follower);
+    }
+    eprintln!("Following {}", path.display());
+    let mut out = io::stdout().lock();
+    // Set when the reader, such as `head`, went away.
+    let mut closed = false;
+    while !closed {
+        if let Err(e) = watcher.wait() {
+            eprintln!("failed to watch {}: {}", path.display(), e);
+            return;
+        }
+        read_new(path, &mut followers, &mut |e| {
+            if closed || !args.filter.matches(&e) || !range.contains(&e) {
+                return;
+            }
+            closed = writeln!(out, "{}", format_entry(&e)).is_err();
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entry_printed_with_stacks() {
+        let e: LogEntry = serde_json::from_value(serde_json::json!({
+            "timestamp": "2025-06-14T10:00:00Z",
+            "pid": 42,
+            "process_name": "server",
+            "cpu_time_percent": 12.5,
+            "memory": {"rss_kb": 2048, "vsz_kb": 0, "swap_kb": 0},
+            "fd_events": [{"fd": 3, "event": "open", "path": "/tmp/x"}],
+            "threads": [
+                {"tid": 43, "stacktrace": [
+                    {"func": "a"}, {"func": "b"}, {"func": "c"},
+                    {"func": "d"}, {"func": "e"}, {"func": "main"},
+                ]},
+                {"tid": 44},
+            ],
+        }))
+        .unwrap();
+        assert_eq!(
+            format_entry(&e),
+            "2025-06-14T10:00:00Z      42 server  CPU 12.5%  RSS 2048 KB\n    \
+             43: a <- b <- c <- d <- e <- ...\n    fd 3 open /tmp/x"
+        );
+    }
+}
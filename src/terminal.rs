@@ -0,0 +1,47 @@
+use nix::libc;
+use nix::sys::termios::{SetArg, Termios, cfmakeraw, tcgetattr, tcsetattr};
+use std::io;
+use std::os::fd::{AsFd, RawFd};
+
+/// Puts the terminal on stdin into raw mode until dropped.
+pub struct RawMode {
+    saved: Termios,
+}
+
+impl RawMode {
+    pub fn enter() -> nix::Result<RawMode> {
+        let saved = tcgetattr(io::stdin().as_fd())?;
+        let mut raw = saved.clone();
+        cfmakeraw(&mut raw);
+        tcsetattr(io::stdin().as_fd(), SetArg::TCSANOW, &raw)?;
+        Ok(RawMode { saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = tcsetattr(io::stdin().as_fd(), SetArg::TCSANOW, &self.saved);
+    }
+}
+
+nix::ioctl_read_bad!(get_window_size, libc::TIOCGWINSZ, libc::winsize);
+nix::ioctl_write_ptr_bad!(put_window_size, libc::TIOCSWINSZ, libc::winsize);
+
+/// Size of the terminal on `fd`, if it is one.
+pub fn window_size(fd: RawFd) -> Option<libc::winsize> {
+    let mut size = libc::winsize {
+        ws_row: 0,
+        ws_col: 0,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    // SAFETY: TIOCGWINSZ only writes a winsize to the pointer.
+    unsafe { get_window_size(fd, &mut size) }.ok().map(|_| size)
+}
+
+/// Resizes the terminal on `fd`, which sends SIGWINCH to its foreground
+/// process group.
+pub fn set_window_size(fd: RawFd, size: &libc::winsize) -> nix::Result<()> {
+    // SAFETY: TIOCSWINSZ only reads a winsize from the pointer.
+    unsafe { put_window_size(fd, size) }.map(|_| ())
+}
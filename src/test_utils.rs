@@ -1,6 +1,10 @@
 use crate::utils::current_date_string;
 use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::Path;
 use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{Receiver, channel};
 use std::{thread, time::Duration};
 use tempfile::{NamedTempFile, TempDir};
 use zstd::stream;
@@ -25,17 +29,33 @@ fn build_fuzmon_command(
     cmd
 }
 
-pub fn wait_until_file_appears(logdir: &TempDir, pid: u32) {
-    let date = current_date_string();
-    let dir = logdir.path().join(&date);
-    let plain = dir.join(format!("{pid}.jsonl"));
-    let zst = dir.join(format!("{pid}.jsonl.zst"));
-    for _ in 0..80 {
-        if plain.exists() || zst.exists() {
-            break;
+/// Waits for a log of `pid` to appear in `dir`, the directory of a day.
+pub fn wait_until_file_appears(dir: &Path, pid: u32) {
+    let prefix = format!("{pid}.");
+    // Any format, including the combined log shared by all pids.
+    let appeared = || {
+        fs::read_dir(dir).is_ok_and(|mut entries| {
+            entries.any(|e| {
+                e.is_ok_and(|e| {
+                    let name = e.file_name().to_string_lossy().into_owned();
+                    name.starts_with(&prefix) || name.starts_with("combined.")
+                })
+            })
+        })
+    };
+    wait_for(appeared);
+}
+
+/// Polls `cond` until it holds, for up to three seconds. Returns whether
+/// it held.
+pub fn wait_for(mut cond: impl FnMut() -> bool) -> bool {
+    for _ in 0..300 {
+        if cond() {
+            return true;
         }
         thread::sleep(Duration::from_millis(10));
     }
+    cond()
 }
 
 pub fn kill_with_sigint_and_wait(child: &mut Child) {
@@ -59,14 +79,21 @@ pub fn create_config(threshold: f64) -> NamedTempFile {
 }
 
 pub fn run_fuzmon(bin: &str, pid: u32, log_dir: &TempDir) -> String {
-    let cfg_file = create_config(0.0);
+    run_fuzmon_with_config(bin, pid, log_dir, &create_config(0.0))
+}
 
-    let mut mon = build_fuzmon_command(bin, pid, log_dir, &cfg_file)
+pub fn run_fuzmon_with_config(
+    bin: &str,
+    pid: u32,
+    log_dir: &TempDir,
+    cfg_file: &NamedTempFile,
+) -> String {
+    let mut mon = build_fuzmon_command(bin, pid, log_dir, cfg_file)
         .stdout(Stdio::null())
         .spawn()
         .expect("run fuzmon");
 
-    wait_until_file_appears(log_dir, pid);
+    wait_until_file_appears(&log_dir.path().join(current_date_string()), pid);
     kill_with_sigint_and_wait(&mut mon);
 
     collect_log_content(log_dir)
@@ -89,15 +116,16 @@ pub fn collect_log_content(log_dir: &TempDir) -> String {
 }
 
 fn append_file(path: &std::path::Path, log_content: &mut String) {
-    if let Some(ext) = path.extension() {
-        if ext == "zst" {
-            if let Ok(data) = fs::read(path) {
-                if let Ok(decoded) = stream::decode_all(&*data) {
-                    log_content.push_str(&String::from_utf8_lossy(&decoded));
-                    return;
-                }
-            }
-        }
+    if path.extension().is_some_and(|e| e == "idx") {
+        return;
+    }
+    if let Some(ext) = path.extension()
+        && ext == "zst"
+        && let Ok(data) = fs::read(path)
+        && let Ok(decoded) = stream::decode_all(&*data)
+    {
+        log_content.push_str(&String::from_utf8_lossy(&decoded));
+        return;
     }
     if let Ok(s) = fs::read_to_string(path) {
         log_content.push_str(&s);
@@ -127,3 +155,57 @@ pub fn run_fuzmon_and_check(bin: &str, pid: u32, log_dir: &TempDir, expected: &[
         );
     }
 }
+
+/// Starts a minimal HTTP server on localhost that answers every request with
+/// 200 and forwards `(path, body)` of each request to the returned receiver.
+pub fn spawn_http_server() -> (String, Receiver<(String, Vec<u8>)>) {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+    let addr = format!("http://{}", listener.local_addr().expect("addr"));
+    let (tx, rx) = channel();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            let tx = tx.clone();
+            thread::spawn(move || {
+                let mut reader = BufReader::new(stream.try_clone().expect("clone"));
+                let mut writer = stream;
+                loop {
+                    let mut request_line = String::new();
+                    if reader.read_line(&mut request_line).unwrap_or(0) == 0 {
+                        return;
+                    }
+                    let path = request_line
+                        .split_whitespace()
+                        .nth(1)
+                        .unwrap_or("")
+                        .to_string();
+                    let mut len = 0;
+                    loop {
+                        let mut header = String::new();
+                        if reader.read_line(&mut header).unwrap_or(0) == 0 {
+                            return;
+                        }
+                        let header = header.trim_end();
+                        if header.is_empty() {
+                            break;
+                        }
+                        if let Some((name, value)) = header.split_once(':')
+                            && name.eq_ignore_ascii_case("content-length")
+                        {
+                            len = value.trim().parse().unwrap_or(0);
+                        }
+                    }
+                    let mut body = vec![0u8; len];
+                    if reader.read_exact(&mut body).is_err() {
+                        return;
+                    }
+                    let _ = writer.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n");
+                    let _ = tx.send((path, body));
+                }
+            });
+        }
+    });
+    (addr, rx)
+}
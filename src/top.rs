@@ -0,0 +1,431 @@
+use nix::poll::{PollFd, PollFlags, poll};
+use nix::unistd::read;
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Write};
+use std::os::fd::AsFd;
+use std::time::{Duration, Instant};
+
+use crate::config::{Config, TopArgs, load_config, uid_from_name};
+use crate::log::frame_name;
+use crate::procinfo::{ProcState, get_proc_usage, process_name, read_tids, rss_kb};
+use crate::run::{ProcessFilter, capture_options, collect_pids, target_name_pattern};
+use crate::stacktrace::{CaptureOptions, capture_c_stack_traces};
+use crate::terminal::{RawMode, window_size};
+
+/// Samples of CPU usage kept for the sparkline of each process.
+const HISTORY: usize = 20;
+
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Draws `values` as a bar per sample, scaled to 100% or the highest value.
+fn sparkline(values: &VecDeque<f32>) -> String {
+    let max = values.iter().copied().fold(100.0f32, f32::max);
+    values
+        .iter()
+        .map(|v| {
+            let level = (v / max * (SPARKS.len() - 1) as f32).round() as usize;
+            SPARKS[level.min(SPARKS.len() - 1)]
+        })
+        .collect()
+}
+
+/// What the process table is sorted by, switched with keys.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum SortBy {
+    #[default]
+    Cpu,
+    Rss,
+    Pid,
+}
+
+impl SortBy {
+    fn name(self) -> &'static str {
+        match self {
+            SortBy::Cpu => "CPU",
+            SortBy::Rss => "RSS",
+            SortBy::Pid => "PID",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Key {
+    Up,
+    Down,
+    Char(u8),
+}
+
+/// Splits what was read from the terminal into keys.
+fn parse_keys(bytes: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match &bytes[i..] {
+            [0x1b, b'[', b'A', ..] => {
+                keys.push(Key::Up);
+                i += 3;
+            }
+            [0x1b, b'[', b'B', ..] => {
+                keys.push(Key::Down);
+                i += 3;
+            }
+            [b, ..] => {
+                keys.push(Key::Char(*b));
+                i += 1;
+            }
+            [] => break,
+        }
+    }
+    keys
+}
+
+struct Row {
+    pid: u32,
+    name: String,
+    cpu: f32,
+    rss: u64,
+    history: VecDeque<f32>,
+}
+
+/// The processes shown and what the user chose to look at.
+#[derive(Default)]
+struct TopView {
+    rows: HashMap<u32, Row>,
+    states: HashMap<u32, ProcState>,
+    sort: SortBy,
+    selected: usize,
+    /// Pid and lines of the last stack captured on demand.
+    stack: Option<(u32, Vec<String>)>,
+}
+
+impl TopView {
+    /// Samples `pids` with the collectors of `run`, forgetting processes
+    /// that exited.
+    fn sample(&mut self, pids: &[u32], filter: &ProcessFilter) {
+        self.rows.retain(|pid, _| pids.contains(pid));
+        self.states.retain(|pid, _| pids.contains(pid));
+        for &pid in pids {
+            let Some(name) = process_name(pid) else {
+                continue;
+            };
+            if filter.skips(pid, &name) {
+                continue;
+            }
+            let state = self.states.entry(pid).or_default();
+            // The first sample of a process has no CPU usage yet.
+            let (cpu, rss) =
+                get_proc_usage(pid, state).unwrap_or_else(|| (0.0, rss_kb(pid).unwrap_or(0)));
+            self.update(pid, name, cpu, rss);
+        }
+    }
+
+    fn update(&mut self, pid: u32, name: String, cpu: f32, rss: u64) {
+        let row = self.rows.entry(pid).or_insert_with(|| Row {
+            pid,
+            name: String::new(),
+            cpu: 0.0,
+            rss: 0,
+            history: VecDeque::new(),
+        });
+        row.name = name;
+        row.cpu = cpu;
+        row.rss = rss;
+        if row.history.len() == HISTORY {
+            row.history.pop_front();
+        }
+        row.history.push_back(cpu);
+    }
+
+    fn sorted(&self) -> Vec<&Row> {
+        let mut rows: Vec<&Row> = self.rows.values().collect();
+        match self.sort {
+            SortBy::Cpu => rows.sort_by(|a, b| b.cpu.total_cmp(&a.cpu).then(a.pid.cmp(&b.pid))),
+            SortBy::Rss => rows.sort_by(|a, b| b.rss.cmp(&a.rss).then(a.pid.cmp(&b.pid))),
+            SortBy::Pid => rows.sort_by_key(|r| r.pid),
+        }
+        rows
+    }
+
+    /// Handles a key; returns false when the user quits.
+    fn key(&mut self, key: Key, capture: &CaptureOptions) -> bool {
+        match key {
+            Key::Char(b'q') | Key::Char(3) => return false,
+            Key::Char(b'c') => self.sort = SortBy::Cpu,
+            Key::Char(b'm') => self.sort = SortBy::Rss,
+            Key::Char(b'p') => self.sort = SortBy::Pid,
+            Key::Up | Key::Char(b'k') => self.selected = self.selected.saturating_sub(1),
+            Key::Down | Key::Char(b'j') => self.selected += 1,
+            Key::Char(b's') => {
+                if let Some(pid) = self.sorted().get(self.selected).map(|r| r.pid) {
+                    self.stack = Some((pid, stack_lines(pid, capture)));
+                }
+            }
+            Key::Char(0x1b) => self.stack = None,
+            _ => {}
+        }
+        self.selected = self.selected.min(self.rows.len().saturating_sub(1));
+        true
+    }
+
+    /// Draws the table and any captured stack into `width` by `height`
+    /// characters.
+    fn render(&self, width: usize, height: usize) -> String {
+        let mut lines = vec![
+            format!(
+                "fuzmon top: {} processes by {}  [c]pu [m]em [p]id  j/k select  [s]tack  [q]uit",
+                self.rows.len(),
+                self.sort.name()
+            ),
+            format!(
+                "{:>7} {:>6} {:>10}  {:<w$}  NAME",
+                "PID",
+                "CPU%",
+                "RSS KB",
+                "CPU HISTORY",
+                w = HISTORY
+            ),
+        ];
+        let stack = self.stack.as_ref().map_or(Vec::new(), |(pid, frames)| {
+            let mut stack = vec![format!("Stack of {} (Esc to close):", pid)];
+            stack.extend(frames.iter().cloned());
+            stack
+        });
+        // The stack takes at most half of the screen.
+        let stack_height = stack.len().min(height / 2);
+        let table_height = height.saturating_sub(lines.len() + stack_height);
+        let rows = self.sorted();
+        // Keep the selected row on screen.
+        let skip = (self.selected + 1).saturating_sub(table_height);
+        let mut selected = None;
+        for (i, row) in rows.iter().enumerate().skip(skip).take(table_height) {
+            let line = format!(
+                "{:>7} {:>6.1} {:>10}  {:<w$}  {}",
+                row.pid,
+                row.cpu,
+                row.rss,
+                sparkline(&row.history),
+                row.name,
+                w = HISTORY
+            );
+            if i == self.selected {
+                selected = Some(lines.len());
+            }
+            lines.push(line);
+        }
+        lines.extend(stack.into_iter().take(stack_height));
+        let lines: Vec<String> = lines
+            .iter()
+            .enumerate()
+            .map(|(i, l)| {
+                let l: String = l.chars().take(width).collect();
+                if Some(i) == selected {
+                    format!("\x1b[7m{}\x1b[0m", l)
+                } else {
+                    l
+                }
+            })
+            .collect();
+        lines.join("\r\n")
+    }
+}
+
+/// Captures the native stacks of all threads of `pid`, one line per frame.
+fn stack_lines(pid: u32, capture: &CaptureOptions) -> Vec<String> {
+    let mut lines = Vec::new();
+    for (tid, trace) in capture_c_stack_traces(&read_tids(pid), capture, None) {
+        lines.push(format!("Thread {}", tid));
+        match trace {
+            Ok(t) => lines.extend(t.frames.iter().map(|f| format!("  {}", frame_name(f)))),
+            Err(e) => lines.push(format!("  capture failed: {}", e)),
+        }
+    }
+    lines
+}
+
+/// Puts the terminal into raw mode on the alternate screen until dropped.
+struct RawTerminal {
+    _raw: RawMode,
+}
+
+impl RawTerminal {
+    fn enter() -> nix::Result<RawTerminal> {
+        let raw = RawMode::enter()?;
+        print!("\x1b[?1049h\x1b[?25l");
+        Ok(RawTerminal { _raw: raw })
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+    }
+}
+
+/// Columns and rows of the terminal, 80 by 24 if unknown.
+fn terminal_size() -> (usize, usize) {
+    match window_size(nix::libc::STDOUT_FILENO) {
+        Some(size) if size.ws_col > 0 && size.ws_row > 0 => {
+            (size.ws_col as usize, size.ws_row as usize)
+        }
+        _ => (80, 24),
+    }
+}
+
+fn draw(view: &TopView) {
+    let (width, height) = terminal_size();
+    let mut out = io::stdout().lock();
+    let _ = write!(out, "\x1b[H\x1b[2J{}", view.render(width, height));
+    let _ = out.flush();
+}
+
+/// Waits up to `timeout` for keys; returns false when the user quits.
+fn handle_keys(view: &mut TopView, timeout: Duration, capture: &CaptureOptions) -> bool {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return true;
+        }
+        let stdin = io::stdin();
+        let mut fds = [PollFd::new(stdin.as_fd(), PollFlags::POLLIN)];
+        let ms = left.as_millis().min(u16::MAX as u128) as u16;
+        if !matches!(poll(&mut fds, ms), Ok(n) if n > 0) {
+            continue;
+        }
+        let mut buf = [0u8; 64];
+        let n = match read(nix::libc::STDIN_FILENO, &mut buf) {
+            Ok(0) | Err(_) => return false,
+            Ok(n) => n,
+        };
+        for key in parse_keys(&buf[..n]) {
+            if !view.key(key, capture) {
+                return false;
+            }
+        }
+        draw(view);
+    }
+}
+
+/// Runs `fuzmon top` until quit and returns the exit status.
+pub fn top(args: &TopArgs) -> i32 {
+    let mut config = match args.config.as_deref() {
+        Some(path) => load_config(path),
+        None => Config::default(),
+    };
+    if let Some(u) = &args.target_user {
+        config.filter.target_user = Some(u.clone());
+    }
+    let target_pid = args.pid.map(|p| p as u32);
+    let target_uid = config.filter.target_user.as_deref().and_then(uid_from_name);
+    let target_name = match target_name_pattern(&config.filter) {
+        Ok(re) => re,
+        Err(e) => {
+            eprintln!("invalid target_process_name: {}", e);
+            return 1;
+        }
+    };
+    let filter = ProcessFilter::new(&config.filter);
+    let capture = capture_options(&config.stacktrace);
+    let interval = Duration::from_secs(config.monitor.interval_sec.filter(|s| *s > 0).unwrap_or(1));
+
+    let _terminal = match RawTerminal::enter() {
+        Ok(t) => t,
+        Err(e) => {
+            eprintln!("fuzmon top needs a terminal: {}", e);
+            return 1;
+        }
+    };
+    let mut view = TopView::default();
+    loop {
+        view.sample(
+            &collect_pids(
+                target_pid,
+                target_uid,
+                target_name.as_ref(),
+                config.filter.include_self.unwrap_or(false),
+            ),
+            &filter,
+        );
+        draw(&view);
+        if !handle_keys(&mut view, interval, &capture) {
+            return 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> CaptureOptions {
+        capture_options(&Default::default())
+    }
+
+    #[test]
+    fn sparkline_scales_to_100_percent() {
+        let values: VecDeque<f32> = [0.0, 50.0, 100.0].into_iter().collect();
+        assert_eq!(sparkline(&values), "▁▅█");
+        let values: VecDeque<f32> = [100.0, 200.0].into_iter().collect();
+        assert_eq!(sparkline(&values), "▅█");
+    }
+
+    #[test]
+    fn arrow_keys_parsed() {
+        assert_eq!(
+            parse_keys(b"\x1b[Aj\x1b[Bq"),
+            vec![Key::Up, Key::Char(b'j'), Key::Down, Key::Char(b'q')]
+        );
+    }
+
+    #[test]
+    fn keys_sort_and_select_rows() {
+        let mut view = TopView::default();
+        view.update(1, "idle".into(), 1.0, 9000);
+        view.update(2, "busy".into(), 80.0, 100);
+        let pids = |v: &TopView| v.sorted().iter().map(|r| r.pid).collect::<Vec<_>>();
+        assert_eq!(pids(&view), [2, 1]);
+        assert!(view.key(Key::Char(b'm'), &options()));
+        assert_eq!(pids(&view), [1, 2]);
+        view.key(Key::Down, &options());
+        view.key(Key::Down, &options());
+        assert_eq!(view.selected, 1);
+        assert!(!view.key(Key::Char(b'q'), &options()));
+    }
+
+    #[test]
+    fn render_marks_selection_and_fits_screen() {
+        let mut view = TopView::default();
+        for pid in 1..=10 {
+            view.update(pid, format!("worker-{}", pid), pid as f32, 1000);
+        }
+        view.selected = 9;
+        let screen = view.render(80, 5);
+        let lines: Vec<&str> = screen.split("\r\n").collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[1].contains("PID"));
+        // The lowest CPU is last and selected, so it is scrolled into view.
+        assert!(lines[2].contains("worker-3"), "{}", screen);
+        assert!(lines[4].starts_with("\x1b[7m      1"), "{}", screen);
+        assert!(lines[4].ends_with("worker-1\x1b[0m"), "{}", screen);
+        let narrow = view.render(30, 5);
+        assert!(narrow.split("\r\n").all(|l| {
+            l.trim_start_matches("\x1b[7m")
+                .trim_end_matches("\x1b[0m")
+                .chars()
+                .count()
+                <= 30
+        }));
+    }
+
+    #[test]
+    fn stack_of_own_process_captured() {
+        let mut view = TopView::default();
+        view.update(std::process::id(), "test".into(), 0.0, 0);
+        view.key(Key::Char(b's'), &options());
+        let (pid, lines) = view.stack.as_ref().unwrap();
+        assert_eq!(*pid, std::process::id());
+        assert!(lines.iter().any(|l| l.starts_with("Thread ")));
+        assert!(view.render(80, 24).contains("Stack of"));
+    }
+}
@@ -1,6 +1,55 @@
-use chrono::Utc;
+use chrono::{Local, NaiveDate, NaiveDateTime, Utc};
+use std::path::Path;
+
+/// Time zone the date directories are named in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Timezone {
+    #[default]
+    Utc,
+    Local,
+}
+
+impl Timezone {
+    /// Parses `output.timezone`: `utc`, `local` or a name from the system
+    /// zoneinfo such as `Asia/Tokyo`, and applies it by setting `TZ`, so
+    /// other local times of the process, like report labels, follow it.
+    /// Must be called before any other thread is started.
+    pub fn apply(name: &str) -> Option<Timezone> {
+        let zoneinfo = std::env::var("TZDIR").unwrap_or_else(|_| "/usr/share/zoneinfo".into());
+        let tz = match name {
+            "local" => return Some(Timezone::Local),
+            "utc" | "UTC" => "UTC",
+            name if !name.contains("..") && Path::new(&zoneinfo).join(name).is_file() => name,
+            _ => return None,
+        };
+        // SAFETY: callers run this at startup while the process is single
+        // threaded, so nothing reads the environment concurrently.
+        unsafe { std::env::set_var("TZ", tz) };
+        Some(if tz == "UTC" {
+            Timezone::Utc
+        } else {
+            Timezone::Local
+        })
+    }
+
+    pub fn now(self) -> NaiveDateTime {
+        match self {
+            Timezone::Utc => Utc::now().naive_utc(),
+            Timezone::Local => Local::now().naive_local(),
+        }
+    }
+
+    pub fn today(self) -> NaiveDate {
+        self.now().date()
+    }
+
+    /// Returns the current date as a YYYYMMDD string.
+    pub fn date_string(self) -> String {
+        self.today().format("%Y%m%d").to_string()
+    }
+}
 
 /// Returns current date as YYYYMMDD string in UTC.
 pub fn current_date_string() -> String {
-    Utc::now().format("%Y%m%d").to_string()
+    Timezone::Utc.date_string()
 }
@@ -0,0 +1,188 @@
+use log::{info, warn};
+use nix::poll::{PollFd, PollFlags, PollTimeout, poll};
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify, InotifyEvent, WatchDescriptor};
+use std::collections::HashMap;
+use std::fs;
+use std::os::fd::AsFd;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Changes that make a report stale: logs appended to, moved in or
+/// removed, and new date directories.
+fn watch_flags() -> AddWatchFlags {
+    AddWatchFlags::IN_MODIFY
+        | AddWatchFlags::IN_CLOSE_WRITE
+        | AddWatchFlags::IN_CREATE
+        | AddWatchFlags::IN_DELETE
+        | AddWatchFlags::IN_MOVED_TO
+        | AddWatchFlags::IN_MOVED_FROM
+}
+
+/// A single watched log, which is watched again when it is rotated.
+struct WatchedFile {
+    path: PathBuf,
+    /// Watches its directory for a new file of the same name.
+    parent: WatchDescriptor,
+}
+
+/// Watches a log, or a directory with the directories below it, for
+/// changes. Directories created later are watched as they appear.
+pub struct LogWatcher {
+    inotify: Inotify,
+    dirs: HashMap<WatchDescriptor, PathBuf>,
+    file: Option<WatchedFile>,
+    /// Left out, such as the report directory inside the watched one.
+    ignore: Option<PathBuf>,
+    /// Least time between two changes being reported, so a log written
+    /// all the time does not keep the report being regenerated.
+    min_interval: Duration,
+    last: Instant,
+}
+
+impl LogWatcher {
+    pub fn new(path: &Path, ignore: Option<&Path>, min_interval: Duration) -> nix::Result<Self> {
+        let mut watcher = LogWatcher {
+            inotify: Inotify::init(InitFlags::IN_CLOEXEC)?,
+            dirs: HashMap::new(),
+            file: None,
+            ignore: ignore.and_then(|p| fs::canonicalize(p).ok()),
+            min_interval,
+            last: Instant::now(),
+        };
+        if path.is_dir() {
+            watcher.add_tree(path);
+        } else {
+            watcher.inotify.add_watch(path, watch_flags())?;
+            let dir = match path.parent() {
+                Some(p) if !p.as_os_str().is_empty() => p,
+                _ => Path::new("."),
+            };
+            let parent = watcher
+                .inotify
+                .add_watch(dir, AddWatchFlags::IN_CREATE | AddWatchFlags::IN_MOVED_TO)?;
+            watcher.file = Some(WatchedFile {
+                path: path.to_path_buf(),
+                parent,
+            });
+        }
+        Ok(watcher)
+    }
+
+    fn add_tree(&mut self, dir: &Path) {
+        if self.ignore.is_some() && fs::canonicalize(dir).ok() == self.ignore {
+            return;
+        }
+        match self.inotify.add_watch(dir, watch_flags()) {
+            Ok(wd) => {
+                self.dirs.insert(wd, dir.to_path_buf());
+            }
+            Err(e) => warn!("failed to watch {}: {}", dir.display(), e),
+        }
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                self.add_tree(&entry.path());
+            }
+        }
+    }
+
+    /// Watches new directories and the file that replaced a rotated log.
+    /// Returns whether `event` changed what is watched.
+    fn handle(&mut self, event: InotifyEvent) -> bool {
+        let created = event
+            .mask
+            .intersects(AddWatchFlags::IN_CREATE | AddWatchFlags::IN_MOVED_TO);
+        if let Some(file) = &self.file
+            && event.wd == file.parent
+        {
+            // Other files in the directory of the log do not matter.
+            if !created || event.name.as_deref() != file.path.file_name() {
+                return false;
+            }
+            info!(
+                "{} was replaced, watching the new file",
+                file.path.display()
+            );
+            if let Err(e) = self.inotify.add_watch(&file.path, watch_flags()) {
+                warn!("failed to watch {}: {}", file.path.display(), e);
+            }
+            return true;
+        }
+        if created
+            && event.mask.contains(AddWatchFlags::IN_ISDIR)
+            && let (Some(parent), Some(name)) = (self.dirs.get(&event.wd), &event.name)
+        {
+            let dir = parent.join(name);
+            self.add_tree(&dir);
+        }
+        true
+    }
+
+    /// Blocks until something changed, and at least the minimum interval
+    /// passed since the last change was reported. All changes queued by
+    /// then are taken at once, so a burst of writes counts as one.
+    pub fn wait(&mut self) -> nix::Result<()> {
+        let mut changed = false;
+        while !changed {
+            for event in self.inotify.read_events()? {
+                changed |= self.handle(event);
+            }
+        }
+        loop {
+            let left = self.min_interval.saturating_sub(self.last.elapsed());
+            if left.is_zero() {
+                break;
+            }
+            let timeout = PollTimeout::try_from(left).unwrap_or(PollTimeout::MAX);
+            let mut fds = [PollFd::new(self.inotify.as_fd(), PollFlags::POLLIN)];
+            if poll(&mut fds, timeout)? > 0 {
+                for event in self.inotify.read_events()? {
+                    self.handle(event);
+                }
+            }
+        }
+        self.last = Instant::now();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn new_directories_are_watched() {
+        let dir = tempdir().unwrap();
+        let mut watcher = LogWatcher::new(dir.path(), None, Duration::ZERO).unwrap();
+        let day = dir.path().join("20250614");
+        fs::create_dir(&day).unwrap();
+        watcher.wait().unwrap();
+        fs::write(day.join("1.jsonl"), "{}\n").unwrap();
+        watcher.wait().unwrap();
+        assert!(watcher.dirs.values().any(|d| *d == day));
+    }
+
+    #[test]
+    fn rotated_file_is_watched_again() {
+        let dir = tempdir().unwrap();
+        let log = dir.path().join("combined.jsonl");
+        fs::write(&log, "{}\n").unwrap();
+        let mut watcher = LogWatcher::new(&log, None, Duration::ZERO).unwrap();
+        fs::rename(&log, dir.path().join("combined.jsonl.1")).unwrap();
+        fs::write(&log, "{}\n").unwrap();
+        watcher.wait().unwrap();
+
+        // Writes to the new log are seen too.
+        let (done, waited) = std::sync::mpsc::channel();
+        let waiter = std::thread::spawn(move || {
+            watcher.wait().unwrap();
+            done.send(()).unwrap();
+        });
+        fs::write(&log, "{}\n{}\n").unwrap();
+        waited.recv_timeout(Duration::from_secs(10)).unwrap();
+        waiter.join().unwrap();
+    }
+}
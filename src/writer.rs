@@ -0,0 +1,558 @@
+use log::{info, warn};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use crate::log::{
+    LogEntry, LogFormat, append_index, dedup_stacks, delta_encode, encode_record, is_stdout,
+    log_path, open_log,
+};
+use crate::procinfo::{ProcState, boot_id, boot_time, hostname, start_time};
+
+/// Seconds between two marks of a log's time index.
+const INDEX_INTERVAL_SECS: i64 = 60;
+
+/// Log files kept open at most; the least recently written is closed first.
+const MAX_OPEN_FILES: usize = 256;
+
+struct Mark {
+    id: u64,
+    secs: i64,
+}
+
+/// When buffered entries reach the disk.
+#[derive(Clone, Copy, Default)]
+pub struct FlushPolicy {
+    /// Seconds entries may stay buffered; 0 flushes every entry.
+    pub interval_secs: u64,
+    /// Also fsync the file on every flush.
+    pub fsync: bool,
+}
+
+/// What a writer wrote since it was created, for the summary of a run.
+#[derive(Default)]
+pub struct WriteStats {
+    pub entries: u64,
+    pub bytes: u64,
+    /// Processes with entries, not counting fuzmon's own overhead.
+    pub pids: HashSet<u32>,
+    /// Threads whose native stack was captured or failed to be.
+    pub stack_captures: u64,
+    pub stack_failures: u64,
+}
+
+struct OpenLog {
+    file: BufWriter<File>,
+    len: u64,
+    dirty: bool,
+    flushed_secs: i64,
+    written_secs: i64,
+    /// Marks of the time index whose records are still buffered, as their
+    /// time and offset. They are indexed once flushed, so a reader never
+    /// seeks past the end of the file.
+    unindexed: Vec<(i64, u64)>,
+}
+
+/// Writes entries to the log files and keeps their time index. Stacks and
+/// delta encoding only refer back to the last mark of a file, so reading
+/// from a mark never needs an entry written before it. Files stay open
+/// while they are written to and are flushed according to the policy.
+pub struct LogWriter {
+    format: LogFormat,
+    flush: FlushPolicy,
+    files: HashMap<String, OpenLog>,
+    marks: HashMap<String, Mark>,
+    next_mark: u64,
+    hostname: Option<String>,
+    boot_id: Option<String>,
+    boot_time: Option<i64>,
+    stdout_closed: bool,
+    /// Recorded in every entry from now on, as the last ones are written.
+    stop_reason: Option<String>,
+    stats: WriteStats,
+}
+
+impl LogWriter {
+    pub fn new(format: LogFormat, flush: FlushPolicy) -> Self {
+        LogWriter {
+            format,
+            flush,
+            files: HashMap::new(),
+            marks: HashMap::new(),
+            next_mark: 0,
+            hostname: hostname(),
+            boot_id: boot_id(),
+            boot_time: boot_time(),
+            stdout_closed: false,
+            stop_reason: None,
+            stats: WriteStats::default(),
+        }
+    }
+
+    /// Marks the entries written from now on as the last ones, stopped for
+    /// `reason`.
+    pub fn stop(&mut self, reason: &str) {
+        self.stop_reason = Some(reason.to_string());
+    }
+
+    pub fn stats(&self) -> &WriteStats {
+        &self.stats
+    }
+
+    pub fn write(&mut self, dir: &str, entry: &mut LogEntry, state: &mut ProcState) {
+        self.count(entry);
+        let stdout = is_stdout(dir);
+        let path = if stdout {
+            dir.to_string()
+        } else {
+            log_path(dir, entry.pid, &self.format)
+        };
+        // The first entry of a process in each file records the host and
+        // when the process started.
+        if state.stack_log.as_ref().is_none_or(|(p, _)| *p != path) {
+            entry.hostname = self.hostname.clone();
+            entry.boot_id = self.boot_id.clone();
+            entry.start_time = self.boot_time.and_then(|b| start_time(entry.pid, b));
+        }
+        entry.stopped = self.stop_reason.clone();
+        if stdout {
+            state.stack_log = Some((path, 0));
+            self.write_stdout(entry);
+            return;
+        }
+        let secs = chrono::DateTime::parse_from_rfc3339(&entry.timestamp)
+            .map(|t| t.timestamp())
+            .ok();
+        let due = secs.filter(|s| {
+            self.marks
+                .get(&path)
+                .is_none_or(|m| *s >= m.secs + INDEX_INTERVAL_SECS)
+        });
+        if let Some(secs) = due {
+            self.next_mark += 1;
+            let mark = Mark {
+                id: self.next_mark,
+                secs,
+            };
+            self.marks.insert(path.clone(), mark);
+        }
+        let mark = self.marks.get(&path).map_or(0, |m| m.id);
+        if state.stack_log.as_ref() != Some(&(path.clone(), mark)) {
+            state.written_stacks.clear();
+            state.interner.clear();
+            state.delta_base = None;
+            state.stack_log = Some((path.clone(), mark));
+        }
+        let data = if self.format.delta {
+            let full = serde_json::to_value(&*entry).unwrap_or_default();
+            dedup_stacks(entry, &mut state.written_stacks);
+            let mut value = serde_json::to_value(&*entry).unwrap_or_default();
+            let tolerance = self.format.delta_memory_tolerance_kb;
+            delta_encode(&mut value, &full, &mut state.delta_base, tolerance);
+            encode_record(&value, &self.format, &mut state.interner)
+        } else {
+            dedup_stacks(entry, &mut state.written_stacks);
+            encode_record(entry, &self.format, &mut state.interner)
+        };
+        let now = secs.unwrap_or_else(|| chrono::Utc::now().timestamp());
+        if let Some(d) = data {
+            self.append(&path, &d, now, due);
+        }
+    }
+
+    /// Counts the process and stack captures of `entry`, before its stacks
+    /// are replaced by references.
+    fn count(&mut self, entry: &LogEntry) {
+        if entry.self_overhead.is_none() {
+            self.stats.pids.insert(entry.pid);
+        }
+        for t in &entry.threads {
+            if t.stacktrace.is_some() || t.capture_error.is_some() {
+                self.stats.stack_captures += 1;
+            }
+            if t.capture_error.is_some() {
+                self.stats.stack_failures += 1;
+            }
+        }
+    }
+
+    /// Streams `entry` as a self-contained JSON line, without stack
+    /// references or delta encoding, so consumers need no earlier lines.
+    fn write_stdout(&mut self, entry: &LogEntry) {
+        if self.stdout_closed {
+            return;
+        }
+        let Ok(mut line) = serde_json::to_vec(entry) else {
+            return;
+        };
+        line.push(b'\n');
+        let mut out = io::stdout().lock();
+        if let Err(e) = out.write_all(&line).and_then(|_| out.flush()) {
+            if e.kind() == io::ErrorKind::BrokenPipe {
+                info!("stdout closed, stopping");
+            } else {
+                warn!("write to stdout failed: {}", e);
+            }
+            self.stdout_closed = true;
+        } else {
+            self.stats.entries += 1;
+            self.stats.bytes += line.len() as u64;
+        }
+    }
+
+    /// Whether streaming to stdout failed, e.g. because the reader exited.
+    pub fn stdout_closed(&self) -> bool {
+        self.stdout_closed
+    }
+
+    /// Appends a record to the open file at `path`, indexed at `mark` if
+    /// given.
+    fn append(&mut self, path: &str, data: &[u8], now: i64, mark: Option<i64>) {
+        if !self.files.contains_key(path) {
+            if self.files.len() >= MAX_OPEN_FILES
+                && let Some(oldest) = self
+                    .files
+                    .iter()
+                    .min_by_key(|(_, f)| f.written_secs)
+                    .map(|(p, _)| p.clone())
+            {
+                self.close(&oldest);
+            }
+            let Some((file, len)) = open_log(path) else {
+                return;
+            };
+            let log = OpenLog {
+                file: BufWriter::new(file),
+                len,
+                dirty: false,
+                flushed_secs: now,
+                written_secs: now,
+                unindexed: Vec::new(),
+            };
+            self.files.insert(path.to_string(), log);
+        }
+        let Some(log) = self.files.get_mut(path) else {
+            return;
+        };
+        if let Err(e) = log.file.write_all(data) {
+            warn!("write {} failed: {}", path, e);
+            self.files.remove(path);
+            return;
+        }
+        // Readers start at the top anyway, so a log gets its index only
+        // once there is a later place to seek to, and short logs get none.
+        if let Some(secs) = mark.filter(|_| log.len > 0) {
+            log.unindexed.push((secs, log.len));
+        }
+        log.len += data.len() as u64;
+        self.stats.entries += 1;
+        self.stats.bytes += data.len() as u64;
+        log.dirty = true;
+        log.written_secs = now;
+        if now >= log.flushed_secs + self.flush.interval_secs as i64 {
+            flush_log(path, log, self.flush.fsync, now);
+        }
+    }
+
+    fn close(&mut self, path: &str) {
+        if let Some(mut log) = self.files.remove(path) {
+            let now = log.written_secs;
+            flush_log(path, &mut log, self.flush.fsync, now);
+        }
+    }
+
+    /// Flushes files whose buffered entries are due and closes the ones not
+    /// written to for a while. Marks expire alike; writing to a file again
+    /// just starts with a new mark.
+    pub fn expire(&mut self, now_secs: i64) {
+        self.marks
+            .retain(|_, m| now_secs < m.secs + INDEX_INTERVAL_SECS);
+        let interval = self.flush.interval_secs as i64;
+        for (path, log) in &mut self.files {
+            if log.dirty && now_secs >= log.flushed_secs + interval {
+                flush_log(path, log, self.flush.fsync, now_secs);
+            }
+        }
+        let idle: Vec<String> = self
+            .files
+            .iter()
+            .filter(|(_, f)| now_secs >= f.written_secs + INDEX_INTERVAL_SECS)
+            .map(|(p, _)| p.clone())
+            .collect();
+        for path in idle {
+            self.close(&path);
+        }
+    }
+}
+
+impl Drop for LogWriter {
+    fn drop(&mut self) {
+        let paths: Vec<String> = self.files.keys().cloned().collect();
+        for path in paths {
+            self.close(&path);
+        }
+    }
+}
+
+fn flush_log(path: &str, log: &mut OpenLog, fsync: bool, now: i64) {
+    log.dirty = false;
+    log.flushed_secs = now;
+    let marks = std::mem::take(&mut log.unindexed);
+    if let Err(e) = log.file.flush() {
+        warn!("write {} failed: {}", path, e);
+        return;
+    }
+    if fsync && let Err(e) = log.file.get_ref().sync_data() {
+        warn!("fsync {} failed: {}", path, e);
+    }
+    for (secs, offset) in marks {
+        append_index(path, secs, offset);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log::{Frame, LOG_VERSION, TimeRange, read_log_checked, read_log_range};
+    use serde_json::json;
+    use std::fs;
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    fn entry(time: &str) -> LogEntry {
+        entry_with_rss(time, 0)
+    }
+
+    fn entry_with_rss(time: &str, rss: u64) -> LogEntry {
+        serde_json::from_value(json!({
+            "version": LOG_VERSION,
+            "timestamp": format!("2025-01-01T00:{}Z", time),
+            "pid": 7,
+            "process_name": "t",
+            "cpu_time_percent": 0.0,
+            "memory": {"rss_kb": rss, "vsz_kb": 0, "swap_kb": 0},
+            "threads": [{"tid": 7, "stacktrace": [{"func": "main"}]}],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn marks_restart_stack_dedup() {
+        let dir = tempdir().unwrap();
+        let dir_s = dir.path().to_str().unwrap();
+        let mut writer = LogWriter::new(LogFormat::default(), FlushPolicy::default());
+        let mut state = ProcState::default();
+        for time in ["00:00", "00:30", "01:00"] {
+            writer.write(dir_s, &mut entry(time), &mut state);
+        }
+        let path = log_path(dir_s, 7, &LogFormat::default());
+        let log = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert!(!lines[1].contains("\"stacktrace\""), "{}", log);
+        assert!(lines[2].contains("\"stacktrace\""), "{}", log);
+
+        let index = fs::read_to_string(format!("{}.idx", path)).unwrap();
+        let second = lines[0].len() + lines[1].len() + 2;
+        assert_eq!(index, format!("1735689660 {}\n", second));
+
+        let range = TimeRange {
+            from: Some(1735689660),
+            to: None,
+        };
+        let entries = read_log_range(Path::new(&path), &range).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert!(entries[0].threads[0].stacktrace.is_some());
+    }
+
+    #[test]
+    fn stats_count_what_was_written() {
+        let dir = tempdir().unwrap();
+        let dir_s = dir.path().to_str().unwrap();
+        let mut writer = LogWriter::new(LogFormat::default(), FlushPolicy::default());
+        let mut state = ProcState::default();
+        let mut failed = entry("00:30");
+        failed.threads[0].stacktrace = None;
+        failed.threads[0].capture_error = Some("EPERM".into());
+        for mut e in [entry("00:00"), failed] {
+            writer.write(dir_s, &mut e, &mut state);
+        }
+        let stats = writer.stats();
+        assert_eq!(stats.entries, 2);
+        assert_eq!(stats.pids.len(), 1);
+        assert_eq!((stats.stack_captures, stats.stack_failures), (2, 1));
+        let bytes = stats.bytes;
+        // Flushes the file.
+        drop(writer);
+        let path = log_path(dir_s, 7, &LogFormat::default());
+        assert_eq!(bytes, fs::metadata(&path).unwrap().len());
+    }
+
+    #[test]
+    fn delta_entries_are_reconstructed() {
+        let dir = tempdir().unwrap();
+        let dir_s = dir.path().to_str().unwrap();
+        let format = LogFormat {
+            delta: true,
+            delta_memory_tolerance_kb: 64,
+            ..Default::default()
+        };
+        let mut writer = LogWriter::new(format.clone(), FlushPolicy::default());
+        let mut state = ProcState::default();
+        for (time, rss) in [("00:00", 1000), ("00:10", 1050), ("00:20", 2000)] {
+            writer.write(dir_s, &mut entry_with_rss(time, rss), &mut state);
+        }
+        let path = log_path(dir_s, 7, &format);
+        let log = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert!(lines[0].contains("\"process_name\""), "{}", log);
+        assert!(!lines[1].contains("\"process_name\""), "{}", log);
+        assert!(!lines[1].contains("\"memory\""), "{}", log);
+        assert!(!lines[1].contains("\"threads\""), "{}", log);
+        assert!(lines[2].contains("\"rss_kb\":2000"), "{}", log);
+
+        let entries = read_log_range(Path::new(&path), &TimeRange::default()).unwrap();
+        assert_eq!(entries.len(), 3);
+        let rss: Vec<u64> = entries.iter().map(|e| e.memory.rss_kb).collect();
+        assert_eq!(rss, vec![1000, 1000, 2000]);
+        assert!(entries.iter().all(|e| e.process_name == "t"));
+        assert!(entries.iter().all(|e| e.threads[0].stacktrace.is_some()));
+    }
+
+    #[test]
+    fn damaged_frame_is_skipped() {
+        let dir = tempdir().unwrap();
+        let dir_s = dir.path().to_str().unwrap();
+        let format = LogFormat {
+            compress: true,
+            zstd_level: 3,
+            ..Default::default()
+        };
+        let mut writer = LogWriter::new(format.clone(), FlushPolicy::default());
+        let mut state = ProcState::default();
+        let path = log_path(dir_s, 7, &format);
+        let mut second = 0;
+        for time in ["00:00", "00:10", "00:20"] {
+            writer.write(dir_s, &mut entry(time), &mut state);
+            if second == 0 {
+                second = fs::metadata(&path).unwrap().len() as usize;
+            }
+        }
+        let mut data = fs::read(&path).unwrap();
+        data[second] ^= 0xff;
+        fs::write(&path, data).unwrap();
+
+        let (entries, corrupt) = read_log_checked(Path::new(&path), &TimeRange::default()).unwrap();
+        assert_eq!(corrupt, 1);
+        let times: Vec<&str> = entries.iter().map(|e| e.timestamp.as_str()).collect();
+        assert_eq!(times, ["2025-01-01T00:00:00Z", "2025-01-01T00:00:20Z"]);
+        assert!(entries[1].threads[0].stacktrace.is_some());
+    }
+
+    #[test]
+    fn compact_strings_after_a_damaged_record_are_not_mixed_up() {
+        for compress in [false, true] {
+            let dir = tempdir().unwrap();
+            let dir_s = dir.path().to_str().unwrap();
+            let format = LogFormat {
+                compact: true,
+                compress,
+                zstd_level: 3,
+                ..Default::default()
+            };
+            let mut writer = LogWriter::new(format.clone(), FlushPolicy::default());
+            let mut state = ProcState::default();
+            let path = log_path(dir_s, 7, &format);
+            let mut ends = Vec::new();
+            for (time, funcs) in [
+                ("00:00", ["a", "main"]),
+                ("00:10", ["b", "main"]),
+                ("00:20", ["c", "b"]),
+            ] {
+                let mut e = entry(time);
+                let frames = funcs.map(|f| Frame {
+                    addr: None,
+                    func: Some(f.into()),
+                    file: None,
+                    line: None,
+                });
+                e.threads[0].stacktrace = Some(frames.to_vec());
+                writer.write(dir_s, &mut e, &mut state);
+                writer.close(&path);
+                ends.push(fs::metadata(&path).unwrap().len() as usize);
+            }
+            let mut data = fs::read(&path).unwrap();
+            // The first byte of the second frame, or the checksum of the
+            // second record.
+            let damaged = if compress { ends[0] } else { ends[1] - 1 };
+            data[damaged] ^= 0xff;
+            fs::write(&path, data).unwrap();
+
+            let (entries, corrupt) =
+                read_log_checked(Path::new(&path), &TimeRange::default()).unwrap();
+            assert_eq!(corrupt, 1);
+            assert_eq!(entries.len(), 2);
+            let funcs: Vec<&str> = entries[1].threads[0]
+                .stacktrace
+                .as_ref()
+                .unwrap()
+                .iter()
+                .map(|f| f.func.as_deref().unwrap())
+                .collect();
+            // "b" was introduced by the lost record.
+            assert_eq!(funcs, ["c", "?"]);
+        }
+    }
+
+    #[test]
+    fn entries_are_buffered_until_the_flush_interval() {
+        let dir = tempdir().unwrap();
+        let dir_s = dir.path().to_str().unwrap();
+        let flush = FlushPolicy {
+            interval_secs: 60,
+            fsync: true,
+        };
+        let mut writer = LogWriter::new(LogFormat::default(), flush);
+        let mut state = ProcState::default();
+        let path = log_path(dir_s, 7, &LogFormat::default());
+        let lines = |path: &str| fs::read_to_string(path).unwrap().lines().count();
+        writer.write(dir_s, &mut entry("00:00"), &mut state);
+        writer.write(dir_s, &mut entry("00:30"), &mut state);
+        assert_eq!(lines(&path), 0);
+        writer.write(dir_s, &mut entry("01:00"), &mut state);
+        assert_eq!(lines(&path), 3);
+        writer.write(dir_s, &mut entry("01:10"), &mut state);
+        writer.expire(1735689600 + 90);
+        assert_eq!(lines(&path), 3);
+        writer.expire(1735689600 + 120);
+        assert_eq!(lines(&path), 4);
+        writer.write(dir_s, &mut entry("02:10"), &mut state);
+        drop(writer);
+        assert_eq!(lines(&path), 5);
+    }
+
+    #[test]
+    fn marks_are_indexed_once_flushed() {
+        let dir = tempdir().unwrap();
+        let dir_s = dir.path().to_str().unwrap();
+        let flush = FlushPolicy {
+            interval_secs: 120,
+            fsync: false,
+        };
+        let mut writer = LogWriter::new(LogFormat::default(), flush);
+        let mut state = ProcState::default();
+        let path = log_path(dir_s, 7, &LogFormat::default());
+        let index = format!("{}.idx", path);
+        writer.write(dir_s, &mut entry("00:00"), &mut state);
+        writer.write(dir_s, &mut entry("01:00"), &mut state);
+        assert!(!Path::new(&index).exists());
+        writer.write(dir_s, &mut entry("02:00"), &mut state);
+        let marks: Vec<u64> = fs::read_to_string(&index)
+            .unwrap()
+            .lines()
+            .map(|l| l.split(' ').nth(1).unwrap().parse().unwrap())
+            .collect();
+        let len = fs::metadata(&path).unwrap().len();
+        assert_eq!(marks.len(), 2);
+        assert!(marks.iter().all(|&o| o < len), "{:?} {}", marks, len);
+    }
+}
@@ -0,0 +1,91 @@
+use fuzmon::test_utils::{run_fuzmon_with_config, spawn_http_server};
+use serde_json::Value;
+use std::fs;
+use std::process::Command;
+use std::time::Duration;
+use tempfile::{NamedTempFile, tempdir};
+
+#[test]
+fn alert_runs_command_posts_webhook_and_marks_entry() {
+    let (addr, requests) = spawn_http_server();
+    let dir = tempdir().expect("dir");
+    // The command blocks on the fifo until it is read below.
+    let fifo = dir.path().join("alert");
+    assert!(
+        Command::new("mkfifo")
+            .arg(&fifo)
+            .status()
+            .expect("mkfifo")
+            .success()
+    );
+    let mut child = Command::new("sleep").arg("30").spawn().expect("spawn");
+    let logdir = tempdir().expect("logdir");
+    let cfg = NamedTempFile::new().expect("cfg");
+    fs::write(
+        cfg.path(),
+        format!(
+            "[[alert.rule]]\nname = \"fds\"\nfd_count = 0\nmarker = true\n\
+             command = 'echo \"$FUZMON_ALERT $FUZMON_PID\" > {}'\n\
+             webhook = \"{addr}/alert\"\n",
+            fifo.display()
+        ),
+    )
+    .expect("write cfg");
+    let log = run_fuzmon_with_config(env!("CARGO_BIN_EXE_fuzmon"), child.id(), &logdir, &cfg);
+    let _ = child.kill();
+    let _ = child.wait();
+
+    assert_eq!(
+        fs::read_to_string(&fifo).unwrap(),
+        format!("fds {}\n", child.id())
+    );
+    let (path, body) = requests
+        .recv_timeout(Duration::from_secs(5))
+        .expect("alert posted");
+    assert_eq!(path, "/alert");
+    let alert: Value = serde_json::from_slice(&body).expect("json");
+    assert_eq!(alert["alert"], "fds");
+    assert_eq!(alert["pid"], child.id());
+    let entries: Vec<Value> = log
+        .lines()
+        .map(|l| serde_json::from_str(l).expect("json"))
+        .collect();
+    // Only the entry where the rule started matching is marked.
+    assert_eq!(entries[0]["alerts"], serde_json::json!(["fds"]));
+    assert!(entries[1..].iter().all(|e| e.get("alerts").is_none()));
+}
+
+/// Monitors a `sleep` for a second with a rule killing it and returns
+/// whether it was killed.
+fn killed_by_rule(allow_actions: bool) -> bool {
+    use std::os::unix::process::ExitStatusExt;
+
+    let mut child = Command::new("sleep").arg("30").spawn().expect("spawn");
+    let logdir = tempdir().expect("logdir");
+    let cfg = NamedTempFile::new().expect("cfg");
+    fs::write(
+        cfg.path(),
+        "[[alert.rule]]\nname = \"runaway\"\nfd_count = 0\nsignal = \"KILL\"\n",
+    )
+    .expect("write cfg");
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_fuzmon"));
+    cmd.args(["run", "--duration", "1", "-p", &child.id().to_string()])
+        .arg("-c")
+        .arg(cfg.path())
+        .arg("-o")
+        .arg(logdir.path());
+    if allow_actions {
+        cmd.arg("--allow-actions");
+    }
+    assert!(cmd.output().expect("run fuzmon").status.success());
+    let status = child.try_wait().expect("try_wait");
+    let _ = child.kill();
+    let _ = child.wait();
+    status.is_some_and(|s| s.signal() == Some(9))
+}
+
+#[test]
+fn enforcement_actions_need_allow_actions() {
+    assert!(killed_by_rule(true));
+    assert!(!killed_by_rule(false));
+}
@@ -27,3 +27,1057 @@ fn help_subcommand_shows_usage() {
     assert!(stdout.contains("fuzmon"));
     assert!(stdout.contains("run"));
 }
+
+#[test]
+fn prune_removes_old_date_directories() {
+    let dir = tempdir().expect("tempdir");
+    let old = dir.path().join("20000101");
+    let today = dir.path().join(fuzmon::utils::current_date_string());
+    let other = dir.path().join("notes");
+    for d in [&old, &today, &other] {
+        fs::create_dir_all(d).unwrap();
+        fs::write(d.join("1.jsonl"), "{}\n").unwrap();
+    }
+    let prune = |extra: &[&str]| {
+        let mut args = vec![
+            "prune",
+            "-o",
+            dir.path().to_str().unwrap(),
+            "--keep-days",
+            "30",
+        ];
+        args.extend_from_slice(extra);
+        Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+            .args(&args)
+            .output()
+            .expect("run fuzmon prune")
+    };
+
+    let out = prune(&["--dry-run"]);
+    assert!(String::from_utf8_lossy(&out.stdout).contains("would remove"));
+    assert!(old.exists());
+
+    let out = prune(&[]);
+    assert!(out.status.success());
+    assert!(!old.exists());
+    assert!(today.exists());
+    assert!(other.exists());
+}
+
+#[test]
+fn convert_directory_round_trips() {
+    let dir = tempdir().expect("tempdir");
+    let day = dir.path().join("in").join("20250101");
+    fs::create_dir_all(&day).unwrap();
+    let line = |ts: &str| {
+        format!(
+            "{{\"timestamp\":\"{ts}\",\"pid\":7,\"process_name\":\"t\",\"cpu_time_percent\":0,\"memory\":{{\"rss_kb\":5,\"vsz_kb\":0,\"swap_kb\":0}}}}\n"
+        )
+    };
+    fs::write(day.join("7.jsonl"), line("1") + &line("2")).unwrap();
+
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+            .args(args)
+            .output()
+            .expect("run fuzmon")
+    };
+    let input = dir.path().join("in");
+    let packed = dir.path().join("packed");
+    let out = run(&[
+        "convert",
+        input.to_str().unwrap(),
+        packed.to_str().unwrap(),
+        "--format",
+        "msgpacks",
+        "--compress",
+    ]);
+    assert!(out.status.success());
+    let converted = packed.join("20250101").join("7.msgpacks.zst");
+    assert!(converted.exists());
+
+    let back = dir.path().join("back.jsonl");
+    run(&[
+        "convert",
+        converted.to_str().unwrap(),
+        back.to_str().unwrap(),
+    ]);
+    let text = fs::read_to_string(&back).unwrap();
+    assert_eq!(text.lines().count(), 2, "{}", text);
+    assert!(text.contains("\"timestamp\":\"2\""), "{}", text);
+}
+
+#[test]
+fn convert_writes_parquet_tables() {
+    let dir = tempdir().expect("tempdir");
+    let input = dir.path().join("7.jsonl");
+    fs::write(
+        &input,
+        "{\"timestamp\":\"2025-01-01T00:00:01Z\",\"pid\":7,\"process_name\":\"t\",\"cpu_time_percent\":50,\"memory\":{\"rss_kb\":5,\"vsz_kb\":0,\"swap_kb\":0}}\n",
+    )
+    .unwrap();
+    let output = dir.path().join("out").join("7.parquet");
+
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "convert",
+            input.to_str().unwrap(),
+            output.to_str().unwrap(),
+            "--format",
+            "parquet",
+            "--compress",
+        ])
+        .output()
+        .expect("run fuzmon convert");
+    assert!(out.status.success());
+    let data = fs::read(&output).unwrap();
+    assert!(data.starts_with(b"PAR1") && data.ends_with(b"PAR1"));
+    let footer = &data[data.len() - 8..data.len() - 4];
+    let meta_len = u32::from_le_bytes(footer.try_into().unwrap()) as usize;
+    let meta = &data[data.len() - 8 - meta_len..data.len() - 8];
+    assert!(meta.windows(6).any(|w| w == b"rss_kb"));
+}
+
+#[test]
+fn merge_combines_copies_and_keeps_reused_pids_apart() {
+    let dir = tempdir().expect("tempdir");
+    let line = |sec: u32| {
+        format!(
+            "{{\"timestamp\":\"2025-01-01T00:00:0{sec}Z\",\"pid\":7,\"process_name\":\"t\",\"cpu_time_percent\":0,\"memory\":{{\"rss_kb\":5,\"vsz_kb\":0,\"swap_kb\":0}}}}\n"
+        )
+    };
+    let write = |host: &str, secs: &[u32]| {
+        let day = dir.path().join(host).join("20250101");
+        fs::create_dir_all(&day).unwrap();
+        let body: String = secs.iter().map(|s| line(*s)).collect();
+        fs::write(day.join("7.jsonl"), body).unwrap();
+        dir.path().join(host)
+    };
+    let a = write("a", &[1, 2]);
+    let b = write("b", &[3, 1, 2]);
+    let c = write("c", &[5, 6]);
+    let merged = dir.path().join("merged");
+
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "merge",
+            a.to_str().unwrap(),
+            b.to_str().unwrap(),
+            c.to_str().unwrap(),
+            "-o",
+            merged.to_str().unwrap(),
+        ])
+        .output()
+        .expect("run fuzmon merge");
+    assert!(out.status.success());
+    let first = fs::read_to_string(merged.join("20250101").join("7.jsonl")).unwrap();
+    let secs: Vec<&str> = first
+        .lines()
+        .map(|l| &l[l.find("00:00:").unwrap() + 6..][..2])
+        .collect();
+    assert_eq!(secs, vec!["01", "02", "03"], "{}", first);
+    let reused = fs::read_to_string(merged.join("20250101").join("7-1.jsonl")).unwrap();
+    assert_eq!(reused.lines().count(), 2, "{}", reused);
+}
+
+#[test]
+fn merge_combines_copies_by_start_time() {
+    let dir = tempdir().expect("tempdir");
+    let write = |host: &str, secs: &[u32]| {
+        let day = dir.path().join(host).join("20250101");
+        fs::create_dir_all(&day).unwrap();
+        // Only the first entry of each copy records when the process started.
+        let body: String = secs
+            .iter()
+            .enumerate()
+            .map(|(i, sec)| {
+                let start = if i == 0 {
+                    ",\"start_time\":\"2024-12-31T23:00:00Z\""
+                } else {
+                    ""
+                };
+                format!(
+                    "{{\"timestamp\":\"2025-01-01T00:00:0{sec}Z\",\"pid\":7,\"process_name\":\"t\",\"cpu_time_percent\":0,\"memory\":{{\"rss_kb\":5,\"vsz_kb\":0,\"swap_kb\":0}}{start}}}\n"
+                )
+            })
+            .collect();
+        fs::write(day.join("7.jsonl"), body).unwrap();
+        dir.path().join(host)
+    };
+    let a = write("a", &[1, 2]);
+    let b = write("b", &[2, 3]);
+    let merged = dir.path().join("merged");
+
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "merge",
+            a.to_str().unwrap(),
+            b.to_str().unwrap(),
+            "-o",
+            merged.to_str().unwrap(),
+        ])
+        .output()
+        .expect("run fuzmon merge");
+    assert!(out.status.success());
+    let text = fs::read_to_string(merged.join("20250101").join("7.jsonl")).unwrap();
+    assert_eq!(text.lines().count(), 3, "{}", text);
+    assert!(!merged.join("20250101").join("7-1.jsonl").exists());
+}
+
+#[test]
+fn dump_upgrades_unversioned_logs() {
+    let dir = tempdir().expect("tempdir");
+    let log_path = dir.path().join("9.jsonl");
+    fs::write(
+        &log_path,
+        "{\"timestamp\":\"2025-06-14T00:00:00Z\",\"pid\":9,\"process_name\":\"old\",\"cpu_time_sec\":1.0,\"memory\":{\"rss_kb\":10}}\n\
+         {\"timestamp\":\"2025-06-14T00:00:10Z\",\"pid\":9,\"process_name\":\"old\",\"cpu_time_sec\":6.0,\"memory\":{\"rss_kb\":10},\"stacktrace\":[[\" 0: 0xdeadbeef main at main.c:42\"]]}\n",
+    )
+    .unwrap();
+
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args(["dump", log_path.to_str().unwrap()])
+        .output()
+        .expect("run fuzmon dump");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("version: 1"), "{}", stdout);
+    assert!(stdout.contains("cpu_time_percent: 50.0"), "{}", stdout);
+    assert!(stdout.contains("swap_kb: 0"), "{}", stdout);
+    assert!(
+        stdout.contains("func: Some(\"0: 0xdeadbeef main at main.c:42\")"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn dump_recovers_complete_frames_of_truncated_log() {
+    let dir = tempdir().expect("tempdir");
+    let log_path = dir.path().join("3.jsonl.zst");
+    let mut data = Vec::new();
+    for ts in ["a", "b", "c"] {
+        let line = format!(
+            "{{\"timestamp\":\"{ts}\",\"pid\":3,\"process_name\":\"t\",\"cpu_time_percent\":0,\"memory\":{{\"rss_kb\":0,\"vsz_kb\":0,\"swap_kb\":0}}}}\n"
+        );
+        data.extend(zstd::stream::encode_all(line.as_bytes(), 0).unwrap());
+    }
+    data.truncate(data.len() - 5);
+    fs::write(&log_path, data).unwrap();
+
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args(["dump", log_path.to_str().unwrap()])
+        .output()
+        .expect("run fuzmon dump");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("timestamp: \"a\""), "{}", stdout);
+    assert!(stdout.contains("timestamp: \"b\""), "{}", stdout);
+    assert!(!stdout.contains("timestamp: \"c\""), "{}", stdout);
+}
+
+#[test]
+fn dump_seeks_to_time_range_with_index() {
+    let dir = tempdir().expect("tempdir");
+    let log_path = dir.path().join("5.jsonl");
+    let line = |ts: &str| {
+        format!(
+            "{{\"timestamp\":\"{ts}\",\"pid\":5,\"process_name\":\"t\",\"cpu_time_percent\":0,\"memory\":{{\"rss_kb\":0,\"vsz_kb\":0,\"swap_kb\":0}}}}\n"
+        )
+    };
+    // Only reachable through the index: a full read finds a corrupt line.
+    let skipped = "not an entry\n";
+    let first = line("2025-01-01T10:00:00Z");
+    let second = line("2025-01-01T10:05:00Z");
+    fs::write(&log_path, format!("{skipped}{first}{second}")).unwrap();
+    fs::write(
+        dir.path().join("5.jsonl.idx"),
+        format!(
+            "1735725600 {}\n1735725900 {}\n",
+            skipped.len(),
+            skipped.len() + first.len()
+        ),
+    )
+    .unwrap();
+
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "dump",
+            log_path.to_str().unwrap(),
+            "--from",
+            "2025-01-01T10:01:00Z",
+        ])
+        .output()
+        .expect("run fuzmon dump");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stdout.contains("10:05:00"), "{}{}", stdout, stderr);
+    assert!(!stdout.contains("10:00:00"), "{}", stdout);
+    assert!(!stderr.contains("corrupt"), "{}", stderr);
+}
+
+#[test]
+fn dump_filters_pids_and_prints_json() {
+    let dir = tempdir().expect("tempdir");
+    let line = |pid: u32, ts: &str| {
+        format!(
+            "{{\"timestamp\":\"{ts}\",\"pid\":{pid},\"process_name\":\"t\",\"cpu_time_percent\":0,\"memory\":{{\"rss_kb\":0,\"vsz_kb\":0,\"swap_kb\":0}}}}\n"
+        )
+    };
+    for pid in [5, 6] {
+        fs::write(
+            dir.path().join(format!("{pid}.jsonl")),
+            line(pid, "2025-01-01T10:00:00Z") + &line(pid, "2025-01-01T10:05:00Z"),
+        )
+        .unwrap();
+    }
+    let dump = |extra: &[&str]| {
+        let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+            .arg("dump")
+            .arg(dir.path())
+            .args(extra)
+            .output()
+            .expect("run fuzmon dump");
+        String::from_utf8_lossy(&out.stdout).into_owned()
+    };
+
+    let json = dump(&["--json", "--pid", "6", "--since", "2025-01-01T10:01:00Z"]);
+    let lines: Vec<serde_json::Value> = json
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 1, "{}", json);
+    assert_eq!(lines[0]["pid"], 6);
+    assert_eq!(lines[0]["timestamp"], "2025-01-01T10:05:00Z");
+
+    let pretty = dump(&["--pretty", "--pid", "5", "--until", "2025-01-01T10:01:00Z"]);
+    assert!(pretty.starts_with("{\n  \""), "{}", pretty);
+    let entry: serde_json::Value = serde_json::from_str(&pretty).unwrap();
+    assert_eq!(entry["pid"], 5);
+}
+
+#[test]
+fn dump_recurses_into_date_directories() {
+    let dir = tempdir().expect("tempdir");
+    for (sub, pid) in [("", 7), ("20250614", 5), ("20250615/13", 6)] {
+        let sub = dir.path().join(sub);
+        fs::create_dir_all(&sub).unwrap();
+        fs::write(
+            sub.join(format!("{pid}.jsonl")),
+            format!(
+                "{{\"timestamp\":\"2025-06-14T00:00:00Z\",\"pid\":{pid},\"process_name\":\"t\",\"cpu_time_percent\":0,\"memory\":{{\"rss_kb\":0,\"vsz_kb\":0,\"swap_kb\":0}}}}\n"
+            ),
+        )
+        .unwrap();
+    }
+    let pids = |extra: &[&str]| {
+        let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+            .arg("dump")
+            .arg(dir.path())
+            .arg("--json")
+            .args(extra)
+            .output()
+            .expect("run fuzmon dump");
+        String::from_utf8_lossy(&out.stdout)
+            .lines()
+            .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap()["pid"].clone())
+            .collect::<Vec<_>>()
+    };
+    // In path order, which is time order for date directories.
+    assert_eq!(pids(&[]), [5, 6, 7]);
+    assert_eq!(pids(&["--date", "20250615"]), [6]);
+    assert_eq!(pids(&["--max-depth", "0"]), [7]);
+    assert_eq!(pids(&["--max-depth", "1"]), [5, 7]);
+}
+
+#[test]
+fn dump_skips_and_counts_corrupt_records() {
+    let dir = tempdir().expect("tempdir");
+    let input = dir.path().join("9.jsonl");
+    let line = |name: &str| {
+        format!(
+            "{{\"timestamp\":\"2025-01-01T00:00:00Z\",\"pid\":9,\"process_name\":\"{name}\",\"cpu_time_percent\":0,\"memory\":{{\"rss_kb\":0,\"vsz_kb\":0,\"swap_kb\":0}}}}\n"
+        )
+    };
+    fs::write(&input, line("p1") + &line("p2") + &line("p3")).unwrap();
+
+    for (format, name) in [("jsonl", "out.jsonl"), ("msgpacks", "out.msgpacks")] {
+        let output = dir.path().join(name);
+        let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+            .args([
+                "convert",
+                input.to_str().unwrap(),
+                output.to_str().unwrap(),
+                "--format",
+                format,
+            ])
+            .output()
+            .expect("run fuzmon convert");
+        assert!(out.status.success());
+        // Damage the second record without breaking its encoding.
+        let mut data = fs::read(&output).unwrap();
+        let at = data.windows(2).position(|w| w == b"p2").unwrap();
+        data[at] = b'q';
+        fs::write(&output, data).unwrap();
+
+        let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+            .args(["dump", output.to_str().unwrap()])
+            .output()
+            .expect("run fuzmon dump");
+        let stdout = String::from_utf8_lossy(&out.stdout);
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        assert!(stdout.contains("\"p1\""), "{}", stdout);
+        assert!(stdout.contains("\"p3\""), "{}", stdout);
+        assert!(!stdout.contains("\"q2\""), "{}", stdout);
+        assert!(stderr.contains("skipped 1 corrupt records"), "{}", stderr);
+    }
+}
+
+#[test]
+fn merge_keeps_hosts_with_the_same_pid_apart() {
+    let dir = tempdir().expect("tempdir");
+    let write = |host: &str| {
+        let day = dir.path().join(host).join("20250101");
+        fs::create_dir_all(&day).unwrap();
+        fs::write(
+            day.join("7.jsonl"),
+            format!(
+                "{{\"timestamp\":\"2025-01-01T00:00:01Z\",\"pid\":7,\"process_name\":\"t\",\"cpu_time_percent\":0,\"memory\":{{\"rss_kb\":5,\"vsz_kb\":0,\"swap_kb\":0}},\"hostname\":\"{host}\",\"boot_id\":\"boot-{host}\"}}\n"
+            ),
+        )
+        .unwrap();
+        dir.path().join(host)
+    };
+    let a = write("a");
+    let b = write("b");
+    let merged = dir.path().join("merged");
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "merge",
+            a.to_str().unwrap(),
+            b.to_str().unwrap(),
+            "-o",
+            merged.to_str().unwrap(),
+        ])
+        .output()
+        .expect("run fuzmon merge");
+    assert!(out.status.success());
+    let day = merged.join("20250101");
+    let first = fs::read_to_string(day.join("7.jsonl")).unwrap();
+    let second = fs::read_to_string(day.join("7-1.jsonl")).unwrap();
+    assert!(first.contains("\"hostname\":\"a\""), "{}", first);
+    assert!(second.contains("\"hostname\":\"b\""), "{}", second);
+}
+
+#[test]
+fn top_needs_a_terminal() {
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .arg("top")
+        .stdin(std::process::Stdio::null())
+        .output()
+        .expect("run top");
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("fuzmon top needs a terminal"), "{}", stderr);
+}
+
+#[test]
+fn top_rejects_invalid_name_pattern() {
+    let dir = tempdir().expect("tempdir");
+    let config = dir.path().join("fuzmon.toml");
+    fs::write(&config, "[filter]\ntarget_process_name = \"(\"\n").unwrap();
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args(["top", "-c", config.to_str().unwrap()])
+        .stdin(std::process::Stdio::null())
+        .output()
+        .expect("run top");
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("invalid target_process_name"), "{}", stderr);
+}
+
+#[test]
+fn query_prints_matching_entries() {
+    let dir = tempdir().expect("tempdir");
+    let log = |day: &str, pid: u32, lines: &[(&str, u64)]| {
+        let day_dir = dir.path().join(day);
+        fs::create_dir_all(&day_dir).unwrap();
+        let data: String = lines
+            .iter()
+            .map(|(ts, rss)| {
+                format!(
+                    "{{\"timestamp\":\"{ts}\",\"pid\":{pid},\"process_name\":\"big\",\"cpu_time_percent\":0,\"memory\":{{\"rss_kb\":{rss},\"vsz_kb\":0,\"swap_kb\":0}}}}\n"
+                )
+            })
+            .collect();
+        fs::write(day_dir.join(format!("{pid}.jsonl")), data).unwrap();
+    };
+    log(
+        "20250614",
+        5,
+        &[
+            ("2025-06-14T10:00:00Z", 2_000_000),
+            ("2025-06-14T11:00:00Z", 500),
+        ],
+    );
+    log("20250615", 5, &[("2025-06-15T10:00:00Z", 3_000_000)]);
+    log("20250615", 6, &[("2025-06-15T10:00:00Z", 9_000_000)]);
+
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "query",
+            dir.path().to_str().unwrap(),
+            "--pid",
+            "5",
+            "--name",
+            "^bi",
+            "--where",
+            "rss_kb>1000000",
+            "--since",
+            "2025-06-14T10:30:00Z",
+        ])
+        .output()
+        .expect("run fuzmon query");
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let lines: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 1, "{}", stdout);
+    assert_eq!(lines[0]["timestamp"], "2025-06-15T10:00:00Z");
+    assert_eq!(lines[0]["memory"]["rss_kb"], 3_000_000);
+
+    let bad = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args(["query", dir.path().to_str().unwrap(), "--where", "rss_kb"])
+        .output()
+        .expect("run fuzmon query");
+    assert!(!bad.status.success());
+}
+
+#[test]
+fn tail_follows_growing_logs() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::process::Stdio;
+
+    let dir = tempdir().expect("tempdir");
+    let day = dir.path().join("20250614");
+    fs::create_dir(&day).unwrap();
+    let frame = |ts: &str, pid: u32| {
+        let line = format!(
+            "{{\"timestamp\":\"{ts}\",\"pid\":{pid},\"process_name\":\"w{pid}\",\"cpu_time_percent\":1.5,\"memory\":{{\"rss_kb\":100,\"vsz_kb\":0,\"swap_kb\":0}}}}\n"
+        );
+        zstd::encode_all(line.as_bytes(), 3).unwrap()
+    };
+    let log = day.join("8.jsonl.zst");
+    fs::write(&log, frame("2025-06-14T10:00:00Z", 8)).unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args(["tail", dir.path().to_str().unwrap(), "--pid", "8,9"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("run fuzmon tail");
+    let mut stderr = BufReader::new(child.stderr.take().unwrap());
+    let mut ready = String::new();
+    stderr.read_line(&mut ready).unwrap();
+    assert!(ready.starts_with("Following"), "{}", ready);
+
+    let mut file = fs::OpenOptions::new().append(true).open(&log).unwrap();
+    file.write_all(&frame("2025-06-14T10:00:01Z", 8)).unwrap();
+    drop(file);
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    let first = lines.next().unwrap().unwrap();
+    // Moved in whole, as a new date directory would be.
+    let next_day = dir.path().join("next");
+    fs::create_dir(&next_day).unwrap();
+    fs::write(
+        next_day.join("9.jsonl.zst"),
+        [
+            frame("2025-06-15T00:00:00Z", 9),
+            frame("2025-06-15T00:00:00Z", 10),
+        ]
+        .concat(),
+    )
+    .unwrap();
+    fs::rename(&next_day, dir.path().join("20250615")).unwrap();
+    let second = lines.next().unwrap().unwrap();
+    let _ = child.kill();
+    let _ = child.wait();
+    assert_eq!(
+        first,
+        "2025-06-14T10:00:01Z       8 w8  CPU 1.5%  RSS 100 KB"
+    );
+    assert!(
+        second.starts_with("2025-06-15T00:00:00Z       9 w9"),
+        "{}",
+        second
+    );
+}
+
+#[test]
+fn grep_finds_stacks_through_a_function() {
+    let dir = tempdir().expect("tempdir");
+    let entry = |pid: u32, func: &str| {
+        format!(
+            "{{\"timestamp\":\"2025-06-14T10:00:00Z\",\"pid\":{pid},\"process_name\":\"p{pid}\",\"cpu_time_percent\":0,\"memory\":{{\"rss_kb\":0,\"vsz_kb\":0,\"swap_kb\":0}},\"threads\":[{{\"tid\":{pid},\"stacktrace\":[{{\"func\":\"{func}\"}},{{\"func\":\"main\"}}]}}]}}\n"
+        )
+    };
+    fs::write(dir.path().join("1.jsonl"), entry(1, "ssl_do_handshake")).unwrap();
+    fs::write(dir.path().join("2.jsonl"), entry(2, "read")).unwrap();
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args(["grep", dir.path().to_str().unwrap(), "ssl_.*handshake"])
+        .output()
+        .expect("run fuzmon grep");
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert_eq!(
+        stdout,
+        "2025-06-14T10:00:00Z pid 1 (p1) thread 1\n  > ssl_do_handshake\n    main\n\n"
+    );
+}
+
+#[test]
+fn config_init_writes_a_commented_config() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("fuzmon.toml");
+    let run = || {
+        Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+            .args(["config", "init"])
+            .arg(&path)
+            .output()
+            .expect("run config init")
+    };
+    assert!(run().status.success());
+    let text = fs::read_to_string(&path).unwrap();
+    assert!(text.contains("[report]"));
+    assert!(text.contains("# zstd_level = 3"));
+
+    // An existing file is left alone.
+    fs::write(&path, "[output]\n").unwrap();
+    let out = run();
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("failed to write"), "{}", stderr);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "[output]\n");
+}
+
+#[test]
+fn daemon_writes_pidfile_and_stops_on_sigterm() {
+    use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify};
+    use std::process::Stdio;
+
+    let dir = tempdir().expect("tempdir");
+    let pidfile = dir.path().join("fuzmon.pid");
+    let out_dir = dir.path().join("out");
+    let mut target = Command::new("cat")
+        .stdin(Stdio::piped())
+        .spawn()
+        .expect("spawn cat");
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .env("RUST_LOG", "info")
+        .arg("run")
+        .args(["--daemon", "--pidfile", pidfile.to_str().unwrap()])
+        .args(["-o", out_dir.to_str().unwrap()])
+        .args(["-p", &target.id().to_string()])
+        .output()
+        .expect("run fuzmon --daemon");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(out.status.success(), "{}", stdout);
+    let pid: i32 = fs::read_to_string(&pidfile)
+        .unwrap()
+        .trim()
+        .parse()
+        .unwrap();
+    assert!(stdout.contains(&format!("as pid {}", pid)), "{}", stdout);
+    // The pid file stays locked by the daemon.
+    let second = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .arg("run")
+        .args(["--daemon", "--pidfile", pidfile.to_str().unwrap()])
+        .args(["-o", dir.path().join("second").to_str().unwrap()])
+        .args(["-p", &target.id().to_string()])
+        .output()
+        .expect("run second fuzmon --daemon");
+    assert!(!second.status.success());
+    let stderr = String::from_utf8_lossy(&second.stderr);
+    assert!(
+        stderr.contains(&format!("already running as pid {}", pid)),
+        "{}",
+        stderr
+    );
+
+    let inotify = Inotify::init(InitFlags::empty()).unwrap();
+    inotify
+        .add_watch(dir.path(), AddWatchFlags::IN_DELETE)
+        .unwrap();
+    let killed = Command::new("kill")
+        .args(["-TERM", &pid.to_string()])
+        .status()
+        .expect("run kill");
+    assert!(killed.success());
+    // The pid file goes last, once the final entries are written.
+    while pidfile.exists() {
+        inotify.read_events().unwrap();
+    }
+    let _ = target.kill();
+    let _ = target.wait();
+    let log = fs::read_to_string(dir.path().join("out.log")).unwrap();
+    assert!(log.contains("SIGTERM received"), "{}", log);
+    let query = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args(["query", out_dir.to_str().unwrap()])
+        .output()
+        .expect("run fuzmon query");
+    let entries = String::from_utf8_lossy(&query.stdout);
+    assert!(entries.contains("\"process_name\":\"cat\""), "{}", entries);
+}
+
+#[test]
+fn install_service_points_unit_at_config() {
+    let dir = tempdir().expect("tempdir");
+    let config = dir.path().join("fuzmon.toml");
+    fs::write(&config, "[output]\npath = \"/var/log/fuzmon\"\n").unwrap();
+    let unit = dir.path().join("fuzmon.service");
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args(["install-service", "-c", config.to_str().unwrap()])
+        .args(["-o", unit.to_str().unwrap()])
+        .output()
+        .expect("run install-service");
+    assert!(out.status.success());
+    let text = fs::read_to_string(&unit).unwrap();
+    let exec = format!(
+        "ExecStart={} run -c {}\n",
+        env!("CARGO_BIN_EXE_fuzmon"),
+        config.canonicalize().unwrap().display()
+    );
+    assert!(text.contains(&exec), "{}", text);
+}
+
+#[test]
+fn run_monitors_processes_matching_name() {
+    use std::io::{BufRead, BufReader};
+    use std::process::Stdio;
+
+    let dir = tempdir().expect("tempdir");
+    let watched = dir.path().join("watched-by-name");
+    fs::write(&watched, "").unwrap();
+    let mut target = Command::new("tail")
+        .arg("-f")
+        .arg(&watched)
+        .spawn()
+        .expect("spawn tail");
+    let mut fuzmon = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args(["run", "-o", "-", "--name", "watched-by-name$"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("run fuzmon");
+    let mut lines = BufReader::new(fuzmon.stdout.take().unwrap()).lines();
+    let first = lines.next().unwrap().unwrap();
+    let _ = fuzmon.kill();
+    let _ = fuzmon.wait();
+    let _ = target.kill();
+    let _ = target.wait();
+    let entry: serde_json::Value = serde_json::from_str(&first).unwrap();
+    assert_eq!(entry["pid"], target.id());
+}
+
+#[test]
+fn run_filters_processes_by_cmdline() {
+    use std::io::{BufRead, BufReader};
+    use std::process::Stdio;
+
+    let dir = tempdir().expect("tempdir");
+    let tail = |name: &str| {
+        let path = dir.path().join(name);
+        fs::write(&path, "").unwrap();
+        Command::new("tail")
+            .arg("-f")
+            .arg(path)
+            .spawn()
+            .expect("spawn tail")
+    };
+    let mut worker = tail("worker.py");
+    let mut web = tail("web.py");
+    // Elsewhere, as fuzmon would match its own command line otherwise.
+    let config_dir = tempdir().expect("tempdir");
+    let config = config_dir.path().join("fuzmon.toml");
+    fs::write(
+        &config,
+        format!(
+            "[filter]\ninclude_cmdline = [\"{}\"]\nignore_cmdline = [\"web\\\\.py\"]\n",
+            dir.path().display()
+        ),
+    )
+    .unwrap();
+    let mut fuzmon = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args(["run", "-o", "-", "-c", config.to_str().unwrap()])
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("run fuzmon");
+    // Two samples of every monitored process.
+    let pids: Vec<serde_json::Value> = BufReader::new(fuzmon.stdout.take().unwrap())
+        .lines()
+        .take(2)
+        .map(|l| serde_json::from_str::<serde_json::Value>(&l.unwrap()).unwrap()["pid"].clone())
+        .collect();
+    let _ = fuzmon.kill();
+    let _ = fuzmon.wait();
+    for t in [&mut worker, &mut web] {
+        let _ = t.kill();
+        let _ = t.wait();
+    }
+    assert_eq!(pids, [worker.id(), worker.id()]);
+}
+
+#[test]
+fn run_stops_after_duration() {
+    use std::process::Stdio;
+
+    let dir = tempdir().expect("tempdir");
+    let mut target = Command::new("cat")
+        .stdin(Stdio::piped())
+        .spawn()
+        .expect("spawn cat");
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .env("RUST_LOG", "info")
+        .args(["run", "--duration", "1s", "-p", &target.id().to_string()])
+        .arg("-o")
+        .arg(dir.path())
+        .output()
+        .expect("run fuzmon");
+    let _ = target.kill();
+    let _ = target.wait();
+    assert!(out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("max_duration_sec reached"), "{}", stderr);
+    let query = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .arg("query")
+        .arg(dir.path())
+        .output()
+        .expect("run fuzmon query");
+    // Sampled every 200 ms, plus the final sample on shutdown.
+    let entries = String::from_utf8_lossy(&query.stdout).lines().count();
+    assert!(entries >= 2, "{}", entries);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("Monitored 1 processes for "), "{}", stdout);
+    assert!(
+        stdout.contains(&format!("Wrote {} entries (", entries)),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains(&format!("bytes of jsonl.zst) to {}", dir.path().display())),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn run_stops_over_disk_budget() {
+    use std::process::Stdio;
+
+    let dir = tempdir().expect("tempdir");
+    let old = dir.path().join("20200101");
+    fs::create_dir(&old).unwrap();
+    fs::write(old.join("1.jsonl"), vec![b'x'; 4096]).unwrap();
+    let config = dir.path().join("fuzmon.toml");
+    fs::write(
+        &config,
+        "[output]\nmax_total_size_gb = 0.000001\nmax_total_size_action = \"stop\"\n",
+    )
+    .unwrap();
+    let mut target = Command::new("cat")
+        .stdin(Stdio::piped())
+        .spawn()
+        .expect("spawn cat");
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args(["run", "-p", &target.id().to_string()])
+        .arg("-c")
+        .arg(&config)
+        .arg("-o")
+        .arg(dir.path())
+        .output()
+        .expect("run fuzmon");
+    let _ = target.kill();
+    let _ = target.wait();
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        stdout.contains("over output.max_total_size_gb"),
+        "{}",
+        stdout
+    );
+    // Nothing is removed with `stop`.
+    assert!(old.join("1.jsonl").exists());
+    let query = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .arg("query")
+        .arg(dir.path())
+        .args(["--pid", &target.id().to_string()])
+        .output()
+        .expect("run fuzmon query");
+    let entries = String::from_utf8_lossy(&query.stdout);
+    let last = entries.lines().last().expect("entries");
+    assert!(
+        last.contains("\"stopped\":\"output.max_total_size_gb exceeded\""),
+        "{}",
+        entries
+    );
+}
+
+#[test]
+fn dry_run_lists_selected_and_skipped_processes() {
+    let dir = tempdir().expect("tempdir");
+    let tail = |name: &str| {
+        let path = dir.path().join(name);
+        fs::write(&path, "").unwrap();
+        Command::new("tail")
+            .arg("-f")
+            .arg(path)
+            .spawn()
+            .expect("spawn tail")
+    };
+    let mut worker = tail("worker.py");
+    let mut web = tail("web.py");
+    let config_dir = tempdir().expect("tempdir");
+    let config = config_dir.path().join("fuzmon.toml");
+    fs::write(
+        &config,
+        format!(
+            "[filter]\ninclude_cmdline = [\"{}\"]\nignore_cmdline = [\"web\\\\.py\"]\n",
+            dir.path().display()
+        ),
+    )
+    .unwrap();
+    let out_dir = config_dir.path().join("out");
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args(["run", "--dry-run", "-c", config.to_str().unwrap()])
+        .arg("-o")
+        .arg(&out_dir)
+        .output()
+        .expect("run fuzmon");
+    for t in [&mut worker, &mut web] {
+        let _ = t.kill();
+        let _ = t.wait();
+    }
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let line = |pid: u32| {
+        stdout
+            .lines()
+            .find(|l| l.split_whitespace().nth(1) == Some(&pid.to_string()))
+            .unwrap_or_default()
+            .to_string()
+    };
+    assert!(line(worker.id()).starts_with("monitor"), "{}", stdout);
+    assert!(line(web.id()).ends_with("(ignore_cmdline)"), "{}", stdout);
+    assert!(
+        line(std::process::id()).ends_with("(include_cmdline)"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.lines().any(|l| l.starts_with("1 monitored,")),
+        "{}",
+        stdout
+    );
+    assert!(!out_dir.exists());
+}
+
+#[test]
+fn fuzmon_itself_left_out_but_overhead_recorded() {
+    let dir = tempdir().expect("tempdir");
+    let dry_run = |extra: &[&str]| {
+        let mon = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+            .args(["run", "--dry-run"])
+            .args(extra)
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .expect("run fuzmon");
+        let pid = mon.id().to_string();
+        let out = mon.wait_with_output().expect("wait");
+        String::from_utf8_lossy(&out.stdout)
+            .lines()
+            .find(|l| l.split_whitespace().nth(1) == Some(&pid))
+            .unwrap_or_default()
+            .to_string()
+    };
+    assert!(dry_run(&[]).ends_with("(include_self)"));
+    assert!(dry_run(&["--include-self"]).starts_with("monitor"));
+
+    // No other process is this busy, so only the overhead is recorded.
+    let mon = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args(["run", "--duration", "1s", "--record-threshold", "100000"])
+        .arg("-o")
+        .arg(dir.path())
+        .spawn()
+        .expect("run fuzmon");
+    let pid = mon.id();
+    assert!(mon.wait_with_output().expect("wait").status.success());
+    let query = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .arg("query")
+        .arg(dir.path())
+        .output()
+        .expect("run fuzmon query");
+    let entries = String::from_utf8_lossy(&query.stdout);
+    assert!(entries.lines().count() >= 2, "{}", entries);
+    for line in entries.lines() {
+        let entry: serde_json::Value = serde_json::from_str(line).unwrap();
+        assert_eq!(entry["pid"], pid, "{}", line);
+        assert!(entry.get("threads").is_none(), "{}", line);
+        assert_eq!(entry["self_overhead"], true, "{}", line);
+    }
+}
+
+#[test]
+fn sigquit_and_sigterm_stop_gracefully() {
+    use nix::sys::signal::{Signal, kill};
+    use nix::unistd::Pid;
+    use std::process::Stdio;
+
+    for signal in [Signal::SIGTERM, Signal::SIGQUIT] {
+        let dir = tempdir().expect("tempdir");
+        let mut target = Command::new("cat")
+            .stdin(Stdio::piped())
+            .spawn()
+            .expect("spawn cat");
+        let mon = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+            .env("RUST_LOG", "info")
+            .args(["run", "-p", &target.id().to_string()])
+            .arg("-o")
+            .arg(dir.path())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("run fuzmon");
+        // The handlers are set once monitoring started.
+        let today = dir.path().join(fuzmon::utils::current_date_string());
+        fuzmon::test_utils::wait_until_file_appears(&today, target.id());
+        kill(Pid::from_raw(mon.id() as i32), signal).expect("kill");
+        let out = mon.wait_with_output().expect("wait");
+        let _ = target.kill();
+        let _ = target.wait();
+        assert!(out.status.success(), "{:?}", out.status);
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        assert!(
+            stderr.contains(&format!("{} received, shutting down", signal)),
+            "{}",
+            stderr
+        );
+    }
+}
+
+#[test]
+fn quiet_and_log_level_flags() {
+    let dir = tempdir().expect("tempdir");
+    let run = |flag: &str| {
+        Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+            .env_remove("RUST_LOG")
+            .args(["run", flag, "-o", dir.path().to_str().unwrap(), "true"])
+            .output()
+            .expect("run fuzmon")
+    };
+    let quiet = run("-q");
+    assert!(quiet.status.success());
+    assert_eq!(String::from_utf8_lossy(&quiet.stdout), "");
+    assert_eq!(String::from_utf8_lossy(&quiet.stderr), "");
+
+    let info = run("--log-level=info");
+    let stdout = String::from_utf8_lossy(&info.stdout);
+    assert!(stdout.contains("Monitored "), "{}", stdout);
+    let stderr = String::from_utf8_lossy(&info.stderr);
+    assert!(stderr.contains("INFO"), "{}", stderr);
+    assert!(stderr.contains("spawned true"), "{}", stderr);
+}
@@ -51,7 +51,7 @@ sys.stdin.readline()
     let base_dir = logdir.path().join(&date);
     let plain = base_dir.join(format!("{}.jsonl", pid));
     let zst = base_dir.join(format!("{}.jsonl.zst", pid));
-    wait_until_file_appears(&logdir, pid);
+    wait_until_file_appears(&base_dir, pid);
 
     child_in.write_all(b"\n").unwrap();
     child_in.flush().unwrap();
@@ -0,0 +1,38 @@
+use fuzmon::test_utils::run_fuzmon_with_config;
+use serde_json::Value;
+use std::fs;
+use std::process::Command;
+use tempfile::{NamedTempFile, tempdir};
+
+/// Whether the first entry logged for an idle process has stacks, with
+/// `monitor` settings that never capture on CPU.
+fn idle_process_has_stacks(monitor: &str) -> bool {
+    let mut child = Command::new("sleep").arg("30").spawn().expect("spawn");
+    let logdir = tempdir().expect("logdir");
+    let cfg = NamedTempFile::new().expect("cfg");
+    fs::write(
+        cfg.path(),
+        format!("[monitor]\nstacktrace_cpu_time_percent_threshold = 1000.0\n{monitor}"),
+    )
+    .expect("write cfg");
+    let log = run_fuzmon_with_config(env!("CARGO_BIN_EXE_fuzmon"), child.id(), &logdir, &cfg);
+    let _ = child.kill();
+    let _ = child.wait();
+    let line = log.lines().next().expect("line");
+    let entry: Value = serde_json::from_str(line).expect("json");
+    entry.get("threads").is_some()
+}
+
+#[test]
+fn stacks_captured_above_rss_threshold() {
+    assert!(idle_process_has_stacks("stacktrace_rss_mb_threshold = 1\n"));
+    assert!(!idle_process_has_stacks(
+        "stacktrace_rss_mb_threshold = 1000000\n"
+    ));
+}
+
+#[test]
+fn rss_growth_measured_from_first_sample() {
+    // The first sample is the baseline, so nothing has grown yet.
+    assert!(!idle_process_has_stacks("stacktrace_rss_growth_mb = 1\n"));
+}
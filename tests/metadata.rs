@@ -1,5 +1,7 @@
-use fuzmon::test_utils::run_fuzmon;
+use fuzmon::test_utils::{kill_with_sigint_and_wait, run_fuzmon, wait_until_file_appears};
+use fuzmon::utils::current_date_string;
 use serde_json::Value;
+use std::fs;
 use std::process::{Command, Stdio};
 use tempfile::tempdir;
 
@@ -20,4 +22,57 @@ fn first_entry_contains_cmdline_and_env() {
     assert_eq!(v.get("cmdline").and_then(|s| s.as_str()), Some("sleep 1"));
     let env = v.get("env").and_then(|s| s.as_str()).unwrap_or("");
     assert!(env.contains("META_VAR=xyz"), "{}", env);
+    let hostname = fs::read_to_string("/proc/sys/kernel/hostname").unwrap();
+    assert_eq!(
+        v.get("hostname").and_then(|s| s.as_str()),
+        Some(hostname.trim())
+    );
+    let boot_id = fs::read_to_string("/proc/sys/kernel/random/boot_id").unwrap();
+    assert_eq!(
+        v.get("boot_id").and_then(|s| s.as_str()),
+        Some(boot_id.trim())
+    );
+    let ts = v.get("timestamp").and_then(|s| s.as_str()).unwrap_or("");
+    assert!(
+        chrono::DateTime::parse_from_rfc3339(ts).is_ok() && ts.contains('.'),
+        "{}",
+        ts
+    );
+    let second = log.lines().nth(1).unwrap_or("{}");
+    assert!(!second.contains("boot_id"), "{}", second);
+}
+
+#[test]
+fn output_path_template_expands_hostname_and_date() {
+    let logdir = tempdir().expect("logdir");
+    let mut child = Command::new("sleep")
+        .arg("5")
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("spawn sleep");
+    let pid = child.id();
+    let template = logdir.path().join("{hostname}").join("logs-{date}");
+    let mut mon = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "run",
+            "-p",
+            &pid.to_string(),
+            "-o",
+            template.to_str().unwrap(),
+        ])
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("run fuzmon");
+    let hostname = fs::read_to_string("/proc/sys/kernel/hostname").unwrap();
+    let expected = logdir
+        .path()
+        .join(hostname.trim())
+        .join(format!("logs-{}", current_date_string()))
+        .join(format!("{pid}.jsonl.zst"));
+    wait_until_file_appears(expected.parent().unwrap(), pid);
+    kill_with_sigint_and_wait(&mut mon);
+    kill_with_sigint_and_wait(&mut child);
+    assert!(expected.exists(), "{}", expected.display());
+    let host_dir = fs::read_dir(logdir.path().join(hostname.trim())).unwrap();
+    assert_eq!(host_dir.count(), 1);
 }
@@ -1,13 +1,24 @@
-use fuzmon::test_utils::run_fuzmon;
+use fuzmon::test_utils::{run_fuzmon, run_fuzmon_with_config};
 use serde_json::Value;
 use std::fs;
 use std::io::Write;
 use std::process::{Command, Stdio};
 
-use tempfile::tempdir;
+use tempfile::{NamedTempFile, TempDir, tempdir};
 
-#[test]
-fn multi_thread_stacktrace_has_multiple_entries() {
+fn thread_count(log: &str) -> usize {
+    let line = log.lines().next().expect("line");
+    let entry: Value = serde_json::from_str(line).expect("json");
+    entry
+        .get("threads")
+        .and_then(|v| v.as_array())
+        .expect("array")
+        .len()
+}
+
+/// Runs `monitor` against a program with an idle worker thread and returns
+/// the collected log.
+fn run_two_thread_prog(monitor: impl FnOnce(u32, &TempDir) -> String) -> String {
     let dir = tempdir().expect("tempdir");
     let src = dir.path().join("prog.c");
     fs::write(
@@ -73,16 +84,33 @@ int main() {
     let pid = child.id();
 
     let logdir = tempdir().expect("logdir");
-    let log = run_fuzmon(env!("CARGO_BIN_EXE_fuzmon"), pid, &logdir);
+    let log = monitor(pid, &logdir);
 
     child_in.write_all(b"\n").unwrap();
     drop(child_in);
     let _ = child.wait();
-    let line = log.lines().next().expect("line");
-    let entry: Value = serde_json::from_str(line).expect("json");
-    let threads = entry
-        .get("threads")
-        .and_then(|v| v.as_array())
-        .expect("array");
-    assert!(threads.len() >= 2, "len {}", threads.len());
+    log
+}
+
+#[test]
+fn multi_thread_stacktrace_has_multiple_entries() {
+    let log =
+        run_two_thread_prog(|pid, logdir| run_fuzmon(env!("CARGO_BIN_EXE_fuzmon"), pid, logdir));
+    let len = thread_count(&log);
+    assert!(len >= 2, "len {}", len);
+}
+
+#[test]
+fn top_threads_limits_traced_threads() {
+    let cfg = NamedTempFile::new().expect("cfg");
+    fs::write(
+        cfg.path(),
+        "[monitor]\nstacktrace_cpu_time_percent_threshold = 0.0\n\
+         [stacktrace]\ntop_threads_per_process = 1\n",
+    )
+    .expect("write cfg");
+    let log = run_two_thread_prog(|pid, logdir| {
+        run_fuzmon_with_config(env!("CARGO_BIN_EXE_fuzmon"), pid, logdir, &cfg)
+    });
+    assert_eq!(thread_count(&log), 1, "{}", log);
 }
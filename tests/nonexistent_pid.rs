@@ -13,4 +13,20 @@ fn nonexistent_pid_exits_immediately() {
     assert!(start.elapsed().as_secs() < 2, "took {:?}", start.elapsed());
     let stdout = String::from_utf8_lossy(&out.stdout);
     assert!(stdout.contains("not found"), "{}", stdout);
+    assert_eq!(out.status.code(), Some(1));
+}
+
+#[test]
+fn invalid_run_options_exit_with_failure() {
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args(["run", "--daemon", "-o", "-", "-p", "1"])
+        .output()
+        .expect("run");
+    assert_eq!(out.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains("--daemon needs an output directory"),
+        "{}",
+        stderr
+    );
 }
@@ -0,0 +1,57 @@
+use fuzmon::test_utils::{run_fuzmon_with_config, spawn_http_server};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::process::Command;
+use std::time::Duration;
+use tempfile::{NamedTempFile, tempdir};
+
+#[test]
+fn otlp_exports_metrics_and_stacks() {
+    let (addr, requests) = spawn_http_server();
+    let mut child = Command::new("sleep").arg("30").spawn().expect("spawn");
+    let pid = child.id();
+
+    let logdir = tempdir().expect("logdir");
+    let cfg = NamedTempFile::new().expect("cfg");
+    fs::write(
+        cfg.path(),
+        format!(
+            "[output]\notlp = \"{addr}\"\n[monitor]\nstacktrace_cpu_time_percent_threshold = 0.0\n"
+        ),
+    )
+    .expect("write cfg");
+    run_fuzmon_with_config(env!("CARGO_BIN_EXE_fuzmon"), pid, &logdir, &cfg);
+    let _ = child.kill();
+    let _ = child.wait();
+
+    let mut bodies: HashMap<String, Value> = HashMap::new();
+    while let Ok((path, body)) = requests.recv_timeout(Duration::from_secs(5)) {
+        bodies.insert(path, serde_json::from_slice(&body).expect("json"));
+        if bodies.len() == 2 {
+            break;
+        }
+    }
+
+    let metrics = bodies.get("/v1/metrics").expect("metrics exported");
+    let names: Vec<&str> = metrics["resourceMetrics"][0]["scopeMetrics"][0]["metrics"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|m| m["name"].as_str())
+        .collect();
+    assert!(names.contains(&"process.memory.rss"), "{:?}", names);
+    let point =
+        &metrics["resourceMetrics"][0]["scopeMetrics"][0]["metrics"][0]["gauge"]["dataPoints"][0];
+    assert_eq!(
+        point["attributes"][0]["value"]["intValue"],
+        pid.to_string(),
+        "{}",
+        point
+    );
+
+    let traces = bodies.get("/v1/traces").expect("stacks exported");
+    let span = &traces["resourceSpans"][0]["scopeSpans"][0]["spans"][0];
+    assert_eq!(span["traceId"].as_str().map(str::len), Some(32), "{}", span);
+    assert!(span["name"].is_string(), "{}", span);
+}
@@ -5,22 +5,34 @@ use tempfile::{NamedTempFile, tempdir};
 use fuzmon::test_utils::wait_until_file_appears;
 use fuzmon::utils::current_date_string;
 
+/// Returns the log written today, skipping its time index.
+fn log_file(logdir: &tempfile::TempDir) -> std::path::PathBuf {
+    let subdir = logdir.path().join(current_date_string());
+    fs::read_dir(&subdir)
+        .unwrap()
+        .flatten()
+        .map(|e| e.path())
+        .find(|p| p.extension().is_none_or(|e| e != "idx"))
+        .unwrap()
+}
+
 fn run_with_format(fmt: &str) -> (tempfile::TempDir, std::path::PathBuf) {
-    let logdir = tempdir().expect("logdir");
-    let cfg_file = NamedTempFile::new().expect("cfg");
     let compress = if fmt.ends_with(".zst") {
         "true"
     } else {
         "false"
     };
+    run_with_output_config(&format!("format='{}'\ncompress={}", fmt, compress))
+}
+
+/// Monitors a short-lived process with `output` appended to the `[output]`
+/// section of the config and returns the log it wrote.
+fn run_with_output_config(output: &str) -> (tempfile::TempDir, std::path::PathBuf) {
+    let logdir = tempdir().expect("logdir");
+    let cfg_file = NamedTempFile::new().expect("cfg");
     fs::write(
         cfg_file.path(),
-        format!(
-            "[output]\npath='{}'\nformat='{}'\ncompress={}",
-            logdir.path().display(),
-            fmt,
-            compress
-        ),
+        format!("[output]\npath='{}'\n{}", logdir.path().display(), output),
     )
     .expect("write cfg");
 
@@ -44,19 +56,12 @@ fn run_with_format(fmt: &str) -> (tempfile::TempDir, std::path::PathBuf) {
         .spawn()
         .expect("run fuzmon");
 
-    wait_until_file_appears(&logdir, pid);
+    wait_until_file_appears(&logdir.path().join(current_date_string()), pid);
     fuzmon::test_utils::kill_with_sigint_and_wait(&mut mon);
 
     fuzmon::test_utils::kill_with_sigint_and_wait(&mut child);
 
-    let date = current_date_string();
-    let subdir = logdir.path().join(date);
-    let path = fs::read_dir(&subdir)
-        .unwrap()
-        .next()
-        .unwrap()
-        .unwrap()
-        .path();
+    let path = log_file(&logdir);
     (logdir, path)
 }
 
@@ -81,19 +86,12 @@ fn run_default() -> (tempfile::TempDir, std::path::PathBuf) {
         .spawn()
         .expect("run fuzmon");
 
-    wait_until_file_appears(&logdir, pid);
+    wait_until_file_appears(&logdir.path().join(current_date_string()), pid);
     fuzmon::test_utils::kill_with_sigint_and_wait(&mut mon);
 
     fuzmon::test_utils::kill_with_sigint_and_wait(&mut child);
 
-    let date = current_date_string();
-    let subdir = logdir.path().join(date);
-    let path = fs::read_dir(&subdir)
-        .unwrap()
-        .next()
-        .unwrap()
-        .unwrap()
-        .path();
+    let path = log_file(&logdir);
     (logdir, path)
 }
 
@@ -147,3 +145,198 @@ fn msgpacks_zst_output_and_dump() {
     assert!(out.contains("process_name"));
     drop(dir);
 }
+
+#[test]
+fn msgpackc_output_and_dump() {
+    let (dir, path) = run_with_format("msgpackc");
+    assert_eq!(path.extension().and_then(|e| e.to_str()), Some("msgpackc"));
+    let out = dump_file(&path);
+    println!("out: {}", out);
+    // Interned names are resolved back to strings.
+    assert!(out.contains("process_name: \""));
+    assert!(!out.contains("process_name: \"?\""));
+    drop(dir);
+}
+
+#[test]
+fn trained_dictionary_is_used_and_found_by_dump() {
+    let samples = tempdir().expect("samples");
+    let mut lines = String::new();
+    for i in 0..2000 {
+        lines.push_str(&format!(
+            "{{\"version\":1,\"timestamp\":\"2025-01-01T00:{:02}:{:02}Z\",\"pid\":{},\"process_name\":\"python3\",\"cpu_time_percent\":{}.5,\"memory\":{{\"rss_kb\":{},\"vsz_kb\":{},\"swap_kb\":0}}}}\n",
+            i / 60 % 60,
+            i % 60,
+            1000 + i % 37,
+            i % 100,
+            9000 + i * 7,
+            20000 + i * 13
+        ));
+    }
+    fs::write(samples.path().join("1.jsonl"), lines).unwrap();
+    let dict = samples.path().join("trained.dict");
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "dict",
+            samples.path().to_str().unwrap(),
+            "-o",
+            dict.to_str().unwrap(),
+        ])
+        .output()
+        .expect("run fuzmon dict");
+    assert!(dict.exists(), "{}", String::from_utf8_lossy(&out.stderr));
+    let dict_id = zstd::zstd_safe::get_dict_id_from_dict(&fs::read(&dict).unwrap());
+    assert!(dict_id.is_some());
+
+    let (dir, path) = run_with_output_config(&format!(
+        "format='jsonl'\ncompress=true\nzstd_level=19\nzstd_dictionary='{}'",
+        dict.display()
+    ));
+    let name = format!("fuzmon-{}.dict", dict_id.unwrap());
+    assert!(dir.path().join(name).exists());
+    let data = fs::read(&path).unwrap();
+    assert_eq!(zstd::zstd_safe::get_dict_id_from_frame(&data), dict_id);
+    let out = dump_file(&path);
+    assert!(out.contains("process_name"), "{}", out);
+}
+
+#[test]
+fn combined_layout_writes_one_file_per_day() {
+    let (dir, path) = run_with_output_config("format='jsonl'\ncompress=false\nlayout='combined'");
+    assert_eq!(
+        path.file_name().and_then(|n| n.to_str()),
+        Some("combined.jsonl")
+    );
+    let names: Vec<String> = fs::read_dir(path.parent().unwrap())
+        .unwrap()
+        .flatten()
+        .map(|e| e.file_name().to_string_lossy().into_owned())
+        .collect();
+    assert!(
+        names.iter().all(|n| n.starts_with("combined.jsonl")),
+        "{:?}",
+        names
+    );
+    // Too short a run to have a place worth seeking to.
+    assert_eq!(names, ["combined.jsonl"]);
+    let out = dump_file(&path);
+    assert!(out.contains("process_name"), "{}", out);
+    drop(dir);
+}
+
+#[test]
+fn timezone_names_date_directories() {
+    let logdir = tempdir().expect("logdir");
+    let cfg_file = NamedTempFile::new().expect("cfg");
+    fs::write(
+        cfg_file.path(),
+        "[output]\nformat='jsonl'\ncompress=false\ntimezone='Asia/Tokyo'\n",
+    )
+    .expect("write cfg");
+    let mut child = Command::new("sleep")
+        .arg("5")
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("spawn sleep");
+    let pid = child.id();
+    let mut mon = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "run",
+            "-p",
+            &pid.to_string(),
+            "-o",
+            logdir.path().to_str().unwrap(),
+            "-c",
+            cfg_file.path().to_str().unwrap(),
+        ])
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("run fuzmon");
+    // Tokyo has no daylight saving time.
+    let tokyo = chrono::FixedOffset::east_opt(9 * 3600).unwrap();
+    let date = chrono::Utc::now().with_timezone(&tokyo).format("%Y%m%d");
+    let expected = logdir
+        .path()
+        .join(date.to_string())
+        .join(format!("{pid}.jsonl"));
+    wait_until_file_appears(expected.parent().unwrap(), pid);
+    fuzmon::test_utils::kill_with_sigint_and_wait(&mut mon);
+    fuzmon::test_utils::kill_with_sigint_and_wait(&mut child);
+    assert!(expected.exists(), "{}", expected.display());
+}
+
+#[test]
+fn hourly_rollover_writes_hour_directories() {
+    let logdir = tempdir().expect("logdir");
+    let cfg_file = NamedTempFile::new().expect("cfg");
+    fs::write(
+        cfg_file.path(),
+        "[output]\nformat='jsonl'\ncompress=false\nrollover='hour'\n",
+    )
+    .expect("write cfg");
+    let mut child = Command::new("sleep")
+        .arg("5")
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("spawn sleep");
+    let pid = child.id();
+    let expected = || {
+        let now = chrono::Utc::now();
+        logdir
+            .path()
+            .join(now.format("%Y%m%d").to_string())
+            .join(now.format("%H").to_string())
+            .join(format!("{pid}.jsonl"))
+    };
+    let before = expected();
+    let mut mon = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "run",
+            "-p",
+            &pid.to_string(),
+            "-o",
+            logdir.path().to_str().unwrap(),
+            "-c",
+            cfg_file.path().to_str().unwrap(),
+        ])
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("run fuzmon");
+    fuzmon::test_utils::wait_for(|| before.exists() || expected().exists());
+    fuzmon::test_utils::kill_with_sigint_and_wait(&mut mon);
+    fuzmon::test_utils::kill_with_sigint_and_wait(&mut child);
+    // The hour may have changed while starting up.
+    let path = if before.exists() { before } else { expected() };
+    assert!(path.exists(), "{}", path.display());
+    let out = dump_file(&path);
+    assert!(out.contains("process_name"), "{}", out);
+}
+
+#[test]
+fn dash_streams_json_lines_to_stdout() {
+    use std::io::{BufRead, BufReader};
+
+    let cwd = tempdir().expect("cwd");
+    let mut child = Command::new("sleep")
+        .arg("5")
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("spawn sleep");
+    let pid = child.id();
+    let mut mon = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args(["run", "-p", &pid.to_string(), "-o", "-"])
+        .current_dir(cwd.path())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("run fuzmon");
+    let mut line = String::new();
+    BufReader::new(mon.stdout.take().unwrap())
+        .read_line(&mut line)
+        .unwrap();
+    fuzmon::test_utils::kill_with_sigint_and_wait(&mut mon);
+    fuzmon::test_utils::kill_with_sigint_and_wait(&mut child);
+    let v: serde_json::Value = serde_json::from_str(&line).expect("json line");
+    assert_eq!(v["pid"].as_u64(), Some(pid as u64), "{}", line);
+    assert!(v.get("crc32").is_none(), "{}", line);
+    assert_eq!(fs::read_dir(cwd.path()).unwrap().count(), 0);
+}
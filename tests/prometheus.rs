@@ -0,0 +1,65 @@
+use fuzmon::test_utils::{kill_with_sigint_and_wait, wait_for};
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::{Command, Stdio};
+use tempfile::{NamedTempFile, tempdir};
+
+fn scrape(addr: &str) -> Option<String> {
+    let mut stream = TcpStream::connect(addr).ok()?;
+    stream
+        .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .ok()?;
+    let mut resp = String::new();
+    stream.read_to_string(&mut resp).ok()?;
+    Some(resp)
+}
+
+#[test]
+fn prometheus_endpoint_serves_process_gauges() {
+    let addr = {
+        let l = TcpListener::bind("127.0.0.1:0").expect("bind");
+        l.local_addr().expect("addr").to_string()
+    };
+    let mut child = Command::new("sleep").arg("30").spawn().expect("spawn");
+    let pid = child.id();
+
+    let logdir = tempdir().expect("logdir");
+    let cfg = NamedTempFile::new().expect("cfg");
+    fs::write(
+        cfg.path(),
+        format!("[output]\nprometheus_listen = \"{addr}\"\n"),
+    )
+    .expect("write cfg");
+    let mut mon = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "run",
+            "-p",
+            &pid.to_string(),
+            "-o",
+            logdir.path().to_str().unwrap(),
+            "-c",
+            cfg.path().to_str().unwrap(),
+        ])
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("run fuzmon");
+
+    let expected = format!("fuzmon_thread_count{{pid=\"{pid}\",name=\"sleep\"}} 1");
+    let mut page = String::new();
+    wait_for(|| {
+        page = scrape(&addr).unwrap_or_default();
+        page.contains(&expected)
+    });
+    kill_with_sigint_and_wait(&mut mon);
+    let _ = child.kill();
+    let _ = child.wait();
+
+    assert!(page.starts_with("HTTP/1.1 200 OK"), "{}", page);
+    assert!(page.contains(&expected), "{}", page);
+    assert!(
+        page.contains(&format!("fuzmon_rss_kb{{pid=\"{pid}\",name=\"sleep\"}}")),
+        "{}",
+        page
+    );
+}
@@ -1,11 +1,11 @@
 use fuzmon::test_utils::run_fuzmon;
 use std::fs;
 use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
 use std::process::{Command, Stdio};
 use tempfile::tempdir;
 
-#[test]
-fn python_stack_trace_contains_functions() {
+fn run_python_script(python: &Path) -> String {
     let dir = tempdir().expect("tempdir");
     let script = dir.path().join("test.py");
     fs::write(
@@ -26,7 +26,7 @@ if __name__ == '__main__':
     )
     .expect("write script");
 
-    let mut child = Command::new("python3")
+    let mut child = Command::new(python)
         .arg(&script)
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
@@ -47,6 +47,12 @@ if __name__ == '__main__':
     child_in.write_all(b"\n").unwrap();
     drop(child_in);
     let _ = child.wait();
+    log
+}
+
+#[test]
+fn python_stack_trace_contains_functions() {
+    let log = run_python_script(Path::new("python3"));
     assert!(log.contains("foo"), "{}", log);
     assert!(log.contains("bar"), "{}", log);
     assert!(log.contains("test.py"), "{}", log);
@@ -69,3 +75,22 @@ if __name__ == '__main__':
     assert!(has_c, "no c stacktrace: {}", first);
     assert!(has_py, "no python stacktrace: {}", first);
 }
+
+#[test]
+fn python_detected_without_python_process_name() {
+    let dir = tempdir().expect("tempdir");
+    let python = String::from_utf8(
+        Command::new("python3")
+            .args(["-c", "import sys; print(sys.executable)"])
+            .output()
+            .expect("python3 executable")
+            .stdout,
+    )
+    .expect("utf8");
+    let worker = dir.path().join("worker");
+    std::os::unix::fs::symlink(python.trim(), &worker).expect("symlink");
+
+    let log = run_python_script(&worker);
+    assert!(log.contains("\"process_name\":\"worker\""), "{}", log);
+    assert!(log.contains("python_stacktrace"), "{}", log);
+}
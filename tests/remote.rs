@@ -0,0 +1,60 @@
+use fuzmon::test_utils::{run_fuzmon_with_config, spawn_http_server};
+use serde_json::Value;
+use std::fs;
+use std::net::TcpListener;
+use std::process::Command;
+use std::time::Duration;
+use tempfile::{NamedTempFile, tempdir};
+
+fn monitor_sleep_with(output: &str) {
+    let mut child = Command::new("sleep").arg("30").spawn().expect("spawn");
+    let logdir = tempdir().expect("logdir");
+    let cfg = NamedTempFile::new().expect("cfg");
+    fs::write(cfg.path(), format!("[output]\n{output}")).expect("write cfg");
+    run_fuzmon_with_config(env!("CARGO_BIN_EXE_fuzmon"), child.id(), &logdir, &cfg);
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+#[test]
+fn remote_sink_posts_compressed_entries() {
+    let (addr, requests) = spawn_http_server();
+    let spool = tempdir().expect("spool");
+    monitor_sleep_with(&format!(
+        "remote_url = \"{addr}/ingest\"\nremote_spool = \"{}\"\n",
+        spool.path().display()
+    ));
+
+    let (path, body) = requests
+        .recv_timeout(Duration::from_secs(5))
+        .expect("batch posted");
+    assert_eq!(path, "/ingest");
+    let lines = zstd::stream::decode_all(&body[..]).expect("zstd");
+    let first = String::from_utf8_lossy(&lines)
+        .lines()
+        .next()
+        .unwrap()
+        .to_string();
+    let entry: Value = serde_json::from_str(&first).expect("json");
+    assert_eq!(entry["process_name"], "sleep");
+    assert!(fs::read_dir(spool.path()).unwrap().next().is_none());
+}
+
+#[test]
+fn remote_sink_spools_when_unreachable() {
+    let addr = {
+        let l = TcpListener::bind("127.0.0.1:0").expect("bind");
+        l.local_addr().expect("addr")
+    };
+    let spool = tempdir().expect("spool");
+    monitor_sleep_with(&format!(
+        "remote_url = \"http://{addr}/ingest\"\nremote_spool = \"{}\"\n",
+        spool.path().display()
+    ));
+
+    let files: Vec<_> = fs::read_dir(spool.path()).unwrap().flatten().collect();
+    assert!(!files.is_empty());
+    let data = fs::read(files[0].path()).unwrap();
+    let lines = zstd::stream::decode_all(&data[..]).expect("zstd");
+    assert!(String::from_utf8_lossy(&lines).contains("\"process_name\":\"sleep\""));
+}
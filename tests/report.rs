@@ -108,6 +108,130 @@ fn html_report_directory() {
     assert!(outdir.path().join("top_rss.svg").exists());
     assert!(html.contains("top_cpu.svg"), "{}", html);
     assert!(html.contains("top_rss.svg"), "{}", html);
+    assert!(outdir.path().join("chart.js").exists());
+    assert!(html.contains("<script src=\"chart.js\">"), "{}", html);
+}
+
+#[test]
+fn interactive_charts_embed_series() {
+    let dir = tempdir().expect("dir");
+    let pid = 4545;
+    let log_path = dir.path().join(format!("{pid}.jsonl"));
+    fs::write(
+        &log_path,
+        format!(
+            "{{\"timestamp\":\"2025-06-14T00:00:00Z\",\"pid\":{pid},\"process_name\":\"a\",\"cpu_time_percent\":25.0,\"memory\":{{\"rss_kb\":2048,\"vsz_kb\":0,\"swap_kb\":0}},\"cmdline\":\"/bin/app --x\"}}\n{{\"timestamp\":\"2025-06-14T00:00:01.500Z\",\"pid\":{pid},\"process_name\":\"a\",\"cpu_time_percent\":75.0,\"memory\":{{\"rss_kb\":4096,\"vsz_kb\":0,\"swap_kb\":0}}}}\n"
+        ),
+    )
+    .unwrap();
+    let outdir = tempdir().expect("outdir");
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "report",
+            log_path.to_str().unwrap(),
+            "-o",
+            outdir.path().to_str().unwrap(),
+        ])
+        .output()
+        .expect("run report");
+    assert!(out.status.success());
+    let script = fs::read_to_string(outdir.path().join("chart.js")).unwrap();
+    assert!(script.contains("function fuzmonChart"));
+    let html = fs::read_to_string(outdir.path().join("index.html")).unwrap();
+    let spec = |id: &str| -> serde_json::Value {
+        let start = format!("fuzmonChart(\"{id}\", ");
+        let rest = &html[html.find(&start).expect("chart") + start.len()..];
+        serde_json::from_str(&rest[..rest.find(");</script>").unwrap()]).unwrap()
+    };
+    let cpu = spec(&format!("{pid}_cpu_chart"));
+    assert_eq!(cpu["series"][0]["label"], format!("{pid} app"));
+    assert_eq!(
+        cpu["series"][0]["data"],
+        serde_json::json!([[1749859200000i64, 25.0], [1749859201500i64, 75.0]])
+    );
+    let rss = spec(&format!("{pid}_rss_chart"));
+    assert_eq!(rss["unit"], "MB");
+    assert_eq!(rss["series"][0]["data"][1][1], 4.0);
+}
+
+#[test]
+fn long_runs_graphed_with_merged_points() {
+    let dir = tempdir().expect("dir");
+    let pid = 4546;
+    let log_path = dir.path().join(format!("{pid}.jsonl"));
+    let log: String = (0..2000)
+        .map(|i| {
+            // One sample in a hundred spikes.
+            let cpu = if i % 100 == 7 { 90.0 } else { 10.0 };
+            format!(
+                "{{\"version\":1,\"timestamp\":\"2025-06-14T{:02}:{:02}:{:02}Z\",\"pid\":{pid},\"process_name\":\"a\",\"cpu_time_percent\":{cpu},\"memory\":{{\"rss_kb\":{},\"vsz_kb\":0,\"swap_kb\":0}}}}\n",
+                i / 3600,
+                i / 60 % 60,
+                i % 60,
+                1000 + i
+            )
+        })
+        .collect();
+    fs::write(&log_path, log).unwrap();
+    let outdir = tempdir().expect("outdir");
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "report",
+            log_path.to_str().unwrap(),
+            "-o",
+            outdir.path().to_str().unwrap(),
+        ])
+        .output()
+        .expect("run report");
+    assert!(out.status.success());
+    let html = fs::read_to_string(outdir.path().join("index.html")).unwrap();
+    let start = format!("fuzmonChart(\"{pid}_cpu_chart\", ");
+    let rest = &html[html.find(&start).expect("chart") + start.len()..];
+    let spec: serde_json::Value =
+        serde_json::from_str(&rest[..rest.find(");</script>").unwrap()]).unwrap();
+    let points = spec["series"][0]["data"].as_array().unwrap();
+    assert!(points.len() <= 300, "{}", points.len());
+    // Merged points keep the peaks.
+    let spikes = points.iter().filter(|p| p[1] == 90.0).count();
+    assert_eq!(spikes, 20, "{:?}", points);
+    // The totals still count every sample.
+    assert!(html.contains("Total runtime: 1999 sec"), "{}", html);
+}
+
+#[test]
+fn html_report_combined_log() {
+    let dir = tempdir().expect("dir");
+    let log = dir.path().join("combined.jsonl");
+    let line = |ts: &str, pid: u32, rss: u64| {
+        format!(
+            "{{\"timestamp\":\"2025-06-14T00:00:{ts}Z\",\"pid\":{pid},\"process_name\":\"p\",\"cpu_time_percent\":5.0,\"memory\":{{\"rss_kb\":{rss},\"vsz_kb\":0,\"swap_kb\":0}}}}\n"
+        )
+    };
+    let lines = [
+        line("00", 1111, 1000),
+        line("00", 2222, 5000),
+        line("10", 1111, 1500),
+        line("10", 2222, 6000),
+    ];
+    fs::write(&log, lines.concat()).unwrap();
+
+    let outdir = tempdir().expect("outdir");
+    Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "report",
+            log.to_str().unwrap(),
+            "-o",
+            outdir.path().to_str().unwrap(),
+        ])
+        .output()
+        .expect("run report");
+    let html = fs::read_to_string(outdir.path().join("index.html")).unwrap();
+    assert!(html.contains("1111"), "{}", html);
+    assert!(html.contains("2222"), "{}", html);
+    let page = fs::read_to_string(outdir.path().join("1111.html")).unwrap();
+    assert!(page.contains("1500"), "{}", page);
+    assert!(!page.contains("6000"), "{}", page);
+    assert!(outdir.path().join("2222.html").exists());
 }
 
 #[test]
@@ -136,6 +260,37 @@ fn command_column_collapsed() {
     assert!(html.contains("border-collapse"), "{}", html);
 }
 
+#[test]
+fn html_report_integrates_sub_second_samples() {
+    let dir = tempdir().expect("dir");
+    let log_path = dir.path().join("4321.jsonl");
+    let log: String = ["00.000", "00.200", "00.400", "00.600", "00.800", "01.000"]
+        .iter()
+        .map(|secs| {
+            format!(
+                "{{\"timestamp\":\"2025-06-14T00:00:{secs}Z\",\"pid\":4321,\"process_name\":\"a\",\"cpu_time_percent\":50.0,\"memory\":{{\"rss_kb\":1000,\"vsz_kb\":0,\"swap_kb\":0}}}}\n"
+            )
+        })
+        .collect();
+    fs::write(&log_path, log).unwrap();
+
+    let outdir = tempdir().expect("outdir");
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "report",
+            log_path.to_str().unwrap(),
+            "-o",
+            outdir.path().to_str().unwrap(),
+        ])
+        .output()
+        .expect("run report");
+    assert!(out.status.success());
+    let html = fs::read_to_string(outdir.path().join("index.html")).unwrap();
+    assert!(html.contains("Total runtime: 1 sec"), "{}", html);
+    assert!(html.contains("Total CPU time: 0.5 sec"), "{}", html);
+    assert!(html.contains("Average CPU usage: 50.0%"), "{}", html);
+}
+
 #[test]
 fn trace_json_created_with_stacktrace() {
     use fuzmon::test_utils::run_fuzmon;
@@ -234,6 +389,134 @@ fn no_trace_link_without_stacktrace() {
     assert!(!trace_path.exists());
     let html = fs::read_to_string(outdir.path().join("index.html")).unwrap();
     assert!(!html.contains(&format!("{}_trace.json", pid)), "{}", html);
+    assert!(!outdir.path().join(format!("{pid}_flame.svg")).exists());
+    assert!(!html.contains(&format!("{}_flame.svg", pid)), "{}", html);
+}
+
+/// Writes a log of `pid` whose thread runs `compute` for 3 seconds, then
+/// `idle` for 1 and `compute` again in the last sample.
+fn write_stack_log(log_path: &std::path::Path, pid: u32) {
+    let entry = |ts: &str, leaf: &str| {
+        format!(
+            "{{\"version\":1,\"timestamp\":\"{ts}\",\"pid\":{pid},\"process_name\":\"app\",\"cpu_time_percent\":100.0,\"memory\":{{\"rss_kb\":1000,\"vsz_kb\":0,\"swap_kb\":0}},\"threads\":[{{\"tid\":{pid},\"stacktrace\":[{{\"func\":\"{leaf}\"}},{{\"func\":\"main\"}}]}}]}}\n"
+        )
+    };
+    let log = [
+        entry("2025-06-14T00:00:00Z", "compute"),
+        entry("2025-06-14T00:00:03Z", "idle"),
+        entry("2025-06-14T00:00:04Z", "compute"),
+    ]
+    .concat();
+    fs::write(log_path, log).unwrap();
+}
+
+#[test]
+fn flamegraph_weights_stacks_by_interval() {
+    let dir = tempdir().expect("dir");
+    let pid = 4343;
+    let log_path = dir.path().join(format!("{pid}.jsonl"));
+    write_stack_log(&log_path, pid);
+
+    let outdir = tempdir().expect("outdir");
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "report",
+            log_path.to_str().unwrap(),
+            "-o",
+            outdir.path().to_str().unwrap(),
+        ])
+        .output()
+        .expect("run report");
+    assert!(out.status.success());
+    let svg = fs::read_to_string(outdir.path().join(format!("{pid}_flame.svg"))).unwrap();
+    assert!(svg.contains("Sampled time by stack"), "{}", svg);
+    // The last sample counts as long as the interval before it.
+    assert!(svg.contains("compute (4,000 ms, 80.00%)"), "{}", svg);
+    assert!(svg.contains("idle (1,000 ms, 20.00%)"), "{}", svg);
+    let html = fs::read_to_string(outdir.path().join("index.html")).unwrap();
+    assert!(
+        html.contains(&format!("<a href=\"{}_flame.svg\"", pid)),
+        "{}",
+        html
+    );
+}
+
+#[test]
+fn hottest_functions_and_stacks_listed() {
+    let dir = tempdir().expect("dir");
+    let pid = 4346;
+    let log_path = dir.path().join(format!("{pid}.jsonl"));
+    write_stack_log(&log_path, pid);
+
+    let outdir = tempdir().expect("outdir");
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "report",
+            log_path.to_str().unwrap(),
+            "-o",
+            outdir.path().to_str().unwrap(),
+        ])
+        .output()
+        .expect("run report");
+    assert!(out.status.success());
+    let html = fs::read_to_string(outdir.path().join("index.html")).unwrap();
+    assert!(html.contains("Hottest functions"), "{}", html);
+    // compute runs 3 + 1 of the 5 seconds, main is on every stack.
+    for row in [
+        "<tr><td>compute</td><td>4.0</td><td>80.0</td><td>80.0</td></tr>",
+        "<tr><td>idle</td><td>1.0</td><td>20.0</td><td>20.0</td></tr>",
+        "<tr><td>main</td><td>0.0</td><td>0.0</td><td>100.0</td></tr>",
+        "<tr><td>compute &lt; main</td><td>4.0</td><td>80.0</td></tr>",
+    ] {
+        assert!(html.contains(row), "{}\n{}", row, html);
+    }
+}
+
+#[test]
+fn trace_rows_named_by_process_and_thread() {
+    let dir = tempdir().expect("dir");
+    let pid = 4345;
+    let log_path = dir.path().join(format!("{pid}.jsonl"));
+    write_stack_log(&log_path, pid);
+
+    let outdir = tempdir().expect("outdir");
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "report",
+            log_path.to_str().unwrap(),
+            "-o",
+            outdir.path().to_str().unwrap(),
+        ])
+        .output()
+        .expect("run report");
+    assert!(out.status.success());
+    let trace = fs::read_to_string(outdir.path().join(format!("{pid}_trace.json"))).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&trace).unwrap();
+    let meta: Vec<&serde_json::Value> = json["traceEvents"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|e| e["ph"] == "M")
+        .collect();
+    assert!(
+        meta.iter().any(|e| e["name"] == "process_name"
+            && e["pid"] == pid
+            && e["args"]["name"] == format!("app ({pid})")),
+        "{}",
+        trace
+    );
+    let threads: Vec<_> = meta
+        .iter()
+        .filter(|e| e["name"] == "thread_name")
+        .map(|e| {
+            (
+                e["tid"].as_u64().unwrap(),
+                e["args"]["name"].as_str().unwrap(),
+            )
+        })
+        .collect();
+    let native = format!("tid {pid} (native)");
+    assert_eq!(threads, [((pid as u64) << 1, native.as_str())], "{}", trace);
 }
 
 #[test]
@@ -320,3 +603,1381 @@ foo()
     }
     assert!(has_pair, "no separate python row: {:?}", tids);
 }
+
+#[test]
+fn python_thread_states_reported() {
+    let dir = tempdir().expect("dir");
+    let pid = 4343;
+    let log_path = dir.path().join(format!("{pid}.jsonl"));
+    let entry = |ts: &str, gil: bool| {
+        format!(
+            "{{\"timestamp\":\"{ts}\",\"pid\":{pid},\"process_name\":\"python3\",\"cpu_time_percent\":0.0,\"memory\":{{\"rss_kb\":1000,\"vsz_kb\":0,\"swap_kb\":0}},\"threads\":[{{\"tid\":{pid},\"python_stacktrace\":[{{\"func\":\"work\",\"file\":\"a.py\",\"line\":1}}],\"python_thread\":{{\"name\":\"MainThread\",\"owns_gil\":{gil},\"active\":true}}}}]}}\n"
+        )
+    };
+    fs::write(
+        &log_path,
+        entry("2025-06-14T00:00:00Z", true) + &entry("2025-06-14T00:00:01Z", false),
+    )
+    .unwrap();
+
+    let outdir = tempdir().expect("outdir");
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "report",
+            log_path.to_str().unwrap(),
+            "-o",
+            outdir.path().to_str().unwrap(),
+        ])
+        .output()
+        .expect("run report");
+    assert!(out.status.success());
+    let html = fs::read_to_string(outdir.path().join("index.html")).unwrap();
+    assert!(html.contains("Python threads"), "{}", html);
+    assert!(
+        html.contains(&format!(
+            "<td>{pid}</td><td>MainThread</td><td>2</td><td>50.0</td><td>100.0</td>"
+        )),
+        "{}",
+        html
+    );
+    let trace = fs::read_to_string(outdir.path().join(format!("{pid}_trace.json"))).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&trace).unwrap();
+    let named = json["traceEvents"].as_array().unwrap().iter().any(|e| {
+        e["ph"] == "M"
+            && e["tid"].as_u64() == Some(((pid as u64) << 1) | 1)
+            && e["args"]["name"] == "MainThread (python)"
+    });
+    assert!(named, "{}", trace);
+}
+
+#[test]
+fn speedscope_profile_written_on_request() {
+    let dir = tempdir().expect("dir");
+    let pid = 4444;
+    let log_path = dir.path().join(format!("{pid}.jsonl"));
+    write_stack_log(&log_path, pid);
+
+    let outdir = tempdir().expect("outdir");
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "report",
+            log_path.to_str().unwrap(),
+            "-o",
+            outdir.path().to_str().unwrap(),
+            "--speedscope",
+        ])
+        .output()
+        .expect("run report");
+    assert!(out.status.success());
+    let path = outdir.path().join(format!("{pid}.speedscope.json"));
+    let file: serde_json::Value = serde_json::from_slice(&fs::read(path).unwrap()).unwrap();
+    let names: Vec<&str> = file["shared"]["frames"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|f| f["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["main", "compute", "idle"]);
+    let profile = &file["profiles"][0];
+    assert_eq!(profile["type"], "sampled");
+    assert_eq!(
+        profile["samples"],
+        serde_json::json!([[0, 1], [0, 2], [0, 1]])
+    );
+    assert_eq!(profile["weights"], serde_json::json!([3000, 1000, 1000]));
+    assert_eq!(profile["endValue"], 5000);
+    let html = fs::read_to_string(outdir.path().join("index.html")).unwrap();
+    assert!(html.contains(&format!("{pid}.speedscope.json")), "{}", html);
+}
+
+#[test]
+fn baseline_comparison_by_process_name() {
+    let log = |dir: &std::path::Path, pid: u32, name: &str, cpu: f64, rss: u64| {
+        // The baseline ran the day before.
+        let day = if pid < 3 { 13 } else { 14 };
+        let entry = |ts: &str| {
+            format!(
+                "{{\"version\":1,\"timestamp\":\"2025-06-{day}T{ts}Z\",\"pid\":{pid},\"process_name\":\"{name}\",\"cpu_time_percent\":{cpu},\"memory\":{{\"rss_kb\":{rss},\"vsz_kb\":0,\"swap_kb\":0}}}}\n"
+            )
+        };
+        let data = entry("00:00:00") + &entry("00:00:10");
+        fs::write(dir.join(format!("{pid}.jsonl")), data).unwrap();
+    };
+    let baseline = tempdir().expect("baseline");
+    log(baseline.path(), 1, "app", 50.0, 1000);
+    log(baseline.path(), 2, "old", 10.0, 500);
+    let current = tempdir().expect("current");
+    log(current.path(), 3, "app", 100.0, 3000);
+    log(current.path(), 4, "fresh", 20.0, 700);
+
+    let outdir = tempdir().expect("outdir");
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "report",
+            "--baseline",
+            baseline.path().to_str().unwrap(),
+            current.path().to_str().unwrap(),
+            // Selects the part of the current run only.
+            "--from",
+            "2025-06-14T00:00:00Z",
+            "-o",
+            outdir.path().to_str().unwrap(),
+        ])
+        .output()
+        .expect("run report");
+    assert!(out.status.success());
+    let html = fs::read_to_string(outdir.path().join("index.html")).unwrap();
+    assert!(
+        html.contains("<tr><td>app</td><td>1 &rarr; 1</td><td>5.0 &rarr; 10.0</td><td>+5.0</td><td>1000 &rarr; 3000</td><td>+2000</td>"),
+        "{}",
+        html
+    );
+    assert!(
+        html.contains("<tr class=\"new\"><td>fresh</td>"),
+        "{}",
+        html
+    );
+    assert!(
+        html.contains("<tr class=\"removed\"><td>old</td>"),
+        "{}",
+        html
+    );
+    // Sorted by the size of the CPU time change.
+    assert!(html.find(">app<").unwrap() < html.find(">fresh<").unwrap());
+}
+
+#[test]
+fn group_by_aggregates_identical_workers() {
+    let dir = tempdir().expect("dir");
+    let log = |pid: u32, name: &str, cmd: &str, rss: u64| {
+        let entry = |ts: &str, extra: &str| {
+            format!(
+                "{{\"version\":1,\"timestamp\":\"{ts}\",\"pid\":{pid},\"process_name\":\"{name}\",\"cpu_time_percent\":10.0,\"memory\":{{\"rss_kb\":{rss},\"vsz_kb\":0,\"swap_kb\":0}}{extra}}}\n"
+            )
+        };
+        let cmdline = format!(",\"cmdline\":\"{cmd}\"");
+        let data = entry("2025-06-14T00:00:00Z", &cmdline) + &entry("2025-06-14T00:00:10Z", "");
+        fs::write(dir.path().join(format!("{pid}.jsonl")), data).unwrap();
+    };
+    log(101, "worker", "worker --id 1", 1000);
+    log(102, "worker", "worker --id 2", 2000);
+    log(103, "worker", "worker --id 13", 3000);
+    log(200, "nginx", "nginx", 500);
+
+    let report = |by: &str| {
+        let outdir = tempdir().expect("outdir");
+        let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+            .args([
+                "report",
+                dir.path().to_str().unwrap(),
+                "-o",
+                outdir.path().to_str().unwrap(),
+                "--group-by",
+                by,
+            ])
+            .output()
+            .expect("run report");
+        assert!(out.status.success());
+        fs::read_to_string(outdir.path().join("index.html")).unwrap()
+    };
+    let html = report("name");
+    assert!(html.contains("<summary>worker</summary>"), "{}", html);
+    assert!(
+        html.contains("</details></td><td>3</td><td>30</td><td>3.0</td><td>6000</td></tr>"),
+        "{}",
+        html
+    );
+    assert!(html.contains("<a href=\"102.html\">102</a>"), "{}", html);
+    assert!(html.find(">worker<").unwrap() < html.find(">nginx<").unwrap());
+    let html = report("cmdline");
+    assert!(
+        html.contains("<summary>worker --id N</summary>"),
+        "{}",
+        html
+    );
+}
+
+#[test]
+fn fd_events_listed_and_traced() {
+    let dir = tempdir().expect("dir");
+    let pid = 4646;
+    let log_path = dir.path().join(format!("{pid}.jsonl"));
+    fs::write(
+        &log_path,
+        format!(
+            "{{\"timestamp\":\"2025-06-14T00:00:00Z\",\"pid\":{pid},\"process_name\":\"a\",\"cpu_time_percent\":0.0,\"memory\":{{\"rss_kb\":1000,\"vsz_kb\":0,\"swap_kb\":0}},\"fd_events\":[{{\"fd\":3,\"event\":\"open\",\"path\":\"/tmp/<data>\"}}]}}\n{{\"timestamp\":\"2025-06-14T00:00:01Z\",\"pid\":{pid},\"process_name\":\"a\",\"cpu_time_percent\":0.0,\"memory\":{{\"rss_kb\":1000,\"vsz_kb\":0,\"swap_kb\":0}},\"fd_events\":[{{\"fd\":3,\"event\":\"close\",\"path\":\"/tmp/<data>\"}}]}}\n"
+        ),
+    )
+    .unwrap();
+    let outdir = tempdir().expect("outdir");
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "report",
+            log_path.to_str().unwrap(),
+            "-o",
+            outdir.path().to_str().unwrap(),
+        ])
+        .output()
+        .expect("run report");
+    assert!(out.status.success());
+    let html = fs::read_to_string(outdir.path().join("index.html")).unwrap();
+    let open = html
+        .find("<td>3</td><td>open</td><td>/tmp/&lt;data&gt;</td>")
+        .expect(&html);
+    let close = html
+        .find("<td>3</td><td>close</td><td>/tmp/&lt;data&gt;</td>")
+        .expect(&html);
+    assert!(open < close, "{}", html);
+
+    let trace: serde_json::Value =
+        serde_json::from_slice(&fs::read(outdir.path().join(format!("{pid}_trace.json"))).unwrap())
+            .unwrap();
+    let instants: Vec<&serde_json::Value> = trace["traceEvents"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|e| e["ph"] == "i")
+        .collect();
+    let names: Vec<&str> = instants
+        .iter()
+        .map(|e| e["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["open /tmp/<data>", "close /tmp/<data>"]);
+    assert_eq!(instants[0]["cat"], "fd");
+    assert_eq!(instants[0]["args"]["path"], "/tmp/<data>");
+    assert_eq!(instants[1]["args"]["event"], "close");
+}
+
+#[test]
+fn threads_ranked_by_cpu() {
+    let dir = tempdir().expect("dir");
+    let pid = 4747;
+    let log_path = dir.path().join(format!("{pid}.jsonl"));
+    let thread = |tid: u32, cpu: f64, func: &str| {
+        let name = if tid == pid {
+            ""
+        } else {
+            ",\"name\":\"hasher\""
+        };
+        format!(
+            "{{\"tid\":{tid}{name},\"cpu_time_percent\":{cpu},\"stacktrace\":[{{\"func\":\"{func}\"}},{{\"func\":\"main\"}}]}}"
+        )
+    };
+    let entry = |ts: &str, threads: &[String]| {
+        format!(
+            "{{\"version\":1,\"timestamp\":\"{ts}\",\"pid\":{pid},\"process_name\":\"a\",\"cpu_time_percent\":100.0,\"memory\":{{\"rss_kb\":1000,\"vsz_kb\":0,\"swap_kb\":0}},\"threads\":[{}]}}\n",
+            threads.join(",")
+        )
+    };
+    let log = [
+        entry(
+            "2025-06-14T00:00:00Z",
+            &[thread(pid, 10.0, "poll"), thread(4748, 90.0, "hash")],
+        ),
+        entry(
+            "2025-06-14T00:00:10Z",
+            &[thread(pid, 30.0, "poll"), thread(4748, 70.0, "sort")],
+        ),
+        entry(
+            "2025-06-14T00:00:20Z",
+            &[thread(pid, 20.0, "poll"), thread(4748, 80.0, "hash")],
+        ),
+    ]
+    .concat();
+    fs::write(&log_path, log).unwrap();
+
+    let outdir = tempdir().expect("outdir");
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "report",
+            log_path.to_str().unwrap(),
+            "-o",
+            outdir.path().to_str().unwrap(),
+        ])
+        .output()
+        .expect("run report");
+    assert!(out.status.success());
+    let html = fs::read_to_string(outdir.path().join("index.html")).unwrap();
+    let busy = html
+        .find("<tr><td>4748</td><td>hasher</td><td>24.0</td><td>90.0</td><td>hash &lt; main</td></tr>")
+        .expect(&html);
+    let idle = html
+        .find(&format!(
+            "<tr><td>{pid}</td><td></td><td>6.0</td><td>30.0</td><td>poll &lt; main</td></tr>"
+        ))
+        .expect(&html);
+    assert!(busy < idle, "{}", html);
+    assert!(outdir.path().join(format!("{pid}_threads.svg")).exists());
+    assert!(html.contains(&format!("{pid}_threads.svg")), "{}", html);
+}
+
+#[test]
+fn lifetimes_drawn_on_index() {
+    let dir = tempdir().expect("dir");
+    let log = |pid: u32, from: &str, to: &str| {
+        let entry = |ts: &str| {
+            format!(
+                "{{\"version\":1,\"timestamp\":\"2025-06-14T00:00:{ts}Z\",\"pid\":{pid},\"process_name\":\"step\",\"cmdline\":\"/bin/step{pid}\",\"cpu_time_percent\":10.0,\"memory\":{{\"rss_kb\":1000,\"vsz_kb\":0,\"swap_kb\":0}}}}\n"
+            )
+        };
+        fs::write(
+            dir.path().join(format!("{pid}.jsonl")),
+            entry(from) + &entry(to),
+        )
+        .unwrap();
+    };
+    log(303, "20", "30");
+    log(301, "00", "10");
+    log(302, "05", "15");
+
+    let outdir = tempdir().expect("outdir");
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "report",
+            dir.path().to_str().unwrap(),
+            "-o",
+            outdir.path().to_str().unwrap(),
+        ])
+        .output()
+        .expect("run report");
+    assert!(out.status.success());
+    let html = fs::read_to_string(outdir.path().join("index.html")).unwrap();
+    assert!(html.contains("<svg id=\"lifetimes\""), "{}", html);
+    let bars: Vec<usize> = [
+        "<rect x=\"160.0\" y=\"2\" width=\"213.3\"",
+        "<rect x=\"266.7\" y=\"20\" width=\"213.3\"",
+        "<rect x=\"586.7\" y=\"38\" width=\"213.3\"",
+    ]
+    .iter()
+    .map(|bar| html.find(bar).expect(&html))
+    .collect();
+    assert!(bars.is_sorted(), "{}", html);
+    assert!(
+        html.contains("<a href=\"301.html\"><text x=\"4\" y=\"13\">301 step301</text></a>"),
+        "{}",
+        html
+    );
+}
+
+#[test]
+fn process_tree_rolls_up_children() {
+    let dir = tempdir().expect("dir");
+    let log = |pid: u32, ppid: u32, cmd: &str| {
+        let entry = |ts: &str, extra: &str| {
+            format!(
+                "{{\"version\":1,\"timestamp\":\"{ts}\",\"pid\":{pid},\"process_name\":\"p\",\"cpu_time_percent\":10.0,\"memory\":{{\"rss_kb\":1000,\"vsz_kb\":0,\"swap_kb\":0}}{extra}}}\n"
+            )
+        };
+        let meta = format!(",\"ppid\":{ppid},\"cmdline\":\"{cmd}\"");
+        let data = entry("2025-06-14T00:00:00Z", &meta) + &entry("2025-06-14T00:00:10Z", "");
+        fs::write(dir.path().join(format!("{pid}.jsonl")), data).unwrap();
+    };
+    log(100, 1, "make");
+    log(101, 100, "cc a.c");
+    log(102, 100, "ld");
+    log(103, 101, "cc1 a.c");
+
+    let outdir = tempdir().expect("outdir");
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "report",
+            dir.path().to_str().unwrap(),
+            "-o",
+            outdir.path().to_str().unwrap(),
+        ])
+        .output()
+        .expect("run report");
+    assert!(out.status.success());
+    let html = fs::read_to_string(outdir.path().join("index.html")).unwrap();
+    let tree = &html[html.find("<h2>Process tree</h2>").expect(&html)..];
+    assert!(
+        tree.contains("<a href=\"100.html\">100</a> make: CPU 4.0 s, RSS 4000 KB (4 processes)"),
+        "{}",
+        tree
+    );
+    assert!(
+        tree.contains("<a href=\"101.html\">101</a> cc a.c: CPU 2.0 s, RSS 2000 KB (2 processes)"),
+        "{}",
+        tree
+    );
+    assert!(
+        tree.contains("<li><a href=\"103.html\">103</a> cc1 a.c: CPU 1.0 s, RSS 1000 KB</li>"),
+        "{}",
+        tree
+    );
+    // The larger subtree comes first.
+    assert!(tree.find(">101<").unwrap() < tree.find(">102<").unwrap());
+
+    let page = fs::read_to_string(outdir.path().join("101.html")).unwrap();
+    assert!(
+        page.contains("<p>Parent: <a href=\"100.html\">100</a> make</p>"),
+        "{}",
+        page
+    );
+    let children = &page[page.find("<h2>Children</h2>").expect(&page)..];
+    assert!(
+        children.contains("<td><a href=\"103.html\">103</a></td>"),
+        "{}",
+        children
+    );
+    let page = fs::read_to_string(outdir.path().join("100.html")).unwrap();
+    assert!(page.contains("<p>Parent: 1</p>"), "{}", page);
+    let children = &page[page.find("<h2>Children</h2>").expect(&page)..];
+    assert!(children.find(">101<").unwrap() < children.find(">102<").unwrap());
+    assert!(!children.contains(">103<"), "{}", children);
+}
+
+#[test]
+fn steadily_growing_rss_listed_as_leak() {
+    let dir = tempdir().expect("dir");
+    let log = |pid: u32, rss: &[u64]| {
+        let data: String = rss
+            .iter()
+            .enumerate()
+            .map(|(i, rss)| {
+                format!(
+                    "{{\"version\":1,\"timestamp\":\"2025-06-14T00:{i:02}:00Z\",\"pid\":{pid},\"process_name\":\"w\",\"cmdline\":\"worker {pid}\",\"cpu_time_percent\":{},\"memory\":{{\"rss_kb\":{rss},\"vsz_kb\":0,\"swap_kb\":0}}}}\n",
+                    if pid == 500 { 50.0 } else { 1.0 }
+                )
+            })
+            .collect();
+        fs::write(dir.path().join(format!("{pid}.jsonl")), data).unwrap();
+    };
+    log(500, &[90000; 6]);
+    log(501, &[1000, 3000, 5000, 5000, 7000, 9000]);
+    log(502, &[1000, 1100, 1200, 1300, 1400, 1500]);
+    log(503, &[1000, 9000, 1000, 9000, 1000, 9000]);
+    let config = dir.path().join("fuzmon.toml");
+    fs::write(&config, "[report]\ntop_cpu = 1\ntop_rss = 1\n").unwrap();
+
+    let outdir = tempdir().expect("outdir");
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "report",
+            dir.path().to_str().unwrap(),
+            "-o",
+            outdir.path().to_str().unwrap(),
+            "-c",
+            config.to_str().unwrap(),
+        ])
+        .output()
+        .expect("run report");
+    assert!(out.status.success());
+    let html = fs::read_to_string(outdir.path().join("index.html")).unwrap();
+    let leaks = html.find("<h2>Possible leaks</h2>").expect(&html);
+    assert!(leaks < html.find("top_cpu.svg").unwrap(), "{}", html);
+    let table = &html[leaks..html[leaks..].find("</table>").unwrap() + leaks];
+    assert!(
+        table.contains("<tr><td><a href=\"501.html\">501</a></td><td>worker 501</td><td>1485.7</td><td>9000</td><td>300</td></tr>"),
+        "{}",
+        table
+    );
+    assert!(!table.contains(">502<"), "{}", table);
+    assert!(!table.contains(">503<"), "{}", table);
+    assert!(!table.contains(">500<"), "{}", table);
+    assert!(outdir.path().join("501.html").exists());
+}
+
+#[test]
+fn cpu_spikes_listed_with_nearest_stack() {
+    let dir = tempdir().expect("dir");
+    let pid = 4848;
+    let log_path = dir.path().join(format!("{pid}.jsonl"));
+    let cpu = [5.0, 5.0, 95.0, 97.0, 99.0, 95.0, 5.0, 5.0, 95.0, 5.0];
+    let log: String = cpu
+        .iter()
+        .enumerate()
+        .map(|(i, cpu)| {
+            let threads = if i == 1 || i == 4 {
+                format!(
+                    ",\"threads\":[{{\"tid\":{pid},\"stacktrace\":[{{\"func\":\"spin{i}\"}},{{\"func\":\"main\"}}]}}]"
+                )
+            } else {
+                String::new()
+            };
+            format!(
+                "{{\"version\":1,\"timestamp\":\"2025-06-14T00:00:{:02}Z\",\"pid\":{pid},\"process_name\":\"a\",\"cpu_time_percent\":{cpu},\"memory\":{{\"rss_kb\":1000,\"vsz_kb\":0,\"swap_kb\":0}}{threads}}}\n",
+                i * 5
+            )
+        })
+        .collect();
+    fs::write(&log_path, log).unwrap();
+    let config = dir.path().join("fuzmon.toml");
+    fs::write(
+        &config,
+        "[report]\nspike_cpu_percent = 90\nspike_min_sec = 15\n",
+    )
+    .unwrap();
+
+    let outdir = tempdir().expect("outdir");
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "report",
+            log_path.to_str().unwrap(),
+            "-o",
+            outdir.path().to_str().unwrap(),
+            "-c",
+            config.to_str().unwrap(),
+        ])
+        .output()
+        .expect("run report");
+    assert!(out.status.success());
+    let html = fs::read_to_string(outdir.path().join("index.html")).unwrap();
+    let spikes = &html[html.find("<h2>CPU spikes</h2>").expect(&html)..];
+    let table = &spikes[..spikes.find("</table>").unwrap()];
+    // Samples at 10s to 25s stay above 90%, up to the next sample at 30s;
+    // the single sample at 40s is too short.
+    assert_eq!(table.matches("<tr><td>").count(), 1, "{}", table);
+    assert!(
+        table.contains("<td>20</td><td>99.0</td><td><a href=\"#spike_stack_0\">"),
+        "{}",
+        table
+    );
+    assert!(
+        spikes.contains("<pre>thread 4848\n  spin4\n  main\n</pre>"),
+        "{}",
+        spikes
+    );
+    let start = 1749859210000i64;
+    assert!(
+        html.contains(&format!("\"bands\":[[{},{}]]", start, start + 20000)),
+        "{}",
+        html
+    );
+}
+
+#[test]
+fn summary_json_for_ci() {
+    let dir = tempdir().expect("dir");
+    let log = |pid: u32, cpu: f64, rss: &[u64]| {
+        let data: String = rss
+            .iter()
+            .enumerate()
+            .map(|(i, rss)| {
+                format!(
+                    "{{\"version\":1,\"timestamp\":\"2025-06-14T00:{i:02}:00Z\",\"pid\":{pid},\"process_name\":\"w|{pid}\",\"cpu_time_percent\":{cpu},\"memory\":{{\"rss_kb\":{rss},\"vsz_kb\":0,\"swap_kb\":0}}}}\n"
+                )
+            })
+            .collect();
+        fs::write(dir.path().join(format!("{pid}.jsonl")), data).unwrap();
+    };
+    log(601, 95.0, &[1000, 3000, 5000, 7000, 9000, 11000]);
+    log(602, 10.0, &[4000; 6]);
+
+    let outdir = tempdir().expect("outdir");
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "report",
+            dir.path().to_str().unwrap(),
+            "-o",
+            outdir.path().to_str().unwrap(),
+            "--summary-md",
+        ])
+        .output()
+        .expect("run report");
+    assert!(out.status.success());
+    let summary: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(outdir.path().join("summary.json")).unwrap())
+            .unwrap();
+    let processes = summary["processes"].as_array().unwrap();
+    assert_eq!(processes.len(), 2);
+    assert_eq!(processes[0]["pid"], 601);
+    assert_eq!(processes[0]["peak_rss_kb"], 11000);
+    assert_eq!(processes[0]["runtime_sec"], 300);
+    assert_eq!(processes[0]["start"], "2025-06-14T00:00:00.000Z");
+    assert_eq!(processes[1]["peak_rss_kb"], 4000);
+    assert!(processes[1]["rss_growth_kb_per_min"].is_null());
+    let leaks = summary["anomalies"]["possible_leaks"].as_array().unwrap();
+    assert_eq!(leaks.len(), 1);
+    assert_eq!(leaks[0]["rss_growth_kb_per_min"], 2000.0);
+    let spikes = summary["anomalies"]["cpu_spikes"].as_array().unwrap();
+    assert_eq!(spikes.len(), 1);
+    assert_eq!(spikes[0]["pid"], 601);
+    assert_eq!(spikes[0]["duration_sec"], 300);
+
+    let csv = fs::read_to_string(outdir.path().join("summary.csv")).unwrap();
+    let mut lines = csv.lines();
+    assert_eq!(
+        lines.next(),
+        Some("pid,cmd,runtime_sec,cpu_time_sec,avg_cpu_percent,peak_rss_kb,start,end")
+    );
+    assert_eq!(
+        lines.next(),
+        Some(
+            "601,(unknown),300,285.0,95.0,11000,2025-06-14T00:00:00.000Z,2025-06-14T00:05:00.000Z"
+        )
+    );
+    assert_eq!(lines.count(), 1);
+
+    let md = fs::read_to_string(outdir.path().join("summary.md")).unwrap();
+    assert!(
+        md.contains("| 601 | w\\|601 | 300 | 285.0 | 95.0 | 11000 |"),
+        "{}",
+        md
+    );
+    assert!(
+        md.contains("- PID 601 (w\\|601): RSS grows by 2000.0 KB/min"),
+        "{}",
+        md
+    );
+    assert!(
+        md.contains("- PID 601 (w\\|601): 2025-06-14T00:00:00.000Z for 300 sec, peak 95.0%"),
+        "{}",
+        md
+    );
+}
+
+#[test]
+fn report_filters_processes_and_time() {
+    let dir = tempdir().expect("dir");
+    for (pid, name) in [(701, "nginx"), (702, "worker"), (703, "worker2")] {
+        let data: String = (0..=10)
+            .map(|i| {
+                format!(
+                    "{{\"version\":1,\"timestamp\":\"2025-06-14T00:{i:02}:00Z\",\"pid\":{pid},\"process_name\":\"{name}\",\"cpu_time_percent\":1.0,\"memory\":{{\"rss_kb\":1000,\"vsz_kb\":0,\"swap_kb\":0}}}}\n"
+                )
+            })
+            .collect();
+        fs::write(dir.path().join(format!("{pid}.jsonl")), data).unwrap();
+    }
+    let report = |args: &[&str]| {
+        let outdir = tempdir().expect("outdir");
+        let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+            .args([
+                "report",
+                dir.path().to_str().unwrap(),
+                "-o",
+                outdir.path().to_str().unwrap(),
+            ])
+            .args(args)
+            .output()
+            .expect("run report");
+        assert!(out.status.success());
+        let summary: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(outdir.path().join("summary.json")).unwrap())
+                .unwrap();
+        summary["processes"].as_array().unwrap().clone()
+    };
+    let pids = |processes: &[serde_json::Value]| -> Vec<u64> {
+        processes
+            .iter()
+            .map(|p| p["pid"].as_u64().unwrap())
+            .collect()
+    };
+    assert_eq!(pids(&report(&["--name", "^worker"])), [702, 703]);
+    assert_eq!(pids(&report(&["--pid", "701,703"])), [701, 703]);
+    assert_eq!(
+        pids(&report(&["--pid", "702", "--name", "nginx"])),
+        [] as [u64; 0]
+    );
+    let window = report(&[
+        "--pid",
+        "701",
+        "--since",
+        "2025-06-14T00:03:00Z",
+        "--until",
+        "2025-06-14T00:05:00Z",
+    ]);
+    assert_eq!(window[0]["start"], "2025-06-14T00:03:00.000Z");
+    assert_eq!(window[0]["end"], "2025-06-14T00:05:00.000Z");
+
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args(["report", dir.path().to_str().unwrap(), "--name", "("])
+        .output()
+        .expect("run report");
+    assert!(!out.status.success());
+}
+
+#[test]
+fn top_flags_override_config() {
+    let dir = tempdir().expect("dir");
+    for (pid, cpu) in [(801, 30.0), (802, 20.0), (803, 10.0)] {
+        fs::write(
+            dir.path().join(format!("{pid}.jsonl")),
+            format!(
+                "{{\"timestamp\":\"2025-06-14T00:00:00Z\",\"pid\":{pid},\"process_name\":\"a\",\"cpu_time_percent\":{cpu},\"memory\":{{\"rss_kb\":1000,\"vsz_kb\":0,\"swap_kb\":0}}}}\n{{\"timestamp\":\"2025-06-14T00:00:10Z\",\"pid\":{pid},\"process_name\":\"a\",\"cpu_time_percent\":{cpu},\"memory\":{{\"rss_kb\":1000,\"vsz_kb\":0,\"swap_kb\":0}}}}\n"
+            ),
+        )
+        .unwrap();
+    }
+    let cfg = NamedTempFile::new().expect("cfg");
+    fs::write(cfg.path(), "[report]\ntop_cpu=1\ntop_rss=1\n").unwrap();
+
+    let outdir = tempdir().expect("outdir");
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "report",
+            dir.path().to_str().unwrap(),
+            "-c",
+            cfg.path().to_str().unwrap(),
+            "-o",
+            outdir.path().to_str().unwrap(),
+            "--top-cpu",
+            "2",
+            "--top-rss",
+            "0",
+        ])
+        .output()
+        .expect("run report");
+    assert!(out.status.success());
+    assert!(outdir.path().join("801.html").exists());
+    assert!(outdir.path().join("802.html").exists());
+    assert!(!outdir.path().join("803.html").exists());
+}
+
+#[test]
+fn dark_theme_recolors_pages_and_graphs() {
+    let dir = tempdir().expect("dir");
+    let pid = 4949;
+    let log_path = dir.path().join(format!("{pid}.jsonl"));
+    fs::write(
+        &log_path,
+        format!(
+            "{{\"timestamp\":\"2025-06-14T00:00:00Z\",\"pid\":{pid},\"process_name\":\"a\",\"cpu_time_percent\":10.0,\"memory\":{{\"rss_kb\":1000,\"vsz_kb\":0,\"swap_kb\":0}}}}\n{{\"timestamp\":\"2025-06-14T00:00:10Z\",\"pid\":{pid},\"process_name\":\"a\",\"cpu_time_percent\":10.0,\"memory\":{{\"rss_kb\":1000,\"vsz_kb\":0,\"swap_kb\":0}}}}\n"
+        ),
+    )
+    .unwrap();
+    let cfg = NamedTempFile::new().expect("cfg");
+    fs::write(cfg.path(), "[report]\ntheme = \"light\"\n").unwrap();
+    let report = |args: &[&str]| {
+        let outdir = tempdir().expect("outdir");
+        let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+            .args([
+                "report",
+                dir.path().to_str().unwrap(),
+                "-o",
+                outdir.path().to_str().unwrap(),
+                "-c",
+                cfg.path().to_str().unwrap(),
+            ])
+            .args(args)
+            .output()
+            .expect("run report");
+        assert!(out.status.success());
+        let read = |name: &str| fs::read_to_string(outdir.path().join(name)).unwrap();
+        (
+            read("index.html"),
+            read(&format!("{pid}.html")),
+            read("top_cpu.svg"),
+        )
+    };
+    let (index, page, svg) = report(&["--theme", "dark"]);
+    assert!(index.contains("body{background:#1e1e1e;"), "{}", index);
+    assert!(page.contains("body{background:#1e1e1e;"), "{}", page);
+    assert!(svg.contains("fill=\"#1E1E1E\""), "{}", svg);
+    assert!(svg.contains("fill=\"#DDDDDD\""), "{}", svg);
+    let (index, _, svg) = report(&[]);
+    assert!(index.contains("body{background:#fff;"), "{}", index);
+    assert!(!svg.contains("#1E1E1E"), "{}", svg);
+}
+
+#[test]
+fn rerun_only_rereads_changed_logs() {
+    let dir = tempdir().expect("dir");
+    let entry = |pid: u32, ts: &str| {
+        format!(
+            "{{\"timestamp\":\"2025-06-14T00:00:{ts}Z\",\"pid\":{pid},\"process_name\":\"a\",\"cpu_time_percent\":10.0,\"memory\":{{\"rss_kb\":1000,\"vsz_kb\":0,\"swap_kb\":0}}}}\n"
+        )
+    };
+    let growing = dir.path().join("901.jsonl");
+    fs::write(&growing, entry(901, "00") + &entry(901, "10")).unwrap();
+    fs::write(
+        dir.path().join("902.jsonl"),
+        entry(902, "00") + &entry(902, "10"),
+    )
+    .unwrap();
+    let outdir = tempdir().expect("outdir");
+    let report = |args: &[&str]| {
+        let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+            .args([
+                "report",
+                dir.path().to_str().unwrap(),
+                "-o",
+                outdir.path().to_str().unwrap(),
+            ])
+            .args(args)
+            .output()
+            .expect("run report");
+        assert!(out.status.success());
+    };
+    report(&[]);
+    assert!(outdir.path().join(".report-cache.json").exists());
+
+    // Pages of unchanged logs are kept as they are.
+    let page = outdir.path().join("902.html");
+    fs::write(&page, "kept").unwrap();
+    fs::write(
+        &growing,
+        entry(901, "00") + &entry(901, "10") + &entry(901, "40"),
+    )
+    .unwrap();
+    report(&[]);
+    assert_eq!(fs::read_to_string(&page).unwrap(), "kept");
+    let html = fs::read_to_string(outdir.path().join("901.html")).unwrap();
+    assert!(html.contains("Total runtime: 40 sec"), "{}", html);
+    let index = fs::read_to_string(outdir.path().join("index.html")).unwrap();
+    assert!(index.contains("<td>40</td>"), "{}", index);
+
+    // Other settings render everything again.
+    report(&["--theme", "dark"]);
+    assert_ne!(fs::read_to_string(&page).unwrap(), "kept");
+}
+
+#[test]
+fn combined_trace_covers_all_processes() {
+    use std::collections::HashSet;
+
+    let dir = tempdir().expect("dir");
+    for pid in [911, 912] {
+        write_stack_log(&dir.path().join(format!("{pid}.jsonl")), pid);
+    }
+    let outdir = tempdir().expect("outdir");
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "report",
+            dir.path().to_str().unwrap(),
+            "-o",
+            outdir.path().to_str().unwrap(),
+            "--combined-trace",
+        ])
+        .output()
+        .expect("run report");
+    assert!(out.status.success());
+    let html = fs::read_to_string(outdir.path().join("index.html")).unwrap();
+    assert!(html.contains("<a href=\"trace.json\">"), "{}", html);
+    let trace = fs::read_to_string(outdir.path().join("trace.json")).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&trace).unwrap();
+    let pids: HashSet<u64> = json["traceEvents"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|e| e["ph"] == "X" && e["name"] == "main")
+        .map(|e| e["pid"].as_u64().unwrap())
+        .collect();
+    assert_eq!(pids, HashSet::from([911, 912]), "{}", trace);
+}
+
+#[test]
+fn trend_lists_daily_totals_per_name() {
+    let dir = tempdir().expect("dir");
+    let log = |day: &str, pid: u32, name: &str, cpu: f64, rss: u64| {
+        let entry = |ts: &str| {
+            format!(
+                "{{\"timestamp\":\"{ts}\",\"pid\":{pid},\"process_name\":\"{name}\",\"cpu_time_percent\":{cpu},\"memory\":{{\"rss_kb\":{rss},\"vsz_kb\":0,\"swap_kb\":0}}}}\n"
+            )
+        };
+        let date = format!("{}-{}-{}", &day[..4], &day[4..6], &day[6..]);
+        let path = dir.path().join(day);
+        fs::create_dir_all(&path).unwrap();
+        fs::write(
+            path.join(format!("{pid}.jsonl")),
+            entry(&format!("{date}T00:00:00Z")) + &entry(&format!("{date}T00:00:10Z")),
+        )
+        .unwrap();
+    };
+    log("20250101", 1, "web", 50.0, 1000);
+    log("20250101", 2, "web", 50.0, 3000);
+    log("20250101", 3, "cron", 10.0, 500);
+    log("20250102", 4, "web", 100.0, 2000);
+    let outdir = tempdir().expect("outdir");
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "report",
+            dir.path().to_str().unwrap(),
+            "-o",
+            outdir.path().to_str().unwrap(),
+            "--trend",
+        ])
+        .output()
+        .expect("run report");
+    assert!(out.status.success());
+    let html = fs::read_to_string(outdir.path().join("index.html")).unwrap();
+    assert!(
+        html.contains("<th>20250101</th><th>20250102</th>"),
+        "{}",
+        html
+    );
+    // CPU seconds are summed per day, the peak RSS is the largest.
+    assert!(
+        html.contains("<tr><td>web</td><td>10.0</td><td>10.0</td></tr>"),
+        "{}",
+        html
+    );
+    assert!(
+        html.contains("<tr><td>cron</td><td>1.0</td><td></td></tr>"),
+        "{}",
+        html
+    );
+    assert!(
+        html.contains("<tr><td>web</td><td>3000</td><td>2000</td></tr>"),
+        "{}",
+        html
+    );
+    assert!(html.contains("\"dates\":true"), "{}", html);
+}
+
+#[test]
+fn secret_env_values_masked_on_page() {
+    let dir = tempdir().expect("dir");
+    let log_path = dir.path().join("951.jsonl");
+    fs::write(
+        &log_path,
+        "{\"timestamp\":\"2025-06-14T00:00:00Z\",\"pid\":951,\"process_name\":\"a\",\"cpu_time_percent\":0.0,\"memory\":{\"rss_kb\":1,\"vsz_kb\":0,\"swap_kb\":0},\"env\":\"HOME=/root\\nAWS_SECRET_ACCESS_KEY=abc123\\nMY_VAR=visible\"}\n",
+    )
+    .unwrap();
+    let config = dir.path().join("fuzmon.toml");
+    fs::write(&config, "[output]\nredact_env = [\"^my_\"]\n").unwrap();
+    let report = |args: &[&str]| {
+        let outdir = tempdir().expect("outdir");
+        let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+            .args([
+                "report",
+                log_path.to_str().unwrap(),
+                "-o",
+                outdir.path().to_str().unwrap(),
+            ])
+            .args(args)
+            .output()
+            .expect("run report");
+        assert!(out.status.success());
+        fs::read_to_string(outdir.path().join("index.html")).unwrap()
+    };
+    let html = report(&[]);
+    assert!(html.contains("AWS_SECRET_ACCESS_KEY=***"), "{}", html);
+    assert!(!html.contains("abc123"), "{}", html);
+    assert!(html.contains("MY_VAR=visible"), "{}", html);
+
+    let html = report(&["-c", config.to_str().unwrap()]);
+    assert!(html.contains("MY_VAR=***"), "{}", html);
+    assert!(html.contains("AWS_SECRET_ACCESS_KEY=abc123"), "{}", html);
+}
+
+#[test]
+fn graph_size_and_dpi_from_config() {
+    let dir = tempdir().expect("dir");
+    let pid = 961;
+    let log_path = dir.path().join(format!("{pid}.jsonl"));
+    write_stack_log(&log_path, pid);
+    let config = dir.path().join("fuzmon.toml");
+    fs::write(
+        &config,
+        "[report]\ngraph_width = 1200\ngraph_height = 500\ngraph_dpi = 192\ngraph_x_labels = 10\n",
+    )
+    .unwrap();
+    let outdir = tempdir().expect("outdir");
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "report",
+            log_path.to_str().unwrap(),
+            "-o",
+            outdir.path().to_str().unwrap(),
+            "-c",
+            config.to_str().unwrap(),
+        ])
+        .output()
+        .expect("run report");
+    assert!(out.status.success());
+    for name in ["cpu", "rss"] {
+        let svg = fs::read_to_string(outdir.path().join(format!("{pid}_{name}.svg"))).unwrap();
+        assert!(
+            svg.contains("width=\"2400\" height=\"1000\""),
+            "{}",
+            &svg[..200]
+        );
+    }
+}
+
+#[test]
+fn timezone_option_labels_graphs_and_times() {
+    let dir = tempdir().expect("dir");
+    let pid = 962;
+    let log_path = dir.path().join(format!("{pid}.jsonl"));
+    write_stack_log(&log_path, pid);
+    let outdir = tempdir().expect("outdir");
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "report",
+            dir.path().to_str().unwrap(),
+            "-o",
+            outdir.path().to_str().unwrap(),
+            "--timezone",
+            "Asia/Tokyo",
+        ])
+        .env("TZ", "UTC")
+        .output()
+        .expect("run report");
+    assert!(out.status.success());
+    let index = fs::read_to_string(outdir.path().join("index.html")).unwrap();
+    assert!(index.contains("09:00:00 +09:00"), "{}", index);
+    assert!(!index.contains("00:00:00 +00:00"), "{}", index);
+    // Charts label times in the same zone in any browser.
+    assert!(index.contains("\"timezone\":\"Asia/Tokyo\""), "{}", index);
+    let svg = fs::read_to_string(outdir.path().join(format!("{pid}_cpu.svg"))).unwrap();
+    assert!(svg.contains("09:00:0"), "{}", svg);
+
+    // Without a zone name, the offset of local time is used.
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "report",
+            dir.path().to_str().unwrap(),
+            "-o",
+            outdir.path().to_str().unwrap(),
+        ])
+        .env("TZ", "Asia/Tokyo")
+        .output()
+        .expect("run report");
+    assert!(out.status.success());
+    let index = fs::read_to_string(outdir.path().join("index.html")).unwrap();
+    assert!(index.contains("\"utc_offset_min\":540"), "{}", index);
+}
+
+#[test]
+fn issues_page_lists_missing_data() {
+    let dir = tempdir().expect("dir");
+    let entry = |sec: u32| {
+        format!(
+            "{{\"version\":1,\"timestamp\":\"2025-06-14T00:00:{sec:02}Z\",\"pid\":963,\"process_name\":\"app\",\"cpu_time_percent\":1.0,\"memory\":{{\"rss_kb\":1000,\"vsz_kb\":0,\"swap_kb\":0}},\"threads\":[{{\"tid\":964,\"capture_error\":\"EPERM: Operation not permitted\"}}]}}\n"
+        )
+    };
+    let mut log: String = [0, 1, 2, 3, 4, 30].map(entry).concat();
+    log.push_str("not json\n");
+    fs::write(dir.path().join("963.jsonl"), log).unwrap();
+    fs::write(dir.path().join("964.msgpacks"), "garbage").unwrap();
+    let outdir = tempdir().expect("outdir");
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "report",
+            dir.path().to_str().unwrap(),
+            "-o",
+            outdir.path().to_str().unwrap(),
+        ])
+        .output()
+        .expect("run report");
+    assert!(out.status.success());
+    let index = fs::read_to_string(outdir.path().join("index.html")).unwrap();
+    assert!(
+        index.contains("<a href=\"issues.html\">Issues (4)</a>"),
+        "{}",
+        index
+    );
+    let issues = fs::read_to_string(outdir.path().join("issues.html")).unwrap();
+    assert!(issues.contains("Unreadable logs"), "{}", issues);
+    assert!(issues.contains("964.msgpacks"), "{}", issues);
+    assert!(issues.contains("963.jsonl</td><td>1</td>"), "{}", issues);
+    assert!(
+        issues.contains("EPERM: Operation not permitted</td><td>6</td>"),
+        "{}",
+        issues
+    );
+    assert!(issues.contains("<td>26.0</td>"), "{}", issues);
+}
+
+#[test]
+fn process_page_graphs_vsz_and_swap() {
+    let dir = tempdir().expect("dir");
+    let pid = 965;
+    let entry = |sec: u32, swap: u64| {
+        format!(
+            "{{\"version\":1,\"timestamp\":\"2025-06-14T00:00:{sec:02}Z\",\"pid\":{pid},\"process_name\":\"app\",\"cpu_time_percent\":1.0,\"memory\":{{\"rss_kb\":1000,\"vsz_kb\":4194304,\"swap_kb\":{swap}}}}}\n"
+        )
+    };
+    let log_path = dir.path().join(format!("{pid}.jsonl"));
+    fs::write(&log_path, entry(0, 0) + &entry(10, 2048)).unwrap();
+    let outdir = tempdir().expect("outdir");
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "report",
+            log_path.to_str().unwrap(),
+            "-o",
+            outdir.path().to_str().unwrap(),
+        ])
+        .output()
+        .expect("run report");
+    assert!(out.status.success());
+    let page = fs::read_to_string(outdir.path().join("index.html")).unwrap();
+    for name in ["rss", "vsz", "swap"] {
+        assert!(page.contains(&format!("{pid}_{name}.svg")), "{}", page);
+        assert!(page.contains(&format!("{pid}_{name}_chart")), "{}", page);
+    }
+    let vsz = fs::read_to_string(outdir.path().join(format!("{pid}_vsz.svg"))).unwrap();
+    assert!(vsz.contains("Virtual memory size (GB)"), "{}", vsz);
+    let swap = fs::read_to_string(outdir.path().join(format!("{pid}_swap.svg"))).unwrap();
+    assert!(swap.contains("Swap usage (MB)"), "{}", swap);
+}
+
+#[test]
+fn watch_regenerates_on_new_logs() {
+    use std::io::{BufRead, BufReader};
+
+    let dir = tempdir().expect("dir");
+    let logs = dir.path().join("logs");
+    let day = logs.join("20250614");
+    fs::create_dir_all(&day).unwrap();
+    write_stack_log(&day.join("966.jsonl"), 966);
+    let outdir = dir.path().join("out");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "report",
+            "--watch",
+            logs.to_str().unwrap(),
+            "-o",
+            outdir.to_str().unwrap(),
+        ])
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("run report");
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    lines.next().unwrap().unwrap();
+    let index = fs::read_to_string(outdir.join("index.html")).unwrap();
+    assert!(index.contains("966"), "{}", index);
+    assert!(!index.contains("967"), "{}", index);
+
+    // Moved in whole, so the report never sees it half written.
+    let incoming = dir.path().join("967.jsonl");
+    write_stack_log(&incoming, 967);
+    fs::rename(&incoming, day.join("967.jsonl")).unwrap();
+    lines.next().unwrap().unwrap();
+    let index = fs::read_to_string(outdir.join("index.html")).unwrap();
+    let _ = child.kill();
+    let _ = child.wait();
+    assert!(index.contains("967.html"), "{}", index);
+}
+
+#[test]
+fn pdf_printed_with_configured_browser() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempdir().expect("dir");
+    let logs = dir.path().join("logs");
+    fs::create_dir(&logs).unwrap();
+    write_stack_log(&logs.join("968.jsonl"), 968);
+    // Stands in for chromium, writing its arguments to the PDF.
+    let browser = dir.path().join("browser");
+    fs::write(
+        &browser,
+        "#!/bin/sh\nfor a; do case $a in --print-to-pdf=*) echo \"$@\" > \"${a#*=}\";; esac; done\n",
+    )
+    .unwrap();
+    fs::set_permissions(&browser, fs::Permissions::from_mode(0o755)).unwrap();
+    let config = dir.path().join("fuzmon.toml");
+    fs::write(
+        &config,
+        format!("[report]\npdf_browser = \"{}\"\n", browser.display()),
+    )
+    .unwrap();
+    let outdir = dir.path().join("out dir");
+    let report = || {
+        let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+            .args([
+                "report",
+                logs.to_str().unwrap(),
+                "-o",
+                outdir.to_str().unwrap(),
+                "-c",
+                config.to_str().unwrap(),
+                "--pdf",
+            ])
+            .output()
+            .expect("run report");
+        assert!(out.status.success());
+    };
+    report();
+    for name in ["index", "968"] {
+        let pdf = fs::read_to_string(outdir.join(format!("{name}.pdf"))).unwrap();
+        assert!(pdf.contains("--headless"), "{}", pdf);
+        assert!(pdf.contains(&format!("/out%20dir/{name}.html")), "{}", pdf);
+    }
+
+    // Pages whose log did not change keep their PDFs.
+    fs::write(outdir.join("968.pdf"), "kept").unwrap();
+    fs::write(outdir.join("index.pdf"), "old").unwrap();
+    report();
+    assert_eq!(fs::read_to_string(outdir.join("968.pdf")).unwrap(), "kept");
+    let index = fs::read_to_string(outdir.join("index.pdf")).unwrap();
+    assert!(index.contains("--headless"), "{}", index);
+}
+
+#[test]
+fn process_stitched_across_date_dirs() {
+    let dir = tempdir().expect("dir");
+    let log = |day: &str, pid: u32, times: &[&str], meta: &str| {
+        let day_dir = dir.path().join(day);
+        fs::create_dir_all(&day_dir).unwrap();
+        let data: String = times
+            .iter()
+            .enumerate()
+            .map(|(i, ts)| {
+                let extra = if i == 0 { meta } else { "" };
+                format!(
+                    "{{\"version\":1,\"timestamp\":\"{ts}\",\"pid\":{pid},\"process_name\":\"p\",\"cpu_time_percent\":10.0,\"memory\":{{\"rss_kb\":1000,\"vsz_kb\":0,\"swap_kb\":0}}{extra}}}\n"
+                )
+            })
+            .collect();
+        fs::write(day_dir.join(format!("{pid}.jsonl")), data).unwrap();
+    };
+    let started = ",\"hostname\":\"h\",\"start_time\":\"2025-06-14T20:00:00.000Z\"";
+    log(
+        "20250614",
+        700,
+        &["2025-06-14T23:59:00Z", "2025-06-14T23:59:50Z"],
+        &format!("{started},\"cmdline\":\"server\""),
+    );
+    log(
+        "20250615",
+        700,
+        &["2025-06-15T00:00:00Z", "2025-06-15T00:01:00Z"],
+        started,
+    );
+    // Logs from before start times were recorded are not joined.
+    log("20250614", 701, &["2025-06-14T23:59:00Z"], "");
+    log("20250615", 701, &["2025-06-15T00:00:00Z"], "");
+
+    let outdir = tempdir().expect("outdir");
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "report",
+            dir.path().to_str().unwrap(),
+            "-o",
+            outdir.path().to_str().unwrap(),
+        ])
+        .output()
+        .expect("run report");
+    assert!(out.status.success());
+    let summary: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(outdir.path().join("summary.json")).unwrap())
+            .unwrap();
+    let processes = summary["processes"].as_array().unwrap();
+    assert_eq!(processes.len(), 3, "{}", summary);
+    assert_eq!(processes[0]["pid"], 700);
+    assert_eq!(processes[0]["cmdline"], "server");
+    assert_eq!(processes[0]["start"], "2025-06-14T23:59:00.000Z");
+    assert_eq!(processes[0]["end"], "2025-06-15T00:01:00.000Z");
+    assert_eq!(processes[0]["runtime_sec"], 120);
+    let page = fs::read_to_string(outdir.path().join("700.html")).unwrap();
+    assert!(page.contains("server"), "{}", page);
+}
+
+#[test]
+fn self_overhead_kept_out_of_rankings() {
+    let dir = tempdir().expect("dir");
+    let log = |pid: u32, cpu: f64, extra: &str| {
+        let data: String = (0..3)
+            .map(|i| {
+                format!(
+                    "{{\"version\":1,\"timestamp\":\"2025-06-14T00:00:{i:02}Z\",\"pid\":{pid},\"process_name\":\"p{pid}\",\"cpu_time_percent\":{cpu},\"memory\":{{\"rss_kb\":1000,\"vsz_kb\":0,\"swap_kb\":0}}{extra}}}\n"
+                )
+            })
+            .collect();
+        fs::write(dir.path().join(format!("{pid}.jsonl")), data).unwrap();
+    };
+    log(720, 10.0, "");
+    log(721, 90.0, ",\"self_overhead\":true");
+
+    let outdir = tempdir().expect("outdir");
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "report",
+            dir.path().to_str().unwrap(),
+            "-o",
+            outdir.path().to_str().unwrap(),
+        ])
+        .output()
+        .expect("run report");
+    assert!(out.status.success());
+    let summary: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(outdir.path().join("summary.json")).unwrap())
+            .unwrap();
+    let processes = summary["processes"].as_array().unwrap();
+    assert_eq!(processes.len(), 1, "{}", summary);
+    assert_eq!(processes[0]["pid"], 720);
+    let index = fs::read_to_string(outdir.path().join("index.html")).unwrap();
+    let (ranked, overhead) = index.split_once("<h2>fuzmon overhead</h2>").unwrap();
+    assert!(!ranked.contains("<td>721</td>"), "{}", index);
+    assert!(overhead.contains("<td>721</td>"), "{}", index);
+    assert!(!outdir.path().join("721.html").exists());
+}
+
+#[test]
+fn reused_pid_in_one_log_kept_apart() {
+    let dir = tempdir().expect("dir");
+    let entry = |ts: &str, cpu: f64, started: &str| {
+        let meta = if started.is_empty() {
+            String::new()
+        } else {
+            format!(",\"hostname\":\"h\",\"start_time\":\"{started}\"")
+        };
+        format!(
+            "{{\"version\":1,\"timestamp\":\"{ts}\",\"pid\":710,\"process_name\":\"p\",\"cpu_time_percent\":{cpu},\"memory\":{{\"rss_kb\":1000,\"vsz_kb\":0,\"swap_kb\":0}}{meta}}}\n"
+        )
+    };
+    let data = [
+        entry("2025-06-14T00:00:00Z", 10.0, "2025-06-13T23:00:00.000Z"),
+        entry("2025-06-14T00:00:10Z", 10.0, ""),
+        entry("2025-06-14T01:00:00Z", 50.0, "2025-06-14T00:59:00.000Z"),
+        entry("2025-06-14T01:00:10Z", 50.0, ""),
+        entry("2025-06-14T01:00:20Z", 50.0, ""),
+    ]
+    .concat();
+    fs::write(dir.path().join("combined.jsonl"), data).unwrap();
+
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .arg("stats")
+        .arg(dir.path())
+        .arg("--json")
+        .output()
+        .expect("run stats");
+    assert!(out.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    let processes = json["processes"].as_array().unwrap();
+    assert_eq!(processes.len(), 2, "{}", json);
+    let mut runtimes: Vec<i64> = processes
+        .iter()
+        .map(|p| p["runtime_sec"].as_i64().unwrap())
+        .collect();
+    runtimes.sort();
+    assert_eq!(runtimes, [10, 20]);
+}
+
+#[test]
+fn stats_printed_as_table_or_json() {
+    let dir = tempdir().expect("dir");
+    let log = |pid: u32, cpu: f64| {
+        let data: String = (0..3)
+            .map(|i| {
+                format!(
+                    "{{\"version\":1,\"timestamp\":\"2025-06-14T00:00:{i:02}Z\",\"pid\":{pid},\"process_name\":\"w\",\"cpu_time_percent\":{cpu},\"memory\":{{\"rss_kb\":{},\"vsz_kb\":0,\"swap_kb\":0}},\"cmdline\":\"worker {pid}\"}}\n",
+                    1000 + i
+                )
+            })
+            .collect();
+        fs::write(dir.path().join(format!("{pid}.jsonl")), data).unwrap();
+    };
+    log(970, 10.0);
+    log(971, 50.0);
+
+    let run = |extra: &[&str]| {
+        let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+            .arg("stats")
+            .arg(dir.path())
+            .args(extra)
+            .output()
+            .expect("run stats");
+        assert!(out.status.success());
+        String::from_utf8(out.stdout).unwrap()
+    };
+    let table = run(&[]);
+    let lines: Vec<&str> = table.lines().collect();
+    assert_eq!(lines.len(), 3, "{}", table);
+    assert!(lines[0].trim_start().starts_with("PID"), "{}", table);
+    assert_eq!(
+        lines[1],
+        "    971          2        1.0     50.0         1002  worker 971"
+    );
+    assert!(lines[2].contains("worker 970"), "{}", table);
+
+    let json: serde_json::Value = serde_json::from_str(&run(&["--json", "--pid", "970"])).unwrap();
+    let processes = json["processes"].as_array().unwrap();
+    assert_eq!(processes.len(), 1);
+    assert_eq!(processes[0]["peak_rss_kb"], 1002);
+    assert!(!dir.path().join("index.html").exists());
+}
@@ -28,3 +28,169 @@ fn spawn_and_monitor_command() {
     assert!(fs::read_dir(sub).unwrap().next().is_some(), "no log file");
     assert!(!log_content.is_empty(), "log empty");
 }
+
+#[test]
+fn exit_code_of_command_propagated() {
+    let dir = tempdir().expect("dir");
+    let run = |script: &str| {
+        Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+            .args(["run", "-o", dir.path().to_str().unwrap()])
+            .args(["/bin/sh", "-c", script])
+            .output()
+            .expect("run")
+            .status
+            .code()
+    };
+    assert_eq!(run("exit 3"), Some(3));
+    assert_eq!(run("kill -TERM $$"), Some(128 + 15));
+    assert_eq!(run("true"), Some(0));
+}
+
+#[test]
+fn signals_forwarded_to_command() {
+    use nix::sys::signal::{Signal, kill};
+    use nix::unistd::Pid;
+    use std::io::{BufRead, BufReader};
+    use std::process::Stdio;
+
+    let dir = tempdir().expect("dir");
+    let mut mon = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args(["run", "-o", dir.path().to_str().unwrap()])
+        .args([
+            "/bin/sh",
+            "-c",
+            "trap 'kill $!; exit 7' TERM; echo ready; sleep 30 & wait",
+        ])
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("run");
+    let mut stdout = BufReader::new(mon.stdout.take().unwrap());
+    let mut line = String::new();
+    stdout.read_line(&mut line).expect("read");
+    assert_eq!(line, "ready\n");
+    kill(Pid::from_raw(mon.id() as i32), Signal::SIGTERM).expect("kill");
+    // Drains stdout, so fuzmon can report the exit.
+    assert!(stdout.lines().all(|l| l.is_ok()));
+    assert_eq!(mon.wait().expect("wait").code(), Some(7));
+}
+
+#[test]
+fn command_stopped_after_duration() {
+    let dir = tempdir().expect("dir");
+    let status = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "run",
+            "--duration",
+            "1s",
+            "-o",
+            dir.path().to_str().unwrap(),
+        ])
+        .args(["/bin/sleep", "600"])
+        .status()
+        .expect("run");
+    // Killed by the SIGTERM fuzmon sends once the time is up.
+    assert_eq!(status.code(), Some(128 + 15));
+}
+
+#[test]
+fn command_stopped_over_disk_budget() {
+    let dir = tempdir().expect("dir");
+    let cfg = dir.path().join("fuzmon.toml");
+    fs::write(
+        &cfg,
+        "[output]\nmax_total_size_gb = 0.000001\nmax_total_size_action = \"stop\"\n",
+    )
+    .unwrap();
+    let old = dir.path().join("20200101");
+    fs::create_dir(&old).unwrap();
+    fs::write(old.join("1.jsonl"), vec![b'x'; 4096]).unwrap();
+    let status = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args(["run", "-o", dir.path().to_str().unwrap()])
+        .arg("-c")
+        .arg(&cfg)
+        .args(["/bin/sleep", "600"])
+        .status()
+        .expect("run");
+    assert_eq!(status.code(), Some(128 + 15));
+}
+
+#[test]
+fn termination_forwarded_to_process_group_on_pty() {
+    use nix::sys::signal::{Signal, kill};
+    use nix::unistd::Pid;
+    use std::io::{BufRead, BufReader};
+    use std::process::Stdio;
+
+    let dir = tempdir().expect("dir");
+    let mut mon = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args(["run", "--pty", "-o", dir.path().to_str().unwrap()])
+        // The job survives the hangup of the shell, so it keeps the pty
+        // open unless it gets the signal too.
+        .args(["/bin/sh", "-c", "trap '' HUP; sleep 600 & echo ready; wait"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("run");
+    let mut stdout = BufReader::new(mon.stdout.take().unwrap());
+    let mut line = String::new();
+    stdout.read_line(&mut line).expect("read");
+    assert_eq!(line, "ready\r\n");
+    kill(Pid::from_raw(mon.id() as i32), Signal::SIGTERM).expect("kill");
+    assert!(stdout.lines().all(|l| l.is_ok()));
+    assert_eq!(mon.wait().expect("wait").code(), Some(128 + 15));
+}
+
+#[test]
+fn pty_gives_command_a_terminal() {
+    let dir = tempdir().expect("dir");
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args(["run", "--pty", "-o", dir.path().to_str().unwrap()])
+        .args([
+            "/bin/sh",
+            "-c",
+            "test -t 0 && test -t 1 && echo out && echo err >&2",
+        ])
+        .stdin(std::process::Stdio::null())
+        .output()
+        .expect("run");
+    assert!(out.status.success());
+    // One terminal keeps stdout and stderr in order.
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.starts_with("out\r\nerr\r\n"), "{}", stdout);
+}
+
+#[test]
+fn run_as_traces_command_of_other_user() {
+    if unsafe { nix::libc::geteuid() } != 0 {
+        eprintln!("skipping: needs root to switch users");
+        return;
+    }
+    let dir = tempdir().expect("dir");
+    let cfg = create_config(0.0);
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "run",
+            "--run-as",
+            "nobody",
+            "-o",
+            dir.path().to_str().unwrap(),
+        ])
+        .args(["-c", cfg.path().to_str().unwrap()])
+        .args([
+            "/bin/sh",
+            "-c",
+            "id -un; id -G; i=0; while [ $i -lt 300000 ]; do i=$((i+1)); done",
+        ])
+        .output()
+        .expect("run");
+    assert!(out.status.success());
+    let groups = Command::new("id")
+        .args(["-G", "nobody"])
+        .output()
+        .expect("id");
+    let expected = format!("nobody\n{}", String::from_utf8_lossy(&groups.stdout));
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.starts_with(&expected), "{}", stdout);
+    let log_content = collect_log_content(&dir);
+    assert!(log_content.contains("\"stacktrace\""), "{}", log_content);
+}
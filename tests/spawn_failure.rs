@@ -9,3 +9,12 @@ fn spawn_invalid_command_prints_message() {
     let stdout = String::from_utf8_lossy(&out.stdout);
     assert!(stdout.contains("failed to spawn"), "{}", stdout);
 }
+
+#[test]
+fn spawn_failure_exits_127() {
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args(["run", "/bin/hogeee"])
+        .output()
+        .expect("run");
+    assert_eq!(out.status.code(), Some(127));
+}
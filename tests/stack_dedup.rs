@@ -0,0 +1,97 @@
+use fuzmon::test_utils::{collect_log_content, create_config, kill_with_sigint_and_wait, wait_for};
+use serde_json::Value;
+use std::fs;
+use std::process::{Command, Stdio};
+use tempfile::tempdir;
+
+#[test]
+fn repeated_stacks_are_written_as_references() {
+    // Built with frame pointers so every sample unwinds to the same stack.
+    let dir = tempdir().expect("tempdir");
+    let src = dir.path().join("prog.c");
+    fs::write(
+        &src,
+        "#include <unistd.h>\nint main() { char c; read(0, &c, 1); return 0; }\n",
+    )
+    .expect("write src");
+    let exe = dir.path().join("prog");
+    assert!(
+        Command::new("gcc")
+            .args([
+                "-O0",
+                "-fno-omit-frame-pointer",
+                src.to_str().unwrap(),
+                "-o",
+                exe.to_str().unwrap()
+            ])
+            .status()
+            .expect("compile")
+            .success()
+    );
+    let mut child = Command::new(&exe)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("spawn prog");
+    let pid = child.id();
+
+    let logdir = tempdir().expect("logdir");
+    let cfg = create_config(0.0);
+    let mut mon = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "run",
+            "-p",
+            &pid.to_string(),
+            "-o",
+            logdir.path().to_str().unwrap(),
+            "-c",
+            cfg.path().to_str().unwrap(),
+        ])
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("run fuzmon");
+
+    let mut log = String::new();
+    wait_for(|| {
+        log = collect_log_content(&logdir);
+        log.lines().count() >= 3
+    });
+    kill_with_sigint_and_wait(&mut mon);
+    let _ = child.kill();
+    let _ = child.wait();
+
+    let entries: Vec<Value> = log
+        .lines()
+        .map(|l| serde_json::from_str(l).expect("json"))
+        .collect();
+    let threads: Vec<&Value> = entries.iter().map(|e| &e["threads"][0]).collect();
+    let reference = threads
+        .iter()
+        .position(|t| t.get("stacktrace").is_none() && t.get("stack_id").is_some())
+        .expect("no stack reference written");
+    let id = &threads[reference]["stack_id"];
+    assert!(
+        threads[..reference]
+            .iter()
+            .any(|t| &t["stack_id"] == id && t.get("stacktrace").is_some()),
+        "{}",
+        log
+    );
+
+    let date_dir = fs::read_dir(logdir.path())
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap()
+        .path();
+    let out = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args(["dump", date_dir.to_str().unwrap()])
+        .output()
+        .expect("run dump");
+    let out = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        out.matches("stacktrace: Some").count() >= 2,
+        "references not resolved: {}",
+        out
+    );
+}
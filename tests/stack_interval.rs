@@ -0,0 +1,53 @@
+use fuzmon::test_utils::{collect_log_content, kill_with_sigint_and_wait, wait_for};
+use serde_json::Value;
+use std::fs;
+use std::process::{Command, Stdio};
+use tempfile::{NamedTempFile, tempdir};
+
+#[test]
+fn stacks_sampled_less_often_than_metrics() {
+    let mut child = Command::new("sleep").arg("30").spawn().expect("spawn");
+    let pid = child.id();
+
+    let logdir = tempdir().expect("logdir");
+    let cfg = NamedTempFile::new().expect("cfg");
+    fs::write(
+        cfg.path(),
+        "[monitor]\nstacktrace_cpu_time_percent_threshold = 0.0\nstacktrace_interval_sec = 3600\n",
+    )
+    .expect("write cfg");
+    let mut mon = Command::new(env!("CARGO_BIN_EXE_fuzmon"))
+        .args([
+            "run",
+            "-p",
+            &pid.to_string(),
+            "-o",
+            logdir.path().to_str().unwrap(),
+            "-c",
+            cfg.path().to_str().unwrap(),
+        ])
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("run fuzmon");
+
+    let mut log = String::new();
+    wait_for(|| {
+        log = collect_log_content(&logdir);
+        log.lines().count() >= 3
+    });
+    kill_with_sigint_and_wait(&mut mon);
+    let _ = child.kill();
+    let _ = child.wait();
+
+    let entries: Vec<Value> = log
+        .lines()
+        .map(|l| serde_json::from_str(l).expect("json"))
+        .collect();
+    assert!(entries.len() >= 3, "{}", log);
+    assert!(entries[0].get("threads").is_some(), "{}", log);
+    assert!(
+        entries[1..].iter().all(|e| e.get("threads").is_none()),
+        "{}",
+        log
+    );
+}
@@ -0,0 +1,43 @@
+use fuzmon::test_utils::run_fuzmon_with_config;
+use std::fs;
+use std::net::UdpSocket;
+use std::process::Command;
+use std::time::Duration;
+use tempfile::{NamedTempFile, tempdir};
+
+#[test]
+fn statsd_receives_process_gauges() {
+    let socket = UdpSocket::bind("127.0.0.1:0").expect("bind");
+    socket
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .expect("timeout");
+    let addr = socket.local_addr().expect("addr");
+    let mut child = Command::new("sleep").arg("30").spawn().expect("spawn");
+    let pid = child.id();
+
+    let logdir = tempdir().expect("logdir");
+    let cfg = NamedTempFile::new().expect("cfg");
+    fs::write(
+        cfg.path(),
+        format!("[output]\nstatsd = \"{addr}\"\nstatsd_template = \"test.{{pid}}.{{metric}}\"\n"),
+    )
+    .expect("write cfg");
+    run_fuzmon_with_config(env!("CARGO_BIN_EXE_fuzmon"), pid, &logdir, &cfg);
+    let _ = child.kill();
+    let _ = child.wait();
+
+    let mut buf = [0u8; 2048];
+    let n = socket.recv(&mut buf).expect("statsd packet");
+    let packet = String::from_utf8_lossy(&buf[..n]).into_owned();
+    assert!(
+        packet.contains(&format!("test.{pid}.cpu_percent:")),
+        "{}",
+        packet
+    );
+    assert!(
+        packet.contains(&format!("test.{pid}.rss_kb:")),
+        "{}",
+        packet
+    );
+    assert!(packet.lines().all(|l| l.ends_with("|g")), "{}", packet);
+}
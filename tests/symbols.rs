@@ -1,11 +1,16 @@
 use std::fs;
 use std::io::{BufRead, BufReader};
+use std::path::Path;
 use std::process::{Command, Stdio};
 use tempfile::tempdir;
 
 use fuzmon::test_utils::run_fuzmon_and_check;
 
 fn run_symbol_test(flags: &[&str], expected: &[&str]) {
+    run_symbol_test_with(flags, expected, |_| {});
+}
+
+fn run_symbol_test_with(flags: &[&str], expected: &[&str], post_build: impl Fn(&Path)) {
     let dir = tempdir().expect("tempdir");
     let src_path = dir.path().join("testprog.c");
     fs::write(
@@ -54,6 +59,7 @@ int main() {
         .status()
         .expect("compile test program");
     assert!(status.success());
+    post_build(&exe_path);
 
     let mut child = Command::new(&exe_path)
         .stdin(Stdio::piped())
@@ -99,3 +105,24 @@ fn symbolized_stack_trace_contains_function_g1() {
 fn symbolized_stack_trace_contains_function_o2() {
     run_symbol_test(&["-g", "-O2"], &["target_function", "main", "testprog.c"]);
 }
+
+#[test]
+fn symbolized_stack_trace_from_gnu_debuglink() {
+    run_symbol_test_with(
+        &["-g", "-O0"],
+        &["target_function", "main", "testprog.c"],
+        |exe| {
+            let exe = exe.to_str().unwrap();
+            let debug = format!("{}.debug", exe);
+            let link = format!("--add-gnu-debuglink={}", debug);
+            for (cmd, args) in [
+                ("objcopy", vec!["--only-keep-debug", exe, &debug]),
+                ("strip", vec!["--strip-debug", exe]),
+                ("objcopy", vec![&link, exe]),
+            ] {
+                let status = Command::new(cmd).args(&args).status().expect(cmd);
+                assert!(status.success());
+            }
+        },
+    );
+}
@@ -0,0 +1,39 @@
+use fuzmon::test_utils::run_fuzmon_with_config;
+use std::fs;
+use std::os::unix::net::UnixDatagram;
+use std::process::Command;
+use std::time::Duration;
+use tempfile::{NamedTempFile, tempdir};
+
+#[test]
+fn syslog_receives_process_events() {
+    let sockdir = tempdir().expect("sockdir");
+    let sock_path = sockdir.path().join("log.sock");
+    let socket = UnixDatagram::bind(&sock_path).expect("bind");
+    socket
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .expect("timeout");
+    let mut child = Command::new("sleep").arg("30").spawn().expect("spawn");
+    let pid = child.id();
+
+    let logdir = tempdir().expect("logdir");
+    let cfg = NamedTempFile::new().expect("cfg");
+    fs::write(
+        cfg.path(),
+        format!("[output]\nsyslog = \"{}\"\n", sock_path.display()),
+    )
+    .expect("write cfg");
+    run_fuzmon_with_config(env!("CARGO_BIN_EXE_fuzmon"), pid, &logdir, &cfg);
+    let _ = child.kill();
+    let _ = child.wait();
+
+    let mut buf = [0u8; 1024];
+    let n = socket.recv(&mut buf).expect("syslog message");
+    let msg = String::from_utf8_lossy(&buf[..n]).into_owned();
+    assert!(msg.starts_with("<30>fuzmon["), "{}", msg);
+    assert!(
+        msg.ends_with(&format!("process {pid} (sleep) appeared")),
+        "{}",
+        msg
+    );
+}